@@ -0,0 +1,23 @@
+//! `cargo-extismx`: `extismx` as a cargo subcommand, so plugins can be built
+//! with `cargo extismx build`
+
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(dead_code)]
+#[path = "extismx.rs"]
+mod extismx;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // Cargo passes the subcommand name before its arguments
+    if args.first().is_some_and(|arg| arg == "extismx") {
+        args.remove(0);
+    }
+    if let Err(e) = extismx::cli::run(args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -2,12 +2,88 @@
 //! 
 //! This module provides the Rust interface for developing Extism plugins.
 
-use std::alloc::{alloc, dealloc, Layout};
-use std::ffi::{c_void, CStr, CString};
-use std::mem;
-use std::slice;
+use std::fmt;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(any(feature = "audit", not(target_arch = "wasm32")))]
+pub mod audit;
+#[cfg(feature = "avro")]
+pub mod avro;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod bench;
+pub mod binary;
+#[cfg(all(feature = "bson", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+pub mod bson;
+#[cfg(all(feature = "bson", target_arch = "wasm32", target_os = "unknown"))]
+compile_error!(
+    "the `bson` feature needs a WASI target such as `wasm32-wasip1`; on \
+     `wasm32-unknown-unknown` the bson crate imports JavaScript functions Extism hosts do not provide"
+);
+#[cfg(not(target_arch = "wasm32"))]
+pub mod build;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod capability;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod codegen;
+#[cfg(all(feature = "config", feature = "yaml"))]
+pub mod config;
+pub mod conformance;
+#[cfg(feature = "crypto")]
+pub mod crypto;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod docs;
+pub mod error;
+pub mod event;
+#[cfg(feature = "flatbuffers")]
+pub mod flatbuffers;
+pub mod guard;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod host;
+pub mod io;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod lint;
+pub mod plugin;
+pub mod pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod provenance;
+#[cfg(all(feature = "vars", feature = "time"))]
+pub mod ratelimit;
+pub mod redact;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod registry;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod scaffold;
+pub mod schema;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod signing;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod size;
+#[cfg(feature = "subplugin")]
+pub mod subplugin;
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+pub mod testing;
+pub mod tls;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod trace;
+pub mod var;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod verify;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod wit;
+#[cfg(feature = "xml")]
+pub mod xml;
+
+pub use error::{Error, ErrorKind};
+pub use tls::{TlsPolicy, TlsVersion};
+pub use event::Event;
 
 // External Extism functions
+#[cfg(not(all(feature = "testing", not(target_arch = "wasm32"))))]
 extern "C" {
     fn extism_input_length() -> u64;
     fn extism_input_load_u8(offset: u64, len: u64, buf: *mut u8);
@@ -18,17 +94,46 @@ extern "C" {
     fn extism_length(pointer: u64) -> u64;
     fn extism_store_u8(pointer: u64, offset: u64, buf: *const u8, len: u64);
     fn extism_load_u8(pointer: u64, offset: u64, len: u64, buf: *mut u8);
+    #[cfg(feature = "http")]
     fn extism_http_request(req: u64, out: *mut u64) -> i32;
+    #[cfg(feature = "http")]
     fn extism_http_status_code(resp: u64) -> i32;
+    #[cfg(feature = "config")]
     fn extism_config_get(key: *const u8, key_len: u64) -> u64;
+    #[cfg(feature = "vars")]
     fn extism_var_get(name: *const u8, name_len: u64) -> u64;
+    #[cfg(feature = "vars")]
     fn extism_var_set(name: *const u8, name_len: u64, value: *const u8, value_len: u64);
+    #[cfg(feature = "log")]
     fn extism_log_info(msg: *const u8, msg_len: u64);
+    #[cfg(feature = "log")]
     fn extism_log_debug(msg: *const u8, msg_len: u64);
+    #[cfg(feature = "log")]
     fn extism_log_warn(msg: *const u8, msg_len: u64);
+    #[cfg(feature = "log")]
     fn extism_log_error(msg: *const u8, msg_len: u64);
+    #[cfg(feature = "var-batch")]
+    fn extism_var_get_many(payload: *const u8, payload_len: u64) -> u64;
+    #[cfg(feature = "var-batch")]
+    fn extism_var_set_many(payload: *const u8, payload_len: u64);
+    #[cfg(feature = "time")]
+    fn extism_time_now_ms() -> u64;
+    #[cfg(feature = "subplugin")]
+    fn extism_plugin_call(
+        plugin: *const u8,
+        plugin_len: u64,
+        function: *const u8,
+        function_len: u64,
+        input: *const u8,
+        input_len: u64,
+        out: *mut u64,
+    ) -> i32;
 }
 
+// Native test builds run against the mock host instead
+#[cfg(all(feature = "testing", not(target_arch = "wasm32")))]
+use testing::kernel::*;
+
 /// Memory allocation in the Extism runtime
 #[derive(Debug)]
 pub struct Memory {
@@ -77,7 +182,7 @@ impl Memory {
 
     /// Load bytes from memory
     pub fn load(&self, offset: u64, length: u64) -> Vec<u8> {
-        let mut data = vec![0u8; length as usize];
+        let mut data = pool::take(length as usize);
         unsafe {
             extism_load_u8(
                 self.offset,
@@ -94,6 +199,12 @@ impl Memory {
         self.load(0, self.len())
     }
 
+    /// Read the bytes of the block incrementally, instead of loading them
+    /// whole
+    pub fn reader(&self) -> io::MemoryReader<'_> {
+        io::MemoryReader::new(self)
+    }
+
     /// Create a Memory object from a string
     pub fn from_string(s: &str) -> Self {
         let bytes = s.as_bytes();
@@ -113,12 +224,48 @@ impl Memory {
         String::from_utf8(self.load_all())
     }
 
-    /// Parse JSON from memory
+    /// Parse JSON from memory, reading it as it is parsed
     pub fn to_json<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
-        let s = self.to_string().map_err(|e| {
-            serde_json::Error::custom(format!("Invalid UTF-8: {}", e))
-        })?;
-        serde_json::from_str(&s)
+        serde_json::from_reader(std::io::BufReader::with_capacity(
+            io::CHUNK_SIZE,
+            self.reader(),
+        ))
+    }
+
+    /// Create a Memory object from a protobuf message
+    #[cfg(feature = "prost")]
+    pub fn from_proto<M: prost::Message>(message: &M) -> Self {
+        let bytes = message.encode_to_vec();
+        let mut mem = Self::new(bytes.len() as u64);
+        mem.store_from_start(&bytes);
+        mem
+    }
+
+    /// Decode a protobuf message from memory
+    #[cfg(feature = "prost")]
+    pub fn to_proto<M: prost::Message + Default>(&self) -> Result<M, prost::DecodeError> {
+        M::decode(self.load_all().as_slice())
+    }
+
+    /// Create a Memory object from record batches, as an Arrow IPC stream
+    #[cfg(feature = "arrow")]
+    pub fn from_arrow(batches: &[arrow::RecordBatch]) -> Result<Self, arrow::ArrowError> {
+        let bytes = arrow::write_stream(batches)?;
+        let mut mem = Self::new(bytes.len() as u64);
+        mem.store_from_start(&bytes);
+        Ok(mem)
+    }
+
+    /// Read the record batches of an Arrow IPC stream in memory
+    #[cfg(feature = "arrow")]
+    pub fn to_arrow(&self) -> Result<Vec<arrow::RecordBatch>, arrow::ArrowError> {
+        arrow::read_stream(&self.load_all())
+    }
+
+    /// Load a FlatBuffers buffer from memory, to read in place
+    #[cfg(feature = "flatbuffers")]
+    pub fn to_flatbuffer(&self) -> flatbuffers::Buffer {
+        flatbuffers::Buffer::new(self.load_all())
     }
 }
 
@@ -131,8 +278,9 @@ impl Drop for Memory {
 }
 
 /// HTTP Request method
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum HttpMethod {
+    #[default]
     Get,
     Post,
     Put,
@@ -142,21 +290,23 @@ pub enum HttpMethod {
     Options,
 }
 
-impl ToString for HttpMethod {
-    fn to_string(&self) -> String {
-        match self {
-            HttpMethod::Get => "GET".to_string(),
-            HttpMethod::Post => "POST".to_string(),
-            HttpMethod::Put => "PUT".to_string(),
-            HttpMethod::Delete => "DELETE".to_string(),
-            HttpMethod::Patch => "PATCH".to_string(),
-            HttpMethod::Head => "HEAD".to_string(),
-            HttpMethod::Options => "OPTIONS".to_string(),
-        }
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method = match self {
+            HttpMethod::Get => "GET",
+            HttpMethod::Post => "POST",
+            HttpMethod::Put => "PUT",
+            HttpMethod::Delete => "DELETE",
+            HttpMethod::Patch => "PATCH",
+            HttpMethod::Head => "HEAD",
+            HttpMethod::Options => "OPTIONS",
+        };
+        f.write_str(method)
     }
 }
 
 /// HTTP Request structure
+#[derive(Default)]
 pub struct HttpRequest {
     /// The request method
     pub method: HttpMethod,
@@ -166,14 +316,26 @@ pub struct HttpRequest {
     pub headers: Vec<(String, String)>,
     /// Request body
     pub body: Option<Vec<u8>>,
+    /// Requirements on the TLS connection, for hosts that enforce them
+    pub tls: Option<TlsPolicy>,
+}
+
+impl HttpRequest {
+    /// Send the request only over a connection satisfying `policy`
+    pub fn with_tls(mut self, policy: TlsPolicy) -> Self {
+        self.tls = Some(policy);
+        self
+    }
 }
 
 /// HTTP Response structure
+#[cfg(feature = "http")]
 pub struct HttpResponse {
     /// The response pointer
     ptr: u64,
 }
 
+#[cfg(feature = "http")]
 impl HttpResponse {
     /// Get the HTTP status code
     pub fn status(&self) -> i32 {
@@ -182,13 +344,14 @@ impl HttpResponse {
 
     /// Get the response body
     pub fn body(&self) -> Vec<u8> {
-        let body_ptr = unsafe { extism_var_get("response:body\0".as_ptr(), 14) };
+        let name = "response:body";
+        let body_ptr = unsafe { extism_var_get(name.as_ptr(), name.len() as u64) };
         if body_ptr == 0 {
             return Vec::new();
         }
 
         let len = unsafe { extism_length(body_ptr) };
-        let mut data = vec![0u8; len as usize];
+        let mut data = pool::take(len as usize);
         unsafe {
             extism_load_u8(body_ptr, 0, len, data.as_mut_ptr());
             extism_free(body_ptr);
@@ -196,11 +359,11 @@ impl HttpResponse {
         data
     }
 
-    /// Get a specific header from the response
+    /// Get a specific header from the response, matching the name case-insensitively
     pub fn header(&self, name: &str) -> Option<String> {
-        let header_var = format!("response:header:{}\0", name);
+        let header_var = format!("response:header:{}", name.to_ascii_lowercase());
         let header_ptr = unsafe { 
-            extism_var_get(header_var.as_ptr(), header_var.len() as u64 - 1) 
+            extism_var_get(header_var.as_ptr(), header_var.len() as u64) 
         };
         
         if header_ptr == 0 {
@@ -208,7 +371,7 @@ impl HttpResponse {
         }
 
         let len = unsafe { extism_length(header_ptr) };
-        let mut data = vec![0u8; len as usize];
+        let mut data = pool::take(len as usize);
         unsafe {
             extism_load_u8(header_ptr, 0, len, data.as_mut_ptr());
             extism_free(header_ptr);
@@ -218,6 +381,7 @@ impl HttpResponse {
     }
 }
 
+#[cfg(feature = "http")]
 impl Drop for HttpResponse {
     fn drop(&mut self) {
         unsafe {
@@ -245,12 +409,69 @@ impl Host {
         String::from_utf8(Self::input())
     }
 
-    /// Parse JSON from the plugin input
+    /// Parse JSON from the plugin input, reading it as it is parsed
     pub fn input_json<T: serde::de::DeserializeOwned>() -> Result<T, serde_json::Error> {
-        let input = Self::input_string().map_err(|e| {
-            serde_json::Error::custom(format!("Invalid UTF-8: {}", e))
-        })?;
-        serde_json::from_str(&input)
+        serde_json::from_reader(std::io::BufReader::with_capacity(
+            io::CHUNK_SIZE,
+            io::InputReader::new(),
+        ))
+    }
+
+    /// Deserialize the plugin input as a YAML document
+    #[cfg(feature = "yaml")]
+    pub fn input_yaml<T: serde::de::DeserializeOwned>() -> Result<T, serde_yaml::Error> {
+        serde_yaml::from_slice(&Self::input())
+    }
+
+    /// Read the plugin input incrementally, instead of copying it whole
+    pub fn input_reader() -> io::InputReader {
+        io::InputReader::new()
+    }
+
+    /// Parse the plugin input as newline-delimited JSON, one record at a
+    /// time
+    ///
+    /// The input is read in `io::CHUNK_SIZE` chunks, so only the current
+    /// chunk and record are held in plugin memory.
+    pub fn input_ndjson<T: serde::de::DeserializeOwned>() -> io::Ndjson<T> {
+        io::Ndjson::new(std::io::BufReader::with_capacity(
+            io::CHUNK_SIZE,
+            io::InputReader::new(),
+        ))
+    }
+
+    /// Deserialize the plugin input as an XML document, reading it as it is
+    /// parsed
+    #[cfg(feature = "xml")]
+    pub fn input_xml<T: serde::de::DeserializeOwned>() -> Result<T, xml::DeError> {
+        xml::from_reader(std::io::BufReader::with_capacity(
+            io::CHUNK_SIZE,
+            io::InputReader::new(),
+        ))
+    }
+
+    /// Deserialize the plugin input as a BSON document
+    #[cfg(all(feature = "bson", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    pub fn input_bson<T: serde::de::DeserializeOwned>() -> Result<T, bson::BsonError> {
+        bson::from_slice(&Self::input())
+    }
+
+    /// Decode a protobuf message from the plugin input
+    #[cfg(feature = "prost")]
+    pub fn input_proto<M: prost::Message + Default>() -> Result<M, prost::DecodeError> {
+        M::decode(Self::input().as_slice())
+    }
+
+    /// Read the record batches of an Arrow IPC stream from the plugin input
+    #[cfg(feature = "arrow")]
+    pub fn input_arrow() -> Result<Vec<arrow::RecordBatch>, arrow::ArrowError> {
+        arrow::read_stream(&Self::input())
+    }
+
+    /// Load the plugin input as a FlatBuffers buffer, to read in place
+    #[cfg(feature = "flatbuffers")]
+    pub fn input_flatbuffer() -> flatbuffers::Buffer {
+        flatbuffers::Buffer::input()
     }
 
     /// Set the plugin output
@@ -266,29 +487,101 @@ impl Host {
     }
 
     /// Set the plugin output from JSON
+    ///
+    /// Its strings are scrubbed by the installed `redact::Redactor` if it
+    /// scrubs output.
     pub fn output_json<T: serde::Serialize>(data: &T) -> Result<(), serde_json::Error> {
-        let json = serde_json::to_string(data)?;
+        let json = redact::to_json(data, true)?;
         Self::output_string(&json);
         Ok(())
     }
 
-    /// Set an error
+    /// Write the plugin output through `std::io::Write`, setting it when
+    /// the writer is flushed or dropped
+    pub fn output_writer() -> io::OutputWriter {
+        io::OutputWriter::new()
+    }
+
+    /// Set the plugin output to a value serialized as an XML document
+    ///
+    /// Like `output_proto`, the document is not scrubbed by the installed
+    /// `redact::Redactor`.
+    #[cfg(feature = "xml")]
+    pub fn output_xml<T: serde::Serialize>(data: &T) -> Result<(), xml::SeError> {
+        Self::output_string(&xml::to_string(data)?);
+        Ok(())
+    }
+
+    /// Set the plugin output to a protobuf message
+    ///
+    /// Unlike `output_json`, the message is not scrubbed by the installed
+    /// `redact::Redactor`.
+    #[cfg(feature = "prost")]
+    pub fn output_proto<M: prost::Message>(message: &M) {
+        Self::output(&message.encode_to_vec());
+    }
+
+    /// Set the plugin output to a value as a BSON document
+    ///
+    /// Like `output_proto`, the document is not scrubbed by the installed
+    /// `redact::Redactor`.
+    #[cfg(all(feature = "bson", not(all(target_arch = "wasm32", target_os = "unknown"))))]
+    pub fn output_bson<T: serde::Serialize>(value: &T) -> Result<(), bson::BsonError> {
+        Self::output(&bson::to_vec(value)?);
+        Ok(())
+    }
+
+    /// Set the plugin output to record batches, as an Arrow IPC stream
+    ///
+    /// Like `output_proto`, the batches are not scrubbed by the installed
+    /// `redact::Redactor`.
+    #[cfg(feature = "arrow")]
+    pub fn output_arrow(batches: &[arrow::RecordBatch]) -> Result<(), arrow::ArrowError> {
+        Self::output(&arrow::write_stream(batches)?);
+        Ok(())
+    }
+
+    /// Set the plugin output to the buffer a `FlatBufferBuilder` finished
+    ///
+    /// Panics if `finish` was not called on the builder.
+    #[cfg(feature = "flatbuffers")]
+    pub fn output_flatbuffer(builder: &flatbuffers::FlatBufferBuilder) {
+        Self::output(builder.finished_data());
+    }
+
+    /// Set an error, scrubbed by the installed `redact::Redactor`
     pub fn error(message: &str) {
+        Self::set_error(&redact::redact(message));
+    }
+
+    fn set_error(message: &str) {
         unsafe {
             extism_error_set(message.as_ptr(), message.len() as u64);
         }
     }
 
+    /// Set an error from JSON, with its strings scrubbed by the installed
+    /// `redact::Redactor`
+    pub fn error_json<T: serde::Serialize>(data: &T) -> Result<(), serde_json::Error> {
+        let json = redact::to_json(data, false)?;
+        Self::set_error(&json);
+        Ok(())
+    }
+
     /// Get a configuration value
+    ///
+    /// The key is passed to the host as it is, without allocating; keys
+    /// built at runtime and read repeatedly can be built once as a
+    /// `var::VarName`.
+    #[cfg(feature = "config")]
     pub fn config(key: &str) -> Option<String> {
-        let key_cstr = CString::new(key).unwrap();
-        let ptr = unsafe { extism_config_get(key_cstr.as_ptr() as *const u8, key.len() as u64) };
+        let ptr = unsafe { extism_config_get(key.as_ptr(), key.len() as u64) };
         if ptr == 0 {
             return None;
         }
 
         let len = unsafe { extism_length(ptr) };
-        let mut data = vec![0u8; len as usize];
+        let mut data = pool::take(len as usize);
         unsafe {
             extism_load_u8(ptr, 0, len, data.as_mut_ptr());
             extism_free(ptr);
@@ -298,15 +591,17 @@ impl Host {
     }
 
     /// Get a variable
+    ///
+    /// Like `config`, the name is passed to the host without allocating.
+    #[cfg(feature = "vars")]
     pub fn var_get(name: &str) -> Option<Vec<u8>> {
-        let name_cstr = CString::new(name).unwrap();
-        let ptr = unsafe { extism_var_get(name_cstr.as_ptr() as *const u8, name.len() as u64) };
+        let ptr = unsafe { extism_var_get(name.as_ptr(), name.len() as u64) };
         if ptr == 0 {
             return None;
         }
 
         let len = unsafe { extism_length(ptr) };
-        let mut data = vec![0u8; len as usize];
+        let mut data = pool::take(len as usize);
         unsafe {
             extism_load_u8(ptr, 0, len, data.as_mut_ptr());
             extism_free(ptr);
@@ -316,11 +611,22 @@ impl Host {
     }
 
     /// Set a variable
+    ///
+    /// Counts against the var writes of the `guard::Budget` in force; a
+    /// write over budget is skipped and fails the call.
+    #[cfg(feature = "vars")]
     pub fn var_set(name: &str, value: &[u8]) {
-        let name_cstr = CString::new(name).unwrap();
+        if guard::charge_var_write() {
+            Self::write_var(name, value);
+        }
+    }
+
+    /// Set a variable the PDK uses internally, outside of any budget
+    #[cfg(feature = "vars")]
+    fn write_var(name: &str, value: &[u8]) {
         unsafe {
             extism_var_set(
-                name_cstr.as_ptr() as *const u8, 
+                name.as_ptr(), 
                 name.len() as u64, 
                 value.as_ptr(), 
                 value.len() as u64
@@ -329,56 +635,89 @@ impl Host {
     }
 
     /// Set a variable from a string
+    #[cfg(feature = "vars")]
     pub fn var_set_string(name: &str, value: &str) {
         Self::var_set(name, value.as_bytes());
     }
 
     /// Log an info message
+    #[cfg(feature = "log")]
     pub fn log_info(message: &str) {
+        let message = redact::redact(message);
         unsafe {
             extism_log_info(message.as_ptr(), message.len() as u64);
         }
     }
 
     /// Log a debug message
+    #[cfg(feature = "log")]
     pub fn log_debug(message: &str) {
+        let message = redact::redact(message);
         unsafe {
             extism_log_debug(message.as_ptr(), message.len() as u64);
         }
     }
 
     /// Log a warning message
+    #[cfg(feature = "log")]
     pub fn log_warn(message: &str) {
+        let message = redact::redact(message);
         unsafe {
             extism_log_warn(message.as_ptr(), message.len() as u64);
         }
     }
 
     /// Log an error message
+    #[cfg(feature = "log")]
     pub fn log_error(message: &str) {
+        let message = redact::redact(message);
         unsafe {
             extism_log_error(message.as_ptr(), message.len() as u64);
         }
     }
 
+    /// The host's clock, in milliseconds since the Unix epoch
+    ///
+    /// Plugins on `wasm32-unknown-unknown` have no clock of their own.
+    #[cfg(feature = "time")]
+    pub fn now_ms() -> u64 {
+        unsafe { extism_time_now_ms() }
+    }
+
     /// Make an HTTP request
-    pub fn http_request(request: &HttpRequest) -> Result<HttpResponse, String> {
+    ///
+    /// Requests to hosts outside the manifest's `allowed_hosts` fail with an
+    /// `ErrorKind::Invalid` error; network failures are `ErrorKind::Retryable`.
+    /// Requests over the `guard::Budget` in force fail with
+    /// `ErrorKind::Invalid` without being sent. Connections that do not
+    /// satisfy the request's `TlsPolicy` fail with an `ErrorKind::Fatal`
+    /// error whose message starts with "TLS verification".
+    #[cfg(feature = "http")]
+    pub fn http_request(request: &HttpRequest) -> Result<HttpResponse, Error> {
+        guard::charge_http()?;
+
         // Convert the request to JSON
         let method = request.method.to_string();
         
         // Set request variables
-        Self::var_set_string("request:method", &method);
-        Self::var_set_string("request:url", &request.url);
+        Self::write_var("request:method", method.as_bytes());
+        Self::write_var("request:url", request.url.as_bytes());
         
         // Set headers
         for (key, value) in &request.headers {
             let header_name = format!("request:header:{}", key);
-            Self::var_set_string(&header_name, value);
+            Self::write_var(&header_name, value.as_bytes());
         }
         
         // Set body if present
         if let Some(body) = &request.body {
-            Self::var_set("request:body", body);
+            Self::write_var("request:body", body);
+        }
+
+        if let Some(tls) = &request.tls {
+            let policy = serde_json::to_vec(tls)
+                .map_err(|e| Error::fatal(format!("Failed to serialize TLS policy: {}", e)))?;
+            Self::write_var("request:tls", &policy);
         }
         
         // Make the request
@@ -388,11 +727,38 @@ impl Host {
         };
         
         if status != 0 {
-            return Err("HTTP request failed".to_string());
+            let error = Self::var_get("response:error")
+                .and_then(|e| serde_json::from_slice(&e).ok())
+                .unwrap_or_else(|| Error::fatal("HTTP request failed"));
+            return Err(error);
         }
         
         Ok(HttpResponse { ptr: response_ptr })
     }
+
+    /// Read the next chunk of a streaming call
+    ///
+    /// For functions called with `Plugin::call_streaming`, each call receives
+    /// one input chunk. Returns `None` on the final call, which the host makes
+    /// after the last chunk so the plugin can flush any buffered output.
+    #[cfg(feature = "vars")]
+    pub fn read_stream() -> Option<Vec<u8>> {
+        if Self::var_get("stream:end").is_some() {
+            return None;
+        }
+        Some(Self::input())
+    }
+
+    /// Write an output chunk for a streaming call
+    ///
+    /// Chunks written during a call are delivered to the host together once
+    /// the call returns.
+    #[cfg(feature = "vars")]
+    pub fn write_stream(data: &[u8]) {
+        let mut chunk = Self::var_get("stream:output").unwrap_or_default();
+        chunk.extend_from_slice(data);
+        Self::write_var("stream:output", &chunk);
+    }
 }
 
 /// Macro for exporting Extism plugin functions
@@ -402,19 +768,20 @@ macro_rules! export_plugin {
         $(
             #[no_mangle]
             pub extern "C" fn $name() -> i32 {
-                match (|| -> Result<$ret, String> {
+                let result = $crate::extism_pdk::plugin::catch(|| -> Result<$ret, $crate::extism_pdk::Error> {
                     $body
-                })() {
-                    Ok(result) => {
-                        if let Err(e) = $crate::Host::output_json(&result) {
-                            $crate::Host::error(&format!("Failed to serialize output: {}", e));
-                            1
-                        } else {
-                            0
-                        }
-                    }
+                });
+                let result = result.and_then(|output| {
+                    $crate::extism_pdk::Host::output_json(&output).map_err(|e| {
+                        $crate::extism_pdk::Error::fatal(format!("Failed to serialize output: {}", e))
+                    })
+                });
+                match result {
+                    Ok(()) => 0,
                     Err(e) => {
-                        $crate::Host::error(&e);
+                        if $crate::extism_pdk::Host::error_json(&e).is_err() {
+                            $crate::extism_pdk::Host::error(&e.message);
+                        }
                         1
                     }
                 }
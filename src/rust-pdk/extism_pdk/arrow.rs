@@ -0,0 +1,43 @@
+//! Tabular payloads in the Arrow IPC stream format
+//!
+//! With the `arrow` feature, analytics plugins exchange record batches with
+//! the host column by column, instead of converting rows to and from JSON:
+//!
+//! ```ignore
+//! let batches = Host::input_arrow()?;
+//! let totals = batches
+//!     .iter()
+//!     .map(|batch| sum_by_region(batch))
+//!     .collect::<Result<Vec<_>, _>>()?;
+//! Host::output_arrow(&totals)?;
+//! ```
+//!
+//! Payloads are single IPC streams: a schema followed by its batches, as
+//! `pyarrow.ipc.new_stream` and `arrow::ipc::writer::StreamWriter` write
+//! them.
+
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+
+pub use arrow_array::RecordBatch;
+pub use arrow_schema::ArrowError;
+
+/// Read the record batches of an IPC stream
+pub fn read_stream(data: &[u8]) -> Result<Vec<RecordBatch>, ArrowError> {
+    StreamReader::try_new(data, None)?.collect()
+}
+
+/// Write record batches as an IPC stream, under the schema of the first
+///
+/// A stream starts with its schema, so there must be at least one batch;
+/// an empty table is written as `RecordBatch::new_empty(schema)`.
+pub fn write_stream(batches: &[RecordBatch]) -> Result<Vec<u8>, ArrowError> {
+    let first = batches.first().ok_or_else(|| {
+        ArrowError::InvalidArgumentError("an IPC stream needs a batch for its schema".to_string())
+    })?;
+    let mut writer = StreamWriter::try_new(Vec::new(), &first.schema())?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.into_inner()
+}
@@ -0,0 +1,228 @@
+//! Tamper-evident audit records of security-relevant plugin actions
+//!
+//! With the `audit` feature, `log` appends a structured record to the
+//! `audit:records` var, apart from the plugin's debug logs:
+//!
+//! ```ignore
+//! audit::log("token.issued", &[("subject", &claims.sub), ("scope", "admin")]);
+//! ```
+//!
+//! Each record holds the SHA-256 hash of the one before it, so a record
+//! removed, reordered or altered after the fact breaks the chain. The host
+//! runtime takes the records out of the var after every call, checks them
+//! against the chain it has seen and passes them to the plugin's audit sink
+//! (`PluginBuilder::with_audit_sink`). `verify` checks a stored chain again.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "audit")]
+use super::Host;
+
+/// Var the records of the current call are appended to, one JSON record
+/// per line
+pub const RECORDS_VAR: &str = "audit:records";
+
+/// Var holding the sequence number and hash of the last record, kept across
+/// calls
+#[cfg(feature = "audit")]
+const HEAD_VAR: &str = "audit:head";
+
+/// The `prev` hash of the first record of a chain
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// One audited action
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the chain, from 0
+    pub seq: u64,
+    /// What happened, such as `"token.issued"`
+    pub event: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    /// When the record was made, in milliseconds since the Unix epoch, if
+    /// the plugin reads the host's clock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub at_ms: Option<u64>,
+    /// Hash of the previous record, or `GENESIS`
+    pub prev: String,
+    /// Hash of this record, over all of its other fields
+    pub hash: String,
+}
+
+/// The fields a record's hash covers, in the order they are hashed
+#[derive(Serialize)]
+struct Hashed<'a> {
+    seq: u64,
+    event: &'a str,
+    fields: &'a BTreeMap<String, String>,
+    at_ms: Option<u64>,
+    prev: &'a str,
+}
+
+impl AuditRecord {
+    /// The hash the record should have, from its other fields
+    pub fn compute_hash(&self) -> String {
+        let hashed = Hashed {
+            seq: self.seq,
+            event: &self.event,
+            fields: &self.fields,
+            at_ms: self.at_ms,
+            prev: &self.prev,
+        };
+        let json = serde_json::to_vec(&hashed).expect("audit records serialize");
+        Sha256::digest(json)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// A record does not continue the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditError {
+    /// The record's hash does not match its contents
+    BadHash { seq: u64 },
+    /// The record does not follow the previous record
+    BrokenChain { seq: u64, expected_seq: u64 },
+    /// A record line is not a valid record
+    Malformed(String),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::BadHash { seq } => {
+                write!(f, "Audit record {} does not match its hash", seq)
+            }
+            AuditError::BrokenChain { seq, expected_seq } => write!(
+                f,
+                "Audit record {} does not follow the chain, expected record {}",
+                seq, expected_seq
+            ),
+            AuditError::Malformed(e) => write!(f, "Malformed audit record: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+/// Checks records one at a time against the chain they continue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditChain {
+    next_seq: u64,
+    prev: String,
+}
+
+impl Default for AuditChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuditChain {
+    /// A chain expecting its first record
+    pub fn new() -> Self {
+        Self {
+            next_seq: 0,
+            prev: GENESIS.to_string(),
+        }
+    }
+
+    /// Check that `record` is intact and follows the records checked
+    /// before it
+    ///
+    /// A record failing the check is not added to the chain.
+    pub fn push(&mut self, record: &AuditRecord) -> Result<(), AuditError> {
+        if record.hash != record.compute_hash() {
+            return Err(AuditError::BadHash { seq: record.seq });
+        }
+        if record.seq != self.next_seq || record.prev != self.prev {
+            return Err(AuditError::BrokenChain {
+                seq: record.seq,
+                expected_seq: self.next_seq,
+            });
+        }
+        self.next_seq += 1;
+        self.prev = record.hash.clone();
+        Ok(())
+    }
+}
+
+/// Check a whole chain, from its first record
+pub fn verify(records: &[AuditRecord]) -> Result<(), AuditError> {
+    let mut chain = AuditChain::new();
+    records.iter().try_for_each(|record| chain.push(record))
+}
+
+/// Parse the records of an `audit:records` var
+pub fn parse_records(data: &[u8]) -> Result<Vec<AuditRecord>, AuditError> {
+    data.split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).map_err(|e| AuditError::Malformed(e.to_string())))
+        .collect()
+}
+
+/// The sequence number and hash of the last record a plugin made
+#[cfg(feature = "audit")]
+#[derive(Serialize, Deserialize)]
+struct Head {
+    seq: u64,
+    hash: String,
+}
+
+/// Record `event` with its `fields` in the audit log
+///
+/// Records are written outside any `guard::Budget`, and are scrubbed by
+/// the installed `redact::Redactor` like log messages.
+#[cfg(feature = "audit")]
+pub fn log(event: &str, fields: &[(&str, &str)]) {
+    let head = Host::var_get(HEAD_VAR).and_then(|value| {
+        let head = serde_json::from_slice::<Head>(&value).ok();
+        super::pool::recycle(value);
+        head
+    });
+    let (seq, prev) = match head {
+        Some(head) => (head.seq + 1, head.hash),
+        None => (0, GENESIS.to_string()),
+    };
+    let mut record = AuditRecord {
+        seq,
+        event: event.to_string(),
+        fields: fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), super::redact::redact(value).into_owned()))
+            .collect(),
+        at_ms: now_ms(),
+        prev,
+        hash: String::new(),
+    };
+    record.hash = record.compute_hash();
+
+    let mut records = Host::var_get(RECORDS_VAR).unwrap_or_default();
+    records.extend(serde_json::to_vec(&record).expect("audit records serialize"));
+    records.push(b'\n');
+    Host::write_var(RECORDS_VAR, &records);
+    super::pool::recycle(records);
+    let head = Head {
+        seq,
+        hash: record.hash,
+    };
+    Host::write_var(
+        HEAD_VAR,
+        &serde_json::to_vec(&head).expect("audit heads serialize"),
+    );
+}
+
+#[cfg(all(feature = "audit", feature = "time"))]
+fn now_ms() -> Option<u64> {
+    Some(Host::now_ms())
+}
+
+#[cfg(all(feature = "audit", not(feature = "time")))]
+fn now_ms() -> Option<u64> {
+    None
+}
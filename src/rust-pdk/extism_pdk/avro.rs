@@ -0,0 +1,260 @@
+//! Avro encoding and Confluent schema registry lookups
+//!
+//! Enabled with the `avro` feature. `encode` and `decode` convert serde
+//! types to and from Avro datums under a schema:
+//!
+//! ```ignore
+//! let schema = Schema::parse_str(ORDER_SCHEMA)?;
+//! let order: Order = avro::decode(&Host::input(), &schema)?;
+//! ```
+//!
+//! Kafka messages written by Confluent serializers carry the id of their
+//! writer schema in front of the datum. A `SchemaRegistry` fetches that
+//! schema with `Host::http_request`, so the registry host must be in the
+//! manifest's `allowed_hosts`:
+//!
+//! ```ignore
+//! let registry = SchemaRegistry::new(&Host::config("registry_url").unwrap_or_default())
+//!     .with_basic_auth(&key, &secret);
+//! let order: Order = registry.decode(&Host::input())?;
+//! Host::output(&registry.encode(&enrich(order), ENRICHED_SCHEMA_ID)?);
+//! ```
+//!
+//! Schemas are immutable once registered, so fetched schemas are kept in
+//! the plugin var `avro:schema:<id>` and fetched once per plugin instance.
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{Error, Host, HttpMethod, HttpRequest};
+
+pub use apache_avro::types::Value;
+pub use apache_avro::Schema;
+
+/// The byte Confluent's wire format starts with
+pub const MAGIC_BYTE: u8 = 0;
+
+/// Prefix of the vars fetched schemas are kept in
+const VAR_PREFIX: &str = "avro:schema:";
+
+/// Content type of schema registry responses
+const REGISTRY_MEDIA_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+/// Why a datum could not be encoded or decoded
+#[derive(Debug)]
+pub enum AvroError {
+    /// The value does not match the schema, or the datum does not decode
+    /// under it
+    Avro(apache_avro::Error),
+    /// The message does not start with the magic byte and a schema id
+    NotWireFormat,
+    /// The registry answered a lookup with an error
+    Registry { status: i32, message: String },
+    /// The registry's schema is not an Avro schema
+    UnsupportedSchemaType(String),
+    /// The registry could not be reached
+    Http(Error),
+}
+
+impl fmt::Display for AvroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AvroError::Avro(e) => write!(f, "Avro error: {}", e),
+            AvroError::NotWireFormat => {
+                write!(f, "Message is not in the schema registry wire format")
+            }
+            AvroError::Registry { status, message } => {
+                write!(f, "Schema registry returned status {}: {}", status, message)
+            }
+            AvroError::UnsupportedSchemaType(kind) => {
+                write!(f, "Unsupported schema type: {}", kind)
+            }
+            AvroError::Http(e) => write!(f, "Schema registry request failed: {}", e.message),
+        }
+    }
+}
+
+impl std::error::Error for AvroError {}
+
+impl From<apache_avro::Error> for AvroError {
+    fn from(e: apache_avro::Error) -> Self {
+        AvroError::Avro(e)
+    }
+}
+
+impl From<AvroError> for Error {
+    fn from(e: AvroError) -> Self {
+        match e {
+            AvroError::Http(e) => e,
+            AvroError::Registry { status, .. } if status >= 500 || status == 429 => {
+                Error::retryable(e.to_string())
+            }
+            AvroError::UnsupportedSchemaType(_) => Error::fatal(e.to_string()),
+            _ => Error::invalid(e.to_string()),
+        }
+    }
+}
+
+/// Encode `value` as an Avro datum of `schema`
+pub fn encode<T: Serialize>(value: &T, schema: &Schema) -> Result<Vec<u8>, AvroError> {
+    let value = apache_avro::to_value(value)?.resolve(schema)?;
+    Ok(apache_avro::to_avro_datum(schema, value)?)
+}
+
+/// Decode an Avro datum written with `schema`
+pub fn decode<T: DeserializeOwned>(data: &[u8], schema: &Schema) -> Result<T, AvroError> {
+    decode_with(data, schema, None)
+}
+
+/// Decode an Avro datum written with `writer`, resolving it to `reader`
+/// when given, as schema evolution requires
+pub fn decode_with<T: DeserializeOwned>(
+    mut data: &[u8],
+    writer: &Schema,
+    reader: Option<&Schema>,
+) -> Result<T, AvroError> {
+    let value = apache_avro::from_avro_datum(writer, &mut data, reader)?;
+    Ok(apache_avro::from_value(&value)?)
+}
+
+/// Prefix a datum with the magic byte and the id of its schema
+pub fn to_wire_format(schema_id: u32, datum: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(5 + datum.len());
+    message.push(MAGIC_BYTE);
+    message.extend_from_slice(&schema_id.to_be_bytes());
+    message.extend_from_slice(datum);
+    message
+}
+
+/// Split a wire format message into its schema id and datum
+pub fn from_wire_format(message: &[u8]) -> Result<(u32, &[u8]), AvroError> {
+    match message {
+        [MAGIC_BYTE, a, b, c, d, datum @ ..] => Ok((u32::from_be_bytes([*a, *b, *c, *d]), datum)),
+        _ => Err(AvroError::NotWireFormat),
+    }
+}
+
+/// A schema as the registry returns it
+#[derive(Debug, Deserialize)]
+struct Registered {
+    #[serde(default)]
+    id: Option<u32>,
+    schema: String,
+    #[serde(default, rename = "schemaType")]
+    schema_type: Option<String>,
+}
+
+/// An error as the registry returns it
+#[derive(Debug, Deserialize)]
+struct RegistryError {
+    message: String,
+}
+
+/// A Confluent-compatible schema registry
+///
+/// Schemas with references to other subjects are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaRegistry {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl SchemaRegistry {
+    /// A registry at `url`, such as `https://psrc-123.us-east-2.aws.confluent.cloud`
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            headers: vec![("Accept".to_string(), REGISTRY_MEDIA_TYPE.to_string())],
+        }
+    }
+
+    /// Authenticate with an API key and secret
+    pub fn with_basic_auth(self, username: &str, password: &str) -> Self {
+        let credentials = super::binary::to_base64(format!("{}:{}", username, password).as_bytes());
+        self.with_header("Authorization", &format!("Basic {}", credentials))
+    }
+
+    /// Send a header with every lookup, such as a bearer token
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The schema registered under `id`
+    pub fn schema(&self, id: u32) -> Result<Schema, AvroError> {
+        let var = format!("{}{}", VAR_PREFIX, id);
+        let cached = Host::var_get(&var).and_then(|schema| String::from_utf8(schema).ok());
+        let schema = match cached {
+            Some(schema) => schema,
+            None => {
+                let schema = self.get(&format!("/schemas/ids/{}", id))?.schema;
+                Host::write_var(&var, schema.as_bytes());
+                schema
+            }
+        };
+        Ok(Schema::parse_str(&schema)?)
+    }
+
+    /// The id and schema of the latest version of `subject`
+    pub fn latest(&self, subject: &str) -> Result<(u32, Schema), AvroError> {
+        let registered = self.get(&format!("/subjects/{}/versions/latest", subject))?;
+        let id = registered.id.ok_or_else(|| AvroError::Registry {
+            status: 200,
+            message: "the response has no schema id".to_string(),
+        })?;
+        Ok((id, Schema::parse_str(&registered.schema)?))
+    }
+
+    /// Encode `value` in the wire format, under the schema registered as
+    /// `schema_id`
+    pub fn encode<T: Serialize>(&self, value: &T, schema_id: u32) -> Result<Vec<u8>, AvroError> {
+        let datum = encode(value, &self.schema(schema_id)?)?;
+        Ok(to_wire_format(schema_id, &datum))
+    }
+
+    /// Decode a wire format message under the schema it was written with
+    pub fn decode<T: DeserializeOwned>(&self, message: &[u8]) -> Result<T, AvroError> {
+        let (id, datum) = from_wire_format(message)?;
+        decode(datum, &self.schema(id)?)
+    }
+
+    /// Decode a wire format message, resolving it to `reader`
+    pub fn decode_as<T: DeserializeOwned>(
+        &self,
+        message: &[u8],
+        reader: &Schema,
+    ) -> Result<T, AvroError> {
+        let (id, datum) = from_wire_format(message)?;
+        decode_with(datum, &self.schema(id)?, Some(reader))
+    }
+
+    fn get(&self, path: &str) -> Result<Registered, AvroError> {
+        let response = Host::http_request(&HttpRequest {
+            method: HttpMethod::Get,
+            url: format!("{}{}", self.url, path),
+            headers: self.headers.clone(),
+            body: None,
+            ..Default::default()
+        })
+        .map_err(AvroError::Http)?;
+        let status = response.status();
+        let body = response.body();
+        if !(200..300).contains(&status) {
+            let message = serde_json::from_slice::<RegistryError>(&body)
+                .map(|e| e.message)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+            return Err(AvroError::Registry { status, message });
+        }
+        let registered: Registered =
+            serde_json::from_slice(&body).map_err(|e| AvroError::Registry {
+                status,
+                message: format!("invalid response: {}", e),
+            })?;
+        match registered.schema_type.as_deref() {
+            None | Some("AVRO") => Ok(registered),
+            Some(kind) => Err(AvroError::UnsupportedSchemaType(kind.to_string())),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! Benchmarking plugin functions
+//!
+//! A `Bench` calls a function repeatedly and reports the latency
+//! percentiles of the calls, with the fuel and host allocations they used:
+//!
+//! ```ignore
+//! let bench = Bench::new("resize", input).with_iterations(10_000);
+//! let wasm = bench.run(&mut plugin)?;
+//! println!("{}", wasm);
+//! ```
+//!
+//! With the `testing` feature the same function can be run natively under
+//! the mock host, to measure the overhead of running it as wasm:
+//!
+//! ```ignore
+//! let native = bench.run_mock(&MockHost::new(), resize)?;
+//! println!("{}\nwasm overhead: {:.1}x", native, wasm.overhead(&native));
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use super::host::{CallError, CallOptions, Plugin};
+#[cfg(feature = "testing")]
+use super::testing::MockHost;
+#[cfg(feature = "testing")]
+use super::Error as PluginError;
+
+/// Where a benchmarked function ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchTarget {
+    /// As wasm, in the host runtime
+    Wasm,
+    /// As native code, under the mock host
+    Native,
+}
+
+impl fmt::Display for BenchTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BenchTarget::Wasm => "wasm",
+            BenchTarget::Native => "native",
+        })
+    }
+}
+
+/// Latencies and resources of the calls of a benchmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// The function that was called
+    pub function: String,
+    /// Where it ran
+    pub target: BenchTarget,
+    /// Number of calls measured, not counting warm-up calls
+    pub iterations: usize,
+    pub p50: Duration,
+    pub p99: Duration,
+    pub mean: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Host memory blocks allocated per call
+    pub allocations_per_call: f64,
+    /// Fuel consumed per call, for wasm
+    pub fuel_per_call: Option<u64>,
+    /// Most memory the plugin held during any call, for wasm
+    pub peak_memory_bytes: Option<usize>,
+}
+
+impl BenchReport {
+    /// How many times slower the median call is than in `baseline`,
+    /// usually a native run of the same function
+    pub fn overhead(&self, baseline: &BenchReport) -> f64 {
+        self.p50.as_secs_f64() / baseline.p50.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}): {} calls, p50 {:.1?}, p99 {:.1?}, mean {:.1?}, min {:.1?}, max {:.1?}, \
+             {:.1} allocations/call",
+            self.function,
+            self.target,
+            self.iterations,
+            self.p50,
+            self.p99,
+            self.mean,
+            self.min,
+            self.max,
+            self.allocations_per_call
+        )?;
+        if let Some(fuel) = self.fuel_per_call {
+            write!(f, ", {} fuel/call", fuel)?;
+        }
+        if let Some(memory) = self.peak_memory_bytes {
+            write!(f, ", {} KiB memory", memory / 1024)?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls a function repeatedly with the same input, measuring each call
+#[derive(Debug, Clone)]
+pub struct Bench {
+    function: String,
+    input: Vec<u8>,
+    iterations: usize,
+    warmup: usize,
+    options: CallOptions,
+}
+
+impl Bench {
+    /// Benchmark `function` with `input`, over 1000 calls after 100 warm-up
+    /// calls
+    pub fn new(function: impl Into<String>, input: impl Into<Vec<u8>>) -> Self {
+        Self {
+            function: function.into(),
+            input: input.into(),
+            iterations: 1000,
+            warmup: 100,
+            options: CallOptions::default(),
+        }
+    }
+
+    /// Measure `iterations` calls
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Make `warmup` calls before measuring, which are not reported
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    /// Call the plugin with `options`, such as config overrides
+    pub fn with_options(mut self, options: CallOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Run the benchmark on a plugin in the host runtime
+    ///
+    /// Fails with the first call that fails.
+    pub fn run(&self, plugin: &mut Plugin) -> Result<BenchReport, CallError> {
+        for _ in 0..self.warmup {
+            plugin.call_with_options(&self.function, &self.input, &self.options)?;
+        }
+        let mut times = Vec::with_capacity(self.iterations);
+        let (mut fuel, mut allocations, mut memory) = (0, 0, 0);
+        for _ in 0..self.iterations {
+            let usage = plugin
+                .call_with_options(&self.function, &self.input, &self.options)?
+                .usage;
+            times.push(usage.wall_time);
+            fuel += usage.fuel_used;
+            allocations += usage.allocations;
+            memory = memory.max(usage.peak_memory_bytes);
+        }
+        Ok(BenchReport {
+            fuel_per_call: Some(fuel / self.iterations as u64),
+            peak_memory_bytes: Some(memory),
+            ..self.report(BenchTarget::Wasm, times, allocations)
+        })
+    }
+
+    /// Run the benchmark natively, calling `export` under the mock host
+    #[cfg(feature = "testing")]
+    pub fn run_mock(
+        &self,
+        host: &MockHost,
+        export: extern "C" fn() -> i32,
+    ) -> Result<BenchReport, PluginError> {
+        for _ in 0..self.warmup {
+            host.call(export, &self.input)?;
+        }
+        let mut times = Vec::with_capacity(self.iterations);
+        let allocations = host.allocations();
+        for _ in 0..self.iterations {
+            let start = std::time::Instant::now();
+            host.call(export, &self.input)?;
+            times.push(start.elapsed());
+        }
+        Ok(self.report(BenchTarget::Native, times, host.allocations() - allocations))
+    }
+
+    fn report(
+        &self,
+        target: BenchTarget,
+        mut times: Vec<Duration>,
+        allocations: u64,
+    ) -> BenchReport {
+        times.sort();
+        let total: Duration = times.iter().sum();
+        BenchReport {
+            function: self.function.clone(),
+            target,
+            iterations: times.len(),
+            p50: percentile(&times, 50),
+            p99: percentile(&times, 99),
+            mean: total / times.len() as u32,
+            min: times[0],
+            max: times[times.len() - 1],
+            allocations_per_call: allocations as f64 / times.len() as f64,
+            fuel_per_call: None,
+            peak_memory_bytes: None,
+        }
+    }
+}
+
+/// The nearest-rank percentile of sorted durations
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let rank = (sorted.len() * percent).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
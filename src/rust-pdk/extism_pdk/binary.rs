@@ -0,0 +1,208 @@
+//! Binary fields in JSON inputs and outputs
+//!
+//! serde writes a `Vec<u8>` as a JSON array of numbers, which other
+//! languages do not read back as bytes. `Base64Bytes` and `HexBytes` hold
+//! bytes that serialize as base64 and hex strings instead:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize, JsonSchema)]
+//! struct Thumbnail {
+//!     name: String,
+//!     image: Base64Bytes,
+//!     digest: HexBytes,
+//! }
+//! ```
+//!
+//! Fields kept as `Vec<u8>` use the same encodings through
+//! `#[serde(with = "binary::base64")]` and `#[serde(with = "binary::hex")]`.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Deref;
+
+use ::base64::alphabet;
+use ::base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use ::base64::engine::DecodePaddingMode;
+use ::base64::Engine;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Standard base64, decoding with or without padding
+const BASE64_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Encode bytes as padded standard base64
+pub fn to_base64(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+/// Decode standard base64, padded or not
+pub fn from_base64(encoded: &str) -> Result<Vec<u8>, DecodeError> {
+    BASE64_LENIENT
+        .decode(encoded)
+        .map_err(|e| DecodeError(e.to_string()))
+}
+
+/// Encode bytes as lowercase hex
+pub fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode hex, in either case
+pub fn from_hex(encoded: &str) -> Result<Vec<u8>, DecodeError> {
+    if !encoded.len().is_multiple_of(2) {
+        return Err(DecodeError("odd number of hex digits".to_string()));
+    }
+    encoded
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| {
+                    DecodeError(format!(
+                        "invalid hex digits {:?}",
+                        String::from_utf8_lossy(pair)
+                    ))
+                })
+        })
+        .collect()
+}
+
+/// A string is not valid base64 or hex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError(String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid binary field: {}", self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// `#[serde(with = "binary::base64")]` for `Vec<u8>` fields
+pub mod base64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_base64(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        super::from_base64(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// `#[serde(with = "binary::hex")]` for `Vec<u8>` fields
+pub mod hex {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::to_hex(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        super::from_hex(&encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Bytes serialized as a standard base64 string
+///
+/// Padded on output; accepted with or without padding on input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Base64Bytes(pub Vec<u8>);
+
+/// Bytes serialized as a lowercase hex string
+///
+/// Accepted in either case on input.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct HexBytes(pub Vec<u8>);
+
+macro_rules! bytes_wrapper {
+    ($name:ident, $codec:ident, $schema:expr) => {
+        impl $name {
+            /// The bytes, without the wrapper
+            pub fn into_inner(self) -> Vec<u8> {
+                self.0
+            }
+        }
+
+        impl From<Vec<u8>> for $name {
+            fn from(data: Vec<u8>) -> Self {
+                Self(data)
+            }
+        }
+
+        impl From<&[u8]> for $name {
+            fn from(data: &[u8]) -> Self {
+                Self(data.to_vec())
+            }
+        }
+
+        impl From<$name> for Vec<u8> {
+            fn from(data: $name) -> Self {
+                data.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = [u8];
+
+            fn deref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $codec::serialize(&self.0, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $codec::deserialize(deserializer).map(Self)
+            }
+        }
+
+        impl JsonSchema for $name {
+            fn schema_name() -> Cow<'static, str> {
+                stringify!($name).into()
+            }
+
+            fn json_schema(_: &mut SchemaGenerator) -> Schema {
+                $schema
+            }
+        }
+    };
+}
+
+bytes_wrapper!(
+    Base64Bytes,
+    base64,
+    json_schema!({
+        "type": "string",
+        "contentEncoding": "base64",
+    })
+);
+
+bytes_wrapper!(
+    HexBytes,
+    hex,
+    json_schema!({
+        "type": "string",
+        "pattern": "^([0-9a-fA-F]{2})*$",
+    })
+);
@@ -0,0 +1,70 @@
+//! BSON input, output and vars
+//!
+//! With the `bson` feature, plugins for MongoDB-centric hosts exchange
+//! documents in BSON instead of converting them to JSON, which would turn
+//! `ObjectId`s and `DateTime`s into `{"$oid": ...}` and `{"$date": ...}`
+//! wrappers:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Order {
+//!     #[serde(rename = "_id")]
+//!     id: ObjectId,
+//!     placed_at: DateTime,
+//!     total: f64,
+//! }
+//!
+//! let mut order: Order = Host::input_bson()?;
+//! order.total = reprice(&order)?;
+//! Host::output_bson(&order)?;
+//! ```
+//!
+//! A BSON payload is a single document, so the values written are structs
+//! or maps. `now` takes the time from the host, which plugins without WASI
+//! have no other clock for.
+//!
+//! The feature builds for native targets and `wasm32-wasip1`, where
+//! `ObjectId::new` draws its random bytes from WASI. On
+//! `wasm32-unknown-unknown` the bson crate imports JavaScript functions,
+//! so the feature does not build for it.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(any(feature = "vars", feature = "time"))]
+use super::Host;
+
+pub use ::bson::error::Error as BsonError;
+pub use ::bson::oid::ObjectId;
+pub use ::bson::{doc, Bson, DateTime, Document};
+
+/// Serialize a value as a BSON document
+pub fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, BsonError> {
+    ::bson::serialize_to_vec(value)
+}
+
+/// Deserialize a value from a BSON document
+pub fn from_slice<T: DeserializeOwned>(data: &[u8]) -> Result<T, BsonError> {
+    ::bson::deserialize_from_slice(data)
+}
+
+/// Read the var `name` as a BSON document, or `None` if it is not set
+#[cfg(feature = "vars")]
+pub fn get_var<T: DeserializeOwned>(name: &str) -> Result<Option<T>, BsonError> {
+    Host::var_get(name)
+        .map(|value| from_slice(&value))
+        .transpose()
+}
+
+/// Set the var `name` to a value as a BSON document
+#[cfg(feature = "vars")]
+pub fn set_var<T: Serialize>(name: &str, value: &T) -> Result<(), BsonError> {
+    Host::var_set(name, &to_vec(value)?);
+    Ok(())
+}
+
+/// The host's clock as a BSON `DateTime`
+#[cfg(feature = "time")]
+pub fn now() -> DateTime {
+    DateTime::from_millis(Host::now_ms() as i64)
+}
@@ -0,0 +1,454 @@
+//! Building plugin crates into modules ready to publish
+//!
+//! A `Builder` compiles a crate with `cargo build --release` for a plugin
+//! target, shrinks the module with `wasm-opt` when it is installed, strips
+//! custom sections such as debug info and producers, checks the module as
+//! the registry would on publish, and writes it next to its metadata:
+//!
+//! ```text
+//! target/extismx/hello.wasm
+//! target/extismx/hello.json
+//! ```
+//!
+//! Metadata comes from the crate's `[package]` table, with the plugin name,
+//! exports, dependencies, capabilities and config keys read from
+//! `[package.metadata.extismx]`:
+//!
+//! ```toml
+//! [package.metadata.extismx]
+//! name = "@acme/hello"
+//! exports = ["greet"]
+//! capabilities = ["http"]
+//! dependencies = { "@acme/log" = "^1" }
+//! config = { greeting = "Word to greet with, such as Hello" }
+//! ```
+//!
+//! Before compiling, the crate's sources are checked for misuses of the
+//! PDK with `lint::lint_crate`; errors fail the build and warnings are
+//! returned with the output.
+//!
+//! When no exports are declared, every function the module exports is
+//! listed. The schemas a module declares with `plugin_metadata!` are read by
+//! calling its `describe` export and published with the metadata.
+//!
+//! Debug builds, made with `with_debug`, keep the module's DWARF debug info
+//! so that the host reports traps at lines of the plugin's source, such as
+//! `src/lib.rs:42:5`, rather than at offsets in the module.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::capability::Capability;
+use super::host::{sha256_hex, Manifest, Plugin, Wasm};
+use super::lint::{self, Lint, Severity};
+use super::registry::{self, PluginMetadata, Target, VersionReq};
+use super::schema::{FunctionSchema, DESCRIBE_EXPORT};
+
+/// A plugin could not be built
+#[derive(Debug)]
+pub enum BuildError {
+    /// A file could not be read or written, or a tool could not be run
+    Io(std::io::Error),
+    /// `cargo` failed or the crate cannot be built as a plugin
+    Cargo(String),
+    /// `wasm-opt` failed
+    WasmOpt(String),
+    /// The built module or its metadata would be refused by the registry
+    Invalid(registry::Error),
+    /// The crate's sources misuse the PDK, as found by `lint::lint_crate`
+    Lint(Vec<Lint>),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Io(e) => write!(f, "Build I/O error: {}", e),
+            BuildError::Cargo(e) => write!(f, "Cargo error: {}", e),
+            BuildError::WasmOpt(e) => write!(f, "wasm-opt error: {}", e),
+            BuildError::Invalid(e) => write!(f, "Invalid plugin: {}", e),
+            BuildError::Lint(lints) => {
+                let errors = lints
+                    .iter()
+                    .filter(|lint| lint.severity == Severity::Error)
+                    .count();
+                write!(f, "{} lint error(s) in the plugin's sources", errors)?;
+                for lint in lints {
+                    write!(f, "\n\n{}", lint)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<std::io::Error> for BuildError {
+    fn from(e: std::io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+/// Settings read from `[package.metadata.extismx]`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct PackageSettings {
+    name: Option<String>,
+    exports: Vec<String>,
+    dependencies: BTreeMap<String, VersionReq>,
+    host: BTreeMap<String, VersionReq>,
+    capabilities: Vec<Capability>,
+    config: BTreeMap<String, String>,
+    target: Option<Target>,
+}
+
+/// Builds a plugin crate into a module and its metadata
+#[derive(Debug, Clone)]
+pub struct Builder {
+    manifest_path: PathBuf,
+    target: Option<Target>,
+    out_dir: Option<PathBuf>,
+    exports: Vec<String>,
+    wasm_opt: bool,
+    strip: bool,
+    debug: bool,
+}
+
+/// A module written by `Builder::build()`
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    /// Path of the wasm module
+    pub wasm: PathBuf,
+    /// Path of the metadata JSON, as taken by `extismx publish --metadata`
+    pub metadata_path: PathBuf,
+    /// Metadata of the plugin
+    pub metadata: PluginMetadata,
+    /// SHA-256 of the module, as hex
+    pub sha256: String,
+    /// Size of the module in bytes
+    pub size: u64,
+    /// Whether the module was shrunk by `wasm-opt`
+    pub optimized: bool,
+    /// Warnings found in the crate's sources, which did not fail the build
+    pub lints: Vec<Lint>,
+}
+
+impl Builder {
+    /// Build the crate whose `Cargo.toml` is at `manifest_path`
+    pub fn new(manifest_path: impl Into<PathBuf>) -> Self {
+        Self {
+            manifest_path: manifest_path.into(),
+            target: None,
+            out_dir: None,
+            exports: Vec::new(),
+            wasm_opt: true,
+            strip: true,
+            debug: false,
+        }
+    }
+
+    /// Build for `target` rather than the crate's configured target, or
+    /// `wasm32-unknown-unknown`
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Write the module and metadata to `out_dir` rather than
+    /// `target/extismx`
+    pub fn with_out_dir(mut self, out_dir: impl Into<PathBuf>) -> Self {
+        self.out_dir = Some(out_dir.into());
+        self
+    }
+
+    /// Require the module to export a function, in addition to those
+    /// declared in `Cargo.toml`
+    pub fn with_export(mut self, export: impl Into<String>) -> Self {
+        self.exports.push(export.into());
+        self
+    }
+
+    /// Run `wasm-opt -Oz` on the module when it is installed, on by default
+    pub fn with_wasm_opt(mut self, wasm_opt: bool) -> Self {
+        self.wasm_opt = wasm_opt;
+        self
+    }
+
+    /// Strip custom sections from the module, on by default
+    pub fn with_strip(mut self, strip: bool) -> Self {
+        self.strip = strip;
+        self
+    }
+
+    /// Keep DWARF debug info in the module, off by default
+    ///
+    /// The crate is still built with optimizations, but neither `wasm-opt`
+    /// nor stripping is run, as both drop the debug info.
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Build the crate, returning where the module and metadata were written
+    pub fn build(&self) -> Result<BuildOutput, BuildError> {
+        let cargo_metadata = self.cargo_metadata()?;
+        let package = self.package(&cargo_metadata)?;
+        let settings: PackageSettings = match package["metadata"].get("extismx") {
+            Some(settings) => serde_json::from_value(settings.clone()).map_err(|e| {
+                BuildError::Cargo(format!("invalid [package.metadata.extismx]: {}", e))
+            })?,
+            None => PackageSettings::default(),
+        };
+        let target = self.target.or(settings.target).unwrap_or_default();
+        let lib = package["targets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|lib| {
+                lib["crate_types"]
+                    .as_array()
+                    .is_some_and(|types| types.iter().any(|kind| kind == "cdylib"))
+            })
+            .and_then(|lib| lib["name"].as_str())
+            .ok_or_else(|| {
+                BuildError::Cargo(
+                    "the crate has no cdylib library; add crate-type = [\"cdylib\"] to [lib]"
+                        .to_string(),
+                )
+            })?;
+        let target_dir = PathBuf::from(
+            cargo_metadata["target_directory"]
+                .as_str()
+                .ok_or_else(|| BuildError::Cargo("no target directory".to_string()))?,
+        );
+
+        let lints = lint::lint_crate(&self.manifest_path)?;
+        if lints.iter().any(|lint| lint.severity == Severity::Error) {
+            return Err(BuildError::Lint(lints));
+        }
+
+        let triple = rust_target(target);
+        let mut command = cargo();
+        command
+            .args(["build", "--release", "--lib", "--target", triple])
+            .arg("--manifest-path")
+            .arg(&self.manifest_path);
+        if self.debug {
+            command
+                .env("CARGO_PROFILE_RELEASE_DEBUG", "true")
+                .env("CARGO_PROFILE_RELEASE_STRIP", "none");
+        }
+        let status = command.status()?;
+        if !status.success() {
+            return Err(BuildError::Cargo(format!("cargo build failed: {}", status)));
+        }
+        let built = target_dir
+            .join(triple)
+            .join("release")
+            .join(format!("{}.wasm", lib.replace('-', "_")));
+        let mut wasm = fs::read(&built)
+            .map_err(|e| BuildError::Cargo(format!("{}: {}", built.display(), e)))?;
+
+        let out_dir = self
+            .out_dir
+            .clone()
+            .unwrap_or_else(|| target_dir.join("extismx"));
+        fs::create_dir_all(&out_dir)?;
+        let file_name = package["name"].as_str().unwrap_or(lib).to_string();
+        let wasm_path = out_dir.join(format!("{}.wasm", file_name));
+
+        // wasm-opt does not take components
+        let optimized = if self.wasm_opt && !self.debug && target != Target::Component {
+            fs::write(&wasm_path, &wasm)?;
+            match wasm_opt(&wasm_path) {
+                Ok(()) => {
+                    wasm = fs::read(&wasm_path)?;
+                    true
+                }
+                Err(BuildError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => false,
+                Err(e) => return Err(e),
+            }
+        } else {
+            false
+        };
+        if self.strip && !self.debug && target != Target::Component {
+            wasm = strip_custom_sections(&wasm)
+                .ok_or_else(|| BuildError::Cargo(format!("{} is truncated", built.display())))?;
+        }
+
+        let mut metadata = PluginMetadata {
+            name: settings
+                .name
+                .unwrap_or_else(|| package["name"].as_str().unwrap_or(lib).to_string()),
+            version: package["version"].as_str().unwrap_or_default().to_string(),
+            description: package["description"].as_str().map(str::to_string),
+            license: package["license"].as_str().map(str::to_string),
+            authors: package["authors"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|author| author.as_str().map(str::to_string))
+                .collect(),
+            exports: settings.exports,
+            dependencies: settings.dependencies,
+            host: settings.host,
+            capabilities: settings.capabilities,
+            config: settings.config,
+            target,
+            schemas: describe(&wasm, target)?,
+        };
+        for export in &self.exports {
+            if !metadata.exports.contains(export) {
+                metadata.exports.push(export.clone());
+            }
+        }
+        if metadata.exports.is_empty() && target != Target::Component {
+            metadata.exports = registry::exported_functions(&wasm)
+                .map_err(|e| BuildError::Invalid(registry::Error::InvalidArtifact(e)))?
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+        }
+        registry::check(&metadata, &wasm, &[]).map_err(BuildError::Invalid)?;
+
+        fs::write(&wasm_path, &wasm)?;
+        let metadata_path = out_dir.join(format!("{}.json", file_name));
+        let mut json = serde_json::to_vec_pretty(&metadata)
+            .map_err(|e| BuildError::Io(std::io::Error::other(e)))?;
+        json.push(b'\n');
+        fs::write(&metadata_path, json)?;
+        Ok(BuildOutput {
+            wasm: wasm_path,
+            metadata_path,
+            metadata,
+            sha256: sha256_hex(&wasm),
+            size: wasm.len() as u64,
+            optimized,
+            lints,
+        })
+    }
+
+    fn cargo_metadata(&self) -> Result<Value, BuildError> {
+        let output = cargo()
+            .args(["metadata", "--format-version", "1", "--no-deps"])
+            .arg("--manifest-path")
+            .arg(&self.manifest_path)
+            .output()?;
+        if !output.status.success() {
+            return Err(BuildError::Cargo(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| BuildError::Cargo(format!("invalid cargo metadata: {}", e)))
+    }
+
+    /// Find the package of the manifest among the workspace members
+    fn package<'a>(&self, cargo_metadata: &'a Value) -> Result<&'a Value, BuildError> {
+        let manifest = fs::canonicalize(&self.manifest_path)?;
+        cargo_metadata["packages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|package| {
+                package["manifest_path"]
+                    .as_str()
+                    .and_then(|path| fs::canonicalize(path).ok())
+                    .is_some_and(|path| path == manifest)
+            })
+            .ok_or_else(|| {
+                BuildError::Cargo(format!(
+                    "{} is not the manifest of a package",
+                    self.manifest_path.display()
+                ))
+            })
+    }
+}
+
+fn cargo() -> Command {
+    Command::new(std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo")))
+}
+
+/// The Rust target that compiles to `target`
+fn rust_target(target: Target) -> &'static str {
+    match target {
+        Target::Wasm32UnknownUnknown => "wasm32-unknown-unknown",
+        Target::Wasm32Wasip1 => "wasm32-wasip1",
+        Target::Component => "wasm32-wasip2",
+    }
+}
+
+/// Optimize a module for size in place, failing with a `NotFound` I/O error
+/// if `wasm-opt` is not installed
+fn wasm_opt(path: &Path) -> Result<(), BuildError> {
+    let output = Command::new("wasm-opt")
+        .args(["-Oz", "--all-features"])
+        .arg(path)
+        .arg("-o")
+        .arg(path)
+        .output()?;
+    if !output.status.success() {
+        return Err(BuildError::WasmOpt(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Read the schemas a core module declares with `plugin_metadata!`
+fn describe(wasm: &[u8], target: Target) -> Result<Vec<FunctionSchema>, BuildError> {
+    let described = target != Target::Component
+        && registry::exported_functions(wasm)
+            .is_ok_and(|exports| exports.contains(DESCRIBE_EXPORT));
+    if !described {
+        return Ok(Vec::new());
+    }
+    let invalid = |e: String| {
+        BuildError::Invalid(registry::Error::InvalidArtifact(format!(
+            "{} failed: {}",
+            DESCRIBE_EXPORT, e
+        )))
+    };
+    let manifest = Manifest::new([Wasm::data(wasm)]).with_wasi(target == Target::Wasm32Wasip1);
+    let mut plugin = Plugin::from_manifest(&manifest).map_err(|e| invalid(e.to_string()))?;
+    let schema = plugin
+        .describe_schema()
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(schema.map_or_else(Vec::new, |schema| schema.functions))
+}
+
+/// Remove the custom sections of a core module, such as `name`, `producers`
+/// and DWARF debug info, returning `None` if the module is truncated
+fn strip_custom_sections(wasm: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = wasm.get(..8)?.to_vec();
+    let mut rest = &wasm[8..];
+    while let Some(&id) = rest.first() {
+        let mut size = 0usize;
+        let mut len = 1;
+        loop {
+            let byte = *rest.get(len)?;
+            size |= usize::from(byte & 0x7f) << (7 * (len - 1));
+            len += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            if len > 5 {
+                return None;
+            }
+        }
+        let section = rest.get(..len + size)?;
+        if id != 0 {
+            stripped.extend_from_slice(section);
+        }
+        rest = &rest[len + size..];
+    }
+    Some(stripped)
+}
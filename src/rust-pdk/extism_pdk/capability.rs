@@ -0,0 +1,76 @@
+//! Capabilities a plugin needs from its host
+//!
+//! Plugins declare the capabilities they need in their registry metadata,
+//! and hosts attach them to the manifest's wasm sources. When loading, the
+//! host checks each declared capability against what the manifest grants,
+//! warning about or refusing plugins that would fail at runtime.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the names of capabilities to call a host function
+const FUNCTION_PREFIX: &str = "function:";
+
+/// Something a plugin needs from its host
+///
+/// Capabilities are written as `http`, `wasi`, `wasi-fs`, `kv` and
+/// `function:<name>`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Capability {
+    /// Send HTTP requests, granted by a manifest's `allowed_hosts`
+    Http,
+    /// Use WASI, granted by a manifest's `wasi`
+    Wasi,
+    /// Access files through WASI, granted by `wasi` with `allowed_paths`
+    WasiFs,
+    /// Keep state between calls in plugin vars, which every host provides
+    Kv,
+    /// Call a function the host provides through a linked module
+    Function(String),
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Http => write!(f, "http"),
+            Capability::Wasi => write!(f, "wasi"),
+            Capability::WasiFs => write!(f, "wasi-fs"),
+            Capability::Kv => write!(f, "kv"),
+            Capability::Function(name) => write!(f, "{}{}", FUNCTION_PREFIX, name),
+        }
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Capability::Http),
+            "wasi" => Ok(Capability::Wasi),
+            "wasi-fs" => Ok(Capability::WasiFs),
+            "kv" => Ok(Capability::Kv),
+            _ => match s.strip_prefix(FUNCTION_PREFIX) {
+                Some(name) if !name.is_empty() => Ok(Capability::Function(name.to_string())),
+                _ => Err(format!("unknown capability {:?}", s)),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Capability {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Capability> for String {
+    fn from(capability: Capability) -> Self {
+        capability.to_string()
+    }
+}
@@ -0,0 +1,952 @@
+//! TypeScript, Go and Rust types generated from plugin schemas
+//!
+//! A `Codegen` turns the JSON Schemas a plugin declares with
+//! `plugin_metadata!` into the types of each function's input and output,
+//! plus a wrapper calling the functions through the host SDK of the
+//! language: `@extism/extism` for TypeScript, `github.com/extism/go-sdk`
+//! for Go, and a client trait implemented for `host::Plugin` for Rust.
+//!
+//! ```ignore
+//! let code = Codegen::from_metadata(&metadata).generate(Language::TypeScript);
+//! ```
+//!
+//! The same types seed the plugin skeletons `Scaffold` writes for the Go,
+//! JavaScript and Python PDKs.
+//!
+//! Objects become interfaces or structs, string enums become union types,
+//! string constants or enums, and anything the generators cannot express, such
+//! as untyped values, becomes `unknown` or `any`.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use serde_json::{Map, Value};
+
+use super::registry::PluginMetadata;
+use super::schema::FunctionSchema;
+
+mod guest;
+
+/// A language types can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// TypeScript interfaces and a class wrapping an `@extism/extism` plugin
+    TypeScript,
+    /// Go structs and a type wrapping a `github.com/extism/go-sdk` plugin
+    Go,
+    /// Rust structs and a client trait implemented for `host::Plugin`
+    Rust,
+}
+
+impl Language {
+    /// Extension of files in the language
+    pub fn extension(self) -> &'static str {
+        match self {
+            Language::TypeScript => "ts",
+            Language::Go => "go",
+            Language::Rust => "rs",
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Language::TypeScript => "typescript",
+            Language::Go => "go",
+            Language::Rust => "rust",
+        })
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "typescript" | "ts" => Ok(Language::TypeScript),
+            "go" => Ok(Language::Go),
+            "rust" | "rs" => Ok(Language::Rust),
+            _ => Err(format!("unknown language {:?}", s)),
+        }
+    }
+}
+
+/// Generates the types and call wrappers of a plugin's functions
+#[derive(Debug, Clone)]
+pub struct Codegen {
+    name: String,
+    functions: Vec<FunctionSchema>,
+    package: Option<String>,
+}
+
+impl Codegen {
+    /// Generate code for the functions of plugin `name`
+    pub fn new(
+        name: impl Into<String>,
+        functions: impl IntoIterator<Item = FunctionSchema>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            functions: functions.into_iter().collect(),
+            package: None,
+        }
+    }
+
+    /// Generate code for the functions described in a plugin's metadata
+    pub fn from_metadata(metadata: &PluginMetadata) -> Self {
+        Self::new(&metadata.name, metadata.schemas.iter().cloned())
+    }
+
+    /// Name the Go package, rather than after the plugin
+    pub fn with_package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Generate a source file in `language`
+    pub fn generate(&self, language: Language) -> String {
+        match language {
+            Language::TypeScript => self.typescript(),
+            Language::Go => self.go(),
+            Language::Rust => self.rust(),
+        }
+    }
+
+    /// Generate TypeScript interfaces and a class calling the functions
+    pub fn typescript(&self) -> String {
+        let (decls, functions) = self.collect();
+        let mut out = format!(
+            "// Generated by extismx codegen from {}; do not edit.\n",
+            self.name
+        );
+        if !functions.is_empty() {
+            out.push_str("\nimport type { Plugin } from \"@extism/extism\";\n");
+        }
+        for decl in &decls {
+            out.push('\n');
+            if let Some(description) = &decl.description {
+                ts_doc(&mut out, "", description);
+            }
+            match &decl.body {
+                Body::Object(fields) => {
+                    let _ = writeln!(out, "export interface {} {{", decl.name);
+                    for field in fields {
+                        if let Some(description) = &field.description {
+                            ts_doc(&mut out, "  ", description);
+                        }
+                        let key = if is_identifier(&field.name) {
+                            field.name.clone()
+                        } else {
+                            format!("{:?}", field.name)
+                        };
+                        let optional = if field.required { "" } else { "?" };
+                        let _ = writeln!(out, "  {}{}: {};", key, optional, ts_type(&field.ty));
+                    }
+                    out.push_str("}\n");
+                }
+                Body::Enum(values) => {
+                    let values: Vec<String> =
+                        values.iter().map(|value| format!("{:?}", value)).collect();
+                    let _ = writeln!(out, "export type {} = {};", decl.name, values.join(" | "));
+                }
+                Body::Alias(ty) => {
+                    let _ = writeln!(out, "export type {} = {};", decl.name, ts_type(ty));
+                }
+            }
+        }
+        if functions.is_empty() {
+            return out;
+        }
+
+        let class = format!("{}Plugin", pascal_case(self.crate_name(), false));
+        let _ = write!(
+            out,
+            "\n/** Calls the functions of {} */\nexport class {} {{\n  constructor(readonly plugin: Plugin) {{}}\n",
+            self.name, class
+        );
+        for function in &functions {
+            let method = camel_case(&function.name);
+            let output = ts_type(&function.output);
+            let (params, input) = match function.input {
+                Type::Null => (String::new(), "null".to_string()),
+                ref ty => (format!("input: {}", ts_type(ty)), "input".to_string()),
+            };
+            let _ = write!(
+                out,
+                "\n  /** Call `{name}` */\n  async {method}({params}): Promise<{output}> {{\n    const output = await this.plugin.call({name:?}, JSON.stringify({input}));\n    if (output === null) {{\n      throw new Error(\"{name} returned no output\");\n    }}\n    return output.json() as {output};\n  }}\n",
+                name = function.name,
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Generate Go structs and a type calling the functions
+    pub fn go(&self) -> String {
+        let (decls, functions) = self.collect();
+        let package = self.package.clone().unwrap_or_else(|| {
+            self.crate_name()
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_ascii_lowercase()
+        });
+        let mut out = format!(
+            "// Code generated by extismx codegen from {}. DO NOT EDIT.\n\npackage {}\n",
+            self.name, package
+        );
+        if !functions.is_empty() {
+            out.push_str(
+                "\nimport (\n\t\"encoding/json\"\n\n\textism \"github.com/extism/go-sdk\"\n)\n",
+            );
+        }
+        go_decls(&mut out, &decls);
+        if functions.is_empty() {
+            return out;
+        }
+
+        let wrapper = format!("{}Plugin", pascal_case(self.crate_name(), true));
+        let _ = write!(
+            out,
+            "\n// {wrapper} calls the functions of {name}.\ntype {wrapper} struct {{\n\tPlugin *extism.Plugin\n}}\n",
+            name = self.name,
+        );
+        for function in &functions {
+            let method = pascal_case(&function.name, true);
+            let output = go_type(&function.output);
+            let (params, input) = match function.input {
+                Type::Null => (String::new(), "nil".to_string()),
+                ref ty => (format!("input {}", go_type(ty)), "input".to_string()),
+            };
+            let _ = write!(
+                out,
+                "\n// {method} calls {name}.\nfunc (p *{wrapper}) {method}({params}) ({output}, error) {{\n\tvar output {output}\n\tdata, err := json.Marshal({input})\n\tif err != nil {{\n\t\treturn output, err\n\t}}\n\t_, result, err := p.Plugin.Call({name:?}, data)\n\tif err != nil {{\n\t\treturn output, err\n\t}}\n\terr = json.Unmarshal(result, &output)\n\treturn output, err\n}}\n",
+                name = function.name,
+            );
+        }
+        out
+    }
+
+    /// Generate Rust types and a client trait calling the functions,
+    /// implemented for `host::Plugin`
+    pub fn rust(&self) -> String {
+        let (decls, functions) = self.collect();
+        let mut body = String::new();
+        for decl in &decls {
+            body.push('\n');
+            if let Some(description) = &decl.description {
+                rust_doc(&mut body, "", description);
+            }
+            match &decl.body {
+                Body::Object(fields) => {
+                    body.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+                    let _ = writeln!(body, "pub struct {} {{", decl.name);
+                    for field in fields {
+                        if let Some(description) = &field.description {
+                            rust_doc(&mut body, "    ", description);
+                        }
+                        let ident = rust_ident(&field.name);
+                        let mut attrs = Vec::new();
+                        if ident.trim_start_matches("r#") != field.name {
+                            attrs.push(format!("rename = {:?}", field.name));
+                        }
+                        let ty = if field.required {
+                            rust_type(&field.ty)
+                        } else {
+                            attrs.push("default, skip_serializing_if = \"Option::is_none\"".into());
+                            rust_type(&optional(field.ty.clone()))
+                        };
+                        if !attrs.is_empty() {
+                            let _ = writeln!(body, "    #[serde({})]", attrs.join(", "));
+                        }
+                        let _ = writeln!(body, "    pub {}: {},", ident, ty);
+                    }
+                    body.push_str("}\n");
+                }
+                Body::Enum(values) => {
+                    body.push_str(
+                        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]\n",
+                    );
+                    let variants: Vec<String> = values
+                        .iter()
+                        .map(|value| pascal_case(value, false))
+                        .collect();
+                    // Rename the variants together when serde can
+                    let rename_all = [("kebab-case", '-'), ("snake_case", '_')]
+                        .into_iter()
+                        .find(|(_, separator)| {
+                            variants
+                                .iter()
+                                .zip(values)
+                                .all(|(variant, value)| &serde_case(variant, *separator) == value)
+                        })
+                        .map(|(rename_all, _)| rename_all)
+                        .filter(|_| variants.iter().zip(values).any(|(v, value)| v != value));
+                    if let Some(rename_all) = rename_all {
+                        let _ = writeln!(body, "#[serde(rename_all = {:?})]", rename_all);
+                    }
+                    let _ = writeln!(body, "pub enum {} {{", decl.name);
+                    for (variant, value) in variants.iter().zip(values) {
+                        if rename_all.is_none() && variant != value {
+                            let _ = writeln!(body, "    #[serde(rename = {:?})]", value);
+                        }
+                        let _ = writeln!(body, "    {},", variant);
+                    }
+                    body.push_str("}\n");
+                }
+                Body::Alias(Type::Union(types)) => {
+                    body.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+                    let _ = writeln!(body, "#[serde(untagged)]\npub enum {} {{", decl.name);
+                    let mut names = BTreeSet::new();
+                    for (i, ty) in types.iter().enumerate() {
+                        let mut variant = match ty {
+                            Type::Named(name) => match name.strip_prefix(decl.name.as_str()) {
+                                Some(rest) if !rest.is_empty() => rest.to_string(),
+                                _ => name.clone(),
+                            },
+                            Type::Any => "Value".to_string(),
+                            Type::Null => "Null".to_string(),
+                            Type::Bool => "Bool".to_string(),
+                            Type::Integer(_) => "Integer".to_string(),
+                            Type::Number(_) => "Number".to_string(),
+                            Type::String => "String".to_string(),
+                            Type::Array(_) => "Array".to_string(),
+                            Type::Map(_) => "Map".to_string(),
+                            Type::Optional(_) | Type::Union(_) => "Value".to_string(),
+                        };
+                        if !names.insert(variant.clone()) {
+                            variant = format!("{}{}", variant, i + 1);
+                        }
+                        match ty {
+                            Type::Null => {
+                                let _ = writeln!(body, "    {},", variant);
+                            }
+                            ty => {
+                                let _ = writeln!(body, "    {}({}),", variant, rust_type(ty));
+                            }
+                        }
+                    }
+                    body.push_str("}\n");
+                }
+                Body::Alias(ty) => {
+                    let _ = writeln!(body, "pub type {} = {};", decl.name, rust_type(ty));
+                }
+            }
+        }
+
+        let mut out = format!(
+            "// Generated by extismx codegen from {}; do not edit.\n",
+            self.name
+        );
+        let mut imports = Vec::new();
+        if body.contains("BTreeMap<") {
+            imports.push("use std::collections::BTreeMap;\n\n");
+        }
+        if !functions.is_empty() {
+            imports.push("use extism_hello_plugin::extism_pdk::host::{CallError, Plugin};\n");
+        }
+        if !decls.is_empty() {
+            imports.push("use serde::{Deserialize, Serialize};\n");
+        }
+        if !imports.is_empty() {
+            out.push('\n');
+            out.extend(imports);
+        }
+        out.push_str(&body);
+        if functions.is_empty() {
+            return out;
+        }
+
+        let client = format!("{}PluginClient", pascal_case(self.crate_name(), false));
+        let methods: Vec<(String, String)> = functions
+            .iter()
+            .map(|function| {
+                let params = match function.input {
+                    Type::Null => String::new(),
+                    ref ty => format!(", input: &{}", rust_type(ty)),
+                };
+                let signature = format!(
+                    "fn {}(&mut self{}) -> Result<{}, CallError>",
+                    rust_ident(&function.name),
+                    params,
+                    rust_type(&function.output)
+                );
+                let input = match function.input {
+                    Type::Null => "&()",
+                    _ => "input",
+                };
+                let call = format!("self.call_json({:?}, {})", function.name, input);
+                (signature, call)
+            })
+            .collect();
+        let _ = writeln!(
+            out,
+            "\n/// Calls the functions of {}\npub trait {} {{",
+            self.name, client
+        );
+        for (i, (function, (signature, _))) in functions.iter().zip(&methods).enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "    /// Call `{}`\n    {};", function.name, signature);
+        }
+        let _ = writeln!(out, "}}\n\nimpl {} for Plugin {{", client);
+        for (i, (signature, call)) in methods.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            let _ = writeln!(out, "    {} {{\n        {}\n    }}", signature, call);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn crate_name(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map_or(self.name.as_str(), |(_, name)| name)
+    }
+
+    /// Collect the named types and the input and output type of each function
+    fn collect(&self) -> (Vec<Decl>, Vec<Function>) {
+        let mut collector = Collector::default();
+        let functions = self
+            .functions
+            .iter()
+            .map(|function| {
+                let name = pascal_case(&function.name, false);
+                Function {
+                    name: function.name.clone(),
+                    input: collector.root(&function.input, &format!("{}Input", name)),
+                    output: collector.root(&function.output, &format!("{}Output", name)),
+                }
+            })
+            .collect();
+        (collector.decls, functions)
+    }
+}
+
+/// A type of a field, input or output
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Any,
+    Null,
+    Bool,
+    /// An integer, with its JSON Schema format, such as `uint16`
+    Integer(Option<String>),
+    /// A number, with its JSON Schema format, such as `float`
+    Number(Option<String>),
+    String,
+    Array(Box<Type>),
+    Map(Box<Type>),
+    Optional(Box<Type>),
+    Union(Vec<Type>),
+    Named(String),
+}
+
+/// A named type
+#[derive(Debug)]
+struct Decl {
+    name: String,
+    description: Option<String>,
+    body: Body,
+}
+
+#[derive(Debug)]
+enum Body {
+    Object(Vec<Field>),
+    Enum(Vec<String>),
+    Alias(Type),
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    description: Option<String>,
+    ty: Type,
+    required: bool,
+}
+
+struct Function {
+    name: String,
+    input: Type,
+    output: Type,
+}
+
+/// Walks schemas, declaring a named type for each object and enum
+#[derive(Default)]
+struct Collector {
+    decls: Vec<Decl>,
+    names: BTreeSet<String>,
+    /// `$defs` of the schema being walked
+    defs: Map<String, Value>,
+}
+
+impl Collector {
+    /// Resolve the root schema of an input or output, naming it after its
+    /// title or `hint`
+    fn root(&mut self, schema: &Value, hint: &str) -> Type {
+        self.defs = schema["$defs"].as_object().cloned().unwrap_or_default();
+        let name = schema["title"]
+            .as_str()
+            .filter(|title| {
+                title.starts_with(|c: char| c.is_ascii_uppercase())
+                    && title.chars().all(|c| c.is_ascii_alphanumeric())
+            })
+            .unwrap_or(hint)
+            .to_string();
+        self.resolve(schema, &name)
+    }
+
+    /// Resolve a schema, declaring objects and enums under `hint` unless
+    /// they are references to named definitions
+    fn resolve(&mut self, schema: &Value, hint: &str) -> Type {
+        let Some(object) = schema.as_object() else {
+            return Type::Any;
+        };
+        if let Some(name) = object
+            .get("$ref")
+            .and_then(Value::as_str)
+            .and_then(|reference| reference.strip_prefix("#/$defs/"))
+        {
+            let def = self.defs.get(name).cloned().unwrap_or(Value::Bool(true));
+            self.define(&def, name);
+            return Type::Named(name.to_string());
+        }
+        if let Some(values) = string_enum(schema) {
+            if values.len() == 1 && object.contains_key("const") {
+                return Type::String;
+            }
+            self.define(schema, hint);
+            return Type::Named(hint.to_string());
+        }
+        for key in ["anyOf", "oneOf"] {
+            if let Some(variants) = object.get(key).and_then(Value::as_array) {
+                let nullable = variants.iter().any(|variant| variant["type"] == "null");
+                let variants: Vec<&Value> = variants
+                    .iter()
+                    .filter(|variant| variant["type"] != "null")
+                    .collect();
+                let mut types: Vec<Type> = if let [variant] = variants.as_slice() {
+                    vec![self.resolve(variant, hint)]
+                } else {
+                    variants
+                        .iter()
+                        .enumerate()
+                        .map(|(i, variant)| self.resolve(variant, &variant_name(hint, variant, i)))
+                        .collect()
+                };
+                let ty = match types.len() {
+                    0 => Type::Null,
+                    1 => types.remove(0),
+                    _ => Type::Union(types),
+                };
+                return if nullable { optional(ty) } else { ty };
+            }
+        }
+        if let Some([only]) = object
+            .get("allOf")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+        {
+            return self.resolve(only, hint);
+        }
+        if let Some(types) = object.get("type").and_then(Value::as_array) {
+            let nullable = types.iter().any(|ty| ty == "null");
+            let mut types: Vec<Type> = types
+                .iter()
+                .filter(|ty| *ty != "null")
+                .map(|ty| {
+                    let mut single = object.clone();
+                    single.insert("type".to_string(), ty.clone());
+                    self.resolve(&Value::Object(single), hint)
+                })
+                .collect();
+            let ty = match types.len() {
+                0 => Type::Null,
+                1 => types.remove(0),
+                _ => Type::Union(types),
+            };
+            return if nullable { optional(ty) } else { ty };
+        }
+        let format = object
+            .get("format")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        match object.get("type").and_then(Value::as_str) {
+            Some("null") => Type::Null,
+            Some("boolean") => Type::Bool,
+            Some("integer") => Type::Integer(format),
+            Some("number") => Type::Number(format),
+            Some("string") => Type::String,
+            Some("array") => match object.get("items") {
+                Some(items) => Type::Array(Box::new(self.resolve(items, &format!("{}Item", hint)))),
+                None => Type::Array(Box::new(Type::Any)),
+            },
+            Some("object") if object.contains_key("properties") => {
+                self.define(schema, hint);
+                Type::Named(hint.to_string())
+            }
+            Some("object") => match object.get("additionalProperties") {
+                Some(values) if values.is_object() => {
+                    Type::Map(Box::new(self.resolve(values, &format!("{}Value", hint))))
+                }
+                _ => Type::Map(Box::new(Type::Any)),
+            },
+            _ => Type::Any,
+        }
+    }
+
+    /// Declare a type `name` unless one was declared already, ahead of the
+    /// types it refers to
+    fn define(&mut self, schema: &Value, name: &str) {
+        if !self.names.insert(name.to_string()) {
+            return;
+        }
+        let index = self.decls.len();
+        self.decls.push(Decl {
+            name: name.to_string(),
+            description: None,
+            body: Body::Alias(Type::Any),
+        });
+        self.decls[index] = self.declare(schema, name);
+    }
+
+    /// Describe a named definition
+    fn declare(&mut self, schema: &Value, name: &str) -> Decl {
+        let body = if let Some(values) = string_enum(schema) {
+            Body::Enum(values)
+        } else if let Some(properties) = schema["properties"].as_object() {
+            let required: BTreeSet<&str> = schema["required"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .collect();
+            let fields = properties
+                .iter()
+                .map(|(key, property)| Field {
+                    name: key.clone(),
+                    description: description(property),
+                    ty: self.resolve(property, &format!("{}{}", name, pascal_case(key, false))),
+                    required: required.contains(key.as_str()),
+                })
+                .collect();
+            Body::Object(fields)
+        } else {
+            match self.resolve(schema, name) {
+                // A definition only referring to itself
+                Type::Named(named) if named == name => Body::Alias(Type::Any),
+                ty => Body::Alias(ty),
+            }
+        };
+        Decl {
+            name: name.to_string(),
+            description: description(schema),
+            body,
+        }
+    }
+}
+
+/// Name a variant of a union after its title, or the tag of an externally
+/// tagged enum variant, falling back to its position
+fn variant_name(hint: &str, variant: &Value, index: usize) -> String {
+    let tag = variant["title"].as_str().or_else(|| {
+        match variant["properties"]
+            .as_object()
+            .map(|properties| properties.keys())
+        {
+            Some(mut keys) if keys.len() == 1 => keys.next().map(String::as_str),
+            _ => None,
+        }
+    });
+    match tag {
+        Some(tag) => format!("{}{}", hint, pascal_case(tag, false)),
+        None => format!("{}{}", hint, index + 1),
+    }
+}
+
+fn optional(ty: Type) -> Type {
+    match ty {
+        Type::Optional(_) | Type::Null | Type::Any => ty,
+        ty => Type::Optional(Box::new(ty)),
+    }
+}
+
+fn description(schema: &Value) -> Option<String> {
+    schema["description"].as_str().map(str::to_string)
+}
+
+/// The values of a string enum, written as `enum` or as `oneOf` constants
+fn string_enum(schema: &Value) -> Option<Vec<String>> {
+    let strings = |values: &Vec<Value>| -> Option<Vec<String>> {
+        values
+            .iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect()
+    };
+    if let Some(values) = schema["enum"].as_array() {
+        return strings(values);
+    }
+    if let Some(value) = schema["const"].as_str() {
+        return Some(vec![value.to_string()]);
+    }
+    let variants = schema["oneOf"].as_array()?;
+    let values: Vec<Value> = variants
+        .iter()
+        .map(|variant| variant.get("const").cloned())
+        .collect::<Option<_>>()?;
+    strings(&values)
+}
+
+fn ts_type(ty: &Type) -> String {
+    match ty {
+        Type::Any => "unknown".to_string(),
+        Type::Null => "null".to_string(),
+        Type::Bool => "boolean".to_string(),
+        Type::Integer(_) | Type::Number(_) => "number".to_string(),
+        Type::String => "string".to_string(),
+        Type::Array(item) => match **item {
+            Type::Optional(_) | Type::Union(_) => format!("({})[]", ts_type(item)),
+            _ => format!("{}[]", ts_type(item)),
+        },
+        Type::Map(value) => format!("Record<string, {}>", ts_type(value)),
+        Type::Optional(ty) => format!("{} | null", ts_type(ty)),
+        Type::Union(types) => types.iter().map(ts_type).collect::<Vec<_>>().join(" | "),
+        Type::Named(name) => name.clone(),
+    }
+}
+
+/// Write Go declarations of named types
+fn go_decls(out: &mut String, decls: &[Decl]) {
+    for decl in decls {
+        out.push('\n');
+        if let Some(description) = &decl.description {
+            go_doc(out, description);
+        }
+        match &decl.body {
+            Body::Object(fields) if fields.is_empty() => {
+                let _ = writeln!(out, "type {} struct{{}}", decl.name);
+            }
+            Body::Object(fields) => {
+                let rows: Vec<[String; 3]> = fields
+                    .iter()
+                    .map(|field| {
+                        let ty = if field.required {
+                            go_type(&field.ty)
+                        } else {
+                            go_type(&Type::Optional(Box::new(field.ty.clone())))
+                        };
+                        let omitempty = if field.required { "" } else { ",omitempty" };
+                        [
+                            pascal_case(&field.name, true),
+                            ty,
+                            format!("`json:\"{}{}\"`", field.name, omitempty),
+                        ]
+                    })
+                    .collect();
+                let _ = writeln!(out, "type {} struct {{", decl.name);
+                go_columns(out, &rows);
+                out.push_str("}\n");
+            }
+            Body::Enum(values) => {
+                let _ = writeln!(out, "type {} string\n\nconst (", decl.name);
+                let rows: Vec<[String; 3]> = values
+                    .iter()
+                    .map(|value| {
+                        [
+                            format!("{}{}", decl.name, pascal_case(value, true)),
+                            decl.name.clone(),
+                            format!("= {:?}", value),
+                        ]
+                    })
+                    .collect();
+                go_columns(out, &rows);
+                out.push_str(")\n");
+            }
+            Body::Alias(ty) => {
+                let _ = writeln!(out, "type {} = {}", decl.name, go_type(ty));
+            }
+        }
+    }
+}
+
+fn go_type(ty: &Type) -> String {
+    match ty {
+        Type::Any | Type::Null | Type::Union(_) => "any".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Integer(format) => match format.as_deref() {
+            Some(
+                format @ ("int" | "int8" | "int16" | "int32" | "int64" | "uint" | "uint8"
+                | "uint16" | "uint32" | "uint64"),
+            ) => format.to_string(),
+            _ => "int64".to_string(),
+        },
+        Type::Number(format) => match format.as_deref() {
+            Some("float") => "float32".to_string(),
+            _ => "float64".to_string(),
+        },
+        Type::String => "string".to_string(),
+        Type::Array(item) => format!("[]{}", go_type(item)),
+        Type::Map(value) => format!("map[string]{}", go_type(value)),
+        // Slices, maps and interfaces can already be nil
+        Type::Optional(ty) => match **ty {
+            Type::Any | Type::Null | Type::Union(_) | Type::Array(_) | Type::Map(_) => go_type(ty),
+            Type::Optional(ref ty) => go_type(&Type::Optional(ty.clone())),
+            _ => format!("*{}", go_type(ty)),
+        },
+        Type::Named(name) => name.clone(),
+    }
+}
+
+fn rust_type(ty: &Type) -> String {
+    match ty {
+        Type::Any | Type::Union(_) => "serde_json::Value".to_string(),
+        Type::Null => "()".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Integer(format) => match format.as_deref() {
+            Some(format @ ("int8" | "int16" | "int32" | "int64")) => format.replace("int", "i"),
+            Some(format @ ("uint8" | "uint16" | "uint32" | "uint64")) => {
+                format.replace("uint", "u")
+            }
+            Some("uint") => "u64".to_string(),
+            _ => "i64".to_string(),
+        },
+        Type::Number(format) => match format.as_deref() {
+            Some("float") => "f32".to_string(),
+            _ => "f64".to_string(),
+        },
+        Type::String => "String".to_string(),
+        Type::Array(item) => format!("Vec<{}>", rust_type(item)),
+        Type::Map(value) => format!("BTreeMap<String, {}>", rust_type(value)),
+        Type::Optional(ty) => format!("Option<{}>", rust_type(ty)),
+        Type::Named(name) => name.clone(),
+    }
+}
+
+/// Write a JSDoc comment
+fn ts_doc(out: &mut String, indent: &str, text: &str) {
+    let lines: Vec<&str> = text.lines().collect();
+    if let [line] = lines.as_slice() {
+        let _ = writeln!(out, "{}/** {} */", indent, line);
+        return;
+    }
+    let _ = writeln!(out, "{}/**", indent);
+    for line in lines {
+        let _ = writeln!(out, "{} * {}", indent, line);
+    }
+    let _ = writeln!(out, "{} */", indent);
+}
+
+fn go_doc(out: &mut String, text: &str) {
+    for line in text.lines() {
+        let _ = writeln!(out, "// {}", line);
+    }
+}
+
+/// Write tab-indented rows with their columns aligned as `gofmt` does
+fn go_columns(out: &mut String, rows: &[[String; 3]]) {
+    let width = |column: usize| rows.iter().map(|row| row[column].len()).max().unwrap_or(0);
+    let (first, second) = (width(0), width(1));
+    for [a, b, c] in rows {
+        let _ = writeln!(out, "\t{:first$} {:second$} {}", a, b, c);
+    }
+}
+
+fn rust_doc(out: &mut String, indent: &str, text: &str) {
+    for line in text.lines() {
+        if line.is_empty() {
+            let _ = writeln!(out, "{}///", indent);
+        } else {
+            let _ = writeln!(out, "{}/// {}", indent, line);
+        }
+    }
+}
+
+/// Keywords that need a raw identifier to be used as names in Rust
+pub(crate) const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl", "in", "let",
+    "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return",
+    "static", "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use",
+    "virtual", "where", "while", "yield",
+];
+
+/// A `snake_case` Rust name for a JSON name, such as `took_ms` for `tookMs`
+fn rust_ident(name: &str) -> String {
+    let mut out = String::new();
+    let mut previous = '_';
+    for c in name.chars() {
+        if c.is_ascii_uppercase() && (previous.is_ascii_lowercase() || previous.is_ascii_digit()) {
+            out.push('_');
+        }
+        if c.is_ascii_alphanumeric() {
+            out.push(c.to_ascii_lowercase());
+        } else if !out.ends_with('_') {
+            out.push('_');
+        }
+        previous = c;
+    }
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert(0, '_');
+    }
+    if RUST_KEYWORDS.contains(&out.as_str()) {
+        out.insert_str(0, "r#");
+    }
+    out
+}
+
+/// The name serde's `kebab-case` or `snake_case` renaming gives a variant
+pub(crate) fn serde_case(variant: &str, separator: char) -> String {
+    let mut out = String::new();
+    for (i, c) in variant.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            out.push(separator);
+        }
+        out.push(c.to_ascii_lowercase());
+    }
+    out
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Join the words of a name as `PascalCase`, writing initialisms such as
+/// `ID` and `URL` in capitals when `initialisms` is set, as Go does
+pub(crate) fn pascal_case(name: &str, initialisms: bool) -> String {
+    let mut out: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let lower = word.to_ascii_lowercase();
+            if initialisms && matches!(lower.as_str(), "id" | "url" | "http" | "api" | "json") {
+                return word.to_ascii_uppercase();
+            }
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    if out.starts_with(|c: char| c.is_ascii_digit()) || out.is_empty() {
+        out.insert(0, 'X');
+    }
+    out
+}
+
+fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name, false);
+    let mut chars = pascal.chars();
+    chars
+        .next()
+        .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+        .unwrap_or_default()
+}
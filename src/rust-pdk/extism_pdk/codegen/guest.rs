@@ -0,0 +1,383 @@
+//! Plugin skeletons for the Go, JavaScript and Python PDKs
+//!
+//! Each skeleton declares the types of the functions' inputs and outputs,
+//! exports each function with the JSON plumbing its PDK needs, and leaves
+//! a stub for the function's logic that fails until it is written.
+
+use std::fmt::Write as _;
+
+use super::{
+    camel_case, go_decls, go_doc, go_type, is_identifier, pascal_case, ts_doc, ts_type, Body,
+    Codegen, Field, Type,
+};
+
+/// Words Python does not accept as names
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+impl Codegen {
+    /// Generate the `main` package of a plugin using
+    /// `github.com/extism/go-pdk`
+    pub(crate) fn go_plugin(&self) -> String {
+        let (decls, functions) = self.collect();
+        let mut out = format!(
+            "// Plugin {} for the Extism Go PDK.\npackage main\n",
+            self.name
+        );
+        if !functions.is_empty() {
+            out.push_str("\nimport (\n\t\"errors\"\n\n\t\"github.com/extism/go-pdk\"\n)\n");
+        }
+        go_decls(&mut out, &decls);
+        for function in &functions {
+            let stub = pascal_case(&function.name, true);
+            let wrapper = format!("export{}", stub);
+            let _ = write!(
+                out,
+                "\n//go:wasmexport {}\nfunc {}() int32 {{\n",
+                function.name, wrapper
+            );
+            let argument = match function.input {
+                Type::Null => "",
+                ref ty => {
+                    let _ = write!(
+                        out,
+                        "\tvar input {}\n\tif err := pdk.InputJSON(&input); err != nil {{\n\t\tpdk.SetError(err)\n\t\treturn 1\n\t}}\n",
+                        go_type(ty)
+                    );
+                    "input"
+                }
+            };
+            match function.output {
+                Type::Null => {
+                    let _ = write!(
+                        out,
+                        "\tif err := {}({}); err != nil {{\n\t\tpdk.SetError(err)\n\t\treturn 1\n\t}}\n",
+                        stub, argument
+                    );
+                }
+                _ => {
+                    let _ = write!(
+                        out,
+                        "\toutput, err := {}({})\n\tif err != nil {{\n\t\tpdk.SetError(err)\n\t\treturn 1\n\t}}\n\tif err := pdk.OutputJSON(output); err != nil {{\n\t\tpdk.SetError(err)\n\t\treturn 1\n\t}}\n",
+                        stub, argument
+                    );
+                }
+            }
+            out.push_str("\treturn 0\n}\n\n");
+            go_doc(&mut out, &format!("{} implements {}.", stub, function.name));
+            let params = match function.input {
+                Type::Null => String::new(),
+                ref ty => format!("input {}", go_type(ty)),
+            };
+            let not_implemented = format!("errors.New(\"{} is not implemented\")", function.name);
+            match function.output {
+                Type::Null => {
+                    let _ = write!(
+                        out,
+                        "func {}({}) error {{\n\treturn {}\n}}\n",
+                        stub, params, not_implemented
+                    );
+                }
+                ref ty => {
+                    let output = go_type(ty);
+                    let _ = write!(
+                        out,
+                        "func {}({}) ({}, error) {{\n\tvar output {}\n\treturn output, {}\n}}\n",
+                        stub, params, output, output, not_implemented
+                    );
+                }
+            }
+        }
+        out.push_str("\nfunc main() {}\n");
+        out
+    }
+
+    /// Generate the module of a plugin using the Extism JavaScript PDK
+    pub(crate) fn js_plugin(&self) -> String {
+        let (decls, functions) = self.collect();
+        let mut out = format!("// Plugin {} for the Extism JavaScript PDK.\n", self.name);
+        for decl in &decls {
+            out.push('\n');
+            let mut doc = decl.description.clone().unwrap_or_default();
+            if !doc.is_empty() {
+                doc.push('\n');
+            }
+            match &decl.body {
+                Body::Object(fields) => {
+                    let _ = write!(doc, "@typedef {{Object}} {}", decl.name);
+                    for field in fields {
+                        let name = if field.required {
+                            field.name.clone()
+                        } else {
+                            format!("[{}]", field.name)
+                        };
+                        let _ = write!(doc, "\n@property {{{}}} {}", ts_type(&field.ty), name);
+                        if let Some(description) = &field.description {
+                            let _ = write!(doc, " {}", description.replace('\n', " "));
+                        }
+                    }
+                }
+                Body::Enum(values) => {
+                    let values: Vec<String> =
+                        values.iter().map(|value| format!("{:?}", value)).collect();
+                    let _ = write!(doc, "@typedef {{{}}} {}", values.join(" | "), decl.name);
+                }
+                Body::Alias(ty) => {
+                    let _ = write!(doc, "@typedef {{{}}} {}", ts_type(ty), decl.name);
+                }
+            }
+            // Typedefs only document a file when written as block comments
+            let doc = doc.replace('\n', "\n * ");
+            let _ = writeln!(out, "/**\n * {}\n */", doc);
+        }
+        for function in &functions {
+            let export = js_name(&function.name);
+            let stub = camel_case(&format!("handle_{}", function.name));
+            out.push('\n');
+            if export != function.name {
+                let _ = writeln!(
+                    out,
+                    "// {:?} is not a JavaScript name, so the module exports it as {}.",
+                    function.name, export
+                );
+            }
+            let _ = writeln!(out, "export function {}() {{", export);
+            let argument = match function.input {
+                Type::Null => "",
+                ref ty => {
+                    let _ = write!(
+                        out,
+                        "  /** @type {{{}}} */\n  const input = JSON.parse(Host.inputString());\n",
+                        ts_type(ty)
+                    );
+                    "input"
+                }
+            };
+            match function.output {
+                Type::Null => {
+                    let _ = writeln!(out, "  {}({});", stub, argument);
+                }
+                _ => {
+                    let _ = write!(
+                        out,
+                        "  const output = {}({});\n  Host.outputString(JSON.stringify(output));\n",
+                        stub, argument
+                    );
+                }
+            }
+            out.push_str("}\n\n");
+            let mut doc = format!("Implements `{}`", function.name);
+            if function.input != Type::Null {
+                let _ = write!(doc, "\n@param {{{}}} input", ts_type(&function.input));
+            }
+            if function.output != Type::Null {
+                let _ = write!(doc, "\n@returns {{{}}}", ts_type(&function.output));
+            }
+            ts_doc(&mut out, "", &doc);
+            let _ = write!(
+                out,
+                "function {}({}) {{\n  throw new Error(\"{} is not implemented\");\n}}\n",
+                stub, argument, function.name
+            );
+        }
+        out
+    }
+
+    /// Generate the declarations `extism-js` compiles a plugin's module
+    /// with, listing its exports
+    pub(crate) fn js_declarations(&self) -> String {
+        let mut out = String::from("declare module \"main\" {\n");
+        for function in &self.functions {
+            let _ = writeln!(out, "  export function {}(): I32;", js_name(&function.name));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Generate the module of a plugin using the Extism Python PDK
+    pub(crate) fn python_plugin(&self) -> String {
+        let (decls, functions) = self.collect();
+        let mut out = format!(
+            "\"\"\"Plugin {} for the Extism Python PDK\"\"\"\n\n",
+            self.name
+        );
+        out.push_str("import json\n");
+        out.push_str(
+            "from typing import Any, Dict, List, Literal, NotRequired, Optional, TypedDict, Union\n",
+        );
+        out.push_str("\nimport extism\n");
+        for decl in &decls {
+            out.push_str("\n\n");
+            match &decl.body {
+                Body::Object(fields)
+                    if fields.iter().all(|field| python_identifier(&field.name)) =>
+                {
+                    let _ = writeln!(out, "class {}(TypedDict):", decl.name);
+                    if let Some(description) = &decl.description {
+                        python_doc(&mut out, "    ", description);
+                    }
+                    for field in fields {
+                        let _ = writeln!(out, "    {}: {}", field.name, python_field(field));
+                        if let Some(description) = &field.description {
+                            python_doc(&mut out, "    ", description);
+                        }
+                    }
+                    if fields.is_empty() && decl.description.is_none() {
+                        out.push_str("    pass\n");
+                    }
+                }
+                Body::Object(fields) => {
+                    if let Some(description) = &decl.description {
+                        python_comment(&mut out, description);
+                    }
+                    let _ = writeln!(out, "{} = TypedDict({:?}, {{", decl.name, decl.name);
+                    for field in fields {
+                        let _ = writeln!(out, "    {:?}: {},", field.name, python_field(field));
+                    }
+                    out.push_str("})\n");
+                }
+                Body::Enum(values) => {
+                    if let Some(description) = &decl.description {
+                        python_comment(&mut out, description);
+                    }
+                    let values: Vec<String> =
+                        values.iter().map(|value| format!("{:?}", value)).collect();
+                    let _ = writeln!(out, "{} = Literal[{}]", decl.name, values.join(", "));
+                }
+                Body::Alias(ty) => {
+                    if let Some(description) = &decl.description {
+                        python_comment(&mut out, description);
+                    }
+                    let _ = writeln!(out, "{} = {}", decl.name, python_type(ty));
+                }
+            }
+        }
+        for function in &functions {
+            let export = python_name(&function.name);
+            let stub = format!("handle_{}", export);
+            out.push_str("\n\n");
+            if export != function.name {
+                let _ = writeln!(
+                    out,
+                    "# {:?} is not a Python name, so the module exports it as {}.",
+                    function.name, export
+                );
+            }
+            let _ = writeln!(out, "@extism.plugin_fn\ndef {}():", export);
+            let (params, argument) = match function.input {
+                Type::Null => (String::new(), ""),
+                ref ty => {
+                    let ty = python_type(ty);
+                    let _ = writeln!(out, "    input: {} = json.loads(extism.input_str())", ty);
+                    (format!("input: {}", ty), "input")
+                }
+            };
+            let returns = match function.output {
+                Type::Null => {
+                    let _ = writeln!(out, "    {}({})", stub, argument);
+                    "None".to_string()
+                }
+                ref ty => {
+                    let _ = writeln!(
+                        out,
+                        "    extism.output_str(json.dumps({}({})))",
+                        stub, argument
+                    );
+                    python_type(ty)
+                }
+            };
+            let _ = write!(
+                out,
+                "\n\ndef {}({}) -> {}:\n    \"\"\"Implements `{}`\"\"\"\n    raise NotImplementedError(\"{} is not implemented\")\n",
+                stub, params, returns, function.name, function.name
+            );
+        }
+        out
+    }
+}
+
+/// The name a function is exported with from JavaScript
+fn js_name(name: &str) -> String {
+    if is_identifier(name) {
+        name.to_string()
+    } else {
+        camel_case(name)
+    }
+}
+
+/// The name a function is exported with from Python
+fn python_name(name: &str) -> String {
+    if python_identifier(name) {
+        return name.to_string();
+    }
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if !python_identifier(&out) {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn python_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !PYTHON_KEYWORDS.contains(&name)
+}
+
+fn python_field(field: &Field) -> String {
+    if field.required {
+        python_type(&field.ty)
+    } else {
+        format!("NotRequired[{}]", python_type(&field.ty))
+    }
+}
+
+/// A Python type annotation, naming declared types as strings so that
+/// they can be used before they are declared
+fn python_type(ty: &Type) -> String {
+    match ty {
+        Type::Any => "Any".to_string(),
+        Type::Null => "None".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Integer(_) => "int".to_string(),
+        Type::Number(_) => "float".to_string(),
+        Type::String => "str".to_string(),
+        Type::Array(item) => format!("List[{}]", python_type(item)),
+        Type::Map(value) => format!("Dict[str, {}]", python_type(value)),
+        Type::Optional(ty) => format!("Optional[{}]", python_type(ty)),
+        Type::Union(types) => {
+            let types: Vec<String> = types.iter().map(python_type).collect();
+            format!("Union[{}]", types.join(", "))
+        }
+        Type::Named(name) => format!("{:?}", name),
+    }
+}
+
+fn python_doc(out: &mut String, indent: &str, text: &str) {
+    let text = text.replace("\"\"\"", "\\\"\\\"\\\"");
+    if text.lines().count() <= 1 {
+        let _ = writeln!(out, "{}\"\"\"{}\"\"\"", indent, text);
+        return;
+    }
+    let _ = writeln!(out, "{}\"\"\"", indent);
+    for line in text.lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = writeln!(out, "{}{}", indent, line);
+        }
+    }
+    let _ = writeln!(out, "{}\"\"\"", indent);
+}
+
+fn python_comment(out: &mut String, text: &str) {
+    for line in text.lines() {
+        let _ = writeln!(out, "# {}", line);
+    }
+}
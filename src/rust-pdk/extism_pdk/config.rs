@@ -0,0 +1,27 @@
+//! Structured config values
+//!
+//! Enabled with the `yaml` feature. Operators write policies and rules in
+//! YAML more readily than in JSON, so `get_yaml` deserializes a config
+//! value written as a YAML document:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Policy {
+//!     allow: Vec<String>,
+//!     max_size: u64,
+//! }
+//!
+//! let policy: Policy = config::get_yaml("policy")?.unwrap_or_default();
+//! ```
+//!
+//! JSON is a subset of YAML, so values written as JSON parse too.
+
+use super::Host;
+
+/// Deserialize the config value `key` as a YAML document, or `None` if it
+/// is not set
+pub fn get_yaml<T: serde::de::DeserializeOwned>(key: &str) -> Result<Option<T>, serde_yaml::Error> {
+    Host::config(key)
+        .map(|value| serde_yaml::from_str(&value))
+        .transpose()
+}
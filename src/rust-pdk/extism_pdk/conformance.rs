@@ -0,0 +1,210 @@
+//! Checking that a host implements the kernel functions the PDK relies on
+//!
+//! Built with the `conformance` feature, the plugin module exports three
+//! functions exercising the host:
+//!
+//! - `conformance` takes a `Setup` and returns a `Report` of the checks it
+//!   could make from inside the plugin: memory blocks, input, config, vars,
+//!   HTTP requests and logging
+//! - `conformance_echo` outputs its input unchanged
+//! - `conformance_error` fails with `ERROR_MESSAGE`
+//!
+//! ```sh
+//! cargo build --release --target wasm32-unknown-unknown --features conformance
+//! extismx conformance target/wasm32-unknown-unknown/release/extism_hello_plugin.wasm
+//! ```
+//!
+//! Outside wasm, `Harness` serves the HTTP endpoints the checks call, runs
+//! the exports through any host given as a closure and adds the checks only
+//! the host side can make, such as vars persisting across calls and errors
+//! reaching the caller. A host is set up for the suite with the config key
+//! `CONFIG_KEY` set to `CONFIG_VALUE`, and HTTP requests allowed to the
+//! harness's address only.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "conformance")]
+mod checks;
+#[cfg(not(target_arch = "wasm32"))]
+mod harness;
+#[cfg(feature = "conformance")]
+pub use checks::run;
+#[cfg(not(target_arch = "wasm32"))]
+pub use harness::Harness;
+
+/// Config key the host must set to `CONFIG_VALUE`
+pub const CONFIG_KEY: &str = "conformance";
+
+/// Value of `CONFIG_KEY`
+pub const CONFIG_VALUE: &str = "ok";
+
+/// Message `conformance_error` fails with
+pub const ERROR_MESSAGE: &str = "conformance error";
+
+/// Var counting the calls of `conformance` on a plugin instance
+pub const CALLS_VAR: &str = "conformance:calls";
+
+/// Where the HTTP checks send their requests
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Setup {
+    /// Base URL of an echo server the host allows requests to, or none to
+    /// skip the HTTP checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_url: Option<String>,
+    /// A URL whose host is not allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub denied_url: Option<String>,
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Passed,
+    Failed,
+    /// The check could not run, such as HTTP checks without an echo server
+    Skipped,
+}
+
+/// A behavior of the host and whether it holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Check {
+    /// What the check covers: `memory`, `io`, `config`, `vars`, `http`,
+    /// `logs` or `errors`
+    pub area: String,
+    pub name: String,
+    pub status: Status,
+    /// What went wrong, for failed and skipped checks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    /// A check passing when `result` is `Ok`, or failing with its error
+    pub fn new(area: &str, name: &str, result: Result<(), String>) -> Self {
+        let (status, detail) = match result {
+            Ok(()) => (Status::Passed, None),
+            Err(detail) => (Status::Failed, Some(detail)),
+        };
+        Self {
+            area: area.to_string(),
+            name: name.to_string(),
+            status,
+            detail,
+        }
+    }
+
+    /// A check that could not run
+    pub fn skipped(area: &str, name: &str, reason: impl Into<String>) -> Self {
+        Self {
+            area: area.to_string(),
+            name: name.to_string(),
+            status: Status::Skipped,
+            detail: Some(reason.into()),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            Status::Passed => "ok",
+            Status::Failed => "FAILED",
+            Status::Skipped => "skipped",
+        };
+        write!(f, "{}/{} ... {}", self.area, self.name, status)?;
+        if let Some(detail) = &self.detail {
+            write!(f, ": {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// The checks made against a host
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Report {
+    pub checks: Vec<Check>,
+    /// Calls of `conformance` the plugin instance had seen before this one,
+    /// counted in `CALLS_VAR`
+    #[serde(default)]
+    pub previous_calls: u64,
+}
+
+impl Report {
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// The checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == Status::Failed)
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{}", check)?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.count(Status::Passed),
+            self.count(Status::Failed),
+            self.count(Status::Skipped)
+        )
+    }
+}
+
+/// What the echo server received, as it returns it
+#[cfg(any(feature = "conformance", not(target_arch = "wasm32")))]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Echo {
+    pub method: String,
+    pub path: String,
+    /// Headers, by lowercased name
+    pub headers: std::collections::BTreeMap<String, String>,
+    pub body: String,
+}
+
+/// The exports of a conformance plugin
+#[cfg(feature = "conformance")]
+mod exports {
+    use super::super::Host;
+
+    #[no_mangle]
+    pub extern "C" fn conformance() -> i32 {
+        let setup: super::Setup = Host::input_json().unwrap_or_default();
+        match Host::output_json(&super::run(&setup)) {
+            Ok(()) => 0,
+            Err(e) => {
+                Host::error(&format!("Failed to serialize report: {}", e));
+                1
+            }
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C" fn conformance_echo() -> i32 {
+        Host::output(&Host::input());
+        0
+    }
+
+    #[no_mangle]
+    pub extern "C" fn conformance_error() -> i32 {
+        Host::error(super::ERROR_MESSAGE);
+        1
+    }
+}
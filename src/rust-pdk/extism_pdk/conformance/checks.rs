@@ -0,0 +1,307 @@
+//! The checks a conformance plugin makes from inside the plugin
+
+use super::super::{Host, HttpMethod, HttpRequest};
+use super::{Check, Echo, Report, Setup, CALLS_VAR, CONFIG_KEY, CONFIG_VALUE};
+
+/// Run the checks a plugin can make, calling the kernel functions directly
+/// where the `Host` API would hide what the host returned
+pub fn run(setup: &Setup) -> Report {
+    let previous_calls = Host::var_get(CALLS_VAR)
+        .and_then(|value| String::from_utf8(value).ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    Host::var_set_string(CALLS_VAR, &(previous_calls + 1).to_string());
+
+    let mut checks = Vec::new();
+    let mut check = |area: &str, name: &str, result: Result<(), String>| {
+        checks.push(Check::new(area, name, result));
+    };
+    check("memory", "alloc-length", alloc_length());
+    check("memory", "store-load", store_load());
+    check("memory", "alloc-distinct", alloc_distinct());
+    check("memory", "free", free());
+    check("memory", "length-unknown", length_unknown());
+    check("io", "input-offset", input_offset());
+    check("config", "config-get", config_get());
+    check("config", "config-missing", config_missing());
+    check("vars", "var-roundtrip", var_roundtrip());
+    check("vars", "var-binary", var_binary());
+    check("vars", "var-overwrite", var_overwrite());
+    check("vars", "var-missing", var_missing());
+    check("logs", "log-levels", log_levels());
+    check("time", "time-now", time_now());
+
+    match &setup.http_url {
+        Some(url) => {
+            let url = url.trim_end_matches('/');
+            checks.push(Check::new("http", "http-get", http_get(url)));
+            checks.push(Check::new("http", "http-post-body", http_post_body(url)));
+            checks.push(Check::new("http", "http-status", http_status(url)));
+        }
+        None => {
+            for name in ["http-get", "http-post-body", "http-status"] {
+                checks.push(Check::skipped("http", name, "no echo server given"));
+            }
+        }
+    }
+    match &setup.denied_url {
+        Some(url) => checks.push(Check::new("http", "http-denied", http_denied(url))),
+        None => checks.push(Check::skipped("http", "http-denied", "no denied URL given")),
+    }
+    Report {
+        checks,
+        previous_calls,
+    }
+}
+
+/// Fail with `message` unless `condition` holds
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(message())
+    }
+}
+
+fn alloc_length() -> Result<(), String> {
+    let offset = unsafe { super::super::extism_alloc(100) };
+    ensure(offset != 0, || "extism_alloc(100) returned 0".to_string())?;
+    let length = unsafe { super::super::extism_length(offset) };
+    unsafe { super::super::extism_free(offset) };
+    ensure(length == 100, || {
+        format!("extism_length returned {} for a 100 byte block", length)
+    })
+}
+
+fn store_load() -> Result<(), String> {
+    let data: Vec<u8> = (0..=255).collect();
+    let offset = unsafe { super::super::extism_alloc(data.len() as u64) };
+    let mut all = vec![0u8; data.len()];
+    let mut middle = vec![0u8; 16];
+    unsafe {
+        super::super::extism_store_u8(offset, 0, data.as_ptr(), data.len() as u64);
+        super::super::extism_load_u8(offset, 0, data.len() as u64, all.as_mut_ptr());
+        super::super::extism_load_u8(offset, 100, 16, middle.as_mut_ptr());
+        super::super::extism_free(offset);
+    }
+    ensure(all == data, || {
+        "extism_load_u8 did not return the bytes stored".to_string()
+    })?;
+    ensure(middle == data[100..116], || {
+        "extism_load_u8 at an offset did not return the bytes stored there".to_string()
+    })
+}
+
+fn alloc_distinct() -> Result<(), String> {
+    let (first, second) = unsafe { (super::super::extism_alloc(8), super::super::extism_alloc(8)) };
+    let mut loaded = [0u8; 8];
+    unsafe {
+        super::super::extism_store_u8(first, 0, [1u8; 8].as_ptr(), 8);
+        super::super::extism_store_u8(second, 0, [2u8; 8].as_ptr(), 8);
+        super::super::extism_load_u8(first, 0, 8, loaded.as_mut_ptr());
+        super::super::extism_free(first);
+        super::super::extism_free(second);
+    }
+    ensure(first != second, || {
+        format!("extism_alloc returned block {} twice", first)
+    })?;
+    ensure(loaded == [1u8; 8], || {
+        "storing to one block changed another".to_string()
+    })
+}
+
+fn free() -> Result<(), String> {
+    let offset = unsafe { super::super::extism_alloc(32) };
+    unsafe { super::super::extism_free(offset) };
+    let length = unsafe { super::super::extism_length(offset) };
+    ensure(length == 0, || {
+        format!("extism_length returned {} for a freed block", length)
+    })
+}
+
+fn length_unknown() -> Result<(), String> {
+    let length = unsafe { super::super::extism_length(u64::MAX / 2) };
+    ensure(length == 0, || {
+        format!(
+            "extism_length returned {} for a block never allocated",
+            length
+        )
+    })
+}
+
+fn input_offset() -> Result<(), String> {
+    let input = Host::input();
+    let length = unsafe { super::super::extism_input_length() };
+    ensure(length == input.len() as u64, || {
+        format!(
+            "extism_input_length returned {} for {} bytes of input",
+            length,
+            input.len()
+        )
+    })?;
+    if input.len() < 3 {
+        return Ok(());
+    }
+    let mut middle = vec![0u8; input.len() - 2];
+    unsafe { super::super::extism_input_load_u8(1, middle.len() as u64, middle.as_mut_ptr()) };
+    ensure(middle == input[1..input.len() - 1], || {
+        "extism_input_load_u8 at an offset did not return the input there".to_string()
+    })
+}
+
+fn config_get() -> Result<(), String> {
+    let value = Host::config(CONFIG_KEY);
+    ensure(value.as_deref() == Some(CONFIG_VALUE), || {
+        format!(
+            "config {:?} is {:?}, expected {:?}",
+            CONFIG_KEY, value, CONFIG_VALUE
+        )
+    })
+}
+
+fn config_missing() -> Result<(), String> {
+    let value = Host::config("conformance-missing");
+    ensure(value.is_none(), || {
+        format!("config of an unset key is {:?}, expected none", value)
+    })
+}
+
+fn var_roundtrip() -> Result<(), String> {
+    Host::var_set_string("conformance:text", "hello");
+    let value = Host::var_get("conformance:text");
+    ensure(value.as_deref() == Some(b"hello".as_slice()), || {
+        format!("var set to \"hello\" read back as {:?}", value)
+    })
+}
+
+fn var_binary() -> Result<(), String> {
+    let data = [0u8, 255, 0, 128, 10];
+    Host::var_set("conformance:binary", &data);
+    let value = Host::var_get("conformance:binary");
+    ensure(value.as_deref() == Some(data.as_slice()), || {
+        format!("binary var read back as {:?}", value)
+    })
+}
+
+fn var_overwrite() -> Result<(), String> {
+    Host::var_set_string("conformance:overwrite", "first value");
+    Host::var_set_string("conformance:overwrite", "second");
+    let value = Host::var_get("conformance:overwrite");
+    ensure(value.as_deref() == Some(b"second".as_slice()), || {
+        format!("overwritten var read back as {:?}", value)
+    })
+}
+
+fn var_missing() -> Result<(), String> {
+    let value = Host::var_get("conformance:missing");
+    ensure(value.is_none(), || {
+        format!("unset var read as {:?}, expected none", value)
+    })
+}
+
+/// The clock must read the current time, and not run backwards
+fn time_now() -> Result<(), String> {
+    // 2020-01-01T00:00:00Z; an earlier reading means the host has no clock
+    const EARLIEST_MS: u64 = 1_577_836_800_000;
+    let first = Host::now_ms();
+    let second = Host::now_ms();
+    ensure(first >= EARLIEST_MS, || {
+        format!("extism_time_now_ms returned {}, before 2020", first)
+    })?;
+    ensure(second >= first, || {
+        format!("clock ran backwards from {} to {}", first, second)
+    })
+}
+
+/// Log at each level; the harness checks the messages arrived when it can
+/// see the host's logs
+fn log_levels() -> Result<(), String> {
+    Host::log_debug("conformance log debug");
+    Host::log_info("conformance log info");
+    Host::log_warn("conformance log warn");
+    Host::log_error("conformance log error");
+    Ok(())
+}
+
+fn send(request: &HttpRequest) -> Result<(i32, Echo, Option<String>), String> {
+    let response = Host::http_request(request)
+        .map_err(|e| format!("{} {} failed: {}", request.method, request.url, e.message))?;
+    let echo = serde_json::from_slice(&response.body()).unwrap_or_default();
+    Ok((
+        response.status(),
+        echo,
+        response.header("X-Conformance-Echo"),
+    ))
+}
+
+fn http_get(url: &str) -> Result<(), String> {
+    let (status, echo, header) = send(&HttpRequest {
+        method: HttpMethod::Get,
+        url: format!("{}/echo?query=1", url),
+        headers: vec![("X-Conformance".to_string(), "get".to_string())],
+        body: None,
+        ..Default::default()
+    })?;
+    ensure(status == 200, || format!("GET returned status {}", status))?;
+    ensure(echo.method == "GET" && echo.path == "/echo?query=1", || {
+        format!("the server received {} {}", echo.method, echo.path)
+    })?;
+    ensure(
+        echo.headers.get("x-conformance").map(String::as_str) == Some("get"),
+        || "the request header did not reach the server".to_string(),
+    )?;
+    ensure(header.as_deref() == Some("1"), || {
+        format!("response header x-conformance-echo read as {:?}", header)
+    })
+}
+
+fn http_post_body(url: &str) -> Result<(), String> {
+    let (status, echo, _) = send(&HttpRequest {
+        method: HttpMethod::Post,
+        url: format!("{}/echo", url),
+        headers: Vec::new(),
+        body: Some(b"conformance body".to_vec()),
+        ..Default::default()
+    })?;
+    ensure(status == 200, || format!("POST returned status {}", status))?;
+    ensure(echo.method == "POST", || {
+        format!("the server received {}", echo.method)
+    })?;
+    ensure(echo.body == "conformance body", || {
+        format!("the server received body {:?}", echo.body)
+    })
+}
+
+fn http_status(url: &str) -> Result<(), String> {
+    let (status, _, _) = send(&HttpRequest {
+        method: HttpMethod::Get,
+        url: format!("{}/status/404", url),
+        headers: Vec::new(),
+        body: None,
+        ..Default::default()
+    })?;
+    ensure(status == 404, || {
+        format!("a 404 response returned status {}", status)
+    })
+}
+
+fn http_denied(url: &str) -> Result<(), String> {
+    let result = Host::http_request(&HttpRequest {
+        method: HttpMethod::Get,
+        url: url.to_string(),
+        headers: Vec::new(),
+        body: None,
+        ..Default::default()
+    });
+    match result {
+        Ok(response) => Err(format!(
+            "a request to a host that is not allowed returned status {}",
+            response.status()
+        )),
+        Err(e) if e.kind == super::super::ErrorKind::Invalid => Ok(()),
+        Err(e) => Err(format!(
+            "a request to a host that is not allowed failed as {:?}, expected invalid: {}",
+            e.kind, e.message
+        )),
+    }
+}
@@ -0,0 +1,222 @@
+//! Running the conformance suite against a host
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use tracing::Level;
+
+use super::{Check, Echo, Report, Setup, CONFIG_KEY, CONFIG_VALUE, ERROR_MESSAGE};
+use crate::extism_pdk::host::{Manifest, PluginBuilder, Wasm};
+
+/// Host whose requests the `http-denied` check expects to be refused
+const DENIED_URL: &str = "http://denied.invalid/";
+
+/// Calls a function of the conformance plugin in the host under test,
+/// returning its output or the error the host reported
+pub type CallFn<'a> = dyn FnMut(&str, &[u8]) -> Result<Vec<u8>, String> + 'a;
+
+/// Serves the echo endpoints of the HTTP checks and runs the suite
+///
+/// `GET|POST /...` answers with the request as JSON and an
+/// `x-conformance-echo: 1` header, and `/status/N` answers with status `N`.
+/// The server stops when the harness is dropped.
+pub struct Harness {
+    server: Arc<tiny_http::Server>,
+    url: String,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Harness {
+    /// Start the echo server on a free port of the loopback interface
+    pub fn start() -> std::io::Result<Self> {
+        let server = tiny_http::Server::http("127.0.0.1:0").map_err(std::io::Error::other)?;
+        let port = server
+            .server_addr()
+            .to_ip()
+            .map(|addr| addr.port())
+            .ok_or_else(|| std::io::Error::other("echo server has no IP address"))?;
+        let server = Arc::new(server);
+        let thread = {
+            let server = server.clone();
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    echo(request);
+                }
+            })
+        };
+        Ok(Self {
+            server,
+            url: format!("http://127.0.0.1:{}", port),
+            thread: Some(thread),
+        })
+    }
+
+    /// Base URL of the echo server
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The input of the `conformance` export
+    pub fn setup(&self) -> Setup {
+        Setup {
+            http_url: Some(self.url.clone()),
+            denied_url: Some(DENIED_URL.to_string()),
+        }
+    }
+
+    /// A manifest setting up this crate's host for the suite
+    pub fn manifest(&self, wasm: Wasm) -> Manifest {
+        Manifest::new([wasm])
+            .with_config_key(CONFIG_KEY, CONFIG_VALUE)
+            .with_allowed_host("127.0.0.1")
+    }
+
+    /// Run the suite through `call`, which calls a function of one instance
+    /// of the conformance plugin in the host under test
+    ///
+    /// The host must be set up with `CONFIG_KEY` and with HTTP requests
+    /// allowed to `url()`, as `manifest` does for this crate's host.
+    pub fn run(&self, call: &mut CallFn<'_>) -> Report {
+        let input = serde_json::to_vec(&self.setup()).unwrap_or_default();
+        let first = match call("conformance", &input)
+            .map(|output| serde_json::from_slice::<Report>(&output).map_err(|e| e.to_string()))
+        {
+            Ok(Ok(report)) => report,
+            Ok(Err(e)) => return failed(format!("invalid report: {}", e)),
+            Err(e) => return failed(format!("conformance failed: {}", e)),
+        };
+        let persist = match call("conformance", &input) {
+            Ok(output) => match serde_json::from_slice::<Report>(&output) {
+                Ok(second) if second.previous_calls == first.previous_calls + 1 => Ok(()),
+                Ok(second) => Err(format!(
+                    "the second call saw {} previous calls, expected {}",
+                    second.previous_calls,
+                    first.previous_calls + 1
+                )),
+                Err(e) => Err(format!("invalid report: {}", e)),
+            },
+            Err(e) => Err(format!("conformance failed on a second call: {}", e)),
+        };
+
+        let mut report = first;
+        report
+            .checks
+            .push(Check::new("vars", "var-persist", persist));
+        let payload: Vec<u8> = (0..100 * 1024).map(|i| (i % 251) as u8).collect();
+        let roundtrip = match call("conformance_echo", &payload) {
+            Ok(output) if output == payload => Ok(()),
+            Ok(output) => Err(format!(
+                "{} bytes of binary input came back as {} different bytes",
+                payload.len(),
+                output.len()
+            )),
+            Err(e) => Err(format!("conformance_echo failed: {}", e)),
+        };
+        report
+            .checks
+            .push(Check::new("io", "output-roundtrip", roundtrip));
+        let error = match call("conformance_error", b"") {
+            Ok(_) => Err("a function setting an error returned output".to_string()),
+            Err(e) if e.contains(ERROR_MESSAGE) => Ok(()),
+            Err(e) => Err(format!(
+                "the call failed with {:?}, which does not contain {:?}",
+                e, ERROR_MESSAGE
+            )),
+        };
+        report.checks.push(Check::new("errors", "error-set", error));
+        report
+    }
+
+    /// Run the suite against this crate's host, also checking that log
+    /// messages reach it at their level
+    pub fn run_plugin(&self, wasm: Wasm) -> Result<Report, crate::extism_pdk::host::Error> {
+        let logs: Arc<Mutex<Vec<(Level, String)>>> = Arc::default();
+        let sink = logs.clone();
+        let mut plugin = PluginBuilder::new(self.manifest(wasm))
+            .with_log_sink(move |level, message| {
+                if let Ok(mut logs) = sink.lock() {
+                    logs.push((level, message.to_string()));
+                }
+            })
+            .build()?;
+        let mut report = self
+            .run(&mut |function, input| plugin.call(function, input).map_err(|e| e.to_string()));
+        let logs = logs.lock().map(|logs| logs.clone()).unwrap_or_default();
+        let missing = [
+            (Level::DEBUG, "debug"),
+            (Level::INFO, "info"),
+            (Level::WARN, "warn"),
+            (Level::ERROR, "error"),
+        ]
+        .into_iter()
+        .filter(|(level, name)| {
+            let message = format!("conformance log {}", name);
+            !logs
+                .iter()
+                .any(|(logged, text)| logged == level && *text == message)
+        })
+        .map(|(_, name)| name)
+        .collect::<Vec<_>>();
+        let result = if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("no {} message arrived", missing.join(", ")))
+        };
+        report
+            .checks
+            .push(Check::new("logs", "logs-delivered", result));
+        Ok(report)
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A report of the whole suite failing before its checks could run
+fn failed(detail: String) -> Report {
+    Report {
+        checks: vec![Check::new("io", "conformance", Err(detail))],
+        previous_calls: 0,
+    }
+}
+
+/// Answer a request with what the server received
+fn echo(mut request: tiny_http::Request) {
+    let mut body = Vec::new();
+    let _ = request.as_reader().read_to_end(&mut body);
+    if let Some(status) = request
+        .url()
+        .strip_prefix("/status/")
+        .and_then(|status| status.parse::<u16>().ok())
+    {
+        let _ = request.respond(tiny_http::Response::empty(status));
+        return;
+    }
+    let echo = Echo {
+        method: request.method().as_str().to_string(),
+        path: request.url().to_string(),
+        headers: request
+            .headers()
+            .iter()
+            .map(|header| {
+                (
+                    header.field.as_str().as_str().to_ascii_lowercase(),
+                    header.value.as_str().to_string(),
+                )
+            })
+            .collect::<BTreeMap<_, _>>(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    };
+    let header = tiny_http::Header::from_bytes("X-Conformance-Echo", "1")
+        .expect("echo header is a valid header");
+    let response = tiny_http::Response::from_data(serde_json::to_vec(&echo).unwrap_or_default())
+        .with_header(header);
+    let _ = request.respond(response);
+}
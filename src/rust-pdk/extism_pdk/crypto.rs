@@ -0,0 +1,229 @@
+//! Hashing, HMAC, encryption and signature verification
+//!
+//! Enabled with the `crypto` feature. Everything is pure Rust, so it builds
+//! for every wasm target:
+//!
+//! ```ignore
+//! let signature = crypto::hex(&crypto::hmac_sha256(secret.as_bytes(), body));
+//! ```
+//!
+//! A `Hasher` digests data as it arrives, including the plugin input and
+//! host memory, which it reads in `CHUNK_SIZE` chunks instead of copying
+//! whole:
+//!
+//! ```ignore
+//! let mut hasher = Hasher::sha256();
+//! hasher.update_input();
+//! let digest = crypto::hex(&hasher.finalize());
+//! ```
+//!
+//! Secrets are compared with `ct_eq`, whose timing does not depend on where
+//! the inputs differ, and held in `Zeroizing` wrappers, which overwrite them
+//! when dropped. A `Zeroizing<Memory>` zeroes its host memory block before
+//! it is freed:
+//!
+//! ```ignore
+//! let token = Zeroizing::new(Host::config("token").unwrap_or_default());
+//! if !crypto::ct_eq(token.as_bytes(), presented.as_bytes()) {
+//!     return Err(Error::invalid("invalid token"));
+//! }
+//! let key = Zeroizing::new(Memory::from_string(&token));
+//! ```
+//!
+//! `seal` and `open` encrypt data a plugin keeps in vars or passes through
+//! parties it does not trust, under keys derived from a host secret:
+//!
+//! ```ignore
+//! let key = SealKey::from_config("state_secret", "session")?;
+//! Host::var_set("session", &crypto::seal(&key, &session, b"session"));
+//! let session = crypto::open(&key, &Host::var_get("session").unwrap_or_default(), b"session")?;
+//! ```
+//!
+//! `verify` checks Ed25519 and minisign signatures of payloads fetched from
+//! elsewhere.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha512};
+use subtle::ConstantTimeEq;
+pub use zeroize::{Zeroize, Zeroizing};
+
+use super::Memory;
+
+mod seal;
+pub mod verify;
+
+pub use seal::{open, seal, SealError, SealKey};
+pub use verify::{MinisignKey, VerificationError};
+
+/// How many bytes `Hasher` reads from memory at a time
+pub const CHUNK_SIZE: u64 = 64 * 1024;
+
+/// SHA-256 digest of `data`
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// SHA-512 digest of `data`
+pub fn sha512(data: &[u8]) -> [u8; 64] {
+    Sha512::digest(data).into()
+}
+
+/// HMAC-SHA256 of `data` under `key`
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// HMAC-SHA512 of `data` under `key`
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Check an HMAC-SHA256 tag in constant time
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+/// Check an HMAC-SHA512 tag in constant time
+pub fn verify_hmac_sha512(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+/// Check if two byte strings are equal in constant time
+///
+/// Only the lengths may leak: the time taken does not depend on the
+/// contents, so comparing a secret does not reveal how much of it a guess
+/// got right.
+pub fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Lowercase hex encoding of `bytes`
+pub fn hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push(DIGITS[(byte >> 4) as usize] as char);
+        hex.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+    hex
+}
+
+/// A digest or MAC computed over data fed to it piece by piece
+#[derive(Clone)]
+pub struct Hasher {
+    state: State,
+}
+
+#[derive(Clone)]
+enum State {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    HmacSha256(Hmac<Sha256>),
+    HmacSha512(Hmac<Sha512>),
+}
+
+impl Hasher {
+    /// A SHA-256 hasher
+    pub fn sha256() -> Self {
+        Self {
+            state: State::Sha256(Sha256::new()),
+        }
+    }
+
+    /// A SHA-512 hasher
+    pub fn sha512() -> Self {
+        Self {
+            state: State::Sha512(Sha512::new()),
+        }
+    }
+
+    /// An HMAC-SHA256 hasher keyed with `key`
+    pub fn hmac_sha256(key: &[u8]) -> Self {
+        Self {
+            state: State::HmacSha256(
+                Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            ),
+        }
+    }
+
+    /// An HMAC-SHA512 hasher keyed with `key`
+    pub fn hmac_sha512(key: &[u8]) -> Self {
+        Self {
+            state: State::HmacSha512(
+                Hmac::new_from_slice(key).expect("HMAC accepts keys of any length"),
+            ),
+        }
+    }
+
+    /// Feed bytes to the hasher
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            State::Sha256(hasher) => Digest::update(hasher, data),
+            State::Sha512(hasher) => Digest::update(hasher, data),
+            State::HmacSha256(mac) => mac.update(data),
+            State::HmacSha512(mac) => mac.update(data),
+        }
+    }
+
+    /// Feed the contents of a memory block to the hasher
+    ///
+    /// Chunks copied out of the block are zeroed once digested.
+    pub fn update_memory(&mut self, memory: &Memory) {
+        let len = memory.len();
+        let mut offset = 0;
+        while offset < len {
+            let chunk = CHUNK_SIZE.min(len - offset);
+            self.update(&Zeroizing::new(memory.load(offset, chunk)));
+            offset += chunk;
+        }
+    }
+
+    /// Feed the plugin input to the hasher
+    ///
+    /// The buffer the input is read into is zeroed once digested.
+    pub fn update_input(&mut self) {
+        let len = unsafe { super::extism_input_length() };
+        let mut buffer = Zeroizing::new(vec![0u8; CHUNK_SIZE.min(len) as usize]);
+        let mut offset = 0;
+        while offset < len {
+            let chunk = CHUNK_SIZE.min(len - offset);
+            unsafe {
+                super::extism_input_load_u8(offset, chunk, buffer.as_mut_ptr());
+            }
+            self.update(&buffer[..chunk as usize]);
+            offset += chunk;
+        }
+    }
+
+    /// The digest or MAC of everything fed to the hasher
+    pub fn finalize(self) -> Vec<u8> {
+        match self.state {
+            State::Sha256(hasher) => hasher.finalize().to_vec(),
+            State::Sha512(hasher) => hasher.finalize().to_vec(),
+            State::HmacSha256(mac) => mac.finalize().into_bytes().to_vec(),
+            State::HmacSha512(mac) => mac.finalize().into_bytes().to_vec(),
+        }
+    }
+}
+
+impl Zeroize for Memory {
+    /// Overwrite the memory block with zeros
+    fn zeroize(&mut self) {
+        let len = self.len();
+        let zeros = vec![0u8; CHUNK_SIZE.min(len) as usize];
+        let mut offset = 0;
+        while offset < len {
+            let chunk = CHUNK_SIZE.min(len - offset);
+            self.store(&zeros[..chunk as usize], offset);
+            offset += chunk;
+        }
+    }
+}
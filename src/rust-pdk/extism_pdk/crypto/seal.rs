@@ -0,0 +1,164 @@
+//! Authenticated encryption under keys derived from a host secret
+
+use std::fmt;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use super::super::Error;
+#[cfg(feature = "config")]
+use super::super::Host;
+
+/// Format version leading every sealed message
+const VERSION: u8 = 1;
+
+/// Length of the XChaCha20-Poly1305 nonce after the version
+const NONCE_LEN: usize = 24;
+
+/// Length of the Poly1305 tag ending every sealed message
+const TAG_LEN: usize = 16;
+
+/// HKDF info separating the encryption key from the nonce key
+const ENCRYPTION_INFO: &[u8] = b"extism_pdk::crypto::seal encryption\0";
+const NONCE_INFO: &[u8] = b"extism_pdk::crypto::seal nonce\0";
+
+/// Error deriving a seal key or opening a sealed message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SealError {
+    /// The config key holding the secret is not set
+    MissingSecret(String),
+    /// The message is truncated or of an unknown format version
+    Malformed,
+    /// The message was not sealed under this key and associated data, or
+    /// was altered
+    Forged,
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SealError::MissingSecret(key) => write!(f, "Secret config key {} is not set", key),
+            SealError::Malformed => write!(f, "Malformed sealed message"),
+            SealError::Forged => write!(f, "Sealed message failed authentication"),
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+impl From<SealError> for Error {
+    fn from(e: SealError) -> Self {
+        match e {
+            SealError::MissingSecret(_) => Error::fatal(e.to_string()),
+            _ => Error::invalid(e.to_string()),
+        }
+    }
+}
+
+/// Keys sealing and opening messages, derived from a secret
+///
+/// The keys are zeroed when dropped.
+pub struct SealKey {
+    encryption: Zeroizing<[u8; 32]>,
+    nonce: Zeroizing<[u8; 32]>,
+}
+
+impl SealKey {
+    /// Derive keys from `secret` with HKDF-SHA256
+    ///
+    /// `context` separates keys derived from the same secret for different
+    /// purposes, such as `"state"` and `"webhooks"`: a message sealed under
+    /// one context cannot be opened under another.
+    pub fn derive(secret: &[u8], context: &str) -> Self {
+        let hkdf = Hkdf::<Sha256>::new(None, secret);
+        let expand = |info: &[u8]| {
+            let mut key = Zeroizing::new([0u8; 32]);
+            hkdf.expand_multi_info(&[info, context.as_bytes()], key.as_mut())
+                .expect("32 bytes is a valid HKDF-SHA256 output length");
+            key
+        };
+        Self {
+            encryption: expand(ENCRYPTION_INFO),
+            nonce: expand(NONCE_INFO),
+        }
+    }
+
+    /// Derive keys from the secret in config key `key`
+    #[cfg(feature = "config")]
+    pub fn from_config(key: &str, context: &str) -> Result<Self, SealError> {
+        let secret = Host::config(key).ok_or_else(|| SealError::MissingSecret(key.to_string()))?;
+        let secret = Zeroizing::new(secret);
+        Ok(Self::derive(secret.as_bytes(), context))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(self.encryption.as_ref().into())
+    }
+
+    /// The nonce for a message, from a MAC of the message and its
+    /// associated data
+    fn nonce(&self, plaintext: &[u8], aad: &[u8]) -> [u8; NONCE_LEN] {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.nonce.as_ref())
+            .expect("HMAC accepts keys of any length");
+        mac.update(&(aad.len() as u64).to_be_bytes());
+        mac.update(aad);
+        mac.update(plaintext);
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&mac.finalize().into_bytes()[..NONCE_LEN]);
+        nonce
+    }
+}
+
+impl fmt::Debug for SealKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealKey").finish_non_exhaustive()
+    }
+}
+
+/// Encrypt and authenticate `plaintext` with XChaCha20-Poly1305
+///
+/// `aad` is authenticated but not encrypted, and must be given again to
+/// open the message; binding a var's name this way stops a sealed value
+/// from being replayed under another var. The nonce is derived from the
+/// message instead of drawn at random, which `wasm32-unknown-unknown`
+/// cannot do, so sealing the same plaintext and `aad` twice gives the same
+/// output.
+pub fn seal(key: &SealKey, plaintext: &[u8], aad: &[u8]) -> Vec<u8> {
+    let nonce = key.nonce(plaintext, aad);
+    let ciphertext = key
+        .cipher()
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("messages held in memory are within XChaCha20-Poly1305 limits");
+    let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+/// Decrypt a message sealed with `seal` under the same key and `aad`
+pub fn open(key: &SealKey, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, SealError> {
+    if sealed.len() < 1 + NONCE_LEN + TAG_LEN || sealed[0] != VERSION {
+        return Err(SealError::Malformed);
+    }
+    let (nonce, ciphertext) = sealed[1..].split_at(NONCE_LEN);
+    key.cipher()
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| SealError::Forged)
+}
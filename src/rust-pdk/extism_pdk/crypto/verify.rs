@@ -0,0 +1,197 @@
+//! Verifying signatures of downloaded payloads
+//!
+//! Plugins that fetch rules, models or updates check them against a public
+//! key they trust, usually from the host config, before using them:
+//!
+//! ```ignore
+//! let key = MinisignKey::parse(&Host::config("rules_key").unwrap_or_default())?;
+//! let rules = Host::http_request(&rules_request)?.body();
+//! let signature = String::from_utf8_lossy(&Host::http_request(&signature_request)?.body()).into_owned();
+//! verify::minisign(&rules, &signature, &key)?;
+//! ```
+//!
+//! `ed25519` checks raw Ed25519 signatures, such as those of the keys
+//! `signing::generate_key` creates; `minisign` checks the signature files
+//! written by `minisign -S`, legacy and pre-hashed alike.
+
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use super::super::Error;
+
+/// Prefix of the untrusted comment lines of minisign files
+const UNTRUSTED_COMMENT: &str = "untrusted comment:";
+
+/// Prefix of the trusted comment line of minisign signatures
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+/// A payload's signature could not be verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationError {
+    /// The public key is not a valid Ed25519 or minisign key
+    InvalidKey(String),
+    /// The signature is not a valid Ed25519 signature or minisign file
+    Malformed(String),
+    /// The signature was made with a key other than the one given
+    KeyMismatch { expected: String, actual: String },
+    /// The signature does not match the payload
+    BadSignature,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationError::InvalidKey(e) => write!(f, "Invalid public key: {}", e),
+            VerificationError::Malformed(e) => write!(f, "Malformed signature: {}", e),
+            VerificationError::KeyMismatch { expected, actual } => write!(
+                f,
+                "Signed with key {} instead of the trusted key {}",
+                actual, expected
+            ),
+            VerificationError::BadSignature => write!(f, "Signature does not match the payload"),
+        }
+    }
+}
+
+impl std::error::Error for VerificationError {}
+
+impl From<VerificationError> for Error {
+    fn from(e: VerificationError) -> Self {
+        match e {
+            VerificationError::InvalidKey(_) => Error::fatal(e.to_string()),
+            _ => Error::invalid(e.to_string()),
+        }
+    }
+}
+
+/// Verify an Ed25519 `signature` of `payload` under a 32 byte public key
+///
+/// Signatures are checked strictly, rejecting the malleable and weak-key
+/// forms plain Ed25519 verification lets through.
+pub fn ed25519(
+    payload: &[u8],
+    signature: &[u8],
+    public_key: &[u8],
+) -> Result<(), VerificationError> {
+    let key: [u8; 32] = public_key.try_into().map_err(|_| {
+        VerificationError::InvalidKey(format!("expected 32 bytes, got {}", public_key.len()))
+    })?;
+    let key =
+        VerifyingKey::from_bytes(&key).map_err(|e| VerificationError::InvalidKey(e.to_string()))?;
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| VerificationError::Malformed(e.to_string()))?;
+    key.verify_strict(payload, &signature)
+        .map_err(|_| VerificationError::BadSignature)
+}
+
+/// A minisign public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinisignKey {
+    id: [u8; 8],
+    key: VerifyingKey,
+}
+
+impl MinisignKey {
+    /// Parse a public key, as the contents of a `minisign.pub` file or its
+    /// base64 line alone
+    pub fn parse(text: &str) -> Result<Self, VerificationError> {
+        let line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_COMMENT))
+            .ok_or_else(|| VerificationError::InvalidKey("no key found".to_string()))?;
+        let bytes = STANDARD
+            .decode(line)
+            .map_err(|e| VerificationError::InvalidKey(e.to_string()))?;
+        if bytes.len() != 42 || &bytes[..2] != b"Ed" {
+            return Err(VerificationError::InvalidKey(
+                "not a minisign Ed25519 public key".to_string(),
+            ));
+        }
+        let key: [u8; 32] = bytes[10..].try_into().expect("key is 32 bytes");
+        Ok(Self {
+            id: bytes[2..10].try_into().expect("key id is 8 bytes"),
+            key: VerifyingKey::from_bytes(&key)
+                .map_err(|e| VerificationError::InvalidKey(e.to_string()))?,
+        })
+    }
+
+    /// The key id, in the uppercase hex minisign displays
+    pub fn id(&self) -> String {
+        key_id(&self.id)
+    }
+}
+
+/// Verify a minisign `signature` file of `payload`, returning its trusted
+/// comment
+///
+/// Both the signature of the payload and the global signature covering the
+/// trusted comment are checked, so the returned comment, often holding a
+/// timestamp and file name, is as authentic as the payload.
+pub fn minisign(
+    payload: &[u8],
+    signature: &str,
+    key: &MinisignKey,
+) -> Result<String, VerificationError> {
+    let malformed = |message: &str| VerificationError::Malformed(message.to_string());
+    let mut lines = signature
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty() && !line.starts_with(UNTRUSTED_COMMENT));
+    let signature_line = lines
+        .next()
+        .ok_or_else(|| malformed("no signature found"))?;
+    let comment = lines
+        .next()
+        .and_then(|line| line.strip_prefix(TRUSTED_COMMENT))
+        .ok_or_else(|| malformed("no trusted comment found"))?;
+    let global_line = lines
+        .next()
+        .ok_or_else(|| malformed("no global signature found"))?;
+
+    let bytes = STANDARD
+        .decode(signature_line)
+        .map_err(|e| VerificationError::Malformed(e.to_string()))?;
+    if bytes.len() != 74 {
+        return Err(malformed("not a minisign Ed25519 signature"));
+    }
+    let (algorithm, id, signature) = (&bytes[..2], &bytes[2..10], &bytes[10..]);
+    if id != key.id {
+        return Err(VerificationError::KeyMismatch {
+            expected: key.id(),
+            actual: key_id(id),
+        });
+    }
+    let signed = match algorithm {
+        b"Ed" => payload.to_vec(),
+        b"ED" => Blake2b512::digest(payload).to_vec(),
+        _ => return Err(malformed("unknown signature algorithm")),
+    };
+    let signature = Signature::from_slice(signature)
+        .map_err(|e| VerificationError::Malformed(e.to_string()))?;
+    key.key
+        .verify_strict(&signed, &signature)
+        .map_err(|_| VerificationError::BadSignature)?;
+
+    let global = STANDARD
+        .decode(global_line)
+        .map_err(|e| VerificationError::Malformed(e.to_string()))?;
+    let global =
+        Signature::from_slice(&global).map_err(|e| VerificationError::Malformed(e.to_string()))?;
+    let mut signed_comment = signature.to_bytes().to_vec();
+    signed_comment.extend_from_slice(comment.as_bytes());
+    key.key
+        .verify_strict(&signed_comment, &global)
+        .map_err(|_| VerificationError::BadSignature)?;
+    Ok(comment.to_string())
+}
+
+/// A minisign key id as minisign displays it, in uppercase hex of the
+/// little-endian integer
+fn key_id(id: &[u8]) -> String {
+    id.iter().rev().map(|b| format!("{:02X}", b)).collect()
+}
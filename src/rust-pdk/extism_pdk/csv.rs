@@ -0,0 +1,57 @@
+//! CSV input and output
+//!
+//! Enabled with the `csv` feature. `reader` parses the plugin input as it
+//! is read, and `writer` writes records to the plugin output, so ETL
+//! plugins use the `csv` crate without wiring up readers and writers:
+//!
+//! ```ignore
+//! #[no_mangle]
+//! pub extern "C" fn normalize() -> i32 {
+//!     let result = plugin::catch(|| {
+//!         let mut writer = csv::writer();
+//!         for row in csv::reader().deserialize::<Row>() {
+//!             writer.serialize(normalize_row(row?))?;
+//!         }
+//!         writer.flush().map_err(|e| Error::fatal(e.to_string()))?;
+//!         Ok(())
+//!     });
+//!     // ...
+//! }
+//! ```
+//!
+//! `export_plugin!` sets the output to the JSON of the value a function
+//! returns, replacing what a writer wrote, so functions writing CSV are
+//! exported without it.
+
+use super::io::{InputReader, OutputWriter};
+
+pub use ::csv::{ReaderBuilder, StringRecord, WriterBuilder};
+
+/// A CSV reader over the plugin input
+pub type Reader = ::csv::Reader<InputReader>;
+
+/// A CSV writer to the plugin output
+///
+/// Records are set as the output when the writer is flushed or dropped.
+pub type Writer = ::csv::Writer<OutputWriter>;
+
+/// Read the plugin input as CSV with a header row
+pub fn reader() -> Reader {
+    reader_with(&ReaderBuilder::new())
+}
+
+/// Read the plugin input as CSV configured by `builder`, such as with
+/// another delimiter or without a header row
+pub fn reader_with(builder: &ReaderBuilder) -> Reader {
+    builder.from_reader(InputReader::new())
+}
+
+/// Write CSV to the plugin output, with a header row for serialized structs
+pub fn writer() -> Writer {
+    writer_with(&WriterBuilder::new())
+}
+
+/// Write CSV to the plugin output configured by `builder`
+pub fn writer_with(builder: &WriterBuilder) -> Writer {
+    builder.from_writer(OutputWriter::new())
+}
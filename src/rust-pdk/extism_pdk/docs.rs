@@ -0,0 +1,326 @@
+//! Markdown documentation of plugin interfaces
+//!
+//! `markdown` renders a plugin's metadata as a page to publish with it: a
+//! summary, the capabilities and config keys its host must grant, its
+//! dependencies, and each exported function with the fields of its input
+//! and output and example payloads built from their JSON Schemas:
+//!
+//! ```ignore
+//! std::fs::write("API.md", docs::markdown(&metadata))?;
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use serde_json::{json, Map, Value};
+
+use super::capability::Capability;
+use super::registry::{PluginMetadata, Target};
+use super::schema::FunctionSchema;
+
+/// Render the documentation of a plugin
+pub fn markdown(metadata: &PluginMetadata) -> String {
+    let mut out = format!("# {}\n", metadata.name);
+    if let Some(description) = &metadata.description {
+        let _ = write!(out, "\n{}\n", description);
+    }
+
+    let mut summary = Vec::new();
+    if !metadata.version.is_empty() {
+        summary.push(("Version", metadata.version.clone()));
+    }
+    if let Some(license) = &metadata.license {
+        summary.push(("License", license.clone()));
+    }
+    if !metadata.authors.is_empty() {
+        summary.push(("Authors", metadata.authors.join(", ")));
+    }
+    if metadata.target != Target::default() {
+        summary.push(("Target", format!("`{}`", metadata.target)));
+    }
+    if !summary.is_empty() {
+        out.push_str("\n| | |\n|---|---|\n");
+        for (name, value) in summary {
+            let _ = writeln!(out, "| {} | {} |", name, cell(&value));
+        }
+    }
+
+    if !metadata.capabilities.is_empty() {
+        out.push_str("\n## Capabilities\n\nThe host's manifest must grant:\n\n");
+        for capability in &metadata.capabilities {
+            let _ = writeln!(out, "- `{}`: {}", capability, grant(capability));
+        }
+    }
+
+    if !metadata.config.is_empty() {
+        out.push_str(
+            "\n## Configuration\n\nThe host must set these config keys:\n\n\
+             | Key | Description |\n|---|---|\n",
+        );
+        for (key, description) in &metadata.config {
+            let _ = writeln!(out, "| `{}` | {} |", key, cell(description));
+        }
+    }
+
+    if !metadata.dependencies.is_empty() || !metadata.host.is_empty() {
+        out.push_str("\n## Dependencies\n\n| Plugin | Version |\n|---|---|\n");
+        for (name, req) in &metadata.dependencies {
+            let _ = writeln!(out, "| `{}` | `{}` |", name, req);
+        }
+        for (name, req) in &metadata.host {
+            let _ = writeln!(out, "| host `{}` | `{}` |", name, req);
+        }
+    }
+
+    // Exports without schemas are listed too, in the order declared
+    let mut names: Vec<&str> = metadata.exports.iter().map(String::as_str).collect();
+    for schema in &metadata.schemas {
+        if !names.contains(&schema.name.as_str()) {
+            names.push(&schema.name);
+        }
+    }
+    if names.is_empty() {
+        return out;
+    }
+    out.push_str("\n## Functions\n");
+    let mut types = BTreeMap::new();
+    for name in names {
+        let _ = write!(out, "\n### `{}`\n", name);
+        match metadata.schemas.iter().find(|schema| schema.name == name) {
+            Some(schema) => function(&mut out, schema, &mut types),
+            None => out.push_str("\nNo schema is declared for this function.\n"),
+        }
+    }
+
+    if !types.is_empty() {
+        out.push_str("\n## Types\n");
+        for (name, schema) in &types {
+            let _ = write!(out, "\n### {}\n", name);
+            if let Some(description) = schema["description"].as_str() {
+                let _ = write!(out, "\n{}\n", description);
+            }
+            out.push('\n');
+            fields(&mut out, schema);
+        }
+    }
+    out
+}
+
+/// Render a function's input and output, collecting the types they refer to
+fn function(out: &mut String, schema: &FunctionSchema, types: &mut BTreeMap<String, Value>) {
+    for (title, root) in [("Input", &schema.input), ("Output", &schema.output)] {
+        let _ = write!(out, "\n#### {}\n\n", title);
+        let defs = root
+            .get("$defs")
+            .or_else(|| root.get("definitions"))
+            .and_then(Value::as_object);
+        for (name, def) in defs.into_iter().flatten() {
+            types.entry(name.clone()).or_insert_with(|| def.clone());
+        }
+        if root["type"] == "null" {
+            out.push_str("None.\n");
+            continue;
+        }
+        // An input or output naming a type is shown with the type's fields
+        let shown = root["$ref"]
+            .as_str()
+            .and_then(|reference| reference.strip_prefix('#'))
+            .and_then(|pointer| root.pointer(pointer))
+            .filter(|def| def.get("properties").is_some())
+            .unwrap_or(root);
+        if let Some(description) = shown["description"].as_str() {
+            let _ = write!(out, "{}\n\n", description);
+        }
+        if shown.get("properties").is_some() {
+            fields(out, shown);
+        } else {
+            let _ = writeln!(out, "{}", type_name(root));
+        }
+        let example = example(root, root, &mut BTreeSet::new());
+        let _ = write!(
+            out,
+            "\nExample:\n\n```json\n{}\n```\n",
+            serde_json::to_string_pretty(&example).unwrap_or_default()
+        );
+    }
+}
+
+/// Render the properties of an object schema as a table
+fn fields(out: &mut String, schema: &Value) {
+    let properties = schema["properties"].as_object();
+    let Some(properties) = properties.filter(|properties| !properties.is_empty()) else {
+        let _ = writeln!(out, "{}", type_name(schema));
+        return;
+    };
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    out.push_str("| Field | Type | Required | Description |\n|---|---|---|---|\n");
+    for (name, property) in properties {
+        let _ = writeln!(
+            out,
+            "| `{}` | {} | {} | {} |",
+            name,
+            cell(&type_name(property)),
+            if required.contains(&name.as_str()) {
+                "yes"
+            } else {
+                "no"
+            },
+            cell(property["description"].as_str().unwrap_or_default())
+        );
+    }
+}
+
+/// A short name of the type a schema describes, linking to named types
+fn type_name(schema: &Value) -> String {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        return format!("[{}](#{})", name, name.to_ascii_lowercase());
+    }
+    if let Some(value) = schema.get("const") {
+        return format!("`{}`", value);
+    }
+    if let Some(values) = schema["enum"].as_array() {
+        let values: Vec<String> = values.iter().map(|value| format!("`{}`", value)).collect();
+        return values.join(" | ");
+    }
+    for key in ["oneOf", "anyOf"] {
+        if let Some(schemas) = schema[key].as_array() {
+            let names: Vec<String> = schemas.iter().map(type_name).collect();
+            return names.join(" | ");
+        }
+    }
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(ty) => vec![ty.as_str()],
+        Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+        _ => return "any".to_string(),
+    };
+    let names: Vec<String> = types
+        .into_iter()
+        .map(|ty| match ty {
+            "array" => match schema.get("items").map(type_name) {
+                Some(items) if items.contains(" | ") => format!("({})[]", items),
+                Some(items) => format!("{}[]", items),
+                None => "array".to_string(),
+            },
+            "object" => match (
+                schema["properties"].as_object(),
+                schema.get("additionalProperties"),
+            ) {
+                (Some(properties), _) if !properties.is_empty() => {
+                    let required = &schema["required"];
+                    let fields: Vec<String> = properties
+                        .iter()
+                        .map(|(name, property)| {
+                            let optional = required.as_array().is_some_and(|required| {
+                                !required.contains(&Value::from(name.as_str()))
+                            });
+                            let mark = if optional { "?" } else { "" };
+                            format!("{}{}: {}", name, mark, type_name(property))
+                        })
+                        .collect();
+                    format!("{{ {} }}", fields.join(", "))
+                }
+                (_, Some(values)) if values.is_object() => format!("map of {}", type_name(values)),
+                _ => "object".to_string(),
+            },
+            "string" => match schema["format"].as_str() {
+                Some(format) => format!("string ({})", format),
+                None => "string".to_string(),
+            },
+            _ => ty.to_string(),
+        })
+        .collect();
+    names.join(" | ")
+}
+
+/// An example value of a schema, using the examples, defaults and
+/// constants it gives, and placeholders otherwise
+///
+/// `root` holds the definitions `$ref`s point to, and `seen` the ones being
+/// expanded, so that recursive types end.
+fn example(schema: &Value, root: &Value, seen: &mut BTreeSet<String>) -> Value {
+    if let Some(value) = schema["examples"].get(0).or(schema.get("default")) {
+        return value.clone();
+    }
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    if let Some(value) = schema["enum"].get(0) {
+        return value.clone();
+    }
+    if let Some(reference) = schema["$ref"].as_str() {
+        let Some(def) = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+        else {
+            return Value::Null;
+        };
+        if !seen.insert(reference.to_string()) {
+            return Value::Null;
+        }
+        let value = example(def, root, seen);
+        seen.remove(reference);
+        return value;
+    }
+    for key in ["oneOf", "anyOf", "allOf"] {
+        if let Some(first) = schema[key].get(0) {
+            return example(first, root, seen);
+        }
+    }
+    let ty = match &schema["type"] {
+        Value::String(ty) => ty.as_str(),
+        Value::Array(types) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|ty| *ty != "null")
+            .unwrap_or("null"),
+        _ if schema.get("properties").is_some() => "object",
+        _ => return Value::Null,
+    };
+    match ty {
+        "object" => {
+            let mut object = Map::new();
+            for (name, property) in schema["properties"].as_object().into_iter().flatten() {
+                object.insert(name.clone(), example(property, root, seen));
+            }
+            Value::Object(object)
+        }
+        "array" => match schema.get("items") {
+            Some(items) => json!([example(items, root, seen)]),
+            None => json!([]),
+        },
+        "string" => Value::from(match schema["format"].as_str() {
+            Some("date-time") => "2024-01-01T00:00:00Z",
+            Some("date") => "2024-01-01",
+            Some("uri") => "https://example.com",
+            Some("email") => "user@example.com",
+            Some("uuid") => "00000000-0000-0000-0000-000000000000",
+            _ => "string",
+        }),
+        "integer" => json!(schema["minimum"].as_i64().unwrap_or(0)),
+        "number" => json!(schema["minimum"].as_f64().unwrap_or(0.0)),
+        "boolean" => json!(false),
+        _ => Value::Null,
+    }
+}
+
+/// What granting a capability takes in the host's manifest
+fn grant(capability: &Capability) -> String {
+    match capability {
+        Capability::Http => "HTTP requests to the hosts listed in `allowed_hosts`".to_string(),
+        Capability::Wasi => "WASI, with `wasi` enabled".to_string(),
+        Capability::WasiFs => "files through WASI, with `wasi` and `allowed_paths`".to_string(),
+        Capability::Kv => "plugin vars, which every host provides".to_string(),
+        Capability::Function(name) => format!("host function `{}`, in a linked module", name),
+    }
+}
+
+/// Escape text for a table cell
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
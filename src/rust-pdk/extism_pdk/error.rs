@@ -0,0 +1,172 @@
+//! Error type returned from plugin functions
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Classification of a plugin error
+///
+/// Hosts can use the kind to decide whether re-invoking the call is worthwhile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// A transient failure; the same call may succeed if retried
+    Retryable,
+    /// A failure that will not go away by retrying
+    Fatal,
+    /// The input was rejected; retrying with the same input will fail again
+    Invalid,
+}
+
+/// Error returned from a plugin function
+///
+/// Exported functions report it to the host as a JSON envelope of the
+/// form `{"kind": "retryable", "message": "..."}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Error {
+    /// The error classification
+    pub kind: ErrorKind,
+    /// A human readable error message
+    pub message: String,
+}
+
+impl Error {
+    /// Create a new error
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Create a retryable error
+    pub fn retryable(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Retryable, message)
+    }
+
+    /// Create a fatal error
+    pub fn fatal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Fatal, message)
+    }
+
+    /// Create an invalid input error
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Invalid, message)
+    }
+
+    /// Check if the call may succeed when retried
+    pub fn is_retryable(&self) -> bool {
+        self.kind == ErrorKind::Retryable
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::fatal(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self::fatal(message)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    /// JSON errors are treated as invalid input, since they almost always come from parsing it
+    fn from(err: serde_json::Error) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+impl From<super::binary::DecodeError> for Error {
+    /// Undecodable base64 or hex is treated as invalid input
+    fn from(err: super::binary::DecodeError) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "flatbuffers")]
+impl From<super::flatbuffers::InvalidFlatbuffer> for Error {
+    /// Buffers failing verification are treated as invalid input
+    fn from(err: super::flatbuffers::InvalidFlatbuffer) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<serde_yaml::Error> for Error {
+    /// YAML errors are treated as invalid input, like JSON errors
+    fn from(err: serde_yaml::Error) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(all(
+    feature = "bson",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl From<super::bson::BsonError> for Error {
+    /// BSON errors are treated as invalid input, like JSON errors
+    fn from(err: super::bson::BsonError) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "prost")]
+impl From<prost::DecodeError> for Error {
+    /// Protobuf decoding errors are treated as invalid input, like JSON errors
+    fn from(err: prost::DecodeError) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<::csv::Error> for Error {
+    /// CSV errors are treated as invalid input, since they almost always
+    /// come from parsing it
+    fn from(err: ::csv::Error) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "xml")]
+impl From<quick_xml::DeError> for Error {
+    /// XML errors are treated as invalid input, like JSON errors
+    fn from(err: quick_xml::DeError) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "xml")]
+impl From<quick_xml::Error> for Error {
+    /// XML parsing errors are treated as invalid input, like JSON errors
+    fn from(err: quick_xml::Error) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
+
+#[cfg(feature = "xml")]
+impl From<quick_xml::SeError> for Error {
+    /// Failing to serialize output is a fault of the plugin, not its input
+    fn from(err: quick_xml::SeError) -> Self {
+        Self::fatal(err.to_string())
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl From<super::arrow::ArrowError> for Error {
+    /// Arrow errors are treated as invalid input, since they almost always
+    /// come from reading it
+    fn from(err: super::arrow::ArrowError) -> Self {
+        Self::invalid(err.to_string())
+    }
+}
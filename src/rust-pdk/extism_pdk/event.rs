@@ -0,0 +1,56 @@
+//! Events published by the host to subscribed plugins
+//!
+//! A plugin subscribes by exporting `subscriptions`, returning the topic
+//! patterns it wants, and receives matching events as JSON input to its
+//! `on_event` export:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn subscriptions() -> Vec<String> {
+//!         Ok(vec!["order.*".to_string()])
+//!     }
+//!
+//!     fn on_event() -> () {
+//!         let event: Event = Host::input_json()?;
+//!         Host::log_info(&format!("got {}", event.topic));
+//!         Ok(())
+//!     }
+//! }
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Export returning the topic patterns a plugin subscribes to
+pub const SUBSCRIPTIONS_EXPORT: &str = "subscriptions";
+
+/// Export called with each event a plugin is subscribed to
+pub const ON_EVENT_EXPORT: &str = "on_event";
+
+/// An event delivered to subscribed plugins
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// The event topic, e.g. `order.created`
+    pub topic: String,
+    /// The event payload
+    #[serde(default)]
+    pub payload: serde_json::Value,
+}
+
+impl Event {
+    /// Create an event with a JSON payload
+    pub fn new(
+        topic: impl Into<String>,
+        payload: &impl Serialize,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            topic: topic.into(),
+            payload: serde_json::to_value(payload)?,
+        })
+    }
+
+    /// Parse the payload
+    pub fn payload<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        T::deserialize(&self.payload)
+    }
+}
@@ -0,0 +1,82 @@
+//! Zero-copy FlatBuffers input
+//!
+//! With the `flatbuffers` feature, latency-critical plugins read tables
+//! generated by `flatc --rust` in place, without deserializing them.
+//! `Host::input_flatbuffer` loads the input once; `Buffer::root` verifies it
+//! and returns the root table, borrowing from the buffer:
+//!
+//! ```ignore
+//! let input = Host::input_flatbuffer();
+//! let order = input.root::<Order>()?;
+//! let total: f64 = order.lines().iter().flatten().map(|line| line.price()).sum();
+//! ```
+//!
+//! Verification checks every offset the table's accessors follow, so a
+//! verified buffer is safe to read however it was produced. It costs a pass
+//! over the buffer, which is much less than decoding it.
+
+use ::flatbuffers::{Follow, Verifiable};
+
+pub use ::flatbuffers::{FlatBufferBuilder, InvalidFlatbuffer, VerifierOptions};
+
+/// A FlatBuffers buffer loaded into plugin memory
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Buffer {
+    data: Vec<u8>,
+}
+
+impl Buffer {
+    /// The plugin input
+    pub fn input() -> Self {
+        Self::new(super::Host::input())
+    }
+
+    /// A buffer over bytes already loaded, such as a var or `Memory` block
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    /// Verify the buffer and access its root table of type `T`
+    pub fn root<'a, T: 'a + Follow<'a> + Verifiable>(
+        &'a self,
+    ) -> Result<T::Inner, InvalidFlatbuffer> {
+        ::flatbuffers::root::<T>(&self.data)
+    }
+
+    /// Verify the buffer within `opts`, for buffers deeper, larger or with
+    /// more tables than the defaults allow
+    pub fn root_with_opts<'a, T: 'a + Follow<'a> + Verifiable>(
+        &'a self,
+        opts: &VerifierOptions,
+    ) -> Result<T::Inner, InvalidFlatbuffer> {
+        ::flatbuffers::root_with_opts::<T>(opts, &self.data)
+    }
+
+    /// Verify a buffer prefixed with its size and access its root table
+    pub fn size_prefixed_root<'a, T: 'a + Follow<'a> + Verifiable>(
+        &'a self,
+    ) -> Result<T::Inner, InvalidFlatbuffer> {
+        ::flatbuffers::size_prefixed_root::<T>(&self.data)
+    }
+
+    /// Whether the buffer carries the four-character file identifier of a
+    /// schema, for inputs that may be one of several schemas
+    ///
+    /// Panics if `identifier` is not four bytes long.
+    pub fn has_identifier(&self, identifier: &str) -> bool {
+        // The root offset, then the identifier
+        self.data.len() >= 8
+            && ::flatbuffers::buffer_has_identifier(&self.data, identifier, false)
+    }
+
+    /// The bytes of the buffer
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<Vec<u8>> for Buffer {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(data)
+    }
+}
@@ -0,0 +1,201 @@
+//! Input size guards and per-call resource budgets
+//!
+//! `max_input_bytes` rejects an oversized input from its length alone,
+//! before any of it is copied into plugin memory, and a `Budget` limits
+//! the host calls one invocation may make:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn fetch_all() -> Vec<i32> {
+//!         guard::max_input_bytes(64 * 1024)?;
+//!         let _budget = Budget::new().with_max_http_calls(5).enter();
+//!         let urls: Vec<String> = Host::input_json()?;
+//!         urls.into_iter()
+//!             .map(|url| {
+//!                 let request = HttpRequest {
+//!                     method: HttpMethod::Get,
+//!                     url,
+//!                     headers: Vec::new(),
+//!                     body: None,
+//!                     ..Default::default()
+//!                 };
+//!                 Ok(Host::http_request(&request)?.status())
+//!             })
+//!             .collect()
+//!     }
+//! }
+//! ```
+//!
+//! A request over the HTTP budget fails with an `ErrorKind::Invalid` error.
+//! A `Host::var_set` over the var budget is not written; the call then
+//! fails with the same kind of error once the function returns, even if
+//! the function went on to succeed.
+
+use std::cell::Cell;
+use std::fmt;
+
+use super::Error;
+
+/// A limit the plugin set for itself was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardError {
+    /// The input is larger than `max_input_bytes` allows
+    InputTooLarge { size: u64, max: u64 },
+    /// More HTTP requests than the budget allows
+    HttpBudgetExceeded { max: u32 },
+    /// More var writes than the budget allows
+    VarBudgetExceeded { max: u32 },
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::InputTooLarge { size, max } => write!(
+                f,
+                "Input of {} bytes exceeds the limit of {} bytes",
+                size, max
+            ),
+            GuardError::HttpBudgetExceeded { max } => {
+                write!(f, "HTTP request budget of {} calls exceeded", max)
+            }
+            GuardError::VarBudgetExceeded { max } => {
+                write!(f, "Var write budget of {} writes exceeded", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+impl From<GuardError> for Error {
+    fn from(e: GuardError) -> Self {
+        Error::invalid(e.to_string())
+    }
+}
+
+/// Fail if the input is larger than `max` bytes
+///
+/// Only the input's length is read, so an oversized input is never
+/// allocated in plugin memory.
+pub fn max_input_bytes(max: u64) -> Result<(), GuardError> {
+    let size = unsafe { super::extism_input_length() };
+    if size > max {
+        return Err(GuardError::InputTooLarge { size, max });
+    }
+    Ok(())
+}
+
+/// Limits on the host calls of one invocation
+///
+/// Limits left unset are unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    max_http_calls: Option<u32>,
+    max_var_writes: Option<u32>,
+}
+
+impl Budget {
+    /// A budget without limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow at most `max` HTTP requests
+    pub fn with_max_http_calls(mut self, max: u32) -> Self {
+        self.max_http_calls = Some(max);
+        self
+    }
+
+    /// Allow at most `max` calls to `Host::var_set`
+    pub fn with_max_var_writes(mut self, max: u32) -> Self {
+        self.max_var_writes = Some(max);
+        self
+    }
+
+    /// Enforce the budget until the returned guard is dropped
+    ///
+    /// Entering a budget replaces the one in force, and starts counting
+    /// from zero.
+    pub fn enter(self) -> BudgetGuard {
+        let previous = STATE.with(|state| {
+            state.replace(Some(Usage {
+                budget: self,
+                http_calls: 0,
+                var_writes: 0,
+            }))
+        });
+        BudgetGuard { previous }
+    }
+}
+
+/// Keeps a `Budget` in force, restoring the previous one when dropped
+#[must_use = "the budget is lifted as soon as the guard is dropped"]
+pub struct BudgetGuard {
+    previous: Option<Usage>,
+}
+
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        STATE.with(|state| state.set(self.previous.take()));
+    }
+}
+
+#[cfg_attr(not(all(feature = "http", feature = "vars")), allow(dead_code))]
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    budget: Budget,
+    http_calls: u32,
+    var_writes: u32,
+}
+
+thread_local! {
+    static STATE: Cell<Option<Usage>> = const { Cell::new(None) };
+    static VIOLATION: Cell<Option<GuardError>> = const { Cell::new(None) };
+}
+
+/// Count an HTTP request against the budget in force
+#[cfg(feature = "http")]
+pub(crate) fn charge_http() -> Result<(), GuardError> {
+    charge(|usage| (&mut usage.http_calls, usage.budget.max_http_calls))
+        .map_err(|max| GuardError::HttpBudgetExceeded { max })
+}
+
+/// Count a var write against the budget in force
+///
+/// Returns `false` if the write is over budget, which fails the call once
+/// the exported function returns.
+#[cfg(feature = "vars")]
+pub(crate) fn charge_var_write() -> bool {
+    match charge(|usage| (&mut usage.var_writes, usage.budget.max_var_writes)) {
+        Ok(()) => true,
+        Err(max) => {
+            let violation = VIOLATION.with(Cell::get);
+            if violation.is_none() {
+                VIOLATION.with(|v| v.set(Some(GuardError::VarBudgetExceeded { max })));
+            }
+            false
+        }
+    }
+}
+
+/// Take the budget violation recorded during the current call, if any
+pub(crate) fn take_violation() -> Option<GuardError> {
+    VIOLATION.with(Cell::take)
+}
+
+/// Increment a counter, or return its limit if that would exceed it
+#[cfg(any(feature = "http", feature = "vars"))]
+fn charge(counter: impl FnOnce(&mut Usage) -> (&mut u32, Option<u32>)) -> Result<(), u32> {
+    STATE.with(|state| {
+        let Some(mut usage) = state.get() else {
+            return Ok(());
+        };
+        let (count, max) = counter(&mut usage);
+        if let Some(max) = max.filter(|max| *count >= *max) {
+            return Err(max);
+        }
+        *count += 1;
+        state.set(Some(usage));
+        Ok(())
+    })
+}
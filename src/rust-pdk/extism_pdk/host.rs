@@ -0,0 +1,785 @@
+//! Host-side runtime for loading and calling plugins
+//!
+//! This implements the kernel functions imported by the PDK on top of
+//! wasmtime, so the same crate can be used to write a plugin and to run it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use wasmtime::{
+    CallHook, Config, Engine, Instance, Linker, Module, Store, Trap, UpdateDeadline,
+    WasmBacktraceDetails,
+};
+
+use super::audit::{AuditError, AuditRecord};
+use super::capability::Capability;
+use super::provenance::ProvenanceError;
+use super::schema::{PluginSchema, DESCRIBE_EXPORT};
+use super::signing::SignatureError;
+use super::trace::Trace;
+use super::Error as PluginError;
+
+mod audit;
+mod cache;
+mod events;
+mod group;
+mod http;
+mod kernel;
+mod lazy;
+mod limits;
+mod log;
+mod manifest;
+mod oci;
+mod options;
+mod pipeline;
+mod pool;
+mod snapshot;
+mod stream;
+mod task;
+mod trap;
+mod wasi;
+
+use audit::AuditSink;
+pub(crate) use cache::sha256_hex;
+pub use cache::ModuleCache;
+pub use events::{Delivery, EventBus};
+pub use group::{GroupLimits, PluginGroup};
+use http::HttpClient;
+use kernel::State;
+pub use lazy::LazyPlugin;
+use limits::MemoryBudget;
+use log::LogSink;
+pub use manifest::{download_cache_dir, Manifest, MemoryOptions, Wasm, WasmMetadata};
+pub use oci::OciAuth;
+pub use options::{CallOptions, CallOutput, CallStats, CancelHandle, ResourceUsage};
+pub use pipeline::{Context, ErrorPolicy, Pipeline, PipelineError};
+pub use pool::{PluginPool, PoolStats, PooledPlugin, RestartPolicy, DEFAULT_RESTART_COOLDOWN};
+pub use tracing::Level;
+pub use trap::TrapFrame;
+
+/// Error loading a plugin
+#[derive(Debug)]
+pub enum Error {
+    /// The wasm file could not be read
+    Io(std::io::Error),
+    /// The wasm module could not be downloaded
+    Fetch(String),
+    /// The manifest is invalid
+    Manifest(String),
+    /// The wasm module could not be compiled or instantiated
+    Wasm(wasmtime::Error),
+    /// The plugin needs more memory than the manifest allows
+    MemoryLimitExceeded,
+    /// A snapshot could not be restored
+    Snapshot(String),
+    /// A pool stopped replacing poisoned instances, as its restart policy allows no more restarts
+    RestartLimitExceeded,
+    /// The wasm module's signature is missing or not trusted
+    Signature(SignatureError),
+    /// The wasm module's provenance is missing or not trusted
+    Provenance(ProvenanceError),
+    /// The wasm modules declare capabilities the manifest does not grant
+    MissingCapabilities(Vec<Capability>),
+    /// The wasm module does not match its pinned hash
+    HashMismatch {
+        /// The hash from the manifest
+        expected: String,
+        /// The hash of the loaded module
+        actual: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Failed to read plugin: {}", e),
+            Error::Fetch(e) => write!(f, "Failed to download plugin: {}", e),
+            Error::Manifest(e) => write!(f, "Invalid manifest: {}", e),
+            Error::MemoryLimitExceeded => write!(f, "Plugin exceeded its memory limit"),
+            Error::Snapshot(e) => write!(f, "Invalid snapshot: {}", e),
+            Error::RestartLimitExceeded => write!(f, "Plugin instance restart limit exceeded"),
+            Error::Signature(e) => write!(f, "{}", e),
+            Error::Provenance(e) => write!(f, "{}", e),
+            Error::MissingCapabilities(capabilities) => write!(
+                f,
+                "Plugin needs capabilities the manifest does not grant: {}",
+                capabilities
+                    .iter()
+                    .map(Capability::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::HashMismatch { expected, actual } => write!(
+                f,
+                "Plugin hash mismatch: expected {}, got {}",
+                expected, actual
+            ),
+            Error::Wasm(e) => write!(f, "Failed to load plugin: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<wasmtime::Error> for Error {
+    fn from(e: wasmtime::Error) -> Self {
+        Error::Wasm(e)
+    }
+}
+
+/// Error calling a plugin function
+#[derive(Debug)]
+pub enum CallError {
+    /// A plugin instance could not be created for the call
+    Instantiate(Error),
+    /// A `PluginGroup` has no plugin with the name
+    PluginNotFound(String),
+    /// The plugin does not export the function
+    FunctionNotFound(String),
+    /// The plugin function returned an error
+    Plugin(PluginError),
+    /// The call ran longer than its timeout and was cancelled
+    Timeout,
+    /// The call was cancelled by the host
+    Cancelled,
+    /// The call consumed all of its fuel
+    OutOfFuel,
+    /// The plugin tried to grow its memory, or the host memory held for it,
+    /// beyond the manifest's limit or its group's memory budget
+    MemoryLimitExceeded,
+    /// The plugin trapped
+    Trap(wasmtime::Error),
+    /// The input or output could not be converted from or to JSON
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::Instantiate(e) => write!(f, "{}", e),
+            CallError::PluginNotFound(name) => write!(f, "Plugin not found: {}", name),
+            CallError::FunctionNotFound(name) => write!(f, "Function not found: {}", name),
+            CallError::Plugin(e) => write!(f, "Plugin error: {}", e),
+            CallError::Timeout => write!(f, "Plugin call timed out"),
+            CallError::Cancelled => write!(f, "Plugin call was cancelled"),
+            CallError::OutOfFuel => write!(f, "Plugin call ran out of fuel"),
+            CallError::MemoryLimitExceeded => write!(f, "Plugin exceeded its memory limit"),
+            CallError::Trap(e) => {
+                write!(f, "Plugin trapped: {}", trap::message(e))?;
+                match trap::location(&trap::frames(e)) {
+                    Some(frame) => write!(f, " at {}", frame),
+                    None => Ok(()),
+                }
+            }
+            CallError::Json(e) => write!(f, "Invalid JSON: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+impl CallError {
+    /// The plugin's stack when it trapped, innermost frame first, or
+    /// nothing for other errors
+    ///
+    /// Frames have source locations when the module keeps its DWARF debug
+    /// info, as modules built by `Builder::with_debug` do.
+    pub fn backtrace(&self) -> Vec<TrapFrame> {
+        match self {
+            CallError::Trap(e) => trap::frames(e),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for CallError {
+    fn from(e: serde_json::Error) -> Self {
+        CallError::Json(e)
+    }
+}
+
+/// Interval at which the shared engine's epoch advances
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Epoch deadline used while instantiating, which cannot be interrupted
+const NO_DEADLINE: u64 = u64::MAX / 2;
+
+/// Fuel given to calls without a fuel limit
+const UNLIMITED_FUEL: u64 = u64::MAX;
+
+/// Get the engine shared by all plugins
+///
+/// Fuel metering is enabled so every call reports the fuel it consumed,
+/// and the DWARF debug info of modules that keep it is read to give traps
+/// source locations. Epoch interruption is enabled and a background thread
+/// advances the epoch every `EPOCH_TICK`; running calls check their timeout
+/// and cancellation on every tick.
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        config.epoch_interruption(true);
+        config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+        let engine = Engine::new(&config).expect("invalid engine configuration");
+
+        let ticker = engine.weak();
+        std::thread::Builder::new()
+            .name("extism-epoch".to_string())
+            .spawn(move || {
+                while let Some(engine) = ticker.upgrade() {
+                    engine.increment_epoch();
+                    drop(engine);
+                    std::thread::sleep(EPOCH_TICK);
+                }
+            })
+            .expect("failed to spawn epoch thread");
+        engine
+    })
+}
+
+/// A compiled plugin that can be instantiated any number of times
+///
+/// Compiling is the expensive part of loading a plugin, so hosts that need
+/// several instances of the same plugin should compile it once.
+#[derive(Clone)]
+pub struct CompiledPlugin {
+    engine: Engine,
+    main: Module,
+    deps: Vec<(String, Module)>,
+    config: BTreeMap<String, String>,
+    timeout: Option<Duration>,
+    memory_max_pages: Option<u32>,
+    wasi: bool,
+    allowed_paths: BTreeMap<PathBuf, PathBuf>,
+    env: BTreeMap<String, String>,
+    http: HttpClient,
+    log: LogSink,
+    audit: AuditSink,
+    memory_budget: Option<Arc<MemoryBudget>>,
+    subplugins: Option<Arc<PluginGroup>>,
+}
+
+impl CompiledPlugin {
+    /// Load and compile the wasm modules of a manifest
+    pub fn new(manifest: &Manifest) -> Result<Self, Error> {
+        PluginBuilder::new(manifest.clone()).compile()
+    }
+
+    fn compile(manifest: &Manifest, cache: Option<&ModuleCache>) -> Result<Self, Error> {
+        let (main, deps) = manifest
+            .wasm
+            .split_last()
+            .ok_or_else(|| Error::Manifest("no wasm modules".to_string()))?;
+
+        let engine = engine().clone();
+        let policy = manifest.trust_policy();
+        let provenance_policy = manifest.provenance_policy();
+        let compile = |wasm: &Wasm| -> Result<Module, Error> {
+            let signature = wasm.meta().signature.as_ref();
+            let provenance = wasm.meta().provenance.as_ref();
+            let wasm = wasm.load()?;
+            policy.verify(&wasm, signature).map_err(Error::Signature)?;
+            provenance_policy
+                .verify(&wasm, provenance)
+                .map_err(Error::Provenance)?;
+            match cache {
+                Some(cache) => cache.load(&engine, &wasm),
+                None => Ok(Module::new(&engine, wasm)?),
+            }
+        };
+        let deps = deps
+            .iter()
+            .map(|wasm| {
+                let name = wasm.name().ok_or_else(|| {
+                    Error::Manifest("linked wasm modules must have a name".to_string())
+                })?;
+                Ok((name.to_string(), compile(wasm)?))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let main = compile(main)?;
+        check_capabilities(manifest, &deps)?;
+
+        Ok(Self {
+            engine,
+            main,
+            deps,
+            config: manifest.config.clone(),
+            timeout: manifest.timeout(),
+            memory_max_pages: manifest.memory.max_pages,
+            wasi: manifest.wasi,
+            allowed_paths: manifest.allowed_paths.clone().unwrap_or_default(),
+            env: manifest.env.clone(),
+            http: HttpClient::new(
+                manifest.allowed_hosts.clone(),
+                manifest.http_proxy.as_deref(),
+            )?,
+            log: LogSink::default(),
+            audit: AuditSink::default(),
+            memory_budget: None,
+            subplugins: None,
+        })
+    }
+
+    /// Create a new plugin instance
+    ///
+    /// The last wasm module is instantiated as the plugin; any modules before it
+    /// are linked in under their names so the main module can import from them.
+    pub fn instantiate(&self) -> Result<Plugin, Error> {
+        let mut linker = Linker::new(&self.engine);
+        kernel::define(&mut linker)?;
+
+        let mut state = State::new(self.config.clone(), self.memory_max_pages);
+        state.http = self.http.clone();
+        state.log = self.log.clone();
+        state.audit = self.audit.clone();
+        state.limiter.budget = self.memory_budget.clone();
+        state.subplugins = self.subplugins.clone();
+        if self.wasi {
+            state.wasi = Some(wasi::context(&self.allowed_paths, &self.env, &self.log)?);
+            wasmtime_wasi::p1::add_to_linker_sync(&mut linker, |state: &mut State| {
+                state
+                    .wasi
+                    .as_mut()
+                    .expect("WASI context is set when WASI is linked")
+            })?;
+        }
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|state| &mut state.limiter);
+        store.set_epoch_deadline(NO_DEADLINE);
+        store.epoch_deadline_callback(|store| {
+            if store.data().is_interrupted() {
+                return Err(Trap::Interrupt.into());
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+        store.set_fuel(UNLIMITED_FUEL)?;
+        store.call_hook(|mut store, hook| {
+            if matches!(hook, CallHook::CallingHost) {
+                store.data_mut().host_calls += 1;
+            }
+            Ok(())
+        });
+
+        let instance = self
+            .deps
+            .iter()
+            .try_for_each(|(name, module)| {
+                linker.module(&mut store, name, module)?;
+                Ok(())
+            })
+            .and_then(|()| linker.instantiate(&mut store, &self.main));
+        let instance = match instance {
+            Ok(instance) => instance,
+            Err(_) if store.data().limiter.exceeded => return Err(Error::MemoryLimitExceeded),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Plugin {
+            store,
+            instance,
+            poisoned: false,
+            timeout: self.timeout,
+            compiled: self.clone(),
+        })
+    }
+}
+
+/// Check the capabilities the modules declare against what the manifest
+/// grants, failing if the manifest enforces them and warning otherwise
+///
+/// `function:` capabilities are granted by a linked module exporting the function.
+fn check_capabilities(manifest: &Manifest, deps: &[(String, Module)]) -> Result<(), Error> {
+    let linked: BTreeSet<&str> = deps
+        .iter()
+        .flat_map(|(_, module)| module.exports().map(|export| export.name()))
+        .collect();
+    let missing: BTreeSet<&Capability> = manifest
+        .wasm
+        .iter()
+        .flat_map(|wasm| &wasm.meta().capabilities)
+        .filter(|capability| match capability {
+            Capability::Function(name) => !linked.contains(name.as_str()),
+            capability => !manifest.grants(capability),
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let error = Error::MissingCapabilities(missing.into_iter().cloned().collect());
+    if manifest.enforce_capabilities {
+        return Err(error);
+    }
+    tracing::warn!("{}", error);
+    Ok(())
+}
+
+/// Builder for plugins with non-default runtime options
+pub struct PluginBuilder {
+    manifest: Manifest,
+    cache: Option<ModuleCache>,
+    log: Option<LogSink>,
+    audit: Option<AuditSink>,
+    subplugins: Option<Arc<PluginGroup>>,
+}
+
+impl PluginBuilder {
+    /// Create a builder for the plugin described by `manifest`
+    pub fn new(manifest: Manifest) -> Self {
+        Self {
+            manifest,
+            cache: None,
+            log: None,
+            audit: None,
+            subplugins: None,
+        }
+    }
+
+    /// Load compiled modules from, and store them in, an on-disk cache
+    pub fn with_cache(mut self, cache: ModuleCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Send the plugin's log messages to `sink` instead of `tracing`
+    ///
+    /// The sink receives messages from the plugin's `Host::log_*` calls and,
+    /// for WASI plugins, each line written to stdout (`INFO`) or stderr (`WARN`).
+    pub fn with_log_sink(mut self, sink: impl Fn(Level, &str) + Send + Sync + 'static) -> Self {
+        self.log = Some(LogSink::new(sink));
+        self
+    }
+
+    /// Send the records the plugin makes with `audit::log` to `sink`
+    /// instead of `tracing`
+    ///
+    /// Records are delivered after each call, failed or not, with the
+    /// result of checking them against the records the instance made
+    /// before.
+    pub fn with_audit_sink(
+        mut self,
+        sink: impl Fn(&AuditRecord, Result<(), AuditError>) + Send + Sync + 'static,
+    ) -> Self {
+        self.audit = Some(AuditSink::new(sink));
+        self
+    }
+
+    /// Let the plugin call the plugins of `group` with `subplugin::call`
+    ///
+    /// Each call goes through the group, under its limits, and runs within
+    /// what is left of the calling plugin's timeout and fuel. The plugin
+    /// itself should not be a member of the group, as its calls would wait
+    /// for a slot it holds.
+    pub fn with_subplugins(mut self, group: Arc<PluginGroup>) -> Self {
+        self.subplugins = Some(group);
+        self
+    }
+
+    /// Compile the plugin without instantiating it
+    pub fn compile(self) -> Result<CompiledPlugin, Error> {
+        let mut compiled = CompiledPlugin::compile(&self.manifest, self.cache.as_ref())?;
+        if let Some(log) = self.log {
+            compiled.log = log;
+        }
+        if let Some(audit) = self.audit {
+            compiled.audit = audit;
+        }
+        compiled.subplugins = self.subplugins;
+        Ok(compiled)
+    }
+
+    /// Compile and instantiate the plugin
+    pub fn build(self) -> Result<Plugin, Error> {
+        self.compile()?.instantiate()
+    }
+}
+
+/// An instantiated plugin
+pub struct Plugin {
+    store: Store<State>,
+    instance: Instance,
+    poisoned: bool,
+    timeout: Option<Duration>,
+    compiled: CompiledPlugin,
+}
+
+impl Plugin {
+    /// Load a plugin from wasm bytes
+    pub fn new(wasm: impl AsRef<[u8]>) -> Result<Self, Error> {
+        Self::from_manifest(&Manifest::new([Wasm::data(wasm.as_ref())]))
+    }
+
+    /// Load a plugin from a wasm file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_manifest(&Manifest::new([Wasm::file(path)]))
+    }
+
+    /// Load a plugin from a manifest
+    pub fn from_manifest(manifest: &Manifest) -> Result<Self, Error> {
+        CompiledPlugin::new(manifest)?.instantiate()
+    }
+
+    /// Compile the modules of a manifest ahead of time, storing them in `cache`
+    ///
+    /// Later loads through a `PluginBuilder` using the same cache skip compilation.
+    pub fn precompile(manifest: &Manifest, cache: &ModuleCache) -> Result<(), Error> {
+        PluginBuilder::new(manifest.clone())
+            .with_cache(cache.clone())
+            .compile()
+            .map(|_| ())
+    }
+
+    /// Check if a previous call trapped, leaving the instance in an unknown state
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Get a var set by the plugin or the host
+    pub fn var(&self, key: &str) -> Option<&[u8]> {
+        self.store.data().vars.get(key).map(Vec::as_slice)
+    }
+
+    /// Set a var the plugin can read with `Host::var_get`
+    pub fn set_var(&mut self, key: impl Into<String>, value: impl Into<Vec<u8>>) {
+        self.store.data_mut().vars.insert(key.into(), value.into());
+    }
+
+    /// Iterate over the plugin's vars
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.store
+            .data()
+            .vars
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    /// Get the config the plugin reads with `Host::config`
+    pub fn config(&self) -> &BTreeMap<String, String> {
+        &self.store.data().config
+    }
+
+    /// Replace the plugin's config without re-instantiating it
+    ///
+    /// Later calls read the new values. Re-instantiating the plugin, as
+    /// `restore` does, goes back to the manifest's config.
+    pub fn update_config<K, V>(&mut self, config: impl IntoIterator<Item = (K, V)>)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.store.data_mut().config = config
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+    }
+
+    /// Get a handle that cancels this instance's in-flight call from another thread
+    ///
+    /// Cancelling while no call is running has no effect on later calls.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.store.data().cancel_handle.clone()
+    }
+
+    /// Check if the plugin exports a function
+    pub fn function_exists(&mut self, name: &str) -> bool {
+        self.instance.get_func(&mut self.store, name).is_some()
+    }
+
+    /// Call a plugin function with raw input bytes, returning the output bytes
+    pub fn call(&mut self, name: &str, input: impl AsRef<[u8]>) -> Result<Vec<u8>, CallError> {
+        Ok(self
+            .call_with_options(name, input, &CallOptions::default())?
+            .output)
+    }
+
+    /// Call a plugin function with per-call options
+    ///
+    /// Each call is recorded as a `plugin.call` tracing span with the function
+    /// name, input and output sizes, and the resources it used.
+    pub fn call_with_options(
+        &mut self,
+        name: &str,
+        input: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<CallOutput, CallError> {
+        let input = input.as_ref();
+        let span = tracing::info_span!(
+            "plugin.call",
+            function = name,
+            input_len = input.len(),
+            output_len = tracing::field::Empty,
+            duration_us = tracing::field::Empty,
+            fuel = tracing::field::Empty,
+            memory_bytes = tracing::field::Empty,
+            host_calls = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
+        let _enter = span.enter();
+
+        let started_at = SystemTime::now();
+        let start = Instant::now();
+        let result = self.invoke(name, input, options);
+        let duration = start.elapsed();
+        span.record("duration_us", duration.as_micros() as u64);
+        if let Some(path) = &options.record {
+            let state = self.store.data_mut();
+            let trace = Trace {
+                function: name.to_string(),
+                input: input.to_vec(),
+                started_at_ms: started_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_millis() as u64),
+                duration_us: duration.as_micros() as u64,
+                events: state
+                    .recording
+                    .take()
+                    .map(|recording| recording.events)
+                    .unwrap_or_default(),
+                output: match &result {
+                    Ok((output, _)) => output.clone(),
+                    Err(_) => state.output.clone(),
+                },
+                error: result.as_ref().err().map(ToString::to_string),
+            };
+            if let Err(e) = trace.save(path) {
+                tracing::warn!("failed to record call to {}: {}", path.display(), e);
+            }
+        }
+
+        match result {
+            Ok((output, fuel_used)) => {
+                let usage = ResourceUsage {
+                    peak_memory_bytes: self.store.data().limiter.peak_bytes,
+                    fuel_used,
+                    wall_time: duration,
+                    host_calls: self.store.data().host_calls,
+                    allocations: self.store.data().allocations,
+                };
+                span.record("output_len", output.len());
+                span.record("fuel", usage.fuel_used);
+                span.record("memory_bytes", usage.peak_memory_bytes);
+                span.record("host_calls", usage.host_calls);
+                let stats = CallStats {
+                    function: name.to_string(),
+                    input_len: input.len(),
+                    output_len: output.len(),
+                };
+                Ok(CallOutput {
+                    output,
+                    stats,
+                    usage,
+                })
+            }
+            Err(e) => {
+                span.record("error", tracing::field::display(&e));
+                Err(e)
+            }
+        }
+    }
+
+    /// Run a plugin function, returning its output and the fuel it consumed
+    fn invoke(
+        &mut self,
+        name: &str,
+        input: &[u8],
+        options: &CallOptions,
+    ) -> Result<(Vec<u8>, u64), CallError> {
+        let func = self
+            .instance
+            .get_typed_func::<(), i32>(&mut self.store, name)
+            .map_err(|_| CallError::FunctionNotFound(name.to_string()))?;
+
+        let timeout = options.timeout.or(self.timeout);
+        self.store.set_epoch_deadline(1);
+        let fuel = options.fuel.unwrap_or(UNLIMITED_FUEL);
+        self.store.set_fuel(fuel).map_err(CallError::Trap)?;
+        let state = self.store.data_mut();
+        state.begin_call(input);
+        state.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        state.cancel = options.cancel.clone();
+        state.config_override = options.config.clone();
+        state.recording = options.record.as_ref().map(|_| kernel::Recording::new());
+        let result = func.call(&mut self.store, ());
+        let state = self.store.data_mut();
+        state.config_override.clear();
+        state.collect_audit_records();
+        let rc = result.map_err(|e| {
+            self.poisoned = true;
+            if self.store.data().limiter.exceeded {
+                return CallError::MemoryLimitExceeded;
+            }
+            match e.downcast_ref::<Trap>() {
+                Some(Trap::Interrupt) if self.store.data().is_cancelled() => CallError::Cancelled,
+                Some(Trap::Interrupt) => CallError::Timeout,
+                Some(Trap::OutOfFuel) => CallError::OutOfFuel,
+                _ => CallError::Trap(e),
+            }
+        })?;
+        let fuel_consumed = fuel - self.store.get_fuel().map_err(CallError::Trap)?;
+
+        let state = self.store.data_mut();
+        if rc != 0 {
+            let error = state.error.take().unwrap_or_default();
+            return Err(CallError::Plugin(decode_error(&error, rc)));
+        }
+        Ok((std::mem::take(&mut state.output), fuel_consumed))
+    }
+
+    /// Call a plugin function with JSON input, parsing the JSON output
+    pub fn call_json<I, O>(&mut self, name: &str, input: &I) -> Result<O, CallError>
+    where
+        I: serde::Serialize,
+        O: serde::de::DeserializeOwned,
+    {
+        let input = serde_json::to_vec(input)?;
+        let output = self.call(name, input)?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+
+    /// Get the JSON Schemas of the plugin's functions, declared with
+    /// `plugin_metadata!`, or `None` if the plugin does not describe them
+    pub fn describe_schema(&mut self) -> Result<Option<PluginSchema>, CallError> {
+        if !self.function_exists(DESCRIBE_EXPORT) {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(
+            &self.call(DESCRIBE_EXPORT, [])?,
+        )?))
+    }
+}
+
+/// Match a string against a pattern where `*` matches any run of characters
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let Some(s) = s.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=s.len())
+                .filter(|&i| s.is_char_boundary(i))
+                .any(|i| wildcard_match(rest, &s[i..]))
+        }
+    }
+}
+
+/// Decode the error set by a plugin, accepting both the JSON envelope and plain messages
+fn decode_error(error: &[u8], rc: i32) -> PluginError {
+    if let Ok(error) = serde_json::from_slice::<PluginError>(error) {
+        return error;
+    }
+    let message = String::from_utf8_lossy(error);
+    if message.is_empty() {
+        PluginError::fatal(format!("Plugin returned error code {}", rc))
+    } else {
+        PluginError::fatal(message)
+    }
+}
@@ -0,0 +1,61 @@
+//! Collecting the audit records plugins make with `audit::log`
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::extism_pdk::audit::{self, AuditChain, AuditError, AuditRecord};
+
+/// Tracing target for audit records
+const TARGET: &str = "extism::audit";
+
+/// A function receiving audit records and whether they continue the chain
+type AuditFn = dyn Fn(&AuditRecord, Result<(), AuditError>) + Send + Sync;
+
+/// Destination for the audit records of a plugin
+///
+/// By default records become `tracing` events on the `extism::audit`
+/// target, at `INFO`, or at `ERROR` when they break the chain.
+#[derive(Clone)]
+pub(crate) struct AuditSink(Arc<AuditFn>);
+
+impl AuditSink {
+    /// Create a sink calling `f` for every record
+    pub fn new(f: impl Fn(&AuditRecord, Result<(), AuditError>) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Check the records of the `audit:records` var against `chain` and
+    /// deliver them
+    ///
+    /// Lines that are not records cannot be delivered, and are logged as
+    /// errors instead.
+    pub fn collect(&self, chain: &mut AuditChain, data: &[u8]) {
+        for line in data.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+            match audit::parse_records(line) {
+                Ok(records) => records.iter().for_each(|record| {
+                    let verified = chain.push(record);
+                    (self.0)(record, verified);
+                }),
+                Err(e) => tracing::error!(target: TARGET, "{}", e),
+            }
+        }
+    }
+}
+
+impl Default for AuditSink {
+    fn default() -> Self {
+        Self::new(|record, verified| {
+            let json = serde_json::to_string(record).unwrap_or_default();
+            match verified {
+                Ok(()) => tracing::info!(target: TARGET, "{}", json),
+                Err(e) => tracing::error!(target: TARGET, "{}: {}", e, json),
+            }
+        })
+    }
+}
+
+impl fmt::Debug for AuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuditSink")
+    }
+}
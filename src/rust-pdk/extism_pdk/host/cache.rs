@@ -0,0 +1,85 @@
+//! On-disk cache of precompiled wasm modules
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+use wasmtime::{Engine, Module};
+
+use super::Error;
+
+/// A directory of precompiled modules
+///
+/// Entries are keyed by the SHA-256 of the wasm bytes and a hash of the
+/// engine's version and configuration, so upgrading wasmtime or changing
+/// engine settings never loads an incompatible artifact.
+///
+/// Precompiled modules are native code: the cache directory must only be
+/// writable by trusted users.
+#[derive(Debug, Clone)]
+pub struct ModuleCache {
+    dir: PathBuf,
+}
+
+impl ModuleCache {
+    /// Use `dir` as the cache directory, creating it when needed
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Get the cache directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Remove all cached modules
+    pub fn clear(&self) -> Result<(), Error> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get the cache entry path for a wasm module
+    fn path(&self, engine: &Engine, wasm: &[u8]) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        engine.precompile_compatibility_hash().hash(&mut hasher);
+        let name = format!("{}-{:016x}.cwasm", sha256_hex(wasm), hasher.finish());
+        self.dir.join(name)
+    }
+
+    /// Load a module from the cache, compiling and storing it on a miss
+    pub(crate) fn load(&self, engine: &Engine, wasm: &[u8]) -> Result<Module, Error> {
+        let path = self.path(engine, wasm);
+        if path.exists() {
+            // Safety: the cache directory is trusted and entries are keyed by
+            // engine compatibility; wasmtime still validates the header and a
+            // rejected entry is simply recompiled.
+            if let Ok(module) = unsafe { Module::deserialize_file(engine, &path) } {
+                return Ok(module);
+            }
+        }
+
+        let compiled = engine.precompile_module(wasm)?;
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first so concurrent readers never see a partial entry
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&tmp, &compiled)?;
+        fs::rename(&tmp, &path)?;
+
+        // Safety: the bytes were just produced by `precompile_module` on this engine
+        Ok(unsafe { Module::deserialize(engine, &compiled)? })
+    }
+}
+
+/// Get the hex encoded SHA-256 of `data`
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
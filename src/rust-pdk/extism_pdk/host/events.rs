@@ -0,0 +1,111 @@
+//! Publishing events to subscribed plugins
+
+use crate::extism_pdk::event::{Event, ON_EVENT_EXPORT, SUBSCRIPTIONS_EXPORT};
+
+use super::{wildcard_match, CallError, CompiledPlugin, Plugin};
+
+/// The outcome of delivering an event to one subscriber
+#[derive(Debug)]
+pub struct Delivery {
+    /// Name the subscriber was registered under
+    pub subscriber: String,
+    /// Result of the subscriber's `on_event` call
+    pub result: Result<(), CallError>,
+}
+
+struct Subscriber {
+    name: String,
+    topics: Vec<String>,
+    compiled: CompiledPlugin,
+    plugin: Plugin,
+}
+
+impl Subscriber {
+    fn wants(&self, topic: &str) -> bool {
+        self.topics
+            .iter()
+            .any(|pattern| wildcard_match(pattern, topic))
+    }
+
+    /// Deliver an event, replacing the instance first if a previous event poisoned it
+    fn deliver(&mut self, event: &[u8]) -> Result<(), CallError> {
+        if self.plugin.is_poisoned() {
+            self.plugin = self
+                .compiled
+                .instantiate()
+                .map_err(CallError::Instantiate)?;
+        }
+        self.plugin.call(ON_EVENT_EXPORT, event).map(|_| ())
+    }
+}
+
+/// Fans out events to the plugins subscribed to their topics
+///
+/// Each subscriber has its own instance, so one failing plugin never affects
+/// delivery to the others.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    /// Create an event bus with no subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a plugin as a subscriber
+    ///
+    /// The plugin's `subscriptions` export is called once to get the topic
+    /// patterns it wants, where `*` matches any run of characters.
+    /// Subscribing again under the same name replaces the previous subscriber.
+    pub fn subscribe(
+        &mut self,
+        name: impl Into<String>,
+        compiled: &CompiledPlugin,
+    ) -> Result<(), CallError> {
+        let name = name.into();
+        let mut plugin = compiled.instantiate().map_err(CallError::Instantiate)?;
+        let topics: Vec<String> = serde_json::from_slice(&plugin.call(SUBSCRIPTIONS_EXPORT, [])?)?;
+        self.unsubscribe(&name);
+        self.subscribers.push(Subscriber {
+            name,
+            topics,
+            compiled: compiled.clone(),
+            plugin,
+        });
+        Ok(())
+    }
+
+    /// Remove a subscriber, returning whether it existed
+    pub fn unsubscribe(&mut self, name: &str) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers
+            .retain(|subscriber| subscriber.name != name);
+        self.subscribers.len() != len
+    }
+
+    /// Get the topic patterns of a subscriber
+    pub fn subscriptions(&self, name: &str) -> Option<&[String]> {
+        self.subscribers
+            .iter()
+            .find(|subscriber| subscriber.name == name)
+            .map(|subscriber| subscriber.topics.as_slice())
+    }
+
+    /// Deliver an event to every subscriber of its topic, in subscription order
+    ///
+    /// Returns one `Delivery` per subscriber that received the event.
+    pub fn publish(&mut self, event: &Event) -> Result<Vec<Delivery>, CallError> {
+        let input = serde_json::to_vec(event)?;
+        Ok(self
+            .subscribers
+            .iter_mut()
+            .filter(|subscriber| subscriber.wants(&event.topic))
+            .map(|subscriber| Delivery {
+                subscriber: subscriber.name.clone(),
+                result: subscriber.deliver(&input),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,248 @@
+//! Aggregate limits for the plugins of one tenant
+//!
+//! A group owns a pool for each of its plugins and admits calls to them
+//! through a shared scheduler: calls wait while the group is at its
+//! concurrency limit or has spent its fuel, which refills as a token bucket
+//! at the configured rate. Memory is charged to a shared budget as instances
+//! grow their linear memory and released when they are dropped.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::{
+    CallError, CallOptions, CallOutput, CompiledPlugin, Error, MemoryBudget, PluginPool,
+    UNLIMITED_FUEL,
+};
+
+/// Limits shared by every plugin in a `PluginGroup`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GroupLimits {
+    /// Total linear memory of all instances, in bytes
+    pub max_memory_bytes: Option<usize>,
+    /// Calls that may run at the same time across the group
+    pub max_concurrent_calls: Option<usize>,
+    /// Fuel the group may consume per second, with bursts of up to one
+    /// second's worth
+    pub fuel_per_second: Option<u64>,
+}
+
+/// Scheduler state behind the group's lock
+struct Quota {
+    /// Calls currently running
+    active: usize,
+    /// Fuel tokens available; negative after calls overspent what was left
+    fuel: f64,
+    /// When `fuel` was last topped up
+    refilled: Instant,
+}
+
+impl Quota {
+    /// Add the fuel earned since the last refill, up to one second's worth
+    fn refill(&mut self, rate: u64) {
+        let now = Instant::now();
+        let earned = now.duration_since(self.refilled).as_secs_f64() * rate as f64;
+        self.fuel = (self.fuel + earned).min(rate as f64);
+        self.refilled = now;
+    }
+}
+
+/// A set of plugins sharing memory, concurrency and fuel limits
+///
+/// Each plugin added to the group gets a lazy `PluginPool`, so instances are
+/// only created, and charged to the memory budget, when calls need them.
+pub struct PluginGroup {
+    limits: GroupLimits,
+    memory: Option<Arc<MemoryBudget>>,
+    pools: BTreeMap<String, PluginPool>,
+    quota: Mutex<Quota>,
+    changed: Condvar,
+}
+
+impl PluginGroup {
+    /// Create an empty group with the given limits
+    pub fn new(limits: GroupLimits) -> Self {
+        Self {
+            limits,
+            memory: limits
+                .max_memory_bytes
+                .map(|max| Arc::new(MemoryBudget::new(max))),
+            pools: BTreeMap::new(),
+            quota: Mutex::new(Quota {
+                active: 0,
+                fuel: limits.fuel_per_second.unwrap_or_default() as f64,
+                refilled: Instant::now(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Add a plugin with a pool of up to `size` instances
+    ///
+    /// Adding a plugin under an existing name replaces it.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        compiled: &CompiledPlugin,
+        size: usize,
+    ) -> Result<(), Error> {
+        let mut compiled = compiled.clone();
+        compiled.memory_budget = self.memory.clone();
+        let pool = PluginPool::lazy_from_compiled(compiled, size)?;
+        self.pools.insert(name.into(), pool);
+        Ok(())
+    }
+
+    /// Remove a plugin, returning whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.pools.remove(name).is_some()
+    }
+
+    /// Get the pool of a plugin
+    pub fn pool(&self, name: &str) -> Option<&PluginPool> {
+        self.pools.get(name)
+    }
+
+    /// Get the group's limits
+    pub fn limits(&self) -> GroupLimits {
+        self.limits
+    }
+
+    /// Get the linear memory used by the group's instances, in bytes
+    ///
+    /// Only tracked when the group has a memory limit.
+    pub fn memory_bytes(&self) -> usize {
+        self.memory.as_ref().map_or(0, |budget| budget.used())
+    }
+
+    /// Get the number of calls currently running
+    pub fn active_calls(&self) -> usize {
+        self.lock().active
+    }
+
+    /// Call a function of one of the group's plugins
+    pub fn call(
+        &self,
+        plugin: &str,
+        function: &str,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, CallError> {
+        Ok(self
+            .call_with_options(plugin, function, input, &CallOptions::default())?
+            .output)
+    }
+
+    /// Call a function of one of the group's plugins with per-call options
+    ///
+    /// Blocks until the group's limits admit the call. When the group has a
+    /// fuel rate, the call's fuel is capped at what the group has left, so a
+    /// call that would overspend it fails with `CallError::OutOfFuel`.
+    pub fn call_with_options(
+        &self,
+        plugin: &str,
+        function: &str,
+        input: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<CallOutput, CallError> {
+        self.call_metered(plugin, function, input.as_ref(), options)
+            .0
+    }
+
+    /// Call a function of one of the group's plugins, also returning the
+    /// fuel the call consumed, whether or not it succeeded
+    pub(crate) fn call_metered(
+        &self,
+        plugin: &str,
+        function: &str,
+        input: &[u8],
+        options: &CallOptions,
+    ) -> (Result<CallOutput, CallError>, u64) {
+        let Some(pool) = self.pools.get(plugin) else {
+            return (Err(CallError::PluginNotFound(plugin.to_string())), 0);
+        };
+        let mut permit = self.admit();
+        let mut options = options.clone();
+        if let Some(available) = permit.fuel {
+            options.fuel = Some(options.fuel.map_or(available, |fuel| fuel.min(available)));
+        }
+
+        let mut instance = match pool.get() {
+            Ok(instance) => instance,
+            Err(e) => return (Err(CallError::Instantiate(e)), 0),
+        };
+        let result = instance.call_with_options(function, input, &options);
+        let fuel = options.fuel.unwrap_or(UNLIMITED_FUEL);
+        let fuel_used = match &result {
+            Ok(output) => output.usage.fuel_used,
+            Err(CallError::FunctionNotFound(_)) => 0,
+            Err(_) => fuel.saturating_sub(instance.store.get_fuel().unwrap_or(fuel)),
+        };
+        if permit.fuel.is_some() {
+            permit.fuel_used = fuel_used;
+        }
+        (result, fuel_used)
+    }
+
+    /// Wait until the group's limits admit a call
+    fn admit(&self) -> Permit<'_> {
+        let mut quota = self.lock();
+        loop {
+            let running = self
+                .limits
+                .max_concurrent_calls
+                .is_some_and(|max| quota.active >= max);
+            if running {
+                quota = self.changed.wait(quota).unwrap_or_else(|e| e.into_inner());
+                continue;
+            }
+            let Some(rate) = self.limits.fuel_per_second.filter(|&rate| rate > 0) else {
+                quota.active += 1;
+                return Permit::new(self, None);
+            };
+            quota.refill(rate);
+            if quota.fuel >= 1.0 {
+                quota.active += 1;
+                return Permit::new(self, Some(quota.fuel as u64));
+            }
+            // Sleep until the bucket holds at least one unit of fuel again
+            let wait = Duration::from_secs_f64((1.0 - quota.fuel) / rate as f64);
+            quota = self
+                .changed
+                .wait_timeout(quota, wait)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Quota> {
+        self.quota.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// An admitted call, which gives back its slot and charges its fuel when dropped
+struct Permit<'a> {
+    group: &'a PluginGroup,
+    /// Fuel available to the call, if the group has a fuel rate
+    fuel: Option<u64>,
+    fuel_used: u64,
+}
+
+impl<'a> Permit<'a> {
+    fn new(group: &'a PluginGroup, fuel: Option<u64>) -> Self {
+        Self {
+            group,
+            fuel,
+            fuel_used: 0,
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut quota = self.group.lock();
+        quota.active -= 1;
+        quota.fuel -= self.fuel_used as f64;
+        drop(quota);
+        self.group.changed.notify_all();
+    }
+}
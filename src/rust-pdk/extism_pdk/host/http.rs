@@ -0,0 +1,206 @@
+//! HTTP requests made by plugins, filtered by the manifest's `allowed_hosts`
+
+use std::collections::HashMap;
+
+use ureq::unversioned::resolver::DefaultResolver;
+use ureq::unversioned::transport::{ConnectProxyConnector, Connector, TcpConnector};
+
+use super::{wildcard_match, Error};
+use crate::extism_pdk::{Error as PluginError, TlsPolicy};
+
+mod tls;
+
+/// Maximum size of a response body handed to a plugin
+const MAX_RESPONSE_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Prefix of the vars the PDK uses to describe a request
+const REQUEST_PREFIX: &str = "request:";
+
+/// Prefix of the vars the host uses to describe a response
+const RESPONSE_PREFIX: &str = "response:";
+
+/// HTTP policy and client for a plugin instance
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    /// Host patterns the plugin may reach; `None` denies every request
+    allowed_hosts: Option<Vec<String>>,
+    agent: ureq::Agent,
+    proxy: Option<ureq::Proxy>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: None,
+            agent: ureq::Agent::new_with_defaults(),
+            proxy: None,
+        }
+    }
+}
+
+impl HttpClient {
+    /// Create a client for the given `allowed_hosts` and optional proxy URL
+    pub fn new(allowed_hosts: Option<Vec<String>>, proxy: Option<&str>) -> Result<Self, Error> {
+        let proxy = proxy
+            .map(ureq::Proxy::new)
+            .transpose()
+            .map_err(|e| Error::Manifest(format!("Invalid HTTP proxy: {}", e)))?;
+        Ok(Self {
+            allowed_hosts,
+            agent: ureq::Agent::new_with_config(agent_config(proxy.clone())),
+            proxy,
+        })
+    }
+
+    /// An agent whose TLS connections satisfy `policy`
+    ///
+    /// Its connections are not pooled with those of other requests.
+    fn policy_agent(&self, policy: &TlsPolicy) -> Result<(ureq::Agent, tls::Failure), PluginError> {
+        let connector = tls::PolicyConnector::new(policy)
+            .map_err(|e| PluginError::invalid(format!("Invalid TLS policy: {}", e)))?;
+        let failure = connector.failure.clone();
+        let connector =
+            ().chain(ConnectProxyConnector::default())
+                .chain(TcpConnector::default())
+                .chain(connector);
+        let agent = ureq::Agent::with_parts(
+            agent_config(self.proxy.clone()),
+            connector,
+            DefaultResolver::default(),
+        );
+        Ok((agent, failure))
+    }
+
+    /// Check if a request to `host` is allowed
+    pub fn is_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts.as_ref().is_some_and(|patterns| {
+            patterns.iter().any(|pattern| {
+                wildcard_match(&pattern.to_ascii_lowercase(), &host.to_ascii_lowercase())
+            })
+        })
+    }
+
+    /// Perform the request described by the `request:*` vars
+    ///
+    /// The request vars are consumed, and the response is stored in
+    /// `response:body` and `response:header:<name>` vars (names lowercased).
+    /// Returns the response status.
+    pub fn send(&self, vars: &mut HashMap<String, Vec<u8>>) -> Result<u16, PluginError> {
+        let request: HashMap<String, Vec<u8>> = take_prefixed(vars, REQUEST_PREFIX);
+        take_prefixed(vars, RESPONSE_PREFIX);
+
+        let text = |key: &str| {
+            request
+                .get(key)
+                .map(|value| String::from_utf8_lossy(value).into_owned())
+        };
+        let url = text("url").ok_or_else(|| PluginError::invalid("HTTP request has no URL"))?;
+        let method = text("method").unwrap_or_else(|| "GET".to_string());
+        let uri: ureq::http::Uri = url
+            .parse()
+            .map_err(|e| PluginError::invalid(format!("Invalid URL {}: {}", url, e)))?;
+        let host = uri.host().unwrap_or_default().to_string();
+        if !self.is_allowed(&host) {
+            return Err(PluginError::invalid(format!(
+                "HTTP request to {} is not allowed",
+                host
+            )));
+        }
+
+        let policy = request
+            .get("tls")
+            .map(|policy| serde_json::from_slice::<TlsPolicy>(policy))
+            .transpose()
+            .map_err(|e| PluginError::invalid(format!("Invalid TLS policy: {}", e)))?;
+        if policy.is_some() && uri.scheme_str() != Some("https") {
+            return Err(PluginError::invalid(format!(
+                "HTTP request to {} has a TLS policy but does not use HTTPS",
+                url
+            )));
+        }
+        let policy_agent = policy
+            .as_ref()
+            .map(|policy| self.policy_agent(policy))
+            .transpose()?;
+        let agent = policy_agent
+            .as_ref()
+            .map_or(&self.agent, |(agent, _)| agent);
+
+        let mut builder = ureq::http::Request::builder()
+            .method(method.as_str())
+            .uri(uri);
+        for (key, value) in &request {
+            if let Some(name) = key.strip_prefix("header:") {
+                builder = builder.header(name, value.as_slice());
+            }
+        }
+        let invalid =
+            |e: ureq::http::Error| PluginError::invalid(format!("Invalid HTTP request: {}", e));
+        let result = match request.get("body") {
+            Some(body) => agent.run(builder.body(body.as_slice()).map_err(invalid)?),
+            None => agent.run(builder.body(()).map_err(invalid)?),
+        };
+        let mut response = result.map_err(|e| {
+            let failure = policy_agent
+                .as_ref()
+                .and_then(|(_, failure)| failure.lock().ok()?.take())
+                .or_else(|| {
+                    policy_agent
+                        .is_some()
+                        .then(|| tls::rustls_error(&e))
+                        .flatten()
+                });
+            match failure {
+                // A connection failing its policy fails again when retried
+                Some(failure) => {
+                    PluginError::fatal(format!("TLS verification of {} failed: {}", host, failure))
+                }
+                None => PluginError::retryable(format!("HTTP request to {} failed: {}", url, e)),
+            }
+        })?;
+
+        for (name, value) in response.headers() {
+            vars.insert(
+                format!("{}header:{}", RESPONSE_PREFIX, name.as_str()),
+                value.as_bytes().to_vec(),
+            );
+        }
+        let status = response.status().as_u16();
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(MAX_RESPONSE_SIZE)
+            .read_to_vec()
+            .map_err(|e| PluginError::retryable(format!("Failed to read HTTP response: {}", e)))?;
+        vars.insert(format!("{}body", RESPONSE_PREFIX), body);
+        Ok(status)
+    }
+}
+
+/// The configuration of the agents of a client
+fn agent_config(proxy: Option<ureq::Proxy>) -> ureq::config::Config {
+    let mut config = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        // Redirects are returned to the plugin, so they cannot reach hosts
+        // outside `allowed_hosts`
+        .max_redirects(0);
+    if proxy.is_some() {
+        config = config.proxy(proxy);
+    }
+    config.build()
+}
+
+/// Remove and return the vars starting with `prefix`, with the prefix stripped
+fn take_prefixed(vars: &mut HashMap<String, Vec<u8>>, prefix: &str) -> HashMap<String, Vec<u8>> {
+    let keys: Vec<String> = vars
+        .keys()
+        .filter(|key| key.starts_with(prefix))
+        .cloned()
+        .collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let value = vars.remove(&key)?;
+            Some((key[prefix.len()..].to_string(), value))
+        })
+        .collect()
+}
@@ -0,0 +1,725 @@
+//! Kernel functions imported by plugins built with the PDK
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use tracing::Level;
+use wasmtime::{Caller, Extern, Linker, Memory};
+use wasmtime_wasi::p1::WasiP1Ctx;
+
+use super::audit::AuditSink;
+use super::http::HttpClient;
+use super::limits::{MemoryLimiter, MAX_MEMORY_BYTES};
+use super::log::LogSink;
+use super::options::{CallOptions, CancelHandle};
+use super::{CallError, PluginGroup};
+use crate::extism_pdk::audit::{self, AuditChain};
+use crate::extism_pdk::trace::{HostCall, TraceEvent, TracedRequest, TracedResponse};
+use crate::extism_pdk::var;
+use crate::extism_pdk::Error as PluginError;
+
+/// The import module the PDK's `extern "C"` block links against
+const MODULE: &str = "env";
+
+/// Per-instance state backing the kernel functions
+#[derive(Default)]
+pub(crate) struct State {
+    /// Input for the current call
+    pub input: Vec<u8>,
+    /// Output set by the current call
+    pub output: Vec<u8>,
+    /// Error set by the current call
+    pub error: Option<Vec<u8>>,
+    /// Memory blocks allocated through `extism_alloc`
+    blocks: HashMap<u64, Vec<u8>>,
+    /// The next block offset to hand out; 0 is reserved for "no block"
+    next_block: u64,
+    /// Bytes held by `blocks`
+    block_bytes: usize,
+    /// Plugin variables, kept across calls
+    pub vars: HashMap<String, Vec<u8>>,
+    /// Bytes held by the names and values of `vars`, recounted at each call
+    /// and after the host sets vars during one
+    var_bytes: usize,
+    /// Plugin configuration
+    pub config: BTreeMap<String, String>,
+    /// Configuration layered over `config` for the current call
+    pub config_override: BTreeMap<String, String>,
+    /// Memory limits for the instance
+    pub limiter: MemoryLimiter,
+    /// WASI context, when WASI is enabled
+    pub wasi: Option<WasiP1Ctx>,
+    /// HTTP client enforcing the manifest's `allowed_hosts`
+    pub http: HttpClient,
+    /// Status of the last HTTP response
+    http_status: u16,
+    /// When the current call times out
+    pub deadline: Option<Instant>,
+    /// Handles that cancel the current call
+    pub cancel: Vec<CancelHandle>,
+    /// The instance's own handle, which cancels whatever call is in flight
+    pub cancel_handle: CancelHandle,
+    /// Where plugin log messages go
+    pub log: LogSink,
+    /// Host functions called during the current call
+    pub host_calls: u64,
+    /// Memory blocks allocated during the current call
+    pub allocations: u64,
+    /// Host calls of the current call, when it is recorded
+    pub recording: Option<Recording>,
+    /// Plugins the instance may call with `extism_plugin_call`
+    pub subplugins: Option<Arc<PluginGroup>>,
+    /// Where the plugin's audit records go
+    pub audit: AuditSink,
+    /// The audit records delivered so far
+    audit_chain: AuditChain,
+}
+
+/// The host calls of a recorded call, as they are made
+pub(crate) struct Recording {
+    start: Instant,
+    pub events: Vec<TraceEvent>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl State {
+    /// Create the state for a new instance
+    pub fn new(config: BTreeMap<String, String>, memory_max_pages: Option<u32>) -> Self {
+        Self {
+            config,
+            limiter: MemoryLimiter::new(memory_max_pages),
+            ..Self::default()
+        }
+    }
+
+    /// Reset the per-call state before a new call
+    pub fn begin_call(&mut self, input: &[u8]) {
+        self.input = input.to_vec();
+        self.output.clear();
+        self.error = None;
+        self.recount();
+        self.limiter.reset_peak();
+        self.limiter.exceeded = false;
+        self.host_calls = 0;
+        self.allocations = 0;
+        self.cancel_handle.reset();
+    }
+
+    /// Check if the host cancelled the current call
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_handle.is_cancelled() || self.cancel.iter().any(CancelHandle::is_cancelled)
+    }
+
+    /// Check if the current call should be interrupted, because it was
+    /// cancelled or ran past its deadline
+    pub fn is_interrupted(&self) -> bool {
+        self.is_cancelled()
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Deliver the audit records the plugin made during the call
+    pub fn collect_audit_records(&mut self) {
+        if let Some(records) = self.vars.remove(audit::RECORDS_VAR) {
+            self.audit.collect(&mut self.audit_chain, &records);
+        }
+    }
+
+    /// Record a host call that started at `started`, if the call is
+    /// recorded
+    fn record(&mut self, started: Instant, call: impl FnOnce() -> HostCall) {
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(TraceEvent {
+                at_us: started.duration_since(recording.start).as_micros() as u64,
+                duration_us: started.elapsed().as_micros() as u64,
+                call: call(),
+            });
+        }
+    }
+
+    /// Options for a sub-plugin call made with `fuel` left, bounded by the
+    /// current call's deadline and cancelled with it
+    fn subcall_options(&self, fuel: u64) -> CallOptions {
+        let mut options = CallOptions::new()
+            .fuel(fuel)
+            .cancel_with(self.cancel_handle.clone());
+        for handle in &self.cancel {
+            options = options.cancel_with(handle.clone());
+        }
+        if let Some(deadline) = self.deadline {
+            options = options.timeout(deadline.saturating_duration_since(Instant::now()));
+        }
+        options
+    }
+
+    /// Host memory held for the instance: its memory blocks, output, error
+    /// and vars
+    fn host_bytes(&self) -> usize {
+        self.block_bytes
+            + self.output.len()
+            + self.error.as_ref().map_or(0, Vec::len)
+            + self.var_bytes
+    }
+
+    /// Hold `bytes` more of host memory for the instance, trapping if that
+    /// exceeds its memory limit
+    fn hold(&mut self, bytes: usize) -> wasmtime::Result<()> {
+        if self.limiter.hold(self.host_bytes().saturating_add(bytes)) {
+            Ok(())
+        } else {
+            Err(wasmtime::Error::msg("plugin exceeded its memory limit"))
+        }
+    }
+
+    /// Hand back host memory the instance no longer holds
+    fn settle(&mut self) {
+        self.limiter.hold(self.host_bytes());
+    }
+
+    /// Recount the host memory held for the instance after the host changed
+    /// its output or vars
+    fn recount(&mut self) {
+        self.var_bytes = self
+            .vars
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .sum();
+        self.settle();
+    }
+
+    /// Allocate a block holding `data` and return its offset
+    fn alloc(&mut self, data: Vec<u8>) -> wasmtime::Result<u64> {
+        self.hold(data.len())?;
+        self.next_block += 1;
+        self.allocations += 1;
+        self.block_bytes += data.len();
+        self.blocks.insert(self.next_block, data);
+        Ok(self.next_block)
+    }
+
+    /// Allocate a zeroed block of `len` bytes for `extism_alloc`, checking
+    /// the length the plugin chose before allocating
+    fn alloc_zeroed(&mut self, len: u64) -> wasmtime::Result<u64> {
+        // A plugin can only fill a block from its linear memory
+        if len > MAX_MEMORY_BYTES as u64 {
+            return Err(wasmtime::Error::msg(format!(
+                "memory block too large: {} bytes",
+                len
+            )));
+        }
+        let len = len as usize;
+        self.hold(len)?;
+        let mut block = Vec::new();
+        if let Err(e) = block.try_reserve_exact(len) {
+            self.settle();
+            return Err(wasmtime::Error::msg(format!(
+                "memory block of {} bytes: {}",
+                len, e
+            )));
+        }
+        block.resize(len, 0);
+        self.settle();
+        self.alloc(block)
+    }
+
+    /// Free a block
+    fn free(&mut self, offset: u64) {
+        if let Some(block) = self.blocks.remove(&offset) {
+            self.block_bytes -= block.len();
+            self.settle();
+        }
+    }
+
+    /// Set the output of the current call
+    fn set_output(&mut self, data: Vec<u8>) -> wasmtime::Result<()> {
+        self.hold(data.len().saturating_sub(self.output.len()))?;
+        self.output = data;
+        self.settle();
+        Ok(())
+    }
+
+    /// Set the error of the current call
+    fn set_error(&mut self, data: Vec<u8>) -> wasmtime::Result<()> {
+        let held = self.error.as_ref().map_or(0, Vec::len);
+        self.hold(data.len().saturating_sub(held))?;
+        self.error = Some(data);
+        self.settle();
+        Ok(())
+    }
+
+    /// Set a var the plugin set
+    fn set_var(&mut self, name: String, value: Vec<u8>) -> wasmtime::Result<()> {
+        let bytes = name.len() + value.len();
+        let replaced = self.vars.get(&name).map_or(0, |old| name.len() + old.len());
+        self.hold(bytes.saturating_sub(replaced))?;
+        self.var_bytes = (self.var_bytes + bytes).saturating_sub(replaced);
+        self.vars.insert(name, value);
+        self.settle();
+        Ok(())
+    }
+
+    /// Get a block by offset
+    fn block(&self, offset: u64) -> wasmtime::Result<&Vec<u8>> {
+        self.blocks
+            .get(&offset)
+            .ok_or_else(|| wasmtime::Error::msg(format!("invalid memory block: {}", offset)))
+    }
+
+    /// Get a mutable block by offset
+    fn block_mut(&mut self, offset: u64) -> wasmtime::Result<&mut Vec<u8>> {
+        self.blocks
+            .get_mut(&offset)
+            .ok_or_else(|| wasmtime::Error::msg(format!("invalid memory block: {}", offset)))
+    }
+}
+
+/// Get the plugin's exported linear memory
+fn memory(caller: &mut Caller<'_, State>) -> wasmtime::Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("plugin does not export memory")),
+    }
+}
+
+/// Read bytes from the plugin's linear memory, checking bounds before
+/// copying them
+fn read(caller: &mut Caller<'_, State>, ptr: u32, len: u64) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    let data = memory.data(&*caller);
+    let start = ptr as usize;
+    let end = usize::try_from(len)
+        .ok()
+        .and_then(|len| start.checked_add(len))
+        .filter(|&end| end <= data.len())
+        .ok_or_else(|| {
+            wasmtime::Error::msg(format!(
+                "memory access out of bounds: {} bytes at {} of {}",
+                len,
+                start,
+                data.len()
+            ))
+        })?;
+    Ok(data[start..end].to_vec())
+}
+
+/// Read a UTF-8 string from the plugin's linear memory
+fn read_string(caller: &mut Caller<'_, State>, ptr: u32, len: u64) -> wasmtime::Result<String> {
+    String::from_utf8(read(caller, ptr, len)?).map_err(wasmtime::Error::msg)
+}
+
+/// Write bytes into the plugin's linear memory
+fn write(caller: &mut Caller<'_, State>, ptr: u32, data: &[u8]) -> wasmtime::Result<()> {
+    let memory = memory(caller)?;
+    memory.write(&mut *caller, ptr as usize, data)?;
+    Ok(())
+}
+
+/// Get the `offset..offset + len` range of a block, checking bounds
+fn range(block: &[u8], offset: u64, len: u64) -> wasmtime::Result<std::ops::Range<usize>> {
+    let start = offset as usize;
+    let end = start.saturating_add(len as usize);
+    if end > block.len() {
+        return Err(wasmtime::Error::msg(format!(
+            "memory access out of bounds: {}..{} of {}",
+            start,
+            end,
+            block.len()
+        )));
+    }
+    Ok(start..end)
+}
+
+/// Check if a var passes an HTTP request or response between the PDK and
+/// the host
+fn is_http_var(name: &str) -> bool {
+    name.starts_with("request:") || name.starts_with("response:")
+}
+
+/// The request described by the `request:*` vars, for a recording
+fn traced_request(vars: &HashMap<String, Vec<u8>>) -> TracedRequest {
+    let mut request = TracedRequest {
+        method: "GET".to_string(),
+        ..TracedRequest::default()
+    };
+    for (key, value) in vars {
+        let text = || String::from_utf8_lossy(value).into_owned();
+        match key.strip_prefix("request:") {
+            Some("method") => request.method = text(),
+            Some("url") => request.url = text(),
+            Some("body") => request.body = Some(value.clone()),
+            Some(name) => {
+                if let Some(header) = name.strip_prefix("header:") {
+                    request.headers.insert(header.to_string(), text());
+                }
+            }
+            None => {}
+        }
+    }
+    request
+}
+
+/// The response stored in the `response:*` vars, for a recording
+fn traced_response(status: u16, vars: &HashMap<String, Vec<u8>>) -> TracedResponse {
+    TracedResponse {
+        status,
+        headers: vars
+            .iter()
+            .filter_map(|(key, value)| {
+                let name = key.strip_prefix("response:header:")?;
+                Some((
+                    name.to_string(),
+                    String::from_utf8_lossy(value).into_owned(),
+                ))
+            })
+            .collect(),
+        body: vars.get("response:body").cloned().unwrap_or_default(),
+    }
+}
+
+/// The error a plugin receives for a failed sub-plugin call
+fn subcall_error(error: CallError) -> PluginError {
+    match error {
+        CallError::Plugin(e) => e,
+        CallError::PluginNotFound(_) | CallError::FunctionNotFound(_) => {
+            PluginError::invalid(error.to_string())
+        }
+        CallError::Timeout => PluginError::retryable(error.to_string()),
+        error => PluginError::fatal(error.to_string()),
+    }
+}
+
+/// Log a message from the plugin
+fn log(caller: &mut Caller<'_, State>, level: Level, ptr: u32, len: u64) -> wasmtime::Result<()> {
+    let started = Instant::now();
+    let message = read_string(caller, ptr, len)?;
+    caller.data().log.log(level, &message);
+    caller.data_mut().record(started, || HostCall::Log {
+        level: level.to_string(),
+        message,
+    });
+    Ok(())
+}
+
+/// Define the kernel functions in `linker`
+pub(crate) fn define(linker: &mut Linker<State>) -> wasmtime::Result<()> {
+    linker.func_wrap(
+        MODULE,
+        "extism_input_length",
+        |caller: Caller<'_, State>| caller.data().input.len() as u64,
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_input_load_u8",
+        |mut caller: Caller<'_, State>, offset: u64, len: u64, buf: u32| {
+            let input = &caller.data().input;
+            let data = input[range(input, offset, len)?].to_vec();
+            write(&mut caller, buf, &data)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_output_set",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let data = read(&mut caller, ptr, len)?;
+            caller.data_mut().set_output(data)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_error_set",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let data = read(&mut caller, ptr, len)?;
+            caller.data_mut().set_error(data)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_alloc",
+        |mut caller: Caller<'_, State>, n: u64| caller.data_mut().alloc_zeroed(n),
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_free",
+        |mut caller: Caller<'_, State>, offset: u64| {
+            caller.data_mut().free(offset);
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_length",
+        |caller: Caller<'_, State>, offset: u64| {
+            caller
+                .data()
+                .blocks
+                .get(&offset)
+                .map_or(0, |block| block.len() as u64)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_store_u8",
+        |mut caller: Caller<'_, State>, offset: u64, start: u64, buf: u32, len: u64| {
+            let data = read(&mut caller, buf, len)?;
+            let block = caller.data_mut().block_mut(offset)?;
+            let range = range(block, start, len)?;
+            block[range].copy_from_slice(&data);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_load_u8",
+        |mut caller: Caller<'_, State>, offset: u64, start: u64, len: u64, buf: u32| {
+            let block = caller.data().block(offset)?;
+            let data = block[range(block, start, len)?].to_vec();
+            write(&mut caller, buf, &data)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_http_request",
+        |mut caller: Caller<'_, State>, _req: u64, out: u32| {
+            let started = Instant::now();
+            let request = caller
+                .data()
+                .recording
+                .is_some()
+                .then(|| traced_request(&caller.data().vars));
+            let State {
+                http,
+                vars,
+                http_status,
+                ..
+            } = caller.data_mut();
+            match http.send(vars) {
+                Ok(status) => {
+                    *http_status = status;
+                    let response = request.as_ref().map(|_| traced_response(status, vars));
+                    let state = caller.data_mut();
+                    state.record(started, || HostCall::Http {
+                        request: request.unwrap_or_default(),
+                        response,
+                        error: None,
+                    });
+                    state.recount();
+                    let handle = state.alloc(Vec::new())?;
+                    write(&mut caller, out, &handle.to_le_bytes())?;
+                    Ok(0)
+                }
+                Err(e) => {
+                    // Surfaced to the plugin as a structured error
+                    *http_status = 0;
+                    vars.insert("response:error".to_string(), serde_json::to_vec(&e)?);
+                    caller.data_mut().recount();
+                    caller.data_mut().record(started, || HostCall::Http {
+                        request: request.unwrap_or_default(),
+                        response: None,
+                        error: Some(e),
+                    });
+                    Ok(1)
+                }
+            }
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_http_status_code",
+        |caller: Caller<'_, State>, _resp: u64| -> i32 { caller.data().http_status.into() },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_config_get",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let started = Instant::now();
+            let key = read_string(&mut caller, ptr, len)?;
+            let state = caller.data_mut();
+            let value = state
+                .config_override
+                .get(&key)
+                .or_else(|| state.config.get(&key))
+                .cloned();
+            state.record(started, || HostCall::Config {
+                key,
+                value: value.clone(),
+            });
+            Ok(match value {
+                Some(value) => state.alloc(value.into_bytes())?,
+                None => 0,
+            })
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_var_get",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let started = Instant::now();
+            let name = read_string(&mut caller, ptr, len)?;
+            let state = caller.data_mut();
+            let value = state.vars.get(&name).cloned();
+            // HTTP vars are recorded with their request instead
+            if !is_http_var(&name) {
+                state.record(started, || HostCall::VarGet {
+                    name,
+                    value: value.clone(),
+                });
+            }
+            Ok(match value {
+                Some(value) => state.alloc(value)?,
+                None => 0,
+            })
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_var_set",
+        |mut caller: Caller<'_, State>, name_ptr: u32, name_len: u64, ptr: u32, len: u64| {
+            let started = Instant::now();
+            let name = read_string(&mut caller, name_ptr, name_len)?;
+            let value = read(&mut caller, ptr, len)?;
+            let state = caller.data_mut();
+            if !is_http_var(&name) {
+                state.record(started, || HostCall::VarSet {
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
+            state.set_var(name, value)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_var_get_many",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let started = Instant::now();
+            let names = var::decode_batch(&read(&mut caller, ptr, len)?)?;
+            let state = caller.data_mut();
+            let values: Vec<_> = names
+                .into_iter()
+                .map(|(name, _)| {
+                    let value = state.vars.get(&name).cloned();
+                    (name, value)
+                })
+                .collect();
+            for (name, value) in &values {
+                if !is_http_var(name) {
+                    state.record(started, || HostCall::VarGet {
+                        name: name.clone(),
+                        value: value.clone(),
+                    });
+                }
+            }
+            let response = var::encode_batch(
+                values
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_deref())),
+            );
+            state.alloc(response)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_var_set_many",
+        |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+            let started = Instant::now();
+            let entries = var::decode_batch(&read(&mut caller, ptr, len)?)?;
+            let state = caller.data_mut();
+            for (name, value) in entries {
+                let Some(value) = value else { continue };
+                if !is_http_var(&name) {
+                    state.record(started, || HostCall::VarSet {
+                        name: name.clone(),
+                        value: value.clone(),
+                    });
+                }
+                state.set_var(name, value)?;
+            }
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_plugin_call",
+        |mut caller: Caller<'_, State>,
+         plugin_ptr: u32,
+         plugin_len: u64,
+         function_ptr: u32,
+         function_len: u64,
+         input_ptr: u32,
+         input_len: u64,
+         out: u32| {
+            let started = Instant::now();
+            let plugin = read_string(&mut caller, plugin_ptr, plugin_len)?;
+            let function = read_string(&mut caller, function_ptr, function_len)?;
+            let input = read(&mut caller, input_ptr, input_len)?;
+            let fuel = caller.get_fuel()?;
+            let (result, fuel_used) = match caller.data().subplugins.clone() {
+                Some(group) => {
+                    let options = caller.data().subcall_options(fuel);
+                    group.call_metered(&plugin, &function, &input, &options)
+                }
+                None => (Err(CallError::PluginNotFound(plugin.clone())), 0),
+            };
+            // The sub-plugin's fuel is spent by its caller
+            caller.set_fuel(fuel.saturating_sub(fuel_used))?;
+            let result = result.map(|output| output.output).map_err(subcall_error);
+            let (rc, data) = match &result {
+                Ok(output) => (0, output.clone()),
+                Err(e) => (1, serde_json::to_vec(e)?),
+            };
+            let state = caller.data_mut();
+            state.record(started, || {
+                let (output, error) = match result {
+                    Ok(output) => (Some(output), None),
+                    Err(e) => (None, Some(e)),
+                };
+                HostCall::PluginCall {
+                    plugin,
+                    function,
+                    input,
+                    output,
+                    error,
+                }
+            });
+            let handle = state.alloc(data)?;
+            write(&mut caller, out, &handle.to_le_bytes())?;
+            Ok(rc)
+        },
+    )?;
+    linker.func_wrap(
+        MODULE,
+        "extism_time_now_ms",
+        |mut caller: Caller<'_, State>| {
+            let started = Instant::now();
+            let ms = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64);
+            caller.data_mut().record(started, || HostCall::Time { ms });
+            ms
+        },
+    )?;
+    for (name, level) in [
+        ("extism_log_info", Level::INFO),
+        ("extism_log_debug", Level::DEBUG),
+        ("extism_log_warn", Level::WARN),
+        ("extism_log_error", Level::ERROR),
+    ] {
+        linker.func_wrap(
+            MODULE,
+            name,
+            move |mut caller: Caller<'_, State>, ptr: u32, len: u64| {
+                log(&mut caller, level, ptr, len)
+            },
+        )?;
+    }
+    Ok(())
+}
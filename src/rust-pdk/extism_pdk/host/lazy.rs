@@ -0,0 +1,78 @@
+//! Plugins instantiated on first use
+
+use super::{CallError, CallOptions, CallOutput, CompiledPlugin, Error, Manifest, Plugin};
+
+/// A compiled plugin that is instantiated on its first call
+///
+/// Compiling up front surfaces invalid modules early, while deferring
+/// instantiation keeps hosts with many rarely used plugins from paying for
+/// their memory until they are needed.
+pub struct LazyPlugin {
+    compiled: CompiledPlugin,
+    plugin: Option<Plugin>,
+}
+
+impl Plugin {
+    /// Compile a plugin now and instantiate it on first call
+    pub fn lazy(manifest: &Manifest) -> Result<LazyPlugin, Error> {
+        Ok(LazyPlugin::from_compiled(CompiledPlugin::new(manifest)?))
+    }
+}
+
+impl LazyPlugin {
+    /// Wrap a compiled plugin without instantiating it
+    pub fn from_compiled(compiled: CompiledPlugin) -> Self {
+        Self {
+            compiled,
+            plugin: None,
+        }
+    }
+
+    /// Check if the plugin has been instantiated
+    pub fn is_instantiated(&self) -> bool {
+        self.plugin.is_some()
+    }
+
+    /// Get the instance, instantiating the plugin if needed
+    pub fn get(&mut self) -> Result<&mut Plugin, Error> {
+        match &mut self.plugin {
+            Some(plugin) => Ok(plugin),
+            plugin => Ok(plugin.insert(self.compiled.instantiate()?)),
+        }
+    }
+
+    /// Call a plugin function with raw input bytes, returning the output bytes
+    pub fn call(&mut self, name: &str, input: impl AsRef<[u8]>) -> Result<Vec<u8>, CallError> {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call(name, input)
+    }
+
+    /// Call a plugin function with per-call options
+    pub fn call_with_options(
+        &mut self,
+        name: &str,
+        input: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<CallOutput, CallError> {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call_with_options(name, input, options)
+    }
+
+    /// Call a plugin function with JSON input, parsing the JSON output
+    pub fn call_json<I, O>(&mut self, name: &str, input: &I) -> Result<O, CallError>
+    where
+        I: serde::Serialize,
+        O: serde::de::DeserializeOwned,
+    {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call_json(name, input)
+    }
+
+    /// Drop the instance, freeing its memory until the next call
+    pub fn unload(&mut self) {
+        self.plugin = None;
+    }
+}
@@ -0,0 +1,167 @@
+//! Resource limits applied to plugin instances
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use wasmtime::ResourceLimiter;
+
+/// Size of a wasm page in bytes
+pub(crate) const PAGE_SIZE: usize = 64 * 1024;
+
+/// Size of the largest wasm32 linear memory in bytes
+pub(crate) const MAX_MEMORY_BYTES: usize = 65536 * PAGE_SIZE;
+
+/// Memory shared by a set of instances, such as the plugins of a `PluginGroup`
+#[derive(Debug)]
+pub(crate) struct MemoryBudget {
+    max_bytes: usize,
+    used: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Create a budget of `max_bytes` shared by every instance charged to it
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the bytes charged to the budget
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Charge `bytes` to the budget, unless that would exceed it
+    fn reserve(&self, bytes: usize) -> bool {
+        self.used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes)
+                    .filter(|&total| total <= self.max_bytes)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Limits the memory of an instance, recording when the limit is hit
+///
+/// The limit covers the instance's linear memory and the host memory held
+/// for it: the memory blocks it allocated, its output and its vars.
+#[derive(Debug, Default)]
+pub(crate) struct MemoryLimiter {
+    /// Maximum memory size in bytes
+    max_bytes: Option<usize>,
+    /// Size of the linear memory in bytes
+    memory_bytes: usize,
+    /// Host memory held for the instance in bytes
+    host_bytes: usize,
+    /// Most linear and host memory held since the last `reset_peak`
+    pub peak_bytes: usize,
+    /// Budget the instance's memory is also charged to
+    pub budget: Option<Arc<MemoryBudget>>,
+    /// Bytes charged to the budget by this instance
+    reserved: usize,
+    /// Whether a memory growth was denied since the last reset
+    pub exceeded: bool,
+}
+
+impl MemoryLimiter {
+    /// Create a limiter allowing at most `max_pages` wasm pages
+    pub fn new(max_pages: Option<u32>) -> Self {
+        Self {
+            max_bytes: max_pages.map(|pages| pages as usize * PAGE_SIZE),
+            memory_bytes: 0,
+            host_bytes: 0,
+            peak_bytes: 0,
+            budget: None,
+            reserved: 0,
+            exceeded: false,
+        }
+    }
+
+    /// Hold `host_bytes` of host memory for the instance, unless growing to
+    /// that would exceed the limit or the budget
+    pub fn hold(&mut self, host_bytes: usize) -> bool {
+        if host_bytes <= self.host_bytes {
+            self.release(self.host_bytes - host_bytes);
+            self.host_bytes = host_bytes;
+            return true;
+        }
+        let total = self.memory_bytes.saturating_add(host_bytes);
+        if !self.charge(total, host_bytes - self.host_bytes) {
+            return false;
+        }
+        self.host_bytes = host_bytes;
+        self.peak_bytes = self.peak_bytes.max(total);
+        true
+    }
+
+    /// Start tracking the peak memory anew, from what is held now
+    pub fn reset_peak(&mut self) {
+        self.peak_bytes = self.memory_bytes + self.host_bytes;
+    }
+
+    /// Charge `bytes` of growth to the budget, unless `total` bytes would
+    /// exceed the limit or the growth the budget
+    fn charge(&mut self, total: usize, bytes: usize) -> bool {
+        if self.max_bytes.is_some_and(|max| total > max) {
+            self.exceeded = true;
+            return false;
+        }
+        if let Some(budget) = &self.budget {
+            if !budget.reserve(bytes) {
+                self.exceeded = true;
+                return false;
+            }
+            self.reserved += bytes;
+        }
+        true
+    }
+
+    /// Hand `bytes` no longer held back to the budget
+    fn release(&mut self, bytes: usize) {
+        if let Some(budget) = &self.budget {
+            let bytes = bytes.min(self.reserved);
+            budget.release(bytes);
+            self.reserved -= bytes;
+        }
+    }
+}
+
+impl ResourceLimiter for MemoryLimiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let total = desired.saturating_add(self.host_bytes);
+        if !self.charge(total, desired.saturating_sub(current)) {
+            return Ok(false);
+        }
+        self.memory_bytes = desired;
+        self.peak_bytes = self.peak_bytes.max(total);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        _desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        Ok(true)
+    }
+}
+
+impl Drop for MemoryLimiter {
+    fn drop(&mut self) {
+        if let Some(budget) = &self.budget {
+            budget.release(self.reserved);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+//! Routing plugin log messages to the host
+
+use std::fmt;
+use std::sync::Arc;
+
+use tracing::Level;
+
+/// Tracing target for plugin log messages
+const TARGET: &str = "extism::plugin";
+
+/// A function receiving plugin log messages
+type LogFn = dyn Fn(Level, &str) + Send + Sync;
+
+/// Destination for log messages from a plugin's `extism_log_*` calls and WASI stdio
+///
+/// By default messages become `tracing` events at the matching level.
+#[derive(Clone)]
+pub(crate) struct LogSink(Arc<LogFn>);
+
+impl LogSink {
+    /// Create a sink calling `f` for every message
+    pub fn new(f: impl Fn(Level, &str) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Log a message
+    pub fn log(&self, level: Level, message: &str) {
+        (self.0)(level, message)
+    }
+}
+
+impl Default for LogSink {
+    fn default() -> Self {
+        Self::new(|level, message| match level {
+            Level::ERROR => tracing::error!(target: TARGET, "{}", message),
+            Level::WARN => tracing::warn!(target: TARGET, "{}", message),
+            Level::INFO => tracing::info!(target: TARGET, "{}", message),
+            Level::DEBUG => tracing::debug!(target: TARGET, "{}", message),
+            Level::TRACE => tracing::trace!(target: TARGET, "{}", message),
+        })
+    }
+}
+
+impl fmt::Debug for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogSink")
+    }
+}
@@ -0,0 +1,528 @@
+//! Plugin manifest describing the wasm sources and policy for a plugin
+//!
+//! The JSON representation follows the standard Extism manifest format:
+//!
+//! ```json
+//! {
+//!   "wasm": [{ "path": "hello.wasm", "capabilities": ["http"] }],
+//!   "config": { "greeting": "Hi" },
+//!   "allowed_hosts": ["*.example.com"],
+//!   "allowed_paths": { "/var/data": "/data" },
+//!   "memory": { "max_pages": 16 },
+//!   "timeout_ms": 1000,
+//!   "wasi": true,
+//!   "env": { "LANG": "C" },
+//!   "trusted_keys": ["<base64 Ed25519 public key>"],
+//!   "require_provenance": true,
+//!   "trusted_builders": ["<base64 Ed25519 public key>"],
+//!   "enforce_capabilities": true
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::cache::sha256_hex;
+use super::oci::{self, OciAuth};
+use super::Error;
+use crate::extism_pdk::capability::Capability;
+use crate::extism_pdk::provenance::{Provenance, ProvenancePolicy};
+use crate::extism_pdk::signing::{Signature, TrustPolicy};
+
+/// Maximum size of a wasm module downloaded from a URL
+const MAX_DOWNLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Metadata common to every wasm source
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WasmMetadata {
+    /// Module name, used when linking several modules
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Expected SHA-256 of the module, hex encoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Detached signature of the module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    /// SLSA provenance attestation of the module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Capabilities the module declares it needs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+}
+
+/// A wasm module source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Wasm {
+    /// A wasm file on disk
+    File {
+        /// Path to the wasm file
+        path: PathBuf,
+        /// Module metadata
+        #[serde(flatten)]
+        meta: WasmMetadata,
+    },
+    /// Inline wasm bytes, base64 encoded in JSON
+    Data {
+        /// The wasm bytes
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+        /// Module metadata
+        #[serde(flatten)]
+        meta: WasmMetadata,
+    },
+    /// A wasm module fetched over HTTP
+    Url {
+        /// The URL to download the module from
+        url: String,
+        /// Headers sent with the download request
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        headers: BTreeMap<String, String>,
+        /// Module metadata
+        #[serde(flatten)]
+        meta: WasmMetadata,
+    },
+    /// A wasm artifact pulled from an OCI registry
+    Oci {
+        /// Image reference, e.g. `ghcr.io/org/plugin:1.2.0`
+        oci: String,
+        /// Registry credentials, if the image is private
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        auth: Option<OciAuth>,
+        /// Module metadata
+        #[serde(flatten)]
+        meta: WasmMetadata,
+    },
+}
+
+impl Wasm {
+    /// Create a wasm source from a file
+    pub fn file(path: impl AsRef<Path>) -> Self {
+        Wasm::File {
+            path: path.as_ref().to_path_buf(),
+            meta: WasmMetadata::default(),
+        }
+    }
+
+    /// Create a wasm source from bytes
+    pub fn data(data: impl Into<Vec<u8>>) -> Self {
+        Wasm::Data {
+            data: data.into(),
+            meta: WasmMetadata::default(),
+        }
+    }
+
+    /// Create a wasm source from a URL
+    pub fn url(url: impl Into<String>) -> Self {
+        Wasm::Url {
+            url: url.into(),
+            headers: BTreeMap::new(),
+            meta: WasmMetadata::default(),
+        }
+    }
+
+    /// Create a wasm source from an OCI image reference
+    pub fn oci(image: impl Into<String>) -> Self {
+        Wasm::Oci {
+            oci: image.into(),
+            auth: None,
+            meta: WasmMetadata::default(),
+        }
+    }
+
+    /// Set the registry credentials for an OCI source
+    ///
+    /// Other sources are returned unchanged.
+    pub fn with_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        if let Wasm::Oci { auth, .. } = &mut self {
+            *auth = Some(OciAuth {
+                username: username.into(),
+                password: password.into(),
+            });
+        }
+        self
+    }
+
+    /// Get the module metadata
+    pub fn meta(&self) -> &WasmMetadata {
+        match self {
+            Wasm::File { meta, .. }
+            | Wasm::Data { meta, .. }
+            | Wasm::Url { meta, .. }
+            | Wasm::Oci { meta, .. } => meta,
+        }
+    }
+
+    fn meta_mut(&mut self) -> &mut WasmMetadata {
+        match self {
+            Wasm::File { meta, .. }
+            | Wasm::Data { meta, .. }
+            | Wasm::Url { meta, .. }
+            | Wasm::Oci { meta, .. } => meta,
+        }
+    }
+
+    /// Set the module name
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.meta_mut().name = Some(name.into());
+        self
+    }
+
+    /// Pin the module to a SHA-256 digest, hex encoded
+    ///
+    /// Loading fails if the module's contents do not match.
+    pub fn with_hash(mut self, sha256: impl Into<String>) -> Self {
+        self.meta_mut().hash = Some(sha256.into());
+        self
+    }
+
+    /// Attach the module's detached signature, checked against the manifest's trusted keys
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.meta_mut().signature = Some(signature);
+        self
+    }
+
+    /// Attach the module's provenance, checked if the manifest requires provenance
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.meta_mut().provenance = Some(provenance);
+        self
+    }
+
+    /// Declare capabilities the module needs, such as those published in
+    /// its registry metadata, checked against what the manifest grants
+    pub fn with_capabilities(mut self, capabilities: impl IntoIterator<Item = Capability>) -> Self {
+        self.meta_mut().capabilities.extend(capabilities);
+        self
+    }
+
+    /// Get the module name
+    pub fn name(&self) -> Option<&str> {
+        self.meta().name.as_deref()
+    }
+
+    /// Load the wasm bytes, verifying them against the pinned hash if there is one
+    ///
+    /// Downloads pinned to a hash are cached in `download_cache_dir()` and
+    /// served from there on later loads.
+    pub fn load(&self) -> Result<Vec<u8>, Error> {
+        let data = match self {
+            Wasm::File { path, .. } => std::fs::read(path)?,
+            Wasm::Data { data, .. } => data.clone(),
+            Wasm::Url { url, headers, meta } => match &meta.hash {
+                Some(hash) => {
+                    let cached = download_cache_dir().join(format!("{}.wasm", hash));
+                    match std::fs::read(&cached) {
+                        Ok(data) if sha256_hex(&data).eq_ignore_ascii_case(hash) => data,
+                        _ => {
+                            let data = download(url, headers)?;
+                            verify(&data, hash)?;
+                            store(&cached, &data)?;
+                            data
+                        }
+                    }
+                }
+                None => download(url, headers)?,
+            },
+            Wasm::Oci { oci, auth, .. } => oci::pull(oci, auth.as_ref())?,
+        };
+        if let Some(hash) = &self.meta().hash {
+            verify(&data, hash)?;
+        }
+        Ok(data)
+    }
+}
+
+/// Get the directory where pinned downloads are cached
+///
+/// This is `$EXTISM_CACHE_DIR/wasm` if set, otherwise `extism/wasm` in the
+/// system temporary directory. Entries are named by their SHA-256 and are
+/// re-verified on every load.
+pub fn download_cache_dir() -> PathBuf {
+    let base = std::env::var_os("EXTISM_CACHE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("extism"));
+    base.join("wasm")
+}
+
+/// Download a wasm module
+fn download(url: &str, headers: &BTreeMap<String, String>) -> Result<Vec<u8>, Error> {
+    let mut request = ureq::get(url);
+    for (key, value) in headers {
+        request = request.header(key, value);
+    }
+    let mut response = request
+        .call()
+        .map_err(|e| Error::Fetch(format!("{}: {}", url, e)))?;
+    response
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_SIZE)
+        .read_to_vec()
+        .map_err(|e| Error::Fetch(format!("{}: {}", url, e)))
+}
+
+/// Check that `data` has the expected SHA-256
+fn verify(data: &[u8], expected: &str) -> Result<(), Error> {
+    let actual = sha256_hex(data);
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(Error::HashMismatch {
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Write a download cache entry, via a temporary file so readers never see a partial entry
+fn store(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Memory limits for a plugin
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryOptions {
+    /// Maximum number of 64 KiB wasm pages the plugin may use, counting the
+    /// host memory held for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_pages: Option<u32>,
+}
+
+/// Plugin sources and policy
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The wasm modules making up the plugin; the last one is the main module
+    pub wasm: Vec<Wasm>,
+    /// Memory limits
+    #[serde(default)]
+    pub memory: MemoryOptions,
+    /// Configuration values readable by the plugin
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, String>,
+    /// Hosts the plugin may send HTTP requests to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Proxy URL for HTTP requests made by the plugin
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_proxy: Option<String>,
+    /// Host directories mapped to guest paths
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_paths: Option<BTreeMap<PathBuf, PathBuf>>,
+    /// Maximum duration of a single call, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    /// Whether the plugin may use WASI
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub wasi: bool,
+    /// Environment variables visible to the plugin through WASI
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Public keys, base64 encoded, that every wasm module must be signed with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_keys: Vec<String>,
+    /// Whether every wasm module must have provenance signed by a trusted builder
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_provenance: bool,
+    /// Public keys, base64 encoded, of the builders whose provenance is trusted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_builders: Vec<String>,
+    /// Whether modules declaring capabilities the manifest does not grant
+    /// fail to load, rather than loading with a warning
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub enforce_capabilities: bool,
+}
+
+impl Manifest {
+    /// Create a manifest from wasm sources
+    pub fn new(wasm: impl IntoIterator<Item = Wasm>) -> Self {
+        Self {
+            wasm: wasm.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Parse a manifest from JSON
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Add a wasm source
+    pub fn with_wasm(mut self, wasm: Wasm) -> Self {
+        self.wasm.push(wasm);
+        self
+    }
+
+    /// Add a wasm module pulled from an OCI registry, e.g. `ghcr.io/org/plugin:1.2.0`
+    pub fn with_wasm_oci(self, image: impl Into<String>) -> Self {
+        self.with_wasm(Wasm::oci(image))
+    }
+
+    /// Add a wasm module downloaded from `url`, pinned to its hex encoded SHA-256
+    pub fn with_wasm_url(self, url: impl Into<String>, sha256: impl Into<String>) -> Self {
+        self.with_wasm(Wasm::url(url).with_hash(sha256))
+    }
+
+    /// Set a configuration value
+    pub fn with_config_key(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set configuration values
+    pub fn with_config(
+        mut self,
+        config: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.config
+            .extend(config.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Allow HTTP requests to a host, which may be a wildcard pattern such as `*.example.com`
+    pub fn with_allowed_host(mut self, host: impl Into<String>) -> Self {
+        self.allowed_hosts
+            .get_or_insert_with(Vec::new)
+            .push(host.into());
+        self
+    }
+
+    /// Send the plugin's HTTP requests through a proxy, e.g. `http://proxy:3128`
+    pub fn with_http_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.http_proxy = Some(proxy.into());
+        self
+    }
+
+    /// Allow the plugin to use WASI
+    ///
+    /// Only the `allowed_paths` directories and `env` variables are visible to
+    /// it; its stdout and stderr are forwarded to the host log.
+    pub fn with_wasi(mut self, wasi: bool) -> Self {
+        self.wasi = wasi;
+        self
+    }
+
+    /// Set an environment variable visible to the plugin through WASI
+    pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Map a host directory to a path inside the plugin
+    ///
+    /// Prefix the host path with `ro:` to mount it read-only. Paths are only
+    /// accessible when WASI is enabled.
+    pub fn with_allowed_path(mut self, host: impl AsRef<Path>, guest: impl AsRef<Path>) -> Self {
+        self.allowed_paths
+            .get_or_insert_with(BTreeMap::new)
+            .insert(host.as_ref().to_path_buf(), guest.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the maximum number of 64 KiB wasm pages the plugin may use
+    pub fn with_memory_max_pages(mut self, max_pages: u32) -> Self {
+        self.memory.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Set the maximum duration of a single call
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Get the maximum duration of a single call
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+
+    /// Only load wasm modules signed with one of the trusted keys
+    ///
+    /// Once a key is trusted, unsigned modules and modules whose signature
+    /// does not verify fail to load with `Error::Signature`.
+    pub fn with_trust_policy(mut self, policy: TrustPolicy) -> Self {
+        self.trusted_keys.extend(policy.trusted_keys);
+        self
+    }
+
+    /// Get the policy for the signatures of the wasm modules
+    pub fn trust_policy(&self) -> TrustPolicy {
+        TrustPolicy {
+            trusted_keys: self.trusted_keys.clone(),
+        }
+    }
+
+    /// Apply a provenance policy to the wasm modules
+    ///
+    /// If the policy requires provenance, modules without a SLSA
+    /// attestation of their contents signed by a trusted builder fail to
+    /// load with `Error::Provenance`.
+    pub fn with_provenance_policy(mut self, policy: ProvenancePolicy) -> Self {
+        self.require_provenance |= policy.required;
+        self.trusted_builders.extend(policy.trusted_builders);
+        self
+    }
+
+    /// Refuse to load modules that declare capabilities the manifest does
+    /// not grant, with `Error::MissingCapabilities`, instead of warning
+    pub fn with_enforced_capabilities(mut self, enforce: bool) -> Self {
+        self.enforce_capabilities = enforce;
+        self
+    }
+
+    /// Check if the manifest grants a capability
+    ///
+    /// `function:` capabilities depend on the linked modules and are checked
+    /// when the plugin is compiled.
+    pub fn grants(&self, capability: &Capability) -> bool {
+        match capability {
+            Capability::Http => self
+                .allowed_hosts
+                .as_ref()
+                .is_some_and(|hosts| !hosts.is_empty()),
+            Capability::Wasi => self.wasi,
+            Capability::WasiFs => {
+                self.wasi
+                    && self
+                        .allowed_paths
+                        .as_ref()
+                        .is_some_and(|paths| !paths.is_empty())
+            }
+            Capability::Kv => true,
+            Capability::Function(_) => false,
+        }
+    }
+
+    /// Get the policy for the provenance of the wasm modules
+    pub fn provenance_policy(&self) -> ProvenancePolicy {
+        ProvenancePolicy {
+            required: self.require_provenance,
+            trusted_builders: self.trusted_builders.clone(),
+        }
+    }
+}
+
+/// Serde support for base64 encoded bytes
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,256 @@
+//! Pulling wasm modules from OCI registries
+//!
+//! Plugins are stored as OCI artifacts whose manifest has a single wasm
+//! layer, as pushed by tools like `oras push <ref> plugin.wasm:application/vnd.wasm.content.layer.v1+wasm`.
+//! Registries that require a token are handled with the standard bearer
+//! token flow, anonymously or with the credentials given in the manifest.
+
+use std::collections::BTreeMap;
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use super::cache::sha256_hex;
+use super::Error;
+
+/// Registry used for references without one, as with `docker pull`
+const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
+
+/// Manifest media types accepted from the registry
+const MANIFEST_TYPES: &str =
+    "application/vnd.oci.image.manifest.v1+json, application/vnd.docker.distribution.manifest.v2+json";
+
+/// Maximum size of a wasm layer
+const MAX_LAYER_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Credentials for a private registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OciAuth {
+    /// Registry user name
+    pub username: String,
+    /// Registry password or access token
+    pub password: String,
+}
+
+/// A parsed image reference such as `ghcr.io/org/plugin:1.2.0`
+#[derive(Debug, PartialEq, Eq)]
+struct Reference {
+    registry: String,
+    repository: String,
+    /// Tag or `sha256:` digest
+    reference: String,
+}
+
+impl Reference {
+    fn parse(s: &str) -> Result<Self, Error> {
+        let invalid = || Error::Manifest(format!("Invalid OCI reference: {}", s));
+        let (name, reference) = match s.split_once('@') {
+            Some((name, digest)) => (name, digest.to_string()),
+            None => match s.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (s, "latest".to_string()),
+            },
+        };
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), rest.to_string())
+            }
+            Some(_) => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+            None => (DEFAULT_REGISTRY.to_string(), format!("library/{}", name)),
+        };
+        if repository.is_empty() || reference.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Reference {
+            registry,
+            repository,
+            reference,
+        })
+    }
+
+    /// Base URL of the registry API; local registries are spoken to over plain HTTP
+    fn base_url(&self) -> String {
+        let host = self.registry.split(':').next().unwrap_or_default();
+        let scheme = if host == "localhost" || host == "127.0.0.1" {
+            "http"
+        } else {
+            "https"
+        };
+        format!("{}://{}/v2/{}", scheme, self.registry, self.repository)
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageManifest {
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Pull the wasm layer of an OCI artifact
+pub(crate) fn pull(image: &str, auth: Option<&OciAuth>) -> Result<Vec<u8>, Error> {
+    let reference = Reference::parse(image)?;
+    let mut client = Client {
+        reference: &reference,
+        auth,
+        authorization: auth.map(basic),
+    };
+
+    let manifest = client.get(
+        &format!("manifests/{}", reference.reference),
+        MANIFEST_TYPES,
+    )?;
+    let manifest: ImageManifest = serde_json::from_slice(&manifest)
+        .map_err(|e| Error::Fetch(format!("{}: invalid image manifest: {}", image, e)))?;
+    let layer = match manifest
+        .layers
+        .iter()
+        .find(|layer| layer.media_type.ends_with("wasm"))
+    {
+        Some(layer) => layer,
+        None if manifest.layers.len() == 1 => &manifest.layers[0],
+        None => {
+            return Err(Error::Fetch(format!("{}: no wasm layer found", image)));
+        }
+    };
+
+    let data = client.get(&format!("blobs/{}", layer.digest), "*/*")?;
+    let expected = layer.digest.strip_prefix("sha256:").unwrap_or_default();
+    let actual = sha256_hex(&data);
+    if !expected.eq_ignore_ascii_case(&actual) {
+        return Err(Error::HashMismatch {
+            expected: layer.digest.clone(),
+            actual: format!("sha256:{}", actual),
+        });
+    }
+    Ok(data)
+}
+
+/// Registry client that negotiates a bearer token on the first 401
+struct Client<'a> {
+    reference: &'a Reference,
+    auth: Option<&'a OciAuth>,
+    authorization: Option<String>,
+}
+
+impl Client<'_> {
+    fn get(&mut self, path: &str, accept: &str) -> Result<Vec<u8>, Error> {
+        let url = format!("{}/{}", self.reference.base_url(), path);
+        let mut retried = false;
+        loop {
+            let mut request = ureq::get(&url)
+                .header("Accept", accept)
+                .config()
+                .http_status_as_error(false)
+                .build();
+            if let Some(authorization) = &self.authorization {
+                request = request.header("Authorization", authorization);
+            }
+            let mut response = request.call().map_err(|e| fetch_error(&url, e))?;
+            let status = response.status().as_u16();
+            if status == 401 && !retried {
+                let challenge = response
+                    .headers()
+                    .get("www-authenticate")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                self.authorization = Some(self.authorize(&challenge)?);
+                retried = true;
+                continue;
+            }
+            if !(200..300).contains(&status) {
+                return Err(Error::Fetch(format!("{}: HTTP status {}", url, status)));
+            }
+            return response
+                .body_mut()
+                .with_config()
+                .limit(MAX_LAYER_SIZE)
+                .read_to_vec()
+                .map_err(|e| fetch_error(&url, e));
+        }
+    }
+
+    /// Answer a `WWW-Authenticate` challenge with an `Authorization` header value
+    fn authorize(&self, challenge: &str) -> Result<String, Error> {
+        let Some(params) = challenge.strip_prefix("Bearer ") else {
+            return self.auth.map(basic).ok_or_else(|| {
+                Error::Fetch(format!(
+                    "{}: registry requires credentials",
+                    self.reference.registry
+                ))
+            });
+        };
+        let params = parse_challenge(params);
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| Error::Fetch(format!("Invalid registry challenge: {}", challenge)))?;
+        let mut request = ureq::get(realm.as_str());
+        if let Some(service) = params.get("service") {
+            request = request.query("service", service);
+        }
+        let scope = params
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.reference.repository));
+        request = request.query("scope", scope);
+        if let Some(auth) = self.auth {
+            request = request.header("Authorization", basic(auth));
+        }
+        let body = request
+            .call()
+            .map_err(|e| fetch_error(realm, e))?
+            .body_mut()
+            .read_to_vec()
+            .map_err(|e| fetch_error(realm, e))?;
+        let token: TokenResponse = serde_json::from_slice(&body)
+            .map_err(|e| Error::Fetch(format!("{}: invalid token response: {}", realm, e)))?;
+        token
+            .token
+            .or(token.access_token)
+            .map(|token| format!("Bearer {}", token))
+            .ok_or_else(|| Error::Fetch(format!("{}: no token in response", realm)))
+    }
+}
+
+/// Parse the `key="value"` pairs of a bearer challenge
+fn parse_challenge(params: &str) -> BTreeMap<String, String> {
+    let mut out = BTreeMap::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, tail) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        out.insert(key, value.to_string());
+        rest = tail;
+    }
+    out
+}
+
+fn basic(auth: &OciAuth) -> String {
+    let credentials = format!("{}:{}", auth.username, auth.password);
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(credentials)
+    )
+}
+
+fn fetch_error(url: &str, e: ureq::Error) -> Error {
+    Error::Fetch(format!("{}: {}", url, e))
+}
@@ -0,0 +1,144 @@
+//! Options for a single plugin call
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Options for a single plugin call
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) fuel: Option<u64>,
+    pub(crate) cancel: Vec<CancelHandle>,
+    pub(crate) config: BTreeMap<String, String>,
+    pub(crate) record: Option<PathBuf>,
+}
+
+impl CallOptions {
+    /// Create default call options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the call with `CallError::Timeout` if it runs longer than `timeout`
+    ///
+    /// Overrides the manifest's `timeout_ms` for this call.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail the call with `CallError::OutOfFuel` once it has consumed `fuel` units
+    ///
+    /// Fuel is consumed roughly once per wasm instruction, so it bounds CPU
+    /// usage deterministically regardless of host load.
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Cancel the call with `CallError::Cancelled` when `handle` is cancelled
+    ///
+    /// Unlike `Plugin::cancel_handle()`, a handle created with
+    /// `CancelHandle::new()` can be shared by calls on different instances,
+    /// e.g. every plugin call made on behalf of one request.
+    pub fn cancel_with(mut self, handle: CancelHandle) -> Self {
+        self.cancel.push(handle);
+        self
+    }
+
+    /// Layer config values over the manifest config for this call
+    ///
+    /// The plugin sees these values through `Host::config()` during the call
+    /// only; the instance keeps its own config for later calls.
+    pub fn config_override<K, V>(mut self, config: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.config
+            .extend(config.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Record the call's input, host calls and outcome to a `Trace` file at
+    /// `path`, whether it succeeds or fails
+    ///
+    /// The trace can be replayed against the plugin's code with
+    /// `MockHost::replay`. Failing to write it is logged and does not fail
+    /// the call.
+    pub fn record(mut self, path: impl Into<PathBuf>) -> Self {
+        self.record = Some(path.into());
+        self
+    }
+}
+
+/// A handle for cancelling plugin calls from another thread
+///
+/// A cancelled call is interrupted at the next epoch tick and fails with
+/// `CallError::Cancelled`, poisoning its instance.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Create a handle that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the calls watching this handle
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the handle has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clear the cancellation so the handle can be reused
+    pub(crate) fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The result of a successful plugin call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallOutput {
+    /// The output set by the plugin
+    pub output: Vec<u8>,
+    /// Statistics about the call
+    pub stats: CallStats,
+    /// Resources the call consumed
+    pub usage: ResourceUsage,
+}
+
+/// Statistics about a plugin call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallStats {
+    /// The function that was called
+    pub function: String,
+    /// Size of the input in bytes
+    pub input_len: usize,
+    /// Size of the output in bytes
+    pub output_len: usize,
+}
+
+/// Resources consumed by a plugin call, for metering and billing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ResourceUsage {
+    /// Most memory the plugin held during the call: its linear memory and
+    /// the host memory held for it, such as memory blocks, output and vars
+    pub peak_memory_bytes: usize,
+    /// Fuel consumed by the call
+    pub fuel_used: u64,
+    /// Wall-clock duration of the call
+    pub wall_time: Duration,
+    /// Number of host functions the plugin called
+    pub host_calls: u64,
+    /// Number of host memory blocks allocated for the call, by the plugin
+    /// or to pass it config values, vars and HTTP responses
+    pub allocations: u64,
+}
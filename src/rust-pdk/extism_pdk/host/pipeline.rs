@@ -0,0 +1,145 @@
+//! Chaining plugins so the output of one becomes the input of the next
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::{CallError, Plugin};
+
+/// Values shared between the stages of a pipeline run
+///
+/// Before each stage runs, the context is copied into the plugin's vars, and
+/// the plugin's vars are copied back afterwards, so a plugin can hand values
+/// to later stages with `Host::var_set`.
+pub type Context = BTreeMap<String, Vec<u8>>;
+
+/// What a pipeline does when a stage fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop the pipeline and return the error
+    #[default]
+    Abort,
+    /// Pass the stage's input on to the next stage unchanged
+    Skip,
+    /// Call the stage again up to this many times while the plugin reports a
+    /// retryable error, then abort
+    Retry(u32),
+}
+
+/// A pipeline stage failed
+#[derive(Debug)]
+pub struct PipelineError {
+    /// Index of the failed stage
+    pub stage: usize,
+    /// Function the stage called
+    pub function: String,
+    /// The call error
+    pub error: CallError,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Pipeline stage {} ({}) failed: {}",
+            self.stage, self.function, self.error
+        )
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+struct Stage {
+    plugin: Plugin,
+    function: String,
+    on_error: ErrorPolicy,
+}
+
+/// A sequence of plugin calls, each fed the previous call's output
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Create an empty pipeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a stage that calls `function` on `plugin`, aborting on error
+    pub fn stage(self, plugin: Plugin, function: impl Into<String>) -> Self {
+        self.stage_with_policy(plugin, function, ErrorPolicy::Abort)
+    }
+
+    /// Add a stage with an error policy
+    pub fn stage_with_policy(
+        mut self,
+        plugin: Plugin,
+        function: impl Into<String>,
+        on_error: ErrorPolicy,
+    ) -> Self {
+        self.stages.push(Stage {
+            plugin,
+            function: function.into(),
+            on_error,
+        });
+        self
+    }
+
+    /// Get the number of stages
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Check if the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run every stage in order, returning the last stage's output
+    pub fn run(&mut self, input: impl Into<Vec<u8>>) -> Result<Vec<u8>, PipelineError> {
+        self.run_with_context(input, &mut Context::new())
+    }
+
+    /// Run every stage in order with a shared context
+    pub fn run_with_context(
+        &mut self,
+        input: impl Into<Vec<u8>>,
+        context: &mut Context,
+    ) -> Result<Vec<u8>, PipelineError> {
+        let mut data = input.into();
+        for (index, stage) in self.stages.iter_mut().enumerate() {
+            for (key, value) in context.iter() {
+                stage.plugin.set_var(key.clone(), value.clone());
+            }
+            let mut attempts = 0;
+            let result = loop {
+                match stage.plugin.call(&stage.function, &data) {
+                    Err(CallError::Plugin(e)) if e.is_retryable() => match stage.on_error {
+                        ErrorPolicy::Retry(max) if attempts < max => attempts += 1,
+                        _ => break Err(CallError::Plugin(e)),
+                    },
+                    result => break result,
+                }
+            };
+            context.extend(
+                stage
+                    .plugin
+                    .vars()
+                    .map(|(key, value)| (key.to_string(), value.to_vec())),
+            );
+            match result {
+                Ok(output) => data = output,
+                Err(_) if stage.on_error == ErrorPolicy::Skip => {}
+                Err(error) => {
+                    return Err(PipelineError {
+                        stage: index,
+                        function: stage.function.clone(),
+                        error,
+                    })
+                }
+            }
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,349 @@
+//! A pool of plugin instances for concurrent calls
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{CallError, CallOptions, CallOutput, CompiledPlugin, Error, Manifest, Plugin};
+
+/// How long a pool at its restart limit waits before trying again
+pub const DEFAULT_RESTART_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Instances currently owned by the pool
+struct Instances {
+    /// Instances waiting to be checked out
+    idle: Vec<Plugin>,
+    /// Number of instances that exist, checked out or idle
+    live: usize,
+    /// Instances created to replace discarded ones
+    restarts: u64,
+    /// Discarded instances not yet replaced
+    replacements: usize,
+    /// Instances discarded after being poisoned or failing to instantiate
+    discarded: u64,
+    /// Instances discarded since an instance was last released healthy
+    consecutive_failures: u32,
+    /// When the last instance was discarded
+    last_failure: Option<Instant>,
+}
+
+/// How a pool replaces instances poisoned by a trap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestartPolicy {
+    /// Stop replacing instances after this many consecutive failures, until an
+    /// instance is released healthy again or the pool's restart cooldown has
+    /// passed; `None` means no limit
+    pub max_restarts: Option<u32>,
+    /// Delay before replacing an instance, doubled for each consecutive failure
+    pub backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// Get the delay before a restart after `failures` consecutive failures
+    fn delay(&self, failures: u32) -> Duration {
+        let shift = failures.saturating_sub(1).min(16);
+        self.backoff.saturating_mul(1 << shift)
+    }
+}
+
+/// Counters describing a pool's instances
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Maximum number of instances
+    pub size: usize,
+    /// Instances that exist, checked out or idle
+    pub live: usize,
+    /// Instances not checked out
+    pub idle: usize,
+    /// Instances created to replace discarded ones
+    pub restarts: u64,
+    /// Instances discarded after being poisoned or failing to instantiate
+    pub discarded: u64,
+    /// Instances discarded since an instance was last released healthy; a
+    /// growing value points to a flapping plugin
+    pub consecutive_failures: u32,
+}
+
+/// A fixed-size pool of instances of the same plugin
+///
+/// A single `Plugin` can only serve one call at a time; the pool hands out
+/// instances to concurrent callers and blocks when all of them are busy.
+/// Instances poisoned by a trap are discarded and replaced on demand,
+/// following the pool's `RestartPolicy`.
+pub struct PluginPool {
+    compiled: CompiledPlugin,
+    size: usize,
+    policy: RestartPolicy,
+    cooldown: Duration,
+    instances: Mutex<Instances>,
+    available: Condvar,
+}
+
+impl PluginPool {
+    /// Compile a plugin and create `size` instances of it
+    pub fn new(manifest: &Manifest, size: usize) -> Result<Self, Error> {
+        Self::from_compiled(CompiledPlugin::new(manifest)?, size)
+    }
+
+    /// Create `size` instances of a compiled plugin
+    pub fn from_compiled(compiled: CompiledPlugin, size: usize) -> Result<Self, Error> {
+        let pool = Self::lazy_from_compiled(compiled, size)?;
+        pool.warm_up(size)?;
+        Ok(pool)
+    }
+
+    /// Compile a plugin for a pool of up to `size` instances, without creating any
+    ///
+    /// Instances are created on demand as calls need them, or ahead of time
+    /// with `warm_up()`, so hosts with many rarely used plugins only pay for
+    /// the memory of instances actually in use.
+    pub fn lazy(manifest: &Manifest, size: usize) -> Result<Self, Error> {
+        Self::lazy_from_compiled(CompiledPlugin::new(manifest)?, size)
+    }
+
+    /// Create a pool of up to `size` instances of a compiled plugin, without creating any
+    pub fn lazy_from_compiled(compiled: CompiledPlugin, size: usize) -> Result<Self, Error> {
+        if size == 0 {
+            return Err(Error::Manifest("pool size must be at least 1".to_string()));
+        }
+        Ok(Self {
+            compiled,
+            size,
+            policy: RestartPolicy::default(),
+            cooldown: DEFAULT_RESTART_COOLDOWN,
+            instances: Mutex::new(Instances {
+                idle: Vec::with_capacity(size),
+                live: 0,
+                restarts: 0,
+                replacements: 0,
+                discarded: 0,
+                consecutive_failures: 0,
+                last_failure: None,
+            }),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Set how poisoned instances are replaced
+    pub fn with_restart_policy(mut self, policy: RestartPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Set how long the pool waits after the last failure, once the restart
+    /// policy's limit is reached, before it replaces instances again
+    ///
+    /// Defaults to `DEFAULT_RESTART_COOLDOWN`. After the cooldown the
+    /// consecutive failures are reset, so the plugin gets `max_restarts`
+    /// more restarts.
+    pub fn with_restart_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Get the maximum number of instances
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get the number of instances not checked out
+    pub fn idle(&self) -> usize {
+        self.lock().idle.len()
+    }
+
+    /// Get counters describing the pool's instances
+    pub fn stats(&self) -> PoolStats {
+        let instances = self.lock();
+        PoolStats {
+            size: self.size,
+            live: instances.live,
+            idle: instances.idle.len(),
+            restarts: instances.restarts,
+            discarded: instances.discarded,
+            consecutive_failures: instances.consecutive_failures,
+        }
+    }
+
+    /// Check out an instance, blocking until one is available
+    ///
+    /// Fails with `Error::RestartLimitExceeded` if a discarded instance needs
+    /// replacing but the restart policy's limit has been reached, until the
+    /// restart cooldown has passed.
+    pub fn get(&self) -> Result<PooledPlugin<'_>, Error> {
+        let mut instances = self.lock();
+        loop {
+            if let Some(plugin) = instances.idle.pop() {
+                return Ok(PooledPlugin {
+                    pool: self,
+                    plugin: Some(plugin),
+                });
+            }
+            if instances.live < self.size {
+                // Create an instance, for the first time in a lazy pool or to
+                // replace one that was discarded after being poisoned
+                let failures = instances.consecutive_failures;
+                if self.policy.max_restarts.is_some_and(|max| failures > max) {
+                    let cooled_down = instances
+                        .last_failure
+                        .is_some_and(|last| last.elapsed() >= self.cooldown);
+                    if !cooled_down {
+                        return Err(Error::RestartLimitExceeded);
+                    }
+                    instances.consecutive_failures = 0;
+                    continue;
+                }
+                let ready = instances
+                    .last_failure
+                    .map(|last| last + self.policy.delay(failures));
+                let wait = ready.and_then(|ready| ready.checked_duration_since(Instant::now()));
+                if let Some(wait) = wait.filter(|wait| !wait.is_zero()) {
+                    instances = self
+                        .available
+                        .wait_timeout(instances, wait)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                    continue;
+                }
+                instances.live += 1;
+                drop(instances);
+                return match self.start() {
+                    Ok(plugin) => Ok(PooledPlugin {
+                        pool: self,
+                        plugin: Some(plugin),
+                    }),
+                    Err(e) => {
+                        self.discard();
+                        Err(e)
+                    }
+                };
+            }
+            instances = self
+                .available
+                .wait(instances)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Create instances until at least `n` are idle, up to the pool's size
+    pub fn warm_up(&self, n: usize) -> Result<(), Error> {
+        loop {
+            let mut instances = self.lock();
+            if instances.idle.len() >= n || instances.live >= self.size {
+                return Ok(());
+            }
+            instances.live += 1;
+            drop(instances);
+            match self.start() {
+                Ok(plugin) => {
+                    self.lock().idle.push(plugin);
+                    self.available.notify_one();
+                }
+                Err(e) => {
+                    self.lock().live -= 1;
+                    self.available.notify_one();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Instantiate the plugin for a slot already counted as live
+    fn start(&self) -> Result<Plugin, Error> {
+        let plugin = self.compiled.instantiate()?;
+        let mut instances = self.lock();
+        if instances.replacements > 0 {
+            instances.replacements -= 1;
+            instances.restarts += 1;
+        }
+        Ok(plugin)
+    }
+
+    /// Call a plugin function on an available instance
+    pub fn call(&self, name: &str, input: impl AsRef<[u8]>) -> Result<Vec<u8>, CallError> {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call(name, input)
+    }
+
+    /// Call a plugin function with per-call options on an available instance
+    pub fn call_with_options(
+        &self,
+        name: &str,
+        input: impl AsRef<[u8]>,
+        options: &CallOptions,
+    ) -> Result<CallOutput, CallError> {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call_with_options(name, input, options)
+    }
+
+    /// Call a plugin function with JSON input on an available instance
+    pub fn call_json<I, O>(&self, name: &str, input: &I) -> Result<O, CallError>
+    where
+        I: serde::Serialize,
+        O: serde::de::DeserializeOwned,
+    {
+        self.get()
+            .map_err(CallError::Instantiate)?
+            .call_json(name, input)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Instances> {
+        self.instances.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Return an instance to the pool
+    fn release(&self, plugin: Plugin) {
+        if plugin.is_poisoned() {
+            self.discard();
+            return;
+        }
+        let mut instances = self.lock();
+        instances.consecutive_failures = 0;
+        instances.idle.push(plugin);
+        drop(instances);
+        self.available.notify_one();
+    }
+
+    /// Forget an instance so a replacement can be created
+    fn discard(&self) {
+        let mut instances = self.lock();
+        instances.live -= 1;
+        instances.replacements += 1;
+        instances.discarded += 1;
+        instances.consecutive_failures += 1;
+        instances.last_failure = Some(Instant::now());
+        drop(instances);
+        self.available.notify_one();
+    }
+}
+
+/// A plugin instance checked out of a `PluginPool`
+///
+/// The instance is returned to the pool when dropped.
+pub struct PooledPlugin<'a> {
+    pool: &'a PluginPool,
+    plugin: Option<Plugin>,
+}
+
+impl Deref for PooledPlugin<'_> {
+    type Target = Plugin;
+
+    fn deref(&self) -> &Plugin {
+        self.plugin.as_ref().expect("plugin already released")
+    }
+}
+
+impl DerefMut for PooledPlugin<'_> {
+    fn deref_mut(&mut self) -> &mut Plugin {
+        self.plugin.as_mut().expect("plugin already released")
+    }
+}
+
+impl Drop for PooledPlugin<'_> {
+    fn drop(&mut self) {
+        if let Some(plugin) = self.plugin.take() {
+            self.pool.release(plugin);
+        }
+    }
+}
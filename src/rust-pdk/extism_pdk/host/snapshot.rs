@@ -0,0 +1,183 @@
+//! Checkpointing plugin instances
+//!
+//! A snapshot holds the plugin's linear memory, its exported mutable globals
+//! and its vars. It is restored into a fresh instance of the same plugin, so
+//! state that is not exported (such as the stack pointer) starts from its
+//! initial value, which is what it is between calls.
+//!
+//! The format is a magic header followed by length-prefixed little-endian
+//! sections: vars, globals, then the raw memory.
+
+use wasmtime::{Extern, Mutability, Val};
+
+use super::limits::PAGE_SIZE;
+use super::{Error, Plugin};
+
+/// Identifies the snapshot format
+const MAGIC: &[u8; 8] = b"XTSNAP01";
+
+/// Name of the memory export that is captured
+const MEMORY_EXPORT: &str = "memory";
+
+impl Plugin {
+    /// Capture the instance's memory, exported mutable globals and vars
+    ///
+    /// Take snapshots between calls; the state of a poisoned instance is not
+    /// worth capturing.
+    pub fn snapshot(&mut self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+
+        let vars = &self.store.data().vars;
+        put_u32(&mut out, vars.len() as u32);
+        for (key, value) in vars {
+            put_bytes(&mut out, key.as_bytes());
+            put_bytes(&mut out, value);
+        }
+
+        let exports: Vec<_> = self
+            .instance
+            .exports(&mut self.store)
+            .filter_map(|export| {
+                let name = export.name().to_string();
+                export.into_global().map(|global| (name, global))
+            })
+            .collect();
+        let mut globals = Vec::new();
+        for (name, global) in exports {
+            if global.ty(&self.store).mutability() != Mutability::Var {
+                continue;
+            }
+            if let Some(bits) = val_bits(&global.get(&mut self.store)) {
+                globals.push((name, bits));
+            }
+        }
+        put_u32(&mut out, globals.len() as u32);
+        for (name, bits) in globals {
+            put_bytes(&mut out, name.as_bytes());
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+
+        let memory = self
+            .instance
+            .get_memory(&mut self.store, MEMORY_EXPORT)
+            .map(|memory| memory.data(&self.store))
+            .unwrap_or_default();
+        out.extend_from_slice(&(memory.len() as u64).to_le_bytes());
+        out.extend_from_slice(memory);
+        out
+    }
+
+    /// Replace the instance's state with a snapshot taken by `snapshot()`
+    ///
+    /// The plugin is re-instantiated before the snapshot is applied, which
+    /// also clears a poisoned instance. WASI file handles are not part of a
+    /// snapshot and start fresh.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader(
+            snapshot
+                .strip_prefix(MAGIC.as_slice())
+                .ok_or_else(|| Error::Snapshot("not a plugin snapshot".to_string()))?,
+        );
+        let mut plugin = self.compiled.instantiate()?;
+
+        let vars = &mut plugin.store.data_mut().vars;
+        for _ in 0..reader.u32()? {
+            let key = String::from_utf8(reader.bytes()?.to_vec())
+                .map_err(|e| Error::Snapshot(e.to_string()))?;
+            vars.insert(key, reader.bytes()?.to_vec());
+        }
+
+        for _ in 0..reader.u32()? {
+            let name = String::from_utf8(reader.bytes()?.to_vec())
+                .map_err(|e| Error::Snapshot(e.to_string()))?;
+            let bits = reader.u64()?;
+            let global = match plugin.instance.get_export(&mut plugin.store, &name) {
+                Some(Extern::Global(global)) => global,
+                _ => return Err(Error::Snapshot(format!("unknown global: {}", name))),
+            };
+            let value = bits_val(&global.get(&mut plugin.store), bits)
+                .ok_or_else(|| Error::Snapshot(format!("unsupported global: {}", name)))?;
+            global
+                .set(&mut plugin.store, value)
+                .map_err(|e| Error::Snapshot(e.to_string()))?;
+        }
+
+        let len = reader.u64()? as usize;
+        let data = reader.take(len)?;
+        if let Some(memory) = plugin.instance.get_memory(&mut plugin.store, MEMORY_EXPORT) {
+            let size = memory.data_size(&plugin.store);
+            if len > size {
+                let pages = (len - size).div_ceil(PAGE_SIZE) as u64;
+                memory
+                    .grow(&mut plugin.store, pages)
+                    .map_err(|_| Error::MemoryLimitExceeded)?;
+            }
+            let memory = memory.data_mut(&mut plugin.store);
+            memory[..len].copy_from_slice(data);
+            memory[len..].fill(0);
+        }
+
+        *self = plugin;
+        Ok(())
+    }
+}
+
+/// Get the bits of a numeric global value
+fn val_bits(val: &Val) -> Option<u64> {
+    match *val {
+        Val::I32(v) => Some(v as u32 as u64),
+        Val::I64(v) => Some(v as u64),
+        Val::F32(v) => Some(v as u64),
+        Val::F64(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Build a value of the same type as `like` from its bits
+fn bits_val(like: &Val, bits: u64) -> Option<Val> {
+    match like {
+        Val::I32(_) => Some(Val::I32(bits as u32 as i32)),
+        Val::I64(_) => Some(Val::I64(bits as i64)),
+        Val::F32(_) => Some(Val::F32(bits as u32)),
+        Val::F64(_) => Some(Val::F64(bits)),
+        _ => None,
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+/// Cursor over the sections of a snapshot
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Snapshot("truncated snapshot".to_string()));
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap_or_default()))
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+}
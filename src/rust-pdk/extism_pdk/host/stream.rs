@@ -0,0 +1,57 @@
+//! Streaming calls that feed a plugin one chunk at a time
+//!
+//! The plugin function is called once per input chunk, and once more with the
+//! `stream:end` var set to signal the end of the stream. Whatever the plugin
+//! writes to the `stream:output` var during a call is handed to the host as an
+//! output chunk, so neither side ever holds the whole dataset.
+
+use super::{CallError, Plugin};
+
+/// Var set on the final call of a stream
+const STREAM_END: &str = "stream:end";
+
+/// Var the plugin writes output chunks to
+const STREAM_OUTPUT: &str = "stream:output";
+
+impl Plugin {
+    /// Call a plugin function once per input chunk, passing each output chunk to `output`
+    ///
+    /// The plugin reads chunks with `Host::read_stream()` and writes output
+    /// with `Host::write_stream()`. A failed call stops the stream.
+    pub fn call_streaming<I, F>(
+        &mut self,
+        name: &str,
+        input: I,
+        mut output: F,
+    ) -> Result<(), CallError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+        F: FnMut(Vec<u8>),
+    {
+        let vars = &mut self.store.data_mut().vars;
+        vars.remove(STREAM_END);
+        vars.remove(STREAM_OUTPUT);
+        for chunk in input {
+            self.stream_chunk(name, chunk.as_ref(), &mut output)?;
+        }
+        self.set_var(STREAM_END, [1]);
+        let result = self.stream_chunk(name, &[], &mut output);
+        self.store.data_mut().vars.remove(STREAM_END);
+        result
+    }
+
+    /// Call the function with one chunk and deliver its output
+    fn stream_chunk(
+        &mut self,
+        name: &str,
+        chunk: &[u8],
+        output: &mut impl FnMut(Vec<u8>),
+    ) -> Result<(), CallError> {
+        self.call(name, chunk)?;
+        if let Some(chunk) = self.store.data_mut().vars.remove(STREAM_OUTPUT) {
+            output(chunk);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! Async plugin calls for tokio hosts
+//!
+//! Calls run on tokio's blocking pool, so awaiting them never stalls the
+//! runtime's worker threads. Dropping a returned future cancels the call: the
+//! plugin is interrupted at its next epoch tick and fails with
+//! `CallError::Cancelled`.
+
+use std::sync::Arc;
+
+use super::{CallError, CallOptions, CallOutput, CancelHandle, Plugin, PluginPool};
+
+/// Cancels a call when the awaiting future goes away
+struct CancelOnDrop(CancelHandle);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Run a call on the blocking pool, cancelling it if the future is dropped
+async fn spawn<T, F>(mut options: CallOptions, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(CallOptions) -> T + Send + 'static,
+{
+    let cancel = CancelHandle::new();
+    options.cancel.push(cancel.clone());
+    let _guard = CancelOnDrop(cancel);
+    match tokio::task::spawn_blocking(move || f(options)).await {
+        Ok(result) => result,
+        Err(e) => match e.try_into_panic() {
+            Ok(panic) => std::panic::resume_unwind(panic),
+            Err(e) => panic!("plugin call task failed: {}", e),
+        },
+    }
+}
+
+impl Plugin {
+    /// Call a plugin function without blocking the async runtime
+    ///
+    /// The plugin is moved onto the blocking pool for the call and handed back
+    /// with the result. If the future is dropped the call is cancelled and the
+    /// instance is discarded.
+    pub async fn call_async(
+        self,
+        name: impl Into<String>,
+        input: impl Into<Vec<u8>>,
+    ) -> (Plugin, Result<Vec<u8>, CallError>) {
+        let (plugin, result) = self
+            .call_async_with_options(name, input, CallOptions::default())
+            .await;
+        (plugin, result.map(|output| output.output))
+    }
+
+    /// Call a plugin function with per-call options without blocking the async runtime
+    pub async fn call_async_with_options(
+        mut self,
+        name: impl Into<String>,
+        input: impl Into<Vec<u8>>,
+        options: CallOptions,
+    ) -> (Plugin, Result<CallOutput, CallError>) {
+        let (name, input) = (name.into(), input.into());
+        spawn(options, move |options| {
+            let result = self.call_with_options(&name, input, &options);
+            (self, result)
+        })
+        .await
+    }
+}
+
+impl PluginPool {
+    /// Call a plugin function on an available instance without blocking the async runtime
+    ///
+    /// Waiting for a free instance also happens on the blocking pool. If the
+    /// future is dropped the call is cancelled and the instance is replaced.
+    pub async fn call_async(
+        self: &Arc<Self>,
+        name: impl Into<String>,
+        input: impl Into<Vec<u8>>,
+    ) -> Result<Vec<u8>, CallError> {
+        self.call_async_with_options(name, input, CallOptions::default())
+            .await
+            .map(|output| output.output)
+    }
+
+    /// Call a plugin function with per-call options without blocking the async runtime
+    pub async fn call_async_with_options(
+        self: &Arc<Self>,
+        name: impl Into<String>,
+        input: impl Into<Vec<u8>>,
+        options: CallOptions,
+    ) -> Result<CallOutput, CallError> {
+        let pool = self.clone();
+        let (name, input) = (name.into(), input.into());
+        spawn(options, move |options| {
+            pool.call_with_options(&name, input, &options)
+        })
+        .await
+    }
+}
@@ -0,0 +1,107 @@
+//! Symbolicated backtraces of plugin traps
+
+use std::fmt;
+
+use wasmtime::{Trap, WasmBacktrace};
+
+/// A frame of a plugin's stack when it trapped
+///
+/// Functions inlined into a wasm function get frames of their own, so a
+/// frame's location is the line that was running rather than the call it
+/// was inlined into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrapFrame {
+    /// Demangled name of the function, when the module names it
+    pub function: Option<String>,
+    /// Source file, when the module keeps its debug info
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// Offset of the instruction in the module's code
+    pub module_offset: Option<usize>,
+}
+
+impl TrapFrame {
+    /// Whether the frame is in the plugin's own code rather than the
+    /// standard library or the crates it depends on
+    pub fn is_plugin_code(&self) -> bool {
+        self.file.as_deref().is_some_and(|file| {
+            !file.starts_with("/rustc/")
+                && !file.contains("/.cargo/registry/")
+                && !file.contains("/library/std/")
+                && !file.contains("/library/core/")
+                && !file.contains("/library/alloc/")
+        })
+    }
+}
+
+impl fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.module_offset) {
+            (Some(file), _) => {
+                write!(f, "{}", file)?;
+                if let Some(line) = self.line {
+                    write!(f, ":{}", line)?;
+                    if let Some(column) = self.column {
+                        write!(f, ":{}", column)?;
+                    }
+                }
+            }
+            (None, Some(offset)) => write!(f, "{:#x}", offset)?,
+            (None, None) => f.write_str("<unknown>")?,
+        }
+        if let Some(function) = &self.function {
+            write!(f, " in {}", function)?;
+        }
+        Ok(())
+    }
+}
+
+/// The frames of a trap, innermost first
+pub(crate) fn frames(error: &wasmtime::Error) -> Vec<TrapFrame> {
+    let Some(backtrace) = error.downcast_ref::<WasmBacktrace>() else {
+        return Vec::new();
+    };
+    let mut frames = Vec::new();
+    for frame in backtrace.frames() {
+        let module_offset = frame.module_offset();
+        if frame.symbols().is_empty() {
+            frames.push(TrapFrame {
+                function: frame.func_name().map(demangle),
+                file: None,
+                line: None,
+                column: None,
+                module_offset,
+            });
+        }
+        for symbol in frame.symbols() {
+            frames.push(TrapFrame {
+                function: symbol.name().or(frame.func_name()).map(demangle),
+                file: symbol.file().map(str::to_string),
+                line: symbol.line(),
+                column: symbol.column(),
+                module_offset,
+            });
+        }
+    }
+    frames
+}
+
+/// The innermost frame in the plugin's own code, which is where a panic or
+/// failed assertion was raised
+pub(crate) fn location(frames: &[TrapFrame]) -> Option<&TrapFrame> {
+    frames.iter().find(|frame| frame.is_plugin_code())
+}
+
+/// What went wrong, without the backtrace wasmtime attaches to traps
+pub(crate) fn message(error: &wasmtime::Error) -> String {
+    match error.downcast_ref::<Trap>() {
+        Some(trap) => trap.to_string(),
+        None if error.is::<WasmBacktrace>() => error.root_cause().to_string(),
+        None => error.to_string(),
+    }
+}
+
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
@@ -0,0 +1,137 @@
+//! WASI support for plugins that use the filesystem, environment or stdio
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use tokio::io::AsyncWrite;
+use wasmtime_wasi::cli::{IsTerminal, StdoutStream};
+use wasmtime_wasi::p1::WasiP1Ctx;
+use wasmtime_wasi::p2::{OutputStream, Pollable, StreamResult};
+use wasmtime_wasi::{FsPerms, WasiCtxBuilder};
+
+use tracing::Level;
+
+use super::log::LogSink;
+use super::Error;
+
+/// Prefix marking an `allowed_paths` entry as read-only
+const READ_ONLY_PREFIX: &str = "ro:";
+
+/// Build the WASI context for an instance
+///
+/// Each `allowed_paths` entry maps a host directory to a guest path; host
+/// paths prefixed with `ro:` are mounted read-only. Plugin stdout and stderr
+/// are forwarded line by line to the plugin's log sink.
+pub(crate) fn context(
+    allowed_paths: &BTreeMap<PathBuf, PathBuf>,
+    env: &BTreeMap<String, String>,
+    log: &LogSink,
+) -> Result<WasiP1Ctx, Error> {
+    let mut builder = WasiCtxBuilder::new();
+    for (host, guest) in allowed_paths {
+        let host = host.to_string_lossy();
+        let (host, perms) = match host.strip_prefix(READ_ONLY_PREFIX) {
+            Some(host) => (host, FsPerms::ReadOnly),
+            None => (&*host, FsPerms::ReadWrite),
+        };
+        builder
+            .preopened_dir(Path::new(host), guest.to_string_lossy(), perms)
+            .map_err(|e| Error::Manifest(format!("cannot open {}: {}", host, e)))?;
+    }
+    for (key, value) in env {
+        builder.env(key, value);
+    }
+    builder.stdout(LogStream::new(log.clone(), Level::INFO));
+    builder.stderr(LogStream::new(log.clone(), Level::WARN));
+    Ok(builder.build_p1())
+}
+
+/// A WASI output stream forwarding complete lines to a log sink
+#[derive(Clone)]
+struct LogStream {
+    log: LogSink,
+    level: Level,
+    pending: Arc<Mutex<Vec<u8>>>,
+}
+
+impl LogStream {
+    fn new(log: LogSink, level: Level) -> Self {
+        Self {
+            log,
+            level,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Buffer `data`, logging every line it completes
+    fn write_bytes(&self, data: &[u8]) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.extend_from_slice(data);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line[..end]);
+            self.log.log(self.level, line.trim_end_matches('\r'));
+        }
+    }
+}
+
+impl IsTerminal for LogStream {
+    fn is_terminal(&self) -> bool {
+        false
+    }
+}
+
+impl StdoutStream for LogStream {
+    fn p2_stream(&self) -> Box<dyn OutputStream> {
+        Box::new(self.clone())
+    }
+
+    fn async_stream(&self) -> Box<dyn AsyncWrite + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl OutputStream for LogStream {
+    fn write(&mut self, bytes: Bytes) -> StreamResult<()> {
+        self.write_bytes(&bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> StreamResult<()> {
+        Ok(())
+    }
+
+    fn check_write(&mut self) -> StreamResult<usize> {
+        Ok(64 * 1024)
+    }
+}
+
+#[wasmtime_wasi::async_trait]
+impl Pollable for LogStream {
+    async fn ready(&mut self) {}
+}
+
+impl AsyncWrite for LogStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.write_bytes(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
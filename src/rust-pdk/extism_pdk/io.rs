@@ -0,0 +1,195 @@
+//! Reading the plugin input incrementally, and writing the output
+//!
+//! `Host::input` copies the whole input into plugin memory at once. An
+//! `InputReader` implements `std::io::Read` over the input instead, copying
+//! only what each read asks for, so large inputs are processed within a
+//! fixed memory budget; a `MemoryReader` does the same for a `Memory`
+//! block. `Host::input_json` and `Memory::to_json` parse JSON through them
+//! without a copy of the whole document, and `Host::input_ndjson` parses
+//! newline-delimited JSON one record at a time:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn count_errors() -> u64 {
+//!         let mut errors = 0;
+//!         for entry in Host::input_ndjson::<LogEntry>() {
+//!             if entry?.level == "error" {
+//!                 errors += 1;
+//!             }
+//!         }
+//!         Ok(errors)
+//!     }
+//! }
+//! ```
+//!
+//! An `OutputWriter` implements `std::io::Write` for libraries that write
+//! their results to a writer. The host takes the output whole when the
+//! function returns, so the writer holds what was written and sets it as
+//! the output when flushed.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::marker::PhantomData;
+
+use super::Memory;
+
+/// How many bytes the readers of the PDK load from the host at a time
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads the plugin input from the host as it is consumed
+#[derive(Debug)]
+pub struct InputReader {
+    offset: u64,
+    len: u64,
+}
+
+impl InputReader {
+    /// A reader from the start of the input
+    pub fn new() -> Self {
+        Self {
+            offset: 0,
+            len: unsafe { super::extism_input_length() },
+        }
+    }
+
+    /// The number of input bytes not read yet
+    pub fn remaining(&self) -> u64 {
+        self.len - self.offset
+    }
+}
+
+impl Default for InputReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Read for InputReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk = (buf.len() as u64).min(self.remaining());
+        if chunk > 0 {
+            unsafe {
+                super::extism_input_load_u8(self.offset, chunk, buf.as_mut_ptr());
+            }
+            self.offset += chunk;
+        }
+        Ok(chunk as usize)
+    }
+}
+
+/// Reads a `Memory` block as it is consumed
+#[derive(Debug)]
+pub struct MemoryReader<'a> {
+    memory: &'a Memory,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a> MemoryReader<'a> {
+    /// A reader from the start of `memory`
+    pub fn new(memory: &'a Memory) -> Self {
+        Self {
+            memory,
+            offset: 0,
+            len: memory.len(),
+        }
+    }
+
+    /// The number of bytes not read yet
+    pub fn remaining(&self) -> u64 {
+        self.len - self.offset
+    }
+}
+
+impl Read for MemoryReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let chunk = (buf.len() as u64).min(self.remaining());
+        if chunk > 0 {
+            unsafe {
+                super::extism_load_u8(self.memory.offset, self.offset, chunk, buf.as_mut_ptr());
+            }
+            self.offset += chunk;
+        }
+        Ok(chunk as usize)
+    }
+}
+
+/// Writes the plugin output
+///
+/// Each flush sets the output to everything written so far, replacing any
+/// output set before. Dropping the writer flushes what was written since.
+#[derive(Debug, Default)]
+pub struct OutputWriter {
+    buffer: Vec<u8>,
+    /// Whether bytes were written since the last flush
+    pending: bool,
+}
+
+impl OutputWriter {
+    /// A writer with nothing written yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        self.pending = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        super::Host::output(&self.buffer);
+        self.pending = false;
+        Ok(())
+    }
+}
+
+impl Drop for OutputWriter {
+    fn drop(&mut self) {
+        if self.pending {
+            super::Host::output(&self.buffer);
+        }
+    }
+}
+
+/// Parses newline-delimited JSON records from a reader, one line at a time
+///
+/// Blank lines are skipped. A line that does not parse yields an error,
+/// and the records after it are still read.
+#[derive(Debug)]
+pub struct Ndjson<T, R = BufReader<InputReader>> {
+    reader: R,
+    line: Vec<u8>,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T, R: BufRead> Ndjson<T, R> {
+    /// Parse the records of `reader`
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            _record: PhantomData,
+        }
+    }
+}
+
+impl<T: serde::de::DeserializeOwned, R: BufRead> Iterator for Ndjson<T, R> {
+    type Item = Result<T, serde_json::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.line.clear();
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(serde_json::Error::io(e))),
+            }
+            if self.line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            return Some(serde_json::from_slice(&self.line));
+        }
+    }
+}
@@ -0,0 +1,504 @@
+//! JSON Web Token verification
+//!
+//! Enabled with the `jwt` feature. `verify` checks a token's signature
+//! and its `exp` and `nbf` claims, then deserializes its claims:
+//!
+//! ```ignore
+//! #[derive(Deserialize)]
+//! struct Claims {
+//!     sub: String,
+//!     scope: String,
+//! }
+//!
+//! let jwks: Jwks = serde_json::from_str(&Host::config("jwks").unwrap_or_default())?;
+//! let claims: Claims = jwt::verify(&token, &jwks)?;
+//! ```
+//!
+//! HS256 tokens are verified with a shared secret, and RS256 and ES256
+//! tokens with the keys of a JSON Web Key Set. A token whose algorithm does
+//! not match the kind of key it is verified with is rejected, as is any
+//! other algorithm, including `none`. `Validation` also checks the
+//! audience and changes the leeway allowed for clock skew:
+//!
+//! ```ignore
+//! let claims: Claims = Validation::new()
+//!     .with_audience("orders-api")
+//!     .verify(&token, secret.as_bytes())?;
+//! ```
+//!
+//! `exp` and `nbf` are checked against the host's clock with the `time`
+//! feature. Without it, plugins built for `wasm32-unknown-unknown`, which
+//! has no clock, must pass the current time with `Validation::with_now`;
+//! tokens carrying `exp` or `nbf` are otherwise rejected with
+//! `JwtError::NoClock`. RS256 keys must be at least 2048 bits.
+
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[cfg(feature = "time")]
+use super::Host;
+use super::{crypto, Error};
+
+/// The smallest RSA modulus accepted for RS256, in bits
+pub const MIN_RSA_BITS: usize = 2048;
+
+/// Why a token was rejected
+#[derive(Debug)]
+pub enum JwtError {
+    /// The token is not three base64url segments of JSON
+    Malformed(String),
+    /// The token is signed with an algorithm other than HS256, RS256 or
+    /// ES256, or one the key cannot verify
+    UnsupportedAlgorithm(String),
+    /// The key set has no key for the token
+    KeyNotFound(Option<String>),
+    /// A key of the key set cannot be used
+    InvalidKey(String),
+    InvalidSignature,
+    /// The token's `exp` has passed
+    Expired,
+    /// The token's `nbf` has not come yet
+    NotYetValid,
+    /// The token is not meant for the expected audience
+    InvalidAudience,
+    /// The token was not issued by the expected issuer
+    InvalidIssuer,
+    /// A claim the validation requires is missing
+    MissingClaim(&'static str),
+    /// There is no clock to check `exp` and `nbf` against
+    NoClock,
+    /// The claims do not deserialize into the requested type
+    Claims(serde_json::Error),
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::Malformed(e) => write!(f, "Malformed token: {}", e),
+            JwtError::UnsupportedAlgorithm(alg) => {
+                write!(f, "Unsupported token algorithm: {}", alg)
+            }
+            JwtError::KeyNotFound(Some(kid)) => write!(f, "No key with id {:?}", kid),
+            JwtError::KeyNotFound(None) => write!(f, "No key for the token's algorithm"),
+            JwtError::InvalidKey(e) => write!(f, "Invalid key: {}", e),
+            JwtError::InvalidSignature => write!(f, "Invalid token signature"),
+            JwtError::Expired => write!(f, "Token has expired"),
+            JwtError::NotYetValid => write!(f, "Token is not valid yet"),
+            JwtError::InvalidAudience => write!(f, "Token is not meant for this audience"),
+            JwtError::InvalidIssuer => write!(f, "Token was not issued by the expected issuer"),
+            JwtError::MissingClaim(claim) => write!(f, "Token has no {:?} claim", claim),
+            JwtError::NoClock => write!(
+                f,
+                "No clock to validate the token against; pass the time with `Validation::with_now`"
+            ),
+            JwtError::Claims(e) => write!(f, "Invalid token claims: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+impl From<JwtError> for Error {
+    fn from(e: JwtError) -> Self {
+        match e {
+            JwtError::NoClock | JwtError::InvalidKey(_) => Error::fatal(e.to_string()),
+            _ => Error::invalid(e.to_string()),
+        }
+    }
+}
+
+/// A signature algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    HS256,
+    RS256,
+    ES256,
+}
+
+/// The header of a token
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    pub alg: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+}
+
+/// A JSON Web Key Set, as served at a `jwks_uri`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// A public key of a JSON Web Key Set
+///
+/// Only RSA keys (`n`, `e`) and P-256 keys (`crv`, `x`, `y`) are used;
+/// other keys are skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub e: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+}
+
+impl Jwk {
+    /// The algorithm the key verifies
+    fn algorithm(&self) -> Option<Algorithm> {
+        match (self.kty.as_str(), self.crv.as_deref()) {
+            ("RSA", _) => Some(Algorithm::RS256),
+            ("EC", Some("P-256")) => Some(Algorithm::ES256),
+            _ => None,
+        }
+    }
+}
+
+/// What a token is verified with
+#[derive(Debug, Clone, Copy)]
+pub enum Key<'a> {
+    /// The shared secret of HS256 tokens
+    Secret(&'a [u8]),
+    /// The public keys of RS256 and ES256 tokens
+    Jwks(&'a Jwks),
+}
+
+impl<'a> From<&'a [u8]> for Key<'a> {
+    fn from(secret: &'a [u8]) -> Self {
+        Key::Secret(secret)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for Key<'a> {
+    fn from(secret: &'a [u8; N]) -> Self {
+        Key::Secret(secret)
+    }
+}
+
+impl<'a> From<&'a Jwks> for Key<'a> {
+    fn from(jwks: &'a Jwks) -> Self {
+        Key::Jwks(jwks)
+    }
+}
+
+/// The registered claims `Validation` checks
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegisteredClaims {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// One audience or several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<u64>,
+}
+
+/// How tokens are validated
+#[derive(Debug, Clone)]
+pub struct Validation {
+    audience: Option<String>,
+    issuer: Option<String>,
+    leeway: u64,
+    require_exp: bool,
+    now: Option<u64>,
+}
+
+impl Default for Validation {
+    fn default() -> Self {
+        Self {
+            audience: None,
+            issuer: None,
+            leeway: 60,
+            require_exp: true,
+            now: None,
+        }
+    }
+}
+
+impl Validation {
+    /// Require `exp`, allowing 60 seconds of clock skew
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `aud` to be, or to contain, `audience`
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Require `iss` to be `issuer`
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Allow `seconds` of clock skew when checking `exp` and `nbf`
+    pub fn with_leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Whether tokens without `exp` are rejected
+    pub fn with_require_exp(mut self, require: bool) -> Self {
+        self.require_exp = require;
+        self
+    }
+
+    /// Check `exp` and `nbf` against `seconds` since the Unix epoch instead
+    /// of the host's or the system's clock
+    pub fn with_now(mut self, seconds: u64) -> Self {
+        self.now = Some(seconds);
+        self
+    }
+
+    /// Verify a token and deserialize its claims
+    pub fn verify<'a, C: DeserializeOwned>(
+        &self,
+        token: &str,
+        key: impl Into<Key<'a>>,
+    ) -> Result<C, JwtError> {
+        let mut segments = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(JwtError::Malformed("expected three segments".to_string()));
+        };
+        // The header and payload, as signed
+        let message = &token[..header.len() + 1 + payload.len()];
+        let header: Header = serde_json::from_slice(&decode(header)?)
+            .map_err(|e| JwtError::Malformed(format!("header: {}", e)))?;
+        let signature = decode(signature)?;
+        verify_signature(&header, message.as_bytes(), &signature, key.into())?;
+
+        let claims: Value = serde_json::from_slice(&decode(payload)?)
+            .map_err(|e| JwtError::Malformed(format!("claims: {}", e)))?;
+        let registered: RegisteredClaims =
+            serde_json::from_value(claims.clone()).map_err(JwtError::Claims)?;
+        self.validate(&registered)?;
+        serde_json::from_value(claims).map_err(JwtError::Claims)
+    }
+
+    /// Check the registered claims
+    fn validate(&self, claims: &RegisteredClaims) -> Result<(), JwtError> {
+        if claims.exp.is_none() && self.require_exp {
+            return Err(JwtError::MissingClaim("exp"));
+        }
+        if claims.exp.is_some() || claims.nbf.is_some() {
+            let now = self.now.or_else(now).ok_or(JwtError::NoClock)?;
+            if claims
+                .exp
+                .is_some_and(|exp| now > exp.saturating_add(self.leeway))
+            {
+                return Err(JwtError::Expired);
+            }
+            if claims
+                .nbf
+                .is_some_and(|nbf| now.saturating_add(self.leeway) < nbf)
+            {
+                return Err(JwtError::NotYetValid);
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match &claims.aud {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                Some(_) => false,
+                None => return Err(JwtError::MissingClaim("aud")),
+            };
+            if !matches {
+                return Err(JwtError::InvalidAudience);
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            match &claims.iss {
+                Some(iss) if iss == issuer => {}
+                Some(_) => return Err(JwtError::InvalidIssuer),
+                None => return Err(JwtError::MissingClaim("iss")),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify a token with the default `Validation` and deserialize its claims
+pub fn verify<'a, C: DeserializeOwned>(
+    token: &str,
+    key: impl Into<Key<'a>>,
+) -> Result<C, JwtError> {
+    Validation::new().verify(token, key)
+}
+
+/// Read a token's header without verifying it, for example to pick a key
+/// set by `kid`
+pub fn decode_header(token: &str) -> Result<Header, JwtError> {
+    let header = token
+        .split('.')
+        .next()
+        .ok_or_else(|| JwtError::Malformed("empty token".to_string()))?;
+    serde_json::from_slice(&decode(header)?)
+        .map_err(|e| JwtError::Malformed(format!("header: {}", e)))
+}
+
+fn decode(segment: &str) -> Result<Vec<u8>, JwtError> {
+    URL_SAFE_NO_PAD
+        .decode(segment.trim_end_matches('='))
+        .map_err(|e| JwtError::Malformed(e.to_string()))
+}
+
+fn verify_signature(
+    header: &Header,
+    message: &[u8],
+    signature: &[u8],
+    key: Key<'_>,
+) -> Result<(), JwtError> {
+    let algorithm = match header.alg.as_str() {
+        "HS256" => Algorithm::HS256,
+        "RS256" => Algorithm::RS256,
+        "ES256" => Algorithm::ES256,
+        alg => return Err(JwtError::UnsupportedAlgorithm(alg.to_string())),
+    };
+    match (algorithm, key) {
+        (Algorithm::HS256, Key::Secret(secret)) => {
+            if crypto::verify_hmac_sha256(secret, message, signature) {
+                Ok(())
+            } else {
+                Err(JwtError::InvalidSignature)
+            }
+        }
+        (Algorithm::HS256, Key::Jwks(_)) | (_, Key::Secret(_)) => {
+            Err(JwtError::UnsupportedAlgorithm(header.alg.clone()))
+        }
+        (algorithm, Key::Jwks(jwks)) => {
+            let mut candidates = jwks
+                .keys
+                .iter()
+                .filter(|jwk| jwk.algorithm() == Some(algorithm))
+                .filter(|jwk| jwk.alg.as_deref().is_none_or(|alg| alg == header.alg))
+                .filter(|jwk| {
+                    jwk.key_use
+                        .as_deref()
+                        .is_none_or(|key_use| key_use == "sig")
+                })
+                .filter(|jwk| header.kid.is_none() || jwk.kid == header.kid)
+                .peekable();
+            if candidates.peek().is_none() {
+                return Err(JwtError::KeyNotFound(header.kid.clone()));
+            }
+            let mut result = Err(JwtError::InvalidSignature);
+            for jwk in candidates {
+                result = match algorithm {
+                    Algorithm::RS256 => verify_rs256(jwk, message, signature),
+                    _ => verify_es256(jwk, message, signature),
+                };
+                if result.is_ok() {
+                    break;
+                }
+            }
+            result
+        }
+    }
+}
+
+/// A base64url field of a key
+fn key_field<'a>(jwk: &'a Jwk, field: &'a Option<String>, name: &str) -> Result<Vec<u8>, JwtError> {
+    let value = field.as_deref().ok_or_else(|| {
+        JwtError::InvalidKey(format!(
+            "{} key {} has no {:?}",
+            jwk.kty,
+            jwk.kid.as_deref().unwrap_or("without id"),
+            name
+        ))
+    })?;
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|e| JwtError::InvalidKey(format!("{:?}: {}", name, e)))
+}
+
+fn verify_rs256(jwk: &Jwk, message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+    use rsa::signature::Verifier;
+
+    let n = rsa::BigUint::from_bytes_be(&key_field(jwk, &jwk.n, "n")?);
+    if n.bits() < MIN_RSA_BITS {
+        return Err(JwtError::InvalidKey(format!(
+            "RSA key of {} bits is smaller than {} bits",
+            n.bits(),
+            MIN_RSA_BITS
+        )));
+    }
+    let e = rsa::BigUint::from_bytes_be(&key_field(jwk, &jwk.e, "e")?);
+    let key = rsa::RsaPublicKey::new(n, e).map_err(|e| JwtError::InvalidKey(e.to_string()))?;
+    let key = rsa::pkcs1v15::VerifyingKey::<sha2::Sha256>::new(key);
+    let signature =
+        rsa::pkcs1v15::Signature::try_from(signature).map_err(|_| JwtError::InvalidSignature)?;
+    key.verify(message, &signature)
+        .map_err(|_| JwtError::InvalidSignature)
+}
+
+fn verify_es256(jwk: &Jwk, message: &[u8], signature: &[u8]) -> Result<(), JwtError> {
+    use p256::ecdsa::signature::Verifier;
+
+    let x = key_field(jwk, &jwk.x, "x")?;
+    let y = key_field(jwk, &jwk.y, "y")?;
+    if x.len() != 32 || y.len() != 32 {
+        return Err(JwtError::InvalidKey(
+            "P-256 coordinates must be 32 bytes".to_string(),
+        ));
+    }
+    let point = p256::EncodedPoint::from_affine_coordinates(
+        p256::FieldBytes::from_slice(&x),
+        p256::FieldBytes::from_slice(&y),
+        false,
+    );
+    let key = p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+        .map_err(|e| JwtError::InvalidKey(e.to_string()))?;
+    let signature =
+        p256::ecdsa::Signature::from_slice(signature).map_err(|_| JwtError::InvalidSignature)?;
+    key.verify(message, &signature)
+        .map_err(|_| JwtError::InvalidSignature)
+}
+
+/// Seconds since the Unix epoch, from the host's clock
+#[cfg(feature = "time")]
+fn now() -> Option<u64> {
+    Some(Host::now_ms() / 1000)
+}
+
+/// Seconds since the Unix epoch, if the target has a clock
+#[cfg(not(feature = "time"))]
+fn now() -> Option<u64> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        return None;
+    }
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()
+        .map(|elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,561 @@
+//! Checks for common mistakes in plugin sources
+//!
+//! `lint_crate` reads the sources of a plugin crate and reports uses of the
+//! PDK that compile but misbehave when the plugin runs:
+//!
+//! - `output-in-export` (error): setting the output with `Host::output*`
+//!   in a function of `export_plugin!`, which sets the output from the
+//!   function's return value and overwrites it
+//! - `output-twice` (warning): setting the output twice in the same block,
+//!   where only the last output is kept
+//! - `http-in-describe` (error): HTTP requests in `describe`, which
+//!   `extismx build` and registries call without network access
+//!
+//! `extismx build` runs the checks before compiling, failing on errors and
+//! printing warnings, and `extismx lint` runs them alone. Each lint is
+//! printed like a compiler diagnostic:
+//!
+//! ```text
+//! error[output-in-export]: `Host::output_json` in export `greet` is overwritten by its return value
+//!   --> src/lib.rs:12:9
+//!    = help: return the output from `greet` instead
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The `Host` functions that set the output
+const OUTPUT_FUNCTIONS: &[&str] = &["output", "output_string", "output_json"];
+
+/// How serious a lint is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Probably a mistake, reported without failing the build
+    Warning,
+    /// Always a mistake, failing the build
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// A mistake found in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// Name of the check, such as `output-twice`
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// The file, relative to the crate when linted with `lint_crate`
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// How to fix it
+    pub help: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}\n  --> {}:{}:{}\n   = help: {}",
+            self.severity,
+            self.rule,
+            self.message,
+            self.file.display(),
+            self.line,
+            self.column,
+            self.help
+        )
+    }
+}
+
+/// Check every `.rs` file under the `src/` directory of the crate whose
+/// `Cargo.toml` is at `manifest_path`
+pub fn lint_crate(manifest_path: impl AsRef<Path>) -> std::io::Result<Vec<Lint>> {
+    let root = manifest_path
+        .as_ref()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut files = Vec::new();
+    sources(&root.join("src"), &mut files)?;
+    files.sort();
+    let mut lints = Vec::new();
+    for file in files {
+        let source = fs::read_to_string(&file)?;
+        let relative = file.strip_prefix(&root).unwrap_or(&file);
+        lints.extend(lint_source(relative, &source));
+    }
+    Ok(lints)
+}
+
+fn sources(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            sources(&path, files)?;
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Check the source of one file, reporting lints against `file`
+pub fn lint_source(file: impl AsRef<Path>, source: &str) -> Vec<Lint> {
+    let mut checker = Checker {
+        file: file.as_ref().to_path_buf(),
+        lints: Vec::new(),
+    };
+    checker.items(&parse(source));
+    checker.lints
+}
+
+/// Where a token starts, 1-based
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+/// A token tree: brackets group the tokens between them
+#[derive(Debug)]
+enum Token {
+    Ident(String, Position),
+    Punct(char),
+    Literal,
+    Group(char, Vec<Token>),
+}
+
+impl Token {
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self, Token::Ident(ident, _) if ident == name)
+    }
+
+    fn is_punct(&self, punct: char) -> bool {
+        matches!(self, Token::Punct(c) if *c == punct)
+    }
+
+    fn is_group(&self, open: char) -> bool {
+        matches!(self, Token::Group(c, _) if *c == open)
+    }
+}
+
+/// Whether a function's output is set from its return value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Export,
+    Plain,
+}
+
+struct Checker {
+    file: PathBuf,
+    lints: Vec<Lint>,
+}
+
+impl Checker {
+    /// Check the functions among items, such as those of a file or module
+    fn items(&mut self, tokens: &[Token]) {
+        let mut i = 0;
+        while i < tokens.len() {
+            if tokens[i].is_ident("export_plugin")
+                && tokens.get(i + 1).is_some_and(|token| token.is_punct('!'))
+            {
+                if let Some(Token::Group(_, inner)) = tokens.get(i + 2) {
+                    self.functions(inner, FunctionKind::Export);
+                    i += 3;
+                    continue;
+                }
+            }
+            if let Some(end) = self.function(tokens, i, FunctionKind::Plain) {
+                i = end;
+                continue;
+            }
+            if let Token::Group(_, inner) = &tokens[i] {
+                self.items(inner);
+            }
+            i += 1;
+        }
+    }
+
+    /// Check every function declared in `tokens`
+    fn functions(&mut self, tokens: &[Token], kind: FunctionKind) {
+        let mut i = 0;
+        while i < tokens.len() {
+            i = self.function(tokens, i, kind).unwrap_or(i + 1);
+        }
+    }
+
+    /// Check the function declared at `tokens[i]`, if any, returning the
+    /// index after its body
+    fn function(&mut self, tokens: &[Token], i: usize, kind: FunctionKind) -> Option<usize> {
+        if !tokens[i].is_ident("fn") {
+            return None;
+        }
+        let Some(Token::Ident(name, _)) = tokens.get(i + 1) else {
+            return None;
+        };
+        // The body is the first brace group, unless the declaration ends first
+        let mut j = i + 2;
+        while j < tokens.len() && !tokens[j].is_group('{') {
+            if tokens[j].is_punct(';') {
+                return Some(j + 1);
+            }
+            j += 1;
+        }
+        let Some(Token::Group(_, body)) = tokens.get(j) else {
+            return Some(j);
+        };
+        let mut calls = Vec::new();
+        self.block(body, kind, name, &mut calls);
+        if name == "describe" {
+            for (method, position) in &calls {
+                if method == "http_request" {
+                    self.lint(
+                        "http-in-describe",
+                        Severity::Error,
+                        *position,
+                        "`describe` makes an HTTP request, but it is called without network \
+                         access"
+                            .to_string(),
+                        "return the schemas without calling the host".to_string(),
+                    );
+                }
+            }
+        }
+        Some(j + 1)
+    }
+
+    /// Check a block of a function's body, collecting the `Host` calls it
+    /// and its nested blocks make
+    fn block(
+        &mut self,
+        tokens: &[Token],
+        kind: FunctionKind,
+        function: &str,
+        calls: &mut Vec<(String, Position)>,
+    ) {
+        let mut outputs = Vec::new();
+        self.statements(tokens, kind, function, calls, &mut outputs);
+        if kind == FunctionKind::Plain {
+            for (method, position) in outputs.iter().skip(1) {
+                self.lint(
+                    "output-twice",
+                    Severity::Warning,
+                    *position,
+                    format!(
+                        "`Host::{}` replaces the output already set in `{}`",
+                        method, function
+                    ),
+                    "build the output first and set it once".to_string(),
+                );
+            }
+        }
+    }
+
+    /// Collect the calls of a block, counting the outputs set directly in
+    /// it, rather than in nested blocks that may not both run
+    fn statements(
+        &mut self,
+        tokens: &[Token],
+        kind: FunctionKind,
+        function: &str,
+        calls: &mut Vec<(String, Position)>,
+        outputs: &mut Vec<(String, Position)>,
+    ) {
+        let mut i = 0;
+        while i < tokens.len() {
+            // Nested functions are checked on their own
+            if let Some(end) = self.function(tokens, i, FunctionKind::Plain) {
+                i = end;
+                continue;
+            }
+            match &tokens[i] {
+                Token::Ident(host, position) if host == "Host" => {
+                    if let (Some(Token::Punct(':')), Some(Token::Punct(':'))) =
+                        (tokens.get(i + 1), tokens.get(i + 2))
+                    {
+                        if let Some(Token::Ident(method, _)) = tokens.get(i + 3) {
+                            calls.push((method.clone(), *position));
+                            if OUTPUT_FUNCTIONS.contains(&method.as_str()) {
+                                if kind == FunctionKind::Export {
+                                    self.lint(
+                                        "output-in-export",
+                                        Severity::Error,
+                                        *position,
+                                        format!(
+                                            "`Host::{}` in export `{}` is overwritten by its \
+                                             return value",
+                                            method, function
+                                        ),
+                                        format!("return the output from `{}` instead", function),
+                                    );
+                                }
+                                outputs.push((method.clone(), *position));
+                            }
+                            i += 4;
+                            continue;
+                        }
+                    }
+                }
+                Token::Group('{', inner) => self.block(inner, kind, function, calls),
+                Token::Group(_, inner) => self.statements(inner, kind, function, calls, outputs),
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    fn lint(
+        &mut self,
+        rule: &'static str,
+        severity: Severity,
+        position: Position,
+        message: String,
+        help: String,
+    ) {
+        self.lints.push(Lint {
+            rule,
+            severity,
+            file: self.file.clone(),
+            line: position.line,
+            column: position.column,
+            message,
+            help,
+        });
+    }
+}
+
+/// Split Rust source into token trees, skipping comments and the contents
+/// of literals
+///
+/// Unbalanced brackets close at the end of the source.
+fn parse(source: &str) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut lexer = Lexer {
+        chars: &chars,
+        index: 0,
+        position: Position { line: 1, column: 1 },
+    };
+    let mut stack: Vec<(char, Vec<Token>)> = vec![(' ', Vec::new())];
+    while let Some(token) = lexer.next() {
+        match token {
+            Lexeme::Open(open) => stack.push((open, Vec::new())),
+            Lexeme::Close => {
+                if stack.len() > 1 {
+                    let (open, tokens) = stack.pop().unwrap_or_default();
+                    push(&mut stack, Token::Group(open, tokens));
+                }
+            }
+            Lexeme::Token(token) => push(&mut stack, token),
+        }
+    }
+    while stack.len() > 1 {
+        let (open, tokens) = stack.pop().unwrap_or_default();
+        push(&mut stack, Token::Group(open, tokens));
+    }
+    stack.pop().map(|(_, tokens)| tokens).unwrap_or_default()
+}
+
+fn push(stack: &mut [(char, Vec<Token>)], token: Token) {
+    if let Some((_, tokens)) = stack.last_mut() {
+        tokens.push(token);
+    }
+}
+
+enum Lexeme {
+    Open(char),
+    Close,
+    Token(Token),
+}
+
+struct Lexer<'a> {
+    chars: &'a [char],
+    index: usize,
+    position: Position,
+}
+
+impl Lexer<'_> {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.index += 1;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn next(&mut self) -> Option<Lexeme> {
+        loop {
+            let c = self.peek(0)?;
+            let position = self.position;
+            match c {
+                _ if c.is_whitespace() => {
+                    self.bump();
+                }
+                '/' if self.peek(1) == Some('/') => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                '/' if self.peek(1) == Some('*') => self.block_comment(),
+                '(' | '[' | '{' => {
+                    self.bump();
+                    return Some(Lexeme::Open(c));
+                }
+                ')' | ']' | '}' => {
+                    self.bump();
+                    return Some(Lexeme::Close);
+                }
+                '"' => {
+                    self.bump();
+                    self.string();
+                    return Some(Lexeme::Token(Token::Literal));
+                }
+                '\'' => {
+                    self.bump();
+                    self.quote();
+                    return Some(Lexeme::Token(Token::Literal));
+                }
+                _ if c.is_ascii_digit() => {
+                    while self
+                        .peek(0)
+                        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                        || (self.peek(0) == Some('.')
+                            && self.peek(1).is_some_and(|c| c.is_ascii_digit()))
+                    {
+                        self.bump();
+                    }
+                    return Some(Lexeme::Token(Token::Literal));
+                }
+                _ if c.is_alphabetic() || c == '_' => {
+                    if self.prefixed_literal() {
+                        return Some(Lexeme::Token(Token::Literal));
+                    }
+                    let mut ident = String::new();
+                    while let Some(c) = self.peek(0).filter(|c| c.is_alphanumeric() || *c == '_') {
+                        ident.push(c);
+                        self.bump();
+                    }
+                    return Some(Lexeme::Token(Token::Ident(ident, position)));
+                }
+                _ => {
+                    self.bump();
+                    return Some(Lexeme::Token(Token::Punct(c)));
+                }
+            }
+        }
+    }
+
+    /// Skip a block comment, which may nest
+    fn block_comment(&mut self) {
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            if c == '/' && self.peek(0) == Some('*') {
+                self.bump();
+                depth += 1;
+            } else if c == '*' && self.peek(0) == Some('/') {
+                self.bump();
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Skip the rest of a string after its opening quote
+    fn string(&mut self) {
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '"' => return,
+                _ => {}
+            }
+        }
+    }
+
+    /// Skip the rest of a character literal or lifetime after its quote
+    fn quote(&mut self) {
+        if self.peek(0) == Some('\\') {
+            while let Some(c) = self.bump() {
+                if c == '\'' {
+                    return;
+                }
+                if c == '\\' {
+                    self.bump();
+                }
+            }
+        } else if self.peek(1) == Some('\'') {
+            self.bump();
+            self.bump();
+        } else {
+            while self
+                .peek(0)
+                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+            {
+                self.bump();
+            }
+        }
+    }
+
+    /// Skip a byte, C or raw string, or a byte literal, if one starts here
+    fn prefixed_literal(&mut self) -> bool {
+        let prefix = match (self.peek(0), self.peek(1)) {
+            (Some('b' | 'c'), Some('r')) => 2,
+            (Some('b' | 'c' | 'r'), _) => 1,
+            _ => return false,
+        };
+        let raw = self.peek(prefix - 1) == Some('r');
+        let mut hashes = 0;
+        if raw {
+            while self.peek(prefix + hashes) == Some('#') {
+                hashes += 1;
+            }
+        }
+        match self.peek(prefix + hashes) {
+            Some('"') => {}
+            Some('\'') if !raw && self.peek(0) == Some('b') => {
+                self.bump();
+                self.bump();
+                self.quote();
+                return true;
+            }
+            _ => return false,
+        }
+        for _ in 0..prefix + hashes + 1 {
+            self.bump();
+        }
+        if !raw {
+            self.string();
+            return true;
+        }
+        // A raw string ends at a quote followed by as many hashes
+        while let Some(c) = self.bump() {
+            if c == '"' && (0..hashes).all(|offset| self.peek(offset) == Some('#')) {
+                for _ in 0..hashes {
+                    self.bump();
+                }
+                return true;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,54 @@
+//! Helpers for running exported plugin functions
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::{guard, Error};
+
+/// Run a plugin function, converting a panic into an error
+///
+/// When the plugin is built with `panic = "abort"` (the default for
+/// `wasm32-unknown-unknown`) panics cannot be caught and `f` is called directly.
+///
+/// A var write `f` made over its `guard::Budget` fails the call even when
+/// `f` succeeds.
+pub fn catch<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    guard::take_violation();
+    let result = call(f);
+    match guard::take_violation() {
+        Some(violation) => Err(violation.into()),
+        None => result,
+    }
+}
+
+/// Call `f`, catching a panic when panics unwind
+fn call<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    if cfg!(panic = "unwind") {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result,
+            Err(payload) => Err(Error::fatal(format!(
+                "Plugin panicked: {}",
+                panic_message(&*payload)
+            ))),
+        }
+    } else {
+        f()
+    }
+}
+
+/// Extract the message from a panic payload
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
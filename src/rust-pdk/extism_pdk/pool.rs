@@ -0,0 +1,109 @@
+//! Reusing the buffers of host-boundary copies
+//!
+//! Every read of a var, config value, HTTP body or `Memory` block copies
+//! the bytes into a fresh `Vec<u8>`. The PDK takes those buffers from a
+//! per-plugin `BufferPool` instead, and `recycle` hands a buffer back when
+//! the plugin is done with it, so a function reading many values reuses a
+//! few allocations:
+//!
+//! ```ignore
+//! for key in &keys {
+//!     if let Some(value) = Host::var_get(key) {
+//!         total += parse_counter(&value)?;
+//!         pool::recycle(value);
+//!     }
+//! }
+//! ```
+//!
+//! Buffers that are not recycled are freed as usual.
+
+use std::cell::RefCell;
+
+/// How many buffers the plugin's pool keeps
+pub const MAX_BUFFERS: usize = 8;
+
+/// The largest buffer the plugin's pool keeps, so one large read does not
+/// stay allocated for the rest of the instance's life
+pub const MAX_CAPACITY: usize = 1024 * 1024;
+
+/// Buffers kept for reuse
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    buffers: Vec<Vec<u8>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// A pool keeping up to `max_buffers` buffers of at most `max_capacity`
+    /// bytes each
+    pub fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            buffers: Vec::new(),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    /// A zeroed buffer of `len` bytes, reusing a kept buffer if one is
+    /// large enough
+    pub fn take(&mut self, len: usize) -> Vec<u8> {
+        let mut buffer = match self.buffers.iter().position(|b| b.capacity() >= len) {
+            Some(i) => self.buffers.swap_remove(i),
+            None => return vec![0; len],
+        };
+        buffer.clear();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    /// Keep `buffer` for reuse, unless the pool is full or the buffer is
+    /// larger than the pool keeps
+    pub fn give(&mut self, buffer: Vec<u8>) {
+        if self.buffers.len() < self.max_buffers
+            && buffer.capacity() > 0
+            && buffer.capacity() <= self.max_capacity
+        {
+            self.buffers.push(buffer);
+        }
+    }
+
+    /// The number of buffers kept
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Whether no buffers are kept
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Free the kept buffers
+    pub fn clear(&mut self) {
+        self.buffers.clear();
+    }
+}
+
+thread_local! {
+    static POOL: RefCell<BufferPool> = RefCell::new(BufferPool::new(MAX_BUFFERS, MAX_CAPACITY));
+}
+
+/// Run `f` with the plugin's pool
+pub fn with<R>(f: impl FnOnce(&mut BufferPool) -> R) -> R {
+    POOL.with(|pool| f(&mut pool.borrow_mut()))
+}
+
+/// A zeroed buffer of `len` bytes from the plugin's pool
+pub fn take(len: usize) -> Vec<u8> {
+    with(|pool| pool.take(len))
+}
+
+/// Hand a buffer read from the host back to the plugin's pool
+pub fn recycle(buffer: Vec<u8>) {
+    with(|pool| pool.give(buffer))
+}
+
+/// Hand the buffer of a string back to the plugin's pool
+pub fn recycle_string(s: String) {
+    recycle(s.into_bytes())
+}
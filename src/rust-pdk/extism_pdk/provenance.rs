@@ -0,0 +1,290 @@
+//! SLSA provenance attestations for plugin wasm modules
+//!
+//! A builder describes how it built a module in an in-toto `Statement` with
+//! a SLSA provenance predicate and signs it with its Ed25519 key into a
+//! DSSE envelope, the `Provenance`. Consumers configure a
+//! `ProvenancePolicy` listing the builders they trust; with a policy
+//! requiring provenance, modules without an attestation signed by a trusted
+//! builder are refused.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use ed25519_dalek::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
+
+use super::host::sha256_hex;
+use super::signing::{decode_key, encode_key, SigningKey, VerifyingKey};
+
+/// Type of in-toto v1 statements
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+
+/// Predicate type of SLSA v1 provenance
+pub const SLSA_PROVENANCE_V1: &str = "https://slsa.dev/provenance/v1";
+
+/// Payload type of DSSE envelopes holding in-toto statements
+const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// An attestation could not be verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProvenanceError {
+    /// The policy requires provenance but the module has none
+    Missing,
+    /// The attestation is not signed by a builder the policy trusts
+    UntrustedBuilder(String),
+    /// The attestation does not name the module's SHA-256
+    SubjectMismatch(String),
+    /// The attestation is malformed or its signature does not verify
+    Invalid(String),
+}
+
+impl fmt::Display for ProvenanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProvenanceError::Missing => write!(f, "Plugin has no provenance attestation"),
+            ProvenanceError::UntrustedBuilder(key) => {
+                write!(f, "Plugin provenance signed by untrusted builder {}", key)
+            }
+            ProvenanceError::SubjectMismatch(sha256) => write!(
+                f,
+                "Plugin provenance does not attest the module with SHA-256 {}",
+                sha256
+            ),
+            ProvenanceError::Invalid(e) => write!(f, "Invalid plugin provenance: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProvenanceError {}
+
+/// An artifact an attestation is about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Subject {
+    /// Artifact name, such as the wasm file name
+    pub name: String,
+    /// Digests of the artifact by algorithm, such as `sha256`, hex encoded
+    pub digest: BTreeMap<String, String>,
+}
+
+/// An in-toto statement about one or more artifacts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Statement {
+    /// Statement type, `STATEMENT_TYPE`
+    #[serde(rename = "_type")]
+    pub statement_type: String,
+    /// Artifacts the statement is about
+    pub subject: Vec<Subject>,
+    /// Type of the predicate, such as `SLSA_PROVENANCE_V1`
+    #[serde(rename = "predicateType")]
+    pub predicate_type: String,
+    /// What is stated about the subjects
+    pub predicate: serde_json::Value,
+}
+
+impl Statement {
+    /// Create SLSA v1 provenance stating that `builder_id` built the module
+    /// `wasm`, named `name`, with a build of type `build_type`
+    ///
+    /// Build parameters and dependencies can be added to the predicate's
+    /// `buildDefinition` afterwards.
+    pub fn slsa(name: &str, wasm: &[u8], builder_id: &str, build_type: &str) -> Self {
+        Self {
+            statement_type: STATEMENT_TYPE.to_string(),
+            subject: vec![Subject {
+                name: name.to_string(),
+                digest: BTreeMap::from([("sha256".to_string(), sha256_hex(wasm))]),
+            }],
+            predicate_type: SLSA_PROVENANCE_V1.to_string(),
+            predicate: serde_json::json!({
+                "buildDefinition": {
+                    "buildType": build_type,
+                    "externalParameters": {},
+                },
+                "runDetails": {
+                    "builder": { "id": builder_id },
+                },
+            }),
+        }
+    }
+
+    /// Get the ID of the builder, from SLSA v1 or v0.2 provenance
+    pub fn builder_id(&self) -> Option<&str> {
+        let builder = self
+            .predicate
+            .pointer("/runDetails/builder/id")
+            .or_else(|| self.predicate.pointer("/builder/id"))?;
+        builder.as_str()
+    }
+}
+
+/// A signature in a DSSE envelope
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvelopeSignature {
+    /// Public key of the signer, base64 encoded
+    pub keyid: String,
+    /// Ed25519 signature of the envelope's payload, base64 encoded
+    pub sig: String,
+}
+
+/// A signed provenance attestation: a DSSE envelope holding a `Statement`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Type of the payload, `application/vnd.in-toto+json`
+    #[serde(rename = "payloadType")]
+    pub payload_type: String,
+    /// The statement's JSON, base64 encoded
+    pub payload: String,
+    /// Signatures of the payload
+    pub signatures: Vec<EnvelopeSignature>,
+}
+
+impl Provenance {
+    /// Sign a statement with a builder's key
+    pub fn sign(statement: &Statement, key: &SigningKey) -> Self {
+        let payload = serde_json::to_vec(statement).unwrap_or_default();
+        let sig = key.sign(&pae(PAYLOAD_TYPE, &payload));
+        Self {
+            payload_type: PAYLOAD_TYPE.to_string(),
+            payload: STANDARD.encode(&payload),
+            signatures: vec![EnvelopeSignature {
+                keyid: encode_key(&key.verifying_key()),
+                sig: STANDARD.encode(sig.to_bytes()),
+            }],
+        }
+    }
+
+    /// Decode the statement, without verifying it
+    pub fn statement(&self) -> Result<Statement, ProvenanceError> {
+        let invalid = |e: String| ProvenanceError::Invalid(e);
+        if self.payload_type != PAYLOAD_TYPE {
+            return Err(invalid(format!(
+                "unexpected payload type {}",
+                self.payload_type
+            )));
+        }
+        let payload = STANDARD
+            .decode(&self.payload)
+            .map_err(|e| invalid(e.to_string()))?;
+        serde_json::from_slice(&payload).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// List the keys whose signatures of the attestation verify
+    pub fn signers(&self) -> Vec<VerifyingKey> {
+        let Ok(payload) = STANDARD.decode(&self.payload) else {
+            return Vec::new();
+        };
+        let message = pae(&self.payload_type, &payload);
+        self.signatures
+            .iter()
+            .filter_map(|signature| {
+                let key = decode_key(&signature.keyid).ok()?;
+                let bytes: [u8; 64] = STANDARD.decode(&signature.sig).ok()?.try_into().ok()?;
+                key.verify(&message, &ed25519_dalek::Signature::from_bytes(&bytes))
+                    .ok()?;
+                Some(key)
+            })
+            .collect()
+    }
+
+    /// Check that the attestation is signed and is SLSA provenance of `wasm`,
+    /// returning its statement
+    ///
+    /// Whether the signers are trusted is up to a `ProvenancePolicy`.
+    pub fn verify(&self, wasm: &[u8]) -> Result<Statement, ProvenanceError> {
+        let statement = self.statement()?;
+        if self.signers().is_empty() {
+            return Err(ProvenanceError::Invalid(
+                "no signature verifies".to_string(),
+            ));
+        }
+        if !statement
+            .predicate_type
+            .starts_with("https://slsa.dev/provenance/")
+        {
+            return Err(ProvenanceError::Invalid(format!(
+                "predicate type {} is not SLSA provenance",
+                statement.predicate_type
+            )));
+        }
+        let sha256 = sha256_hex(wasm);
+        let attested = statement.subject.iter().any(|subject| {
+            subject
+                .digest
+                .get("sha256")
+                .is_some_and(|digest| digest.eq_ignore_ascii_case(&sha256))
+        });
+        if !attested {
+            return Err(ProvenanceError::SubjectMismatch(sha256));
+        }
+        Ok(statement)
+    }
+}
+
+/// The builders whose provenance is accepted
+///
+/// A policy that does not require provenance accepts every module.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProvenancePolicy {
+    /// Whether modules must have provenance signed by a trusted builder
+    pub required: bool,
+    /// Public keys of trusted builders, base64 encoded
+    pub trusted_builders: Vec<String>,
+}
+
+impl ProvenancePolicy {
+    /// Create a policy that requires provenance but trusts no builders yet
+    pub fn new() -> Self {
+        Self {
+            required: true,
+            trusted_builders: Vec::new(),
+        }
+    }
+
+    /// Trust provenance signed with `key`
+    pub fn with_builder(mut self, key: &VerifyingKey) -> Self {
+        self.trusted_builders.push(encode_key(key));
+        self
+    }
+
+    /// Check a module's provenance against the policy
+    pub fn verify(
+        &self,
+        wasm: &[u8],
+        provenance: Option<&Provenance>,
+    ) -> Result<(), ProvenanceError> {
+        if !self.required {
+            return Ok(());
+        }
+        let provenance = provenance.ok_or(ProvenanceError::Missing)?;
+        provenance.verify(wasm)?;
+        let signers = provenance.signers();
+        if !signers
+            .iter()
+            .any(|key| self.trusted_builders.contains(&encode_key(key)))
+        {
+            let keys = provenance
+                .signatures
+                .iter()
+                .map(|signature| signature.keyid.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(ProvenanceError::UntrustedBuilder(keys));
+        }
+        Ok(())
+    }
+}
+
+/// DSSE pre-authentication encoding of a payload, which is what is signed
+fn pae(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
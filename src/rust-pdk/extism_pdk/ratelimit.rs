@@ -0,0 +1,153 @@
+//! Rate limiting calls to upstream services across plugin calls
+//!
+//! A `TokenBucket` keeps its tokens in a plugin var and refills them by the
+//! host's clock, so every call of the plugin draws from the same bucket:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn lookup() -> String {
+//!         TokenBucket::new("geocoder", 5.0, 10).acquire()?;
+//!         let response = Host::http_request(&request)?;
+//!         Ok(String::from_utf8_lossy(&response.body()).into_owned())
+//!     }
+//! }
+//! ```
+//!
+//! A call over the limit fails with an `ErrorKind::Retryable` error, which
+//! hosts can retry once `retry_after` has passed. Vars belong to a plugin
+//! instance, so instances of a pool each have their own buckets.
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{pool, Error, Host};
+
+/// Prefix of the vars buckets are kept in
+const VAR_PREFIX: &str = "ratelimit:";
+
+/// A bucket had too few tokens for a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitError {
+    /// The bucket will have enough tokens after `retry_after`
+    RateLimited { retry_after: Duration },
+    /// More tokens were asked for at once than the bucket holds
+    ExceedsBurst { requested: u32, burst: u32 },
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::RateLimited { retry_after } => write!(
+                f,
+                "Rate limited, retry after {} ms",
+                retry_after.as_millis()
+            ),
+            RateLimitError::ExceedsBurst { requested, burst } => write!(
+                f,
+                "Requested {} tokens from a bucket holding at most {}",
+                requested, burst
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RateLimitError {}
+
+impl From<RateLimitError> for Error {
+    fn from(e: RateLimitError) -> Self {
+        match e {
+            RateLimitError::RateLimited { .. } => Error::retryable(e.to_string()),
+            RateLimitError::ExceedsBurst { .. } => Error::invalid(e.to_string()),
+        }
+    }
+}
+
+/// A token bucket kept in the plugin var `ratelimit:<name>`
+///
+/// The bucket starts full, holds at most `burst` tokens and refills at
+/// `rate` tokens per second. Buckets with the same name share their tokens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBucket {
+    /// The var the bucket is kept in, built once rather than on every draw
+    var: String,
+    rate: f64,
+    burst: u32,
+}
+
+/// A bucket's tokens as of `updated_ms`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Level {
+    tokens: f64,
+    updated_ms: u64,
+}
+
+impl TokenBucket {
+    /// A bucket refilling `rate` tokens per second, up to `burst`
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn new(name: impl Into<String>, rate: f64, burst: u32) -> Self {
+        assert!(
+            rate.is_finite() && rate > 0.0,
+            "rate must be a positive number of tokens per second"
+        );
+        Self {
+            var: format!("{}{}", VAR_PREFIX, name.into()),
+            rate,
+            burst,
+        }
+    }
+
+    /// Take one token
+    pub fn acquire(&self) -> Result<(), RateLimitError> {
+        self.acquire_n(1)
+    }
+
+    /// Take `n` tokens at once, for calls that cost more than one
+    ///
+    /// No tokens are taken when the bucket holds fewer than `n`.
+    pub fn acquire_n(&self, n: u32) -> Result<(), RateLimitError> {
+        if n > self.burst {
+            return Err(RateLimitError::ExceedsBurst {
+                requested: n,
+                burst: self.burst,
+            });
+        }
+        let now_ms = Host::now_ms();
+        let tokens = self.tokens(now_ms);
+        let missing = f64::from(n) - tokens;
+        if missing > 0.0 {
+            return Err(RateLimitError::RateLimited {
+                retry_after: Duration::from_millis((missing / self.rate * 1000.0).ceil() as u64),
+            });
+        }
+        let level = Level {
+            tokens: tokens - f64::from(n),
+            updated_ms: now_ms,
+        };
+        let value = serde_json::to_vec(&level).expect("a bucket level serializes");
+        Host::write_var(&self.var, &value);
+        Ok(())
+    }
+
+    /// The whole tokens the bucket holds now
+    pub fn available(&self) -> u32 {
+        self.tokens(Host::now_ms()) as u32
+    }
+
+    /// The tokens at `now_ms`, refilled since the bucket was last drawn from
+    fn tokens(&self, now_ms: u64) -> f64 {
+        let burst = f64::from(self.burst);
+        let Some(level) = Host::var_get(&self.var).and_then(|value| {
+            let level = serde_json::from_slice::<Level>(&value).ok();
+            pool::recycle(value);
+            level
+        }) else {
+            return burst;
+        };
+        // A clock set back refills nothing
+        let elapsed_ms = now_ms.saturating_sub(level.updated_ms);
+        (level.tokens + elapsed_ms as f64 / 1000.0 * self.rate).min(burst)
+    }
+}
@@ -0,0 +1,215 @@
+//! Scrubbing secrets before they leave the plugin
+//!
+//! An installed `Redactor` replaces its secrets in log messages and error
+//! strings, and optionally in JSON output, before they are handed to the
+//! host:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn call_api() -> Response {
+//!         Redactor::new()
+//!             .with_config_secret("api_key")
+//!             .with_pattern(r"Bearer [A-Za-z0-9._~+/-]+=*")?
+//!             .with_output(true)
+//!             .install();
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! The redactor stays installed for the rest of the instance's life, or
+//! until `clear` is called.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use regex_lite::Regex;
+use serde_json::Value;
+
+use super::Error;
+
+/// What secrets are replaced with by default
+pub const REPLACEMENT: &str = "[REDACTED]";
+
+/// A pattern passed to `Redactor::with_pattern` is not a valid regex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub pattern: String,
+    pub message: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid redaction pattern {:?}: {}",
+            self.pattern, self.message
+        )
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl From<PatternError> for Error {
+    fn from(e: PatternError) -> Self {
+        Error::fatal(e.to_string())
+    }
+}
+
+/// Secret values and patterns to scrub from what the plugin hands the host
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    secrets: Vec<String>,
+    patterns: Vec<Regex>,
+    replacement: String,
+    output: bool,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self {
+            secrets: Vec::new(),
+            patterns: Vec::new(),
+            replacement: REPLACEMENT.to_string(),
+            output: false,
+        }
+    }
+}
+
+impl Redactor {
+    /// A redactor without secrets
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scrub a secret value
+    ///
+    /// Empty values are ignored.
+    pub fn with_secret(mut self, secret: impl Into<String>) -> Self {
+        let secret = secret.into();
+        if !secret.is_empty() && !self.secrets.contains(&secret) {
+            self.secrets.push(secret);
+            // Longer secrets first, so a secret containing another is
+            // replaced whole
+            self.secrets
+                .sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        }
+        self
+    }
+
+    /// Scrub the value of a config key, if it is set
+    #[cfg(feature = "config")]
+    pub fn with_config_secret(self, key: &str) -> Self {
+        match super::Host::config(key) {
+            Some(value) => self.with_secret(value),
+            None => self,
+        }
+    }
+
+    /// Scrub every match of a regex
+    pub fn with_pattern(mut self, pattern: &str) -> Result<Self, PatternError> {
+        let regex = Regex::new(pattern).map_err(|e| PatternError {
+            pattern: pattern.to_string(),
+            message: e.to_string(),
+        })?;
+        self.patterns.push(regex);
+        Ok(self)
+    }
+
+    /// Replace secrets with `replacement` instead of `REPLACEMENT`
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Also scrub the strings of output set with `Host::output_json`
+    pub fn with_output(mut self, output: bool) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Scrub secrets from a string
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = Cow::Owned(text.replace(secret.as_str(), &self.replacement));
+            }
+        }
+        for pattern in &self.patterns {
+            if let Cow::Owned(replaced) =
+                pattern.replace_all(&text, regex_lite::NoExpand(&self.replacement))
+            {
+                text = Cow::Owned(replaced);
+            }
+        }
+        text
+    }
+
+    /// Scrub secrets from the strings of a JSON value, leaving object keys
+    /// as they are
+    pub fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => {
+                if let Cow::Owned(redacted) = self.redact(text) {
+                    *text = redacted;
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact_json(value)),
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|value| self.redact_json(value)),
+            _ => {}
+        }
+    }
+
+    /// Scrub log messages, errors and, if enabled, output from now on
+    ///
+    /// Replaces the redactor installed before.
+    pub fn install(self) {
+        INSTALLED.with(|installed| *installed.borrow_mut() = Some(Rc::new(self)));
+    }
+}
+
+thread_local! {
+    static INSTALLED: RefCell<Option<Rc<Redactor>>> = const { RefCell::new(None) };
+}
+
+/// Remove the installed redactor
+pub fn clear() {
+    INSTALLED.with(|installed| installed.borrow_mut().take());
+}
+
+/// The installed redactor, if any
+pub fn installed() -> Option<Rc<Redactor>> {
+    INSTALLED.with(|installed| installed.borrow().clone())
+}
+
+/// Scrub a string with the installed redactor
+pub fn redact(text: &str) -> Cow<'_, str> {
+    match installed() {
+        Some(redactor) => match redactor.redact(text) {
+            Cow::Borrowed(_) => Cow::Borrowed(text),
+            Cow::Owned(redacted) => Cow::Owned(redacted),
+        },
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// Serialize `data` to JSON, scrubbed with the installed redactor if
+/// `output` is false or the redactor scrubs output
+pub(crate) fn to_json<T: serde::Serialize>(
+    data: &T,
+    output: bool,
+) -> Result<String, serde_json::Error> {
+    match installed().filter(|redactor| !output || redactor.output) {
+        Some(redactor) => {
+            let mut value = serde_json::to_value(data)?;
+            redactor.redact_json(&mut value);
+            serde_json::to_string(&value)
+        }
+        None => serde_json::to_string(data),
+    }
+}
@@ -0,0 +1,888 @@
+//! A registry of published plugins
+//!
+//! `Registry` keeps plugin versions and their metadata in a pluggable
+//! `Storage`, and `Server` exposes it over HTTP so teams can host a private
+//! registry of plugins:
+//!
+//! | Method   | Path                                                 | Description                   |
+//! |----------|------------------------------------------------------|-------------------------------|
+//! | `GET`    | `/api/v1/plugins`                                    | List plugin names             |
+//! | `PUT`    | `/api/v1/plugins/new`                                | Publish a version             |
+//! | `GET`    | `/api/v1/plugins/{name}`                             | List releases                 |
+//! | `GET`    | `/api/v1/plugins/{name}/versions`                    | List versions                 |
+//! | `GET`    | `/api/v1/plugins/{name}/stats`                       | Get download statistics       |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}`                   | Get version metadata          |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}/download`          | Download the wasm             |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}/download/{target}` | Download a build for a target |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}/delta/{from}`      | Download a patch              |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}/sbom`              | Get the CycloneDX SBOM        |
+//! | `GET`    | `/api/v1/plugins/{name}/{version}/provenance`        | Get the SLSA provenance       |
+//! | `PUT`    | `/api/v1/plugins/{name}/{version}/yank`              | Yank a version                |
+//! | `DELETE` | `/api/v1/plugins/{name}/{version}/yank`              | Unyank a version              |
+//! | `PUT`    | `/api/v1/plugins/{name}/{version}/deprecation`       | Deprecate a version           |
+//! | `DELETE` | `/api/v1/plugins/{name}/{version}/deprecation`       | Undeprecate a version         |
+//! | `GET`    | `/api/v1/search`                                     | Search plugins                |
+//! | `GET`    | `/api/v1/blobs/sha256/{digest}`                      | Download a module by SHA-256  |
+//! | `PUT`    | `/api/v1/orgs/{org}`                                 | Create an organization        |
+//! | `GET`    | `/api/v1/orgs/{org}`                                 | Get an organization           |
+//! | `PUT`    | `/api/v1/orgs/{org}/members/{user}`                  | Add a member                  |
+//! | `DELETE` | `/api/v1/orgs/{org}/members/{user}`                  | Remove a member               |
+//! | `POST`   | `/api/v1/tokens`                                     | Issue a token                 |
+//! | `DELETE` | `/api/v1/tokens/{id}`                                | Revoke a token                |
+//! | `POST`   | `/api/v1/gc`                                         | Delete unneeded modules       |
+//! | `GET`    | `/api/v1/openapi.json`                               | Get the OpenAPI document      |
+//!
+//! A publish request body is the metadata JSON, the wasm module and,
+//! optionally, the JSON of its `Attachments` (signature, SBOM and
+//! provenance), then a JSON header and the binary of each build for another
+//! target, each prefixed with its length as a little-endian `u32`.
+//! Search takes the `q`, `capability`, `license` and `author` query
+//! parameters of a `SearchQuery`. Organization, token and `gc` routes
+//! require the admin token; with `Registry::with_read_auth()`, every route requires
+//! a token. `Client` speaks this API.
+//!
+//! Webhooks added with `Registry::with_webhook()` are notified of publishes
+//! and yanks with HMAC-signed JSON payloads.
+//!
+//! The server also implements the OCI distribution API under `/v2/`, so
+//! releases can be pulled and pushed as OCI artifacts by tools like `oras`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::capability::Capability;
+use super::host::sha256_hex;
+use super::provenance::{Provenance, ProvenanceError};
+use super::schema::FunctionSchema;
+use super::signing::{Signature, SignatureError};
+
+mod auth;
+mod blobs;
+mod client;
+mod credentials;
+mod delta;
+mod gc;
+mod lock;
+mod mirror;
+mod oci;
+mod oidc;
+mod openapi;
+mod resolve;
+mod sbom;
+mod search;
+mod server;
+mod stats;
+mod storage;
+mod target;
+mod validate;
+mod webhook;
+
+pub use auth::{Access, IssuedToken, Organization, TokenInfo, TokenScope, ALL_NAMESPACES};
+pub use blobs::BlobReference;
+pub use client::Client;
+pub use credentials::Credentials;
+pub use gc::{CollectedBlob, GcOptions, GcReport};
+pub use lock::{LockedPlugin, Lockfile, LOCKFILE_NAME};
+pub use mirror::Mirror;
+pub use oidc::OidcProvider;
+pub use openapi::openapi;
+pub use resolve::{Index, Resolution, Resolver};
+pub use sbom::{Component, DependencyNode, LicenseChoice, Sbom, SbomMetadata};
+pub use search::{SearchQuery, SearchResult};
+pub use semver::{Version, VersionReq};
+pub use server::Server;
+pub use stats::{Downloads, PluginInfo, VersionDownloads};
+pub use storage::{FileStorage, MemoryStorage, S3Storage, Storage};
+pub use target::{Target, TargetArtifact, TargetBuild};
+pub use webhook::{Webhook, WebhookEvent, WebhookPayload};
+
+pub(crate) use validate::exported_functions;
+
+/// Magic number at the start of every wasm module
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// Header carrying the SHA-256 of a published wasm module
+const CHECKSUM_HEADER: &str = "X-Checksum-Sha256";
+
+/// Maximum length of a plugin name
+const MAX_NAME_LEN: usize = 64;
+
+/// Registry error
+#[derive(Debug)]
+pub enum Error {
+    /// A file or socket operation failed
+    Io(std::io::Error),
+    /// The storage backend failed
+    Storage(String),
+    /// The plugin name is invalid
+    InvalidName(String),
+    /// The version is not a valid semantic version
+    InvalidVersion(String),
+    /// The published artifact or its metadata is invalid
+    InvalidArtifact(String),
+    /// A request is malformed
+    InvalidRequest(String),
+    /// The plugin or version does not exist
+    NotFound(String),
+    /// An organization or other record already exists
+    AlreadyExists(String),
+    /// The version has already been published
+    VersionExists {
+        /// Plugin name
+        name: String,
+        /// The existing version
+        version: String,
+    },
+    /// The request has no valid token for the operation
+    Unauthorized,
+    /// A signature is missing, invalid or not trusted
+    Signature(SignatureError),
+    /// A provenance attestation is invalid
+    Provenance(ProvenanceError),
+    /// No set of versions satisfies the dependency requirements
+    Unresolvable(String),
+    /// A lockfile could not be parsed
+    InvalidLockfile(String),
+    /// A wasm module does not match the hash recorded in the lockfile
+    LockMismatch {
+        /// The locked plugin and version
+        name: String,
+        /// The locked hash
+        expected: String,
+        /// The hash of the module
+        actual: String,
+    },
+    /// A request to a registry could not be made
+    Request(String),
+    /// A registry responded with an error
+    Remote {
+        /// HTTP status of the response
+        status: u16,
+        /// Error message from the registry
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Registry I/O error: {}", e),
+            Error::Storage(e) => write!(f, "Registry storage error: {}", e),
+            Error::InvalidName(name) => write!(f, "Invalid plugin name: {}", name),
+            Error::InvalidVersion(version) => write!(f, "Invalid version: {}", version),
+            Error::InvalidArtifact(e) => write!(f, "Invalid artifact: {}", e),
+            Error::InvalidRequest(e) => write!(f, "Invalid request: {}", e),
+            Error::NotFound(what) => write!(f, "Not found: {}", what),
+            Error::AlreadyExists(what) => write!(f, "{} already exists", what),
+            Error::VersionExists { name, version } => {
+                write!(f, "{} {} has already been published", name, version)
+            }
+            Error::Unauthorized => write!(f, "Missing or invalid token"),
+            Error::Signature(e) => write!(f, "{}", e),
+            Error::Provenance(e) => write!(f, "{}", e),
+            Error::Unresolvable(e) => write!(f, "Failed to resolve dependencies: {}", e),
+            Error::InvalidLockfile(e) => write!(f, "Invalid lockfile: {}", e),
+            Error::LockMismatch {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} does not match the lockfile: expected SHA-256 {}, got {}",
+                name, expected, actual
+            ),
+            Error::Request(e) => write!(f, "Registry request failed: {}", e),
+            Error::Remote { status, message } => {
+                write!(f, "Registry error (HTTP {}): {}", status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Storage(format!("invalid record: {}", e))
+    }
+}
+
+/// Metadata published with a plugin version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginMetadata {
+    /// Plugin name: lowercase letters, digits, `-` and `_`, optionally
+    /// scoped to an organization as `@org/name`
+    pub name: String,
+    /// Semantic version
+    pub version: String,
+    /// Short description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// SPDX license expression
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Plugin authors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    /// Functions the plugin exports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exports: Vec<String>,
+    /// Plugins this plugin needs, with the semver range of each, such as
+    /// `^1.2`, `~1.2.3` or `=1.2.3`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Host capabilities this plugin needs, with the semver range of each
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub host: BTreeMap<String, VersionReq>,
+    /// What the plugin needs its host's manifest to grant, such as `http`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<Capability>,
+    /// Config keys the host must set for the plugin, with a description of
+    /// each
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, String>,
+    /// What the published module was compiled for
+    #[serde(default, skip_serializing_if = "Target::is_default")]
+    pub target: Target,
+    /// JSON Schemas of the inputs and outputs of exported functions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schemas: Vec<FunctionSchema>,
+}
+
+impl PluginMetadata {
+    /// Create metadata for a plugin version
+    pub fn new(name: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: version.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Set the description
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the license
+    pub fn with_license(mut self, license: impl Into<String>) -> Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    /// Add an author
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.authors.push(author.into());
+        self
+    }
+
+    /// Add an exported function
+    pub fn with_export(mut self, export: impl Into<String>) -> Self {
+        self.exports.push(export.into());
+        self
+    }
+
+    /// Add a dependency on versions of another plugin matching `req`
+    ///
+    /// As with cargo, a bare version such as `1.2` means `^1.2`; pin an
+    /// exact version with `=1.2.3`.
+    pub fn with_dependency(mut self, name: impl Into<String>, req: VersionReq) -> Self {
+        self.dependencies.insert(name.into(), req);
+        self
+    }
+
+    /// Declare that the plugin needs a capability, such as `Capability::Http`
+    pub fn with_capability(mut self, capability: Capability) -> Self {
+        self.capabilities.push(capability);
+        self
+    }
+
+    /// Add a dependency on versions of a host capability matching `req`
+    pub fn with_host_requirement(mut self, capability: impl Into<String>, req: VersionReq) -> Self {
+        self.host.insert(capability.into(), req);
+        self
+    }
+
+    /// Set what the published module was compiled for, which is
+    /// `Target::Wasm32UnknownUnknown` by default
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Add the JSON Schemas of an exported function
+    pub fn with_schema(mut self, schema: FunctionSchema) -> Self {
+        self.schemas.push(schema);
+        self
+    }
+}
+
+/// A published plugin version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    /// Metadata published with the version
+    pub metadata: PluginMetadata,
+    /// SHA-256 of the wasm module, as hex
+    pub sha256: String,
+    /// Size of the wasm module in bytes
+    pub size: u64,
+    /// When the version was published, in seconds since the Unix epoch
+    pub published_at: u64,
+    /// Detached signature of the wasm module, if it was published signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    /// Whether an SBOM was published with the version
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sbom: bool,
+    /// Whether a SLSA provenance attestation was published with the version
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub provenance: bool,
+    /// Whether the version was yanked, which excludes it from new resolutions
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub yanked: bool,
+    /// When the version was yanked, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub yanked_at: Option<u64>,
+    /// Why the version is deprecated, if it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deprecated: Option<String>,
+    /// Builds for other targets than the module's
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub targets: Vec<TargetArtifact>,
+}
+
+impl Release {
+    /// Select the build to install on a host that runs `target`
+    ///
+    /// Without a build for `target`, a `wasm32-unknown-unknown` build is
+    /// selected, since every host runs those.
+    pub fn artifact(&self, target: Target) -> Option<TargetArtifact> {
+        let mut artifacts = std::iter::once(self.module()).chain(self.targets.iter().cloned());
+        let fallback = Target::Wasm32UnknownUnknown;
+        artifacts
+            .clone()
+            .find(|artifact| artifact.target == target)
+            .or_else(|| artifacts.find(|artifact| artifact.target == fallback))
+    }
+
+    /// Describe the release's module as a build for its target
+    pub(crate) fn module(&self) -> TargetArtifact {
+        TargetArtifact {
+            target: self.metadata.target,
+            sha256: self.sha256.clone(),
+            size: self.size,
+            signature: self.signature.clone(),
+        }
+    }
+}
+
+/// Optional documents published along with a plugin version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachments {
+    /// Detached signature of the wasm module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    /// Bill of materials of the plugin crate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sbom: Option<Sbom>,
+    /// SLSA provenance of the wasm module, signed by its builder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+    /// Builds of the plugin for other targets
+    ///
+    /// They are sent as separate parts of a publish request rather than in
+    /// the attachments JSON.
+    #[serde(skip)]
+    pub builds: Vec<TargetBuild>,
+}
+
+/// Plugin versions and metadata kept in a `Storage`
+///
+/// Each plugin has an index record listing its releases, and each release's
+/// wasm module is stored as a separate blob.
+pub struct Registry {
+    storage: Box<dyn Storage>,
+    publish_token: Option<String>,
+    /// Whether every read needs a token
+    read_auth: bool,
+    /// Tokens that may read every plugin
+    read_tokens: Vec<String>,
+    oidc: Option<OidcProvider>,
+    webhooks: Vec<Webhook>,
+    /// Serializes read-modify-write updates of index records
+    write: Mutex<()>,
+}
+
+impl Registry {
+    /// Create a registry backed by `storage`
+    pub fn new(storage: impl Storage + 'static) -> Self {
+        Self {
+            storage: Box::new(storage),
+            publish_token: None,
+            read_auth: false,
+            read_tokens: Vec::new(),
+            oidc: None,
+            webhooks: Vec::new(),
+            write: Mutex::new(()),
+        }
+    }
+
+    /// Require a bearer token to publish over HTTP
+    ///
+    /// Without one, anyone who can reach the server can publish unscoped
+    /// plugins and manage organizations and tokens. The token is also the
+    /// admin token, which may publish to every namespace.
+    pub fn with_publish_token(mut self, token: impl Into<String>) -> Self {
+        self.publish_token = Some(token.into());
+        self
+    }
+
+    /// Require a token for every request, not just publishing, so the
+    /// registry can distribute proprietary plugins
+    ///
+    /// Reads then need the admin token, a token added with
+    /// `with_read_token()`, an issued token or an ID token of the OIDC
+    /// provider. Without a publish token, no one is an admin.
+    pub fn with_read_auth(mut self) -> Self {
+        self.read_auth = true;
+        self
+    }
+
+    /// Accept a static token that may read every plugin, including those of
+    /// private organizations
+    pub fn with_read_token(mut self, token: impl Into<String>) -> Self {
+        self.read_tokens.push(token.into());
+        self
+    }
+
+    /// Accept ID tokens of an OpenID Connect provider, which act as the
+    /// user they were issued to with read access to every namespace the
+    /// user may read
+    pub fn with_oidc(mut self, provider: OidcProvider) -> Self {
+        self.oidc = Some(provider);
+        self
+    }
+
+    /// Post published and yanked events to a webhook
+    ///
+    /// Deliveries are signed with the webhook's secret and sent in the
+    /// background after the change is stored, so downstream systems can
+    /// deploy new versions or invalidate caches.
+    pub fn with_webhook(mut self, webhook: Webhook) -> Self {
+        self.webhooks.push(webhook);
+        self
+    }
+
+    /// List the names of all published plugins
+    pub fn plugins(&self) -> Result<Vec<String>, Error> {
+        let mut names: Vec<String> = self
+            .storage
+            .list(INDEX_PREFIX)?
+            .iter()
+            .filter_map(|key| key.strip_prefix(INDEX_PREFIX)?.strip_suffix(".json"))
+            .map(str::to_string)
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    /// List the releases of a plugin, oldest version first
+    pub fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        validate_name(name)?;
+        let releases = self.index(name)?;
+        if releases.is_empty() {
+            return Err(Error::NotFound(name.to_string()));
+        }
+        Ok(releases)
+    }
+
+    /// Get a release of a plugin
+    pub fn release(&self, name: &str, version: &str) -> Result<Release, Error> {
+        self.releases(name)?
+            .into_iter()
+            .find(|release| release.metadata.version == version)
+            .ok_or_else(|| Error::NotFound(format!("{} {}", name, version)))
+    }
+
+    /// Find plugins whose latest release that is not yanked matches `query`,
+    /// best matches first
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        let mut results = Vec::new();
+        for name in self.plugins()? {
+            let latest = self
+                .index(&name)?
+                .into_iter()
+                .rfind(|release| !release.yanked);
+            let Some(release) = latest else {
+                continue;
+            };
+            if let Some(score) = query.score(&release) {
+                results.push(SearchResult { score, release });
+            }
+        }
+        results.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.release.metadata.name.cmp(&b.release.metadata.name))
+        });
+        Ok(results)
+    }
+
+    /// Get the wasm module of a release
+    pub fn download(&self, name: &str, version: &str) -> Result<Vec<u8>, Error> {
+        let release = self.release(name, version)?;
+        match self.blob(&release.sha256) {
+            Err(Error::NotFound(_)) => {}
+            result => return result,
+        }
+        // Registries from before modules were stored by digest
+        match self.storage.get(&artifact_key(name, version))? {
+            Some(wasm) => Ok(wasm),
+            None if release.yanked => Err(Error::NotFound(format!(
+                "module of {} {}, which was yanked and collected",
+                name, version
+            ))),
+            None => Err(Error::Storage(format!(
+                "missing artifact for {} {}",
+                name, version
+            ))),
+        }
+    }
+
+    /// Get the build of a release to install on a host that runs `target`,
+    /// as selected by `Release::artifact()`
+    pub fn download_target(
+        &self,
+        name: &str,
+        version: &str,
+        target: Target,
+    ) -> Result<Vec<u8>, Error> {
+        let release = self.release(name, version)?;
+        match release.artifact(target) {
+            Some(artifact) if artifact.sha256 == release.sha256 => self.download(name, version),
+            Some(artifact) => self.blob(&artifact.sha256),
+            None => Err(Error::NotFound(format!(
+                "{} build of {} {}",
+                target, name, version
+            ))),
+        }
+    }
+
+    /// Get a patch turning the module of version `from` into that of `version`
+    ///
+    /// Patches are computed on first request and cached, so hosts updating
+    /// from an older version only transfer what changed.
+    pub fn delta(&self, name: &str, from: &str, version: &str) -> Result<Vec<u8>, Error> {
+        let new = self.download(name, version)?;
+        self.release(name, from)?;
+        let key = delta_key(name, from, version);
+        if let Some(patch) = self.storage.get(&key)? {
+            return Ok(patch);
+        }
+        let patch = delta::diff(&self.download(name, from)?, &new)?;
+        self.storage.put(&key, &patch)?;
+        Ok(patch)
+    }
+
+    /// Get the SBOM published with a release
+    pub fn sbom(&self, name: &str, version: &str) -> Result<Sbom, Error> {
+        if !self.release(name, version)?.sbom {
+            return Err(Error::NotFound(format!("SBOM for {} {}", name, version)));
+        }
+        let data = self
+            .storage
+            .get(&sbom_key(name, version))?
+            .ok_or_else(|| Error::Storage(format!("missing SBOM for {} {}", name, version)))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Get the provenance attestation published with a release
+    pub fn provenance(&self, name: &str, version: &str) -> Result<Provenance, Error> {
+        if !self.release(name, version)?.provenance {
+            return Err(Error::NotFound(format!(
+                "provenance for {} {}",
+                name, version
+            )));
+        }
+        let data = self
+            .storage
+            .get(&provenance_key(name, version))?
+            .ok_or_else(|| {
+                Error::Storage(format!("missing provenance for {} {}", name, version))
+            })?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Publish a new plugin version
+    pub fn publish(&self, metadata: PluginMetadata, wasm: &[u8]) -> Result<Release, Error> {
+        self.publish_with(metadata, wasm, Attachments::default())
+    }
+
+    /// Publish a new plugin version with attachments
+    ///
+    /// The module must be valid wasm exporting every function the metadata
+    /// declares, and the metadata must be well formed. A signature must
+    /// verify against the module; it is stored in the release for clients to
+    /// check against their trust policy. An SBOM is stored next to the module
+    /// and served by `sbom()`. Builds for other targets are checked the same
+    /// way and served by `download_target()`.
+    pub fn publish_with(
+        &self,
+        metadata: PluginMetadata,
+        wasm: &[u8],
+        attachments: Attachments,
+    ) -> Result<Release, Error> {
+        let Attachments {
+            signature,
+            sbom,
+            provenance,
+            builds,
+        } = attachments;
+        let version = check(&metadata, wasm, &builds)?;
+        if let Some(signature) = &signature {
+            signature.verify(wasm).map_err(Error::Signature)?;
+        }
+        for build in &builds {
+            if let Some(signature) = &build.signature {
+                signature.verify(&build.wasm).map_err(Error::Signature)?;
+            }
+        }
+        if let Some(provenance) = &provenance {
+            provenance.verify(wasm).map_err(Error::Provenance)?;
+        }
+        for name in metadata.dependencies.keys() {
+            validate_name(name)?;
+        }
+
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+        let mut releases = self.index(&metadata.name)?;
+        if releases
+            .iter()
+            .any(|release| release_version(release) == version)
+        {
+            return Err(Error::VersionExists {
+                name: metadata.name,
+                version: metadata.version,
+            });
+        }
+
+        let release = Release {
+            sha256: sha256_hex(wasm),
+            size: wasm.len() as u64,
+            published_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            signature,
+            sbom: sbom.is_some(),
+            provenance: provenance.is_some(),
+            yanked: false,
+            yanked_at: None,
+            deprecated: None,
+            targets: builds
+                .iter()
+                .map(|build| TargetArtifact {
+                    target: build.target,
+                    sha256: sha256_hex(&build.wasm),
+                    size: build.wasm.len() as u64,
+                    signature: build.signature.clone(),
+                })
+                .collect(),
+            metadata,
+        };
+        let name = &release.metadata.name;
+        let reference = BlobReference {
+            name: name.clone(),
+            version: release.metadata.version.clone(),
+        };
+        self.add_blob(&release.sha256, wasm, reference.clone())?;
+        for (build, artifact) in builds.iter().zip(&release.targets) {
+            self.add_blob(&artifact.sha256, &build.wasm, reference.clone())?;
+        }
+        if let Some(sbom) = &sbom {
+            self.storage.put(
+                &sbom_key(name, &release.metadata.version),
+                &serde_json::to_vec(sbom)?,
+            )?;
+        }
+        if let Some(provenance) = &provenance {
+            self.storage.put(
+                &provenance_key(name, &release.metadata.version),
+                &serde_json::to_vec(provenance)?,
+            )?;
+        }
+        releases.push(release.clone());
+        releases.sort_by_key(release_version);
+        self.storage
+            .put(&index_key(name), &serde_json::to_vec(&releases)?)?;
+        webhook::notify(&self.webhooks, WebhookEvent::Published, &release);
+        Ok(release)
+    }
+
+    /// Yank a release, so new resolutions skip it
+    ///
+    /// Yanked releases can still be downloaded, so lockfiles pinning them
+    /// keep working, until `gc()` deletes their modules after the retention
+    /// window.
+    pub fn yank(&self, name: &str, version: &str) -> Result<Release, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let release = self.update_release(name, version, |release| {
+            release.yanked = true;
+            release.yanked_at.get_or_insert(now);
+        })?;
+        webhook::notify(&self.webhooks, WebhookEvent::Yanked, &release);
+        Ok(release)
+    }
+
+    /// Undo yanking a release, unless `gc()` deleted its module
+    pub fn unyank(&self, name: &str, version: &str) -> Result<Release, Error> {
+        self.download(name, version)?;
+        self.update_release(name, version, |release| {
+            release.yanked = false;
+            release.yanked_at = None;
+        })
+    }
+
+    /// Mark a release deprecated, with a message shown when it is installed
+    pub fn deprecate(
+        &self,
+        name: &str,
+        version: &str,
+        message: impl Into<String>,
+    ) -> Result<Release, Error> {
+        let message = message.into();
+        self.update_release(name, version, |release| release.deprecated = Some(message))
+    }
+
+    /// Remove the deprecation of a release
+    pub fn undeprecate(&self, name: &str, version: &str) -> Result<Release, Error> {
+        self.update_release(name, version, |release| release.deprecated = None)
+    }
+
+    fn update_release(
+        &self,
+        name: &str,
+        version: &str,
+        update: impl FnOnce(&mut Release),
+    ) -> Result<Release, Error> {
+        validate_name(name)?;
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+        let mut releases = self.index(name)?;
+        let release = releases
+            .iter_mut()
+            .find(|release| release.metadata.version == version)
+            .ok_or_else(|| Error::NotFound(format!("{} {}", name, version)))?;
+        update(release);
+        let release = release.clone();
+        self.storage
+            .put(&index_key(name), &serde_json::to_vec(&releases)?)?;
+        Ok(release)
+    }
+
+    /// Read a plugin's index record, which is empty for unknown plugins
+    fn index(&self, name: &str) -> Result<Vec<Release>, Error> {
+        match self.storage.get(&index_key(name))? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Storage key prefix of index records
+const INDEX_PREFIX: &str = "index/";
+
+fn index_key(name: &str) -> String {
+    format!("{}{}.json", INDEX_PREFIX, name)
+}
+
+fn artifact_key(name: &str, version: &str) -> String {
+    format!("artifacts/{}/{}.wasm", name, version)
+}
+
+fn delta_key(name: &str, from: &str, version: &str) -> String {
+    format!("deltas/{}/{}/{}.zst", name, version, from)
+}
+
+fn provenance_key(name: &str, version: &str) -> String {
+    format!("artifacts/{}/{}.intoto.json", name, version)
+}
+
+fn sbom_key(name: &str, version: &str) -> String {
+    format!("artifacts/{}/{}.cdx.json", name, version)
+}
+
+/// Get the year, month and day of a number of days since the Unix epoch
+fn civil_date(days: u64) -> (i64, i64, i64) {
+    // After Howard Hinnant's algorithm
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Run the checks of `Registry::publish_with()` that do not depend on what
+/// is already published, returning the parsed version
+pub(crate) fn check(
+    metadata: &PluginMetadata,
+    wasm: &[u8],
+    builds: &[TargetBuild],
+) -> Result<semver::Version, Error> {
+    validate_name(&metadata.name)?;
+    let version = semver::Version::parse(&metadata.version)
+        .map_err(|_| Error::InvalidVersion(metadata.version.clone()))?;
+    if !wasm.starts_with(WASM_MAGIC) {
+        return Err(Error::InvalidArtifact("not a wasm module".to_string()));
+    }
+    validate::validate(metadata, wasm, builds)?;
+    Ok(version)
+}
+
+/// Parse a release's version, which was validated when it was published
+fn release_version(release: &Release) -> semver::Version {
+    semver::Version::parse(&release.metadata.version).unwrap_or(semver::Version::new(0, 0, 0))
+}
+
+/// Check that a plugin name is valid, optionally scoped as `@org/name`
+pub(crate) fn validate_name(name: &str) -> Result<(), Error> {
+    let valid = match name.strip_prefix('@') {
+        Some(scoped) => scoped
+            .split_once('/')
+            .is_some_and(|(org, name)| valid_identifier(org) && valid_identifier(name)),
+        None => valid_identifier(name),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidName(name.to_string()))
+    }
+}
+
+/// Check that a plugin or organization name is non-empty, short, and only
+/// uses lowercase letters, digits, `-` and `_`, starting with a letter or digit
+fn valid_identifier(name: &str) -> bool {
+    name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
@@ -0,0 +1,336 @@
+//! Organizations, namespaces and scoped API tokens
+//!
+//! Plugins named `@org/plugin` belong to the `@org` namespace, which only
+//! members of the organization can publish to. Members authenticate with API
+//! tokens issued by the registry admin, each scoped to read or publish in
+//! some namespaces. The registry's publish token acts as the admin token.
+
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+
+use super::{sha256_hex, valid_identifier, Error, Registry};
+
+/// Storage key prefix of organization records
+const ORG_PREFIX: &str = "orgs/";
+
+/// Storage key prefix of token records
+const TOKEN_PREFIX: &str = "tokens/";
+
+/// Prefix of issued tokens, so they are recognizable in logs and secrets scanners
+const TOKEN_MARKER: &str = "extismx_";
+
+/// Scope namespace covering every namespace, including unscoped plugins
+pub const ALL_NAMESPACES: &str = "*";
+
+/// What a token may do in a namespace
+///
+/// `Publish` includes `Read`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Read plugins, including those of private organizations
+    Read,
+    /// Read and publish plugins
+    Publish,
+}
+
+/// A namespace and the access a token has to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenScope {
+    /// `@org`, or `*` for every namespace
+    pub namespace: String,
+    /// Granted access
+    pub access: Access,
+}
+
+impl TokenScope {
+    /// Read access to `namespace`
+    pub fn read(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            access: Access::Read,
+        }
+    }
+
+    /// Publish access to `namespace`
+    pub fn publish(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            access: Access::Publish,
+        }
+    }
+}
+
+/// An organization owning the `@name` namespace
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organization {
+    /// Users who may publish to the namespace, sorted
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Whether reading the namespace's plugins requires a token
+    #[serde(default)]
+    pub private: bool,
+}
+
+/// A stored API token, identified by the SHA-256 of its secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// SHA-256 of the token, as hex
+    pub id: String,
+    /// User the token acts as
+    pub user: String,
+    /// What the token grants
+    pub scopes: Vec<TokenScope>,
+}
+
+/// A newly issued token
+///
+/// The secret is only available at issue time; the registry keeps its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuedToken {
+    /// Token ID, used to revoke it
+    pub id: String,
+    /// Bearer token to authenticate with
+    pub token: String,
+}
+
+/// Get the namespace of a plugin name, such as `@org` for `@org/plugin`
+pub(crate) fn namespace(name: &str) -> Option<&str> {
+    name.starts_with('@')
+        .then(|| name.split_once('/').map(|(namespace, _)| namespace))
+        .flatten()
+}
+
+impl Registry {
+    /// Create an organization owning the `@name` namespace
+    pub fn create_org(&self, name: &str, private: bool) -> Result<Organization, Error> {
+        validate_org(name)?;
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+        if self.storage.get(&org_key(name))?.is_some() {
+            return Err(Error::AlreadyExists(format!("organization {}", name)));
+        }
+        let org = Organization {
+            members: Vec::new(),
+            private,
+        };
+        self.storage
+            .put(&org_key(name), &serde_json::to_vec(&org)?)?;
+        Ok(org)
+    }
+
+    /// Get an organization
+    pub fn org(&self, name: &str) -> Result<Organization, Error> {
+        validate_org(name)?;
+        let data = self
+            .storage
+            .get(&org_key(name))?
+            .ok_or_else(|| Error::NotFound(format!("organization {}", name)))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Add a user to an organization
+    pub fn add_member(&self, org: &str, user: &str) -> Result<Organization, Error> {
+        self.update_org(org, |org| {
+            if let Err(i) = org
+                .members
+                .binary_search_by(|member| member.as_str().cmp(user))
+            {
+                org.members.insert(i, user.to_string());
+            }
+        })
+    }
+
+    /// Remove a user from an organization
+    pub fn remove_member(&self, org: &str, user: &str) -> Result<Organization, Error> {
+        self.update_org(org, |org| org.members.retain(|member| member != user))
+    }
+
+    /// Issue a token acting as `user` with the given scopes
+    ///
+    /// Scopes must name `*` or an existing organization.
+    pub fn issue_token(&self, user: &str, scopes: Vec<TokenScope>) -> Result<IssuedToken, Error> {
+        if user.is_empty() {
+            return Err(Error::InvalidRequest("token user is empty".to_string()));
+        }
+        for scope in &scopes {
+            if scope.namespace != ALL_NAMESPACES {
+                let org = scope
+                    .namespace
+                    .strip_prefix('@')
+                    .ok_or_else(|| Error::InvalidName(format!("namespace {}", scope.namespace)))?;
+                self.org(org)?;
+            }
+        }
+        let mut secret = [0u8; 32];
+        rand_core::OsRng.fill_bytes(&mut secret);
+        let token = format!("{}{}", TOKEN_MARKER, hex(&secret));
+        let info = TokenInfo {
+            id: sha256_hex(token.as_bytes()),
+            user: user.to_string(),
+            scopes,
+        };
+        self.storage
+            .put(&token_key(&info.id), &serde_json::to_vec(&info)?)?;
+        Ok(IssuedToken { id: info.id, token })
+    }
+
+    /// Revoke a token by its ID
+    pub fn revoke_token(&self, id: &str) -> Result<(), Error> {
+        if !id.chars().all(|c| c.is_ascii_hexdigit()) || !self.storage.delete(&token_key(id))? {
+            return Err(Error::NotFound(format!("token {}", id)));
+        }
+        Ok(())
+    }
+
+    /// Check that `token` is the admin token
+    ///
+    /// Without a publish token configured, anyone is an admin, unless reads
+    /// require authentication.
+    pub(crate) fn authorize_admin(&self, token: Option<&str>) -> Result<(), Error> {
+        match &self.publish_token {
+            Some(expected) if token.is_some_and(|token| same_secret(token, expected)) => Ok(()),
+            None if !self.read_auth => Ok(()),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    /// Check that `token` is valid, if every request needs one
+    pub(crate) fn authenticate(&self, token: Option<&str>) -> Result<(), Error> {
+        if !self.read_auth || self.authorize_admin(token).is_ok() {
+            return Ok(());
+        }
+        let token = token.ok_or(Error::Unauthorized)?;
+        if self.is_read_token(token) || self.identify(token)?.is_some() {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+
+    /// Check that `token` grants `access` to the plugin `name`
+    ///
+    /// The admin token may do anything. Otherwise unscoped plugins can be
+    /// read by anyone and published by tokens scoped to `*`, and plugins of
+    /// an organization can be published by its members and read by anyone
+    /// unless the organization is private. With read authentication,
+    /// "anyone" means any valid token. Static read tokens may read
+    /// everything. The organization must exist.
+    pub(crate) fn authorize(
+        &self,
+        token: Option<&str>,
+        name: &str,
+        access: Access,
+    ) -> Result<(), Error> {
+        let namespace = namespace(name);
+        let org = namespace
+            .map(|namespace| self.org(&namespace[1..]))
+            .transpose()?;
+        if self.authorize_admin(token).is_ok()
+            || (access == Access::Read && token.is_some_and(|token| self.is_read_token(token)))
+        {
+            return Ok(());
+        }
+        let info = match token {
+            Some(token) => self.identify(token)?,
+            None => None,
+        };
+        let authenticated = info.is_some();
+        let granted = info.is_some_and(|info| {
+            let member = org
+                .as_ref()
+                .is_none_or(|org| org.members.contains(&info.user));
+            member
+                && info.scopes.iter().any(|scope| {
+                    scope.access >= access
+                        && (scope.namespace == ALL_NAMESPACES
+                            || Some(scope.namespace.as_str()) == namespace)
+                })
+        });
+        let public = access == Access::Read
+            && org.as_ref().is_none_or(|org| !org.private)
+            && (authenticated || !self.read_auth);
+        if granted || public {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+
+    fn is_read_token(&self, token: &str) -> bool {
+        self.read_tokens
+            .iter()
+            .any(|read_token| same_secret(read_token, token))
+    }
+
+    /// Look up who a token acts as: an issued token, or an ID token of the
+    /// OIDC provider, which may read what its user may
+    fn identify(&self, token: &str) -> Result<Option<TokenInfo>, Error> {
+        if let Some(info) = self.token(token)? {
+            return Ok(Some(info));
+        }
+        let Some(oidc) = &self.oidc else {
+            return Ok(None);
+        };
+        match oidc.validate(token) {
+            Ok(user) => Ok(Some(TokenInfo {
+                id: String::new(),
+                user,
+                scopes: vec![TokenScope::read(ALL_NAMESPACES)],
+            })),
+            Err(Error::Unauthorized) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Look up an issued token by its secret
+    fn token(&self, token: &str) -> Result<Option<TokenInfo>, Error> {
+        match self
+            .storage
+            .get(&token_key(&sha256_hex(token.as_bytes())))?
+        {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn update_org(
+        &self,
+        name: &str,
+        update: impl FnOnce(&mut Organization),
+    ) -> Result<Organization, Error> {
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+        let mut org = self.org(name)?;
+        update(&mut org);
+        self.storage
+            .put(&org_key(name), &serde_json::to_vec(&org)?)?;
+        Ok(org)
+    }
+}
+
+fn validate_org(name: &str) -> Result<(), Error> {
+    if valid_identifier(name) {
+        Ok(())
+    } else {
+        Err(Error::InvalidName(format!("organization {}", name)))
+    }
+}
+
+fn org_key(name: &str) -> String {
+    format!("{}{}.json", ORG_PREFIX, name)
+}
+
+fn token_key(id: &str) -> String {
+    format!("{}{}.json", TOKEN_PREFIX, id)
+}
+
+/// Compare two secrets by their SHA-256, as issued tokens are looked up
+///
+/// How long the comparison takes then depends on the digests, which reveals
+/// nothing about how much of a guess matches the secret.
+fn same_secret(a: &str, b: &str) -> bool {
+    sha256_hex(a.as_bytes()) == sha256_hex(b.as_bytes())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
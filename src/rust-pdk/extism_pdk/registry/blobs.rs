@@ -0,0 +1,81 @@
+//! Content-addressed storage of wasm modules
+
+use serde::{Deserialize, Serialize};
+
+use super::{Error, Registry};
+
+/// Storage key prefix of modules, stored by SHA-256
+pub(super) const BLOB_PREFIX: &str = "blobs/sha256/";
+
+/// Storage key prefix of the releases referencing each module
+const REFS_PREFIX: &str = "blobs/refs/";
+
+/// A release whose module is a blob
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BlobReference {
+    /// Plugin name
+    pub name: String,
+    /// Plugin version
+    pub version: String,
+}
+
+impl Registry {
+    /// Get a wasm module by its SHA-256
+    ///
+    /// Modules are stored once however many releases share them, so a
+    /// digest pins a module independently of the names it is published under.
+    pub fn blob(&self, sha256: &str) -> Result<Vec<u8>, Error> {
+        let sha256 = normalize_digest(sha256)?;
+        self.storage
+            .get(&blob_key(&sha256))?
+            .ok_or_else(|| Error::NotFound(format!("blob {}", sha256)))
+    }
+
+    /// List the releases whose module has the given SHA-256, so the blob is
+    /// kept as long as this is not empty
+    pub fn blob_references(&self, sha256: &str) -> Result<Vec<BlobReference>, Error> {
+        let sha256 = normalize_digest(sha256)?;
+        match self.storage.get(&refs_key(&sha256))? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Store a module unless an identical one is already stored, and record
+    /// that a release references it; the caller must hold the write lock
+    pub(crate) fn add_blob(
+        &self,
+        sha256: &str,
+        wasm: &[u8],
+        reference: BlobReference,
+    ) -> Result<(), Error> {
+        let mut references = self.blob_references(sha256)?;
+        if references.is_empty() {
+            self.storage.put(&blob_key(sha256), wasm)?;
+        }
+        if let Err(i) = references.binary_search(&reference) {
+            references.insert(i, reference);
+        }
+        self.storage
+            .put(&refs_key(sha256), &serde_json::to_vec(&references)?)
+    }
+}
+
+/// Check that a digest is a hex SHA-256, returning it in lowercase
+fn normalize_digest(sha256: &str) -> Result<String, Error> {
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidArtifact(format!(
+            "invalid SHA-256 {}",
+            sha256
+        )));
+    }
+    Ok(sha256.to_ascii_lowercase())
+}
+
+pub(super) fn blob_key(sha256: &str) -> String {
+    format!("{}{}", BLOB_PREFIX, sha256)
+}
+
+pub(super) fn refs_key(sha256: &str) -> String {
+    format!("{}{}.json", REFS_PREFIX, sha256)
+}
@@ -0,0 +1,667 @@
+//! Client for a registry's HTTP API
+
+use std::path::PathBuf;
+
+use super::target::BuildHeader;
+use super::{
+    delta, release_version, sha256_hex, Attachments, Credentials, Downloads, Error, GcOptions,
+    GcReport, IssuedToken, Mirror, Organization, PluginInfo, PluginMetadata, Release, Sbom,
+    SearchQuery, SearchResult, Signature, Target, TargetArtifact, TokenScope, CHECKSUM_HEADER,
+};
+use crate::extism_pdk::provenance::{Provenance, ProvenancePolicy};
+use crate::extism_pdk::signing::TrustPolicy;
+
+/// Maximum size of a downloaded wasm module
+const MAX_DOWNLOAD_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Talks to a registry served by `Server`
+#[derive(Clone)]
+pub struct Client {
+    /// Base URL of the registry, without a trailing `/`
+    url: String,
+    agent: ureq::Agent,
+    trust: TrustPolicy,
+    provenance: ProvenancePolicy,
+    /// Token sent with read requests
+    token: Option<String>,
+    mirror: Option<Mirror>,
+}
+
+impl Client {
+    /// Create a client for the registry at `url`, such as `https://plugins.example.com`
+    pub fn new(url: impl Into<String>) -> Self {
+        let config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build();
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new_with_config(config),
+            trust: TrustPolicy::default(),
+            provenance: ProvenancePolicy::default(),
+            token: None,
+            mirror: None,
+        }
+    }
+
+    /// Authenticate reads with a token, to access private organizations
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Authenticate with the token stored for this registry, if there is one
+    pub fn with_credentials(mut self, credentials: &Credentials) -> Self {
+        if let Some(token) = credentials.token(&self.url) {
+            self.token = Some(token.to_string());
+        }
+        self
+    }
+
+    /// Only accept downloads signed with a key the policy trusts
+    pub fn with_trust_policy(mut self, trust: TrustPolicy) -> Self {
+        self.trust = trust;
+        self
+    }
+
+    /// Only accept downloads with provenance signed by a builder the policy trusts
+    pub fn with_provenance_policy(mut self, policy: ProvenancePolicy) -> Self {
+        self.provenance = policy;
+        self
+    }
+
+    /// Keep a local mirror of the indexes and modules this client fetches
+    ///
+    /// Downloads are served from the mirror when it has the module, and
+    /// index lookups fall back to it when the registry cannot be reached, so
+    /// installs work offline once the mirror is populated by `sync_mirror()`
+    /// or earlier installs.
+    pub fn with_mirror(mut self, path: impl Into<PathBuf>) -> Self {
+        self.mirror = Some(Mirror::new(path));
+        self
+    }
+
+    /// Get the client's mirror
+    pub fn mirror(&self) -> Option<&Mirror> {
+        self.mirror.as_ref()
+    }
+
+    /// Get the base URL of the registry
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Publish a wasm module as a new plugin version
+    ///
+    /// The module's SHA-256 is sent along with it, so the registry rejects
+    /// uploads corrupted in transit, and checked against the published release.
+    pub fn publish(
+        &self,
+        artifact: &[u8],
+        metadata: &PluginMetadata,
+        token: &str,
+    ) -> Result<Release, Error> {
+        self.publish_with(artifact, metadata, &Attachments::default(), token)
+    }
+
+    /// Publish a wasm module with a detached signature made by `signing::sign()`
+    pub fn publish_signed(
+        &self,
+        artifact: &[u8],
+        metadata: &PluginMetadata,
+        signature: &Signature,
+        token: &str,
+    ) -> Result<Release, Error> {
+        let attachments = Attachments {
+            signature: Some(signature.clone()),
+            ..Attachments::default()
+        };
+        self.publish_with(artifact, metadata, &attachments, token)
+    }
+
+    /// Publish a wasm module with attachments, such as an SBOM made by
+    /// `Sbom::generate()` or builds for other targets
+    pub fn publish_with(
+        &self,
+        artifact: &[u8],
+        metadata: &PluginMetadata,
+        attachments: &Attachments,
+        token: &str,
+    ) -> Result<Release, Error> {
+        let sha256 = sha256_hex(artifact);
+        let metadata = serde_json::to_vec(metadata)?;
+        let builds = &attachments.builds;
+        let attachments = serde_json::to_vec(attachments)?;
+        let headers = builds
+            .iter()
+            .map(|build| {
+                serde_json::to_vec(&BuildHeader {
+                    target: build.target,
+                    signature: build.signature.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut parts = vec![metadata.as_slice(), artifact, attachments.as_slice()];
+        for (header, build) in headers.iter().zip(builds) {
+            parts.push(header);
+            parts.push(&build.wasm);
+        }
+        let mut body = Vec::with_capacity(parts.iter().map(|part| 4 + part.len()).sum());
+        for part in parts {
+            body.extend_from_slice(&(part.len() as u32).to_le_bytes());
+            body.extend_from_slice(part);
+        }
+
+        let url = self.api(&["plugins", "new"]);
+        let response = self
+            .authorize(self.agent.put(&url), Some(token))
+            .header(CHECKSUM_HEADER, &sha256)
+            .send(&body[..])
+            .map_err(|e| request_error(&url, e))?;
+        let release: Release = serde_json::from_slice(&read(&url, response)?)?;
+        if release.sha256 != sha256 {
+            return Err(Error::InvalidArtifact(format!(
+                "registry recorded SHA-256 {}, expected {}",
+                release.sha256, sha256
+            )));
+        }
+        Ok(release)
+    }
+
+    /// List the names of all published plugins
+    pub fn plugins(&self) -> Result<Vec<String>, Error> {
+        self.or_mirror(self.get_json(&["plugins"]), |mirror| {
+            mirror.plugins().map(Some)
+        })
+    }
+
+    /// List the releases of a plugin, oldest version first
+    pub fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        let releases = self.or_mirror(self.get_json(&["plugins", name]), |mirror| {
+            mirror.releases(name)
+        })?;
+        if let Some(mirror) = &self.mirror {
+            mirror.store_releases(name, &releases)?;
+        }
+        Ok(releases)
+    }
+
+    /// List the published versions of a plugin, oldest first
+    pub fn versions(&self, name: &str) -> Result<Vec<String>, Error> {
+        self.or_mirror(self.get_json(&["plugins", name, "versions"]), |mirror| {
+            Ok(mirror.releases(name)?.map(|releases| {
+                releases
+                    .into_iter()
+                    .map(|release| release.metadata.version)
+                    .collect()
+            }))
+        })
+    }
+
+    /// Get the metadata of a plugin version
+    pub fn release(&self, name: &str, version: &str) -> Result<Release, Error> {
+        self.or_mirror(self.get_json(&["plugins", name, version]), |mirror| {
+            Ok(mirror.releases(name)?.and_then(|releases| {
+                releases
+                    .into_iter()
+                    .find(|release| release.metadata.version == version)
+            }))
+        })
+    }
+
+    /// Download every version of the given plugins, or of all plugins if
+    /// `names` is empty, into the client's mirror
+    ///
+    /// Returns the number of modules downloaded; modules already mirrored
+    /// are skipped.
+    pub fn sync_mirror(&self, names: &[&str]) -> Result<usize, Error> {
+        let mirror = self
+            .mirror
+            .as_ref()
+            .ok_or_else(|| Error::InvalidRequest("the client has no mirror".to_string()))?;
+        let names: Vec<String> = match names {
+            [] => self.get_json(&["plugins"])?,
+            names => names.iter().map(|name| name.to_string()).collect(),
+        };
+        let mut downloaded = 0;
+        for name in names {
+            for release in self.releases(&name)? {
+                if mirror.has_blob(&release.sha256) {
+                    continue;
+                }
+                self.download(&name, &release.metadata.version)?;
+                downloaded += 1;
+            }
+        }
+        Ok(downloaded)
+    }
+
+    /// Get the SBOM published with a plugin version
+    pub fn sbom(&self, name: &str, version: &str) -> Result<Sbom, Error> {
+        self.get_json(&["plugins", name, version, "sbom"])
+    }
+
+    /// Get the OpenAPI document describing the registry's API
+    pub fn openapi(&self) -> Result<serde_json::Value, Error> {
+        self.get_json(&["openapi.json"])
+    }
+
+    /// Get the SLSA provenance attestation published with a plugin version
+    pub fn provenance(&self, name: &str, version: &str) -> Result<Provenance, Error> {
+        self.get_json(&["plugins", name, version, "provenance"])
+    }
+
+    /// Get the download counts of a plugin
+    pub fn downloads(&self, name: &str) -> Result<Downloads, Error> {
+        self.get_json(&["plugins", name, "stats"])
+    }
+
+    /// Get the releases and download counts of a plugin
+    pub fn info(&self, name: &str) -> Result<PluginInfo, Error> {
+        Ok(PluginInfo {
+            name: name.to_string(),
+            releases: self.releases(name)?,
+            downloads: self.downloads(name)?,
+        })
+    }
+
+    /// Find plugins matching `query`, best matches first
+    pub fn search(&self, query: &SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        let url = self.api(&["search"]);
+        let params = [
+            ("q", &query.text),
+            ("capability", &query.capability),
+            ("license", &query.license),
+            ("author", &query.author),
+        ];
+        let mut request = self.authorize(self.agent.get(&url), None);
+        for (key, value) in params {
+            if let Some(value) = value {
+                request = request.query(key, value);
+            }
+        }
+        let response = request.call().map_err(|e| request_error(&url, e))?;
+        Ok(serde_json::from_slice(&read(&url, response)?)?)
+    }
+
+    /// Download the wasm module of a plugin version, verifying its SHA-256
+    ///
+    /// Deprecated versions are downloaded with a warning. With a trust policy, the module must also carry a signature by a
+    /// trusted key, or the download fails with `Error::Signature`; likewise
+    /// with a provenance policy and `Error::Provenance`. With a
+    /// mirror holding an older version of the plugin, only a patch from it
+    /// is downloaded.
+    pub fn download(&self, name: &str, version: &str) -> Result<Vec<u8>, Error> {
+        let release = self.release(name, version)?;
+        self.download_artifact(name, &release, release.module())
+    }
+
+    /// Download the build of a plugin version to install on a host that runs
+    /// `target`, as selected by `Release::artifact()`, verifying it like
+    /// `download()`
+    pub fn download_target(
+        &self,
+        name: &str,
+        version: &str,
+        target: Target,
+    ) -> Result<Vec<u8>, Error> {
+        let release = self.release(name, version)?;
+        let artifact = release
+            .artifact(target)
+            .ok_or_else(|| Error::NotFound(format!("{} build of {} {}", target, name, version)))?;
+        self.download_artifact(name, &release, artifact)
+    }
+
+    /// Download a plugin version as a patch from `base`, the module of
+    /// version `from`, verifying the patched module like `download()`
+    pub fn download_delta(
+        &self,
+        name: &str,
+        version: &str,
+        from: &str,
+        base: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let release = self.release(name, version)?;
+        let module = release.module();
+        let wasm = self.fetch_delta(name, version, from, base)?;
+        let wasm = self.store(name, &release, &module, wasm)?;
+        self.accept(name, &release, &module, wasm)
+    }
+
+    /// Download a wasm module by its SHA-256, verifying it
+    pub fn blob(&self, sha256: &str) -> Result<Vec<u8>, Error> {
+        if let Some(wasm) = match &self.mirror {
+            Some(mirror) => mirror.blob(sha256)?,
+            None => None,
+        } {
+            return Ok(wasm);
+        }
+        let wasm = self.get(&["blobs", "sha256", sha256])?;
+        let actual = sha256_hex(&wasm);
+        if !actual.eq_ignore_ascii_case(sha256) {
+            return Err(Error::InvalidArtifact(format!(
+                "blob {} has SHA-256 {}",
+                sha256, actual
+            )));
+        }
+        if let Some(mirror) = &self.mirror {
+            mirror.store_blob(&wasm)?;
+        }
+        Ok(wasm)
+    }
+
+    /// Get the URL of a wasm module by its SHA-256, to pin it in a manifest
+    /// with `Manifest::with_wasm_url(url, sha256)`
+    pub fn blob_url(&self, sha256: &str) -> String {
+        self.api(&["blobs", "sha256", sha256])
+    }
+
+    /// Yank a plugin version, so new resolutions skip it
+    ///
+    /// Yanked versions can still be downloaded, so lockfiles pinning them
+    /// keep working.
+    pub fn yank(&self, name: &str, version: &str, token: &str) -> Result<Release, Error> {
+        self.send_json("PUT", &["plugins", name, version, "yank"], &(), token)
+    }
+
+    /// Undo yanking a plugin version
+    pub fn unyank(&self, name: &str, version: &str, token: &str) -> Result<Release, Error> {
+        Ok(serde_json::from_slice(
+            &self.delete(&["plugins", name, version, "yank"], token)?,
+        )?)
+    }
+
+    /// Mark a plugin version deprecated, with a message shown when it is installed
+    pub fn deprecate(
+        &self,
+        name: &str,
+        version: &str,
+        message: &str,
+        token: &str,
+    ) -> Result<Release, Error> {
+        let body = serde_json::json!({ "message": message });
+        self.send_json(
+            "PUT",
+            &["plugins", name, version, "deprecation"],
+            &body,
+            token,
+        )
+    }
+
+    /// Remove the deprecation of a plugin version
+    pub fn undeprecate(&self, name: &str, version: &str, token: &str) -> Result<Release, Error> {
+        Ok(serde_json::from_slice(&self.delete(
+            &["plugins", name, version, "deprecation"],
+            token,
+        )?)?)
+    }
+
+    /// Create an organization owning the `@name` namespace, with the admin token
+    pub fn create_org(
+        &self,
+        name: &str,
+        private: bool,
+        token: &str,
+    ) -> Result<Organization, Error> {
+        let body = serde_json::json!({ "private": private });
+        self.send_json("PUT", &["orgs", name], &body, token)
+    }
+
+    /// Get an organization, with the admin token
+    pub fn org(&self, name: &str, token: &str) -> Result<Organization, Error> {
+        let url = self.api(&["orgs", name]);
+        let response = self
+            .authorize(self.agent.get(&url), Some(token))
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        Ok(serde_json::from_slice(&read(&url, response)?)?)
+    }
+
+    /// Add a user to an organization, with the admin token
+    pub fn add_member(&self, org: &str, user: &str, token: &str) -> Result<Organization, Error> {
+        self.send_json("PUT", &["orgs", org, "members", user], &(), token)
+    }
+
+    /// Remove a user from an organization, with the admin token
+    pub fn remove_member(&self, org: &str, user: &str, token: &str) -> Result<Organization, Error> {
+        Ok(serde_json::from_slice(
+            &self.delete(&["orgs", org, "members", user], token)?,
+        )?)
+    }
+
+    /// Issue a token acting as `user`, with the admin token
+    pub fn issue_token(
+        &self,
+        user: &str,
+        scopes: &[TokenScope],
+        token: &str,
+    ) -> Result<IssuedToken, Error> {
+        let body = serde_json::json!({ "user": user, "scopes": scopes });
+        self.send_json("POST", &["tokens"], &body, token)
+    }
+
+    /// Revoke a token by its ID, with the admin token
+    pub fn revoke_token(&self, id: &str, token: &str) -> Result<(), Error> {
+        self.delete(&["tokens", id], token)?;
+        Ok(())
+    }
+
+    /// Delete the modules the registry no longer needs, or with
+    /// `GcOptions::with_dry_run(true)` only report them, with the admin token
+    pub fn gc(&self, options: &GcOptions, token: &str) -> Result<GcReport, Error> {
+        self.send_json("POST", &["gc"], options, token)
+    }
+
+    /// Fall back to the mirror when the registry cannot be reached
+    /// Find the newest mirrored module of an older version to patch
+    fn delta_base(&self, name: &str, release: &Release) -> Option<(String, Vec<u8>)> {
+        let mirror = self.mirror.as_ref()?;
+        let version = release_version(release);
+        let mut releases = self.releases(name).ok()?;
+        releases.retain(|base| release_version(base) < version && mirror.has_blob(&base.sha256));
+        let base = releases.into_iter().max_by_key(release_version)?;
+        let wasm = mirror.blob(&base.sha256).ok()??;
+        Some((base.metadata.version, wasm))
+    }
+
+    fn fetch_delta(
+        &self,
+        name: &str,
+        version: &str,
+        from: &str,
+        base: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let patch = self.get(&["plugins", name, version, "delta", from])?;
+        delta::apply(base, &patch)
+    }
+
+    fn download_artifact(
+        &self,
+        name: &str,
+        release: &Release,
+        artifact: TargetArtifact,
+    ) -> Result<Vec<u8>, Error> {
+        let version = &release.metadata.version;
+        let mirrored = match &self.mirror {
+            Some(mirror) => mirror.blob(&artifact.sha256)?,
+            None => None,
+        };
+        let wasm = match mirrored {
+            Some(wasm) => wasm,
+            // Only the modules of releases have patches
+            None if artifact.sha256 == release.sha256 => {
+                // A failed patch falls back to the full module
+                let patched = self.delta_base(name, release).and_then(|(from, base)| {
+                    self.fetch_delta(name, version, &from, &base)
+                        .ok()
+                        .filter(|wasm| sha256_hex(wasm) == release.sha256)
+                });
+                let wasm = match patched {
+                    Some(wasm) => wasm,
+                    None => self.get(&["plugins", name, version, "download"])?,
+                };
+                self.store(name, release, &artifact, wasm)?
+            }
+            None => {
+                let target = artifact.target.to_string();
+                let wasm = self.get(&["plugins", name, version, "download", &target])?;
+                self.store(name, release, &artifact, wasm)?
+            }
+        };
+        self.accept(name, release, &artifact, wasm)
+    }
+
+    /// Verify a downloaded build against its release and mirror it
+    fn store(
+        &self,
+        name: &str,
+        release: &Release,
+        artifact: &TargetArtifact,
+        wasm: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        let actual = sha256_hex(&wasm);
+        if actual != artifact.sha256 {
+            return Err(Error::InvalidArtifact(format!(
+                "{} {} ({}) has SHA-256 {}, expected {}",
+                name, release.metadata.version, artifact.target, actual, artifact.sha256
+            )));
+        }
+        if let Some(mirror) = &self.mirror {
+            mirror.store_blob(&wasm)?;
+        }
+        Ok(wasm)
+    }
+
+    /// Check a module against the trust and provenance policies and warn if
+    /// it is deprecated
+    fn accept(
+        &self,
+        name: &str,
+        release: &Release,
+        artifact: &TargetArtifact,
+        wasm: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.trust
+            .verify(&wasm, artifact.signature.as_ref())
+            .map_err(Error::Signature)?;
+        if self.provenance.required {
+            let provenance = match release.provenance {
+                true => Some(self.provenance(name, &release.metadata.version)?),
+                false => None,
+            };
+            self.provenance
+                .verify(&wasm, provenance.as_ref())
+                .map_err(Error::Provenance)?;
+        }
+        if let Some(message) = &release.deprecated {
+            tracing::warn!(
+                plugin = name,
+                version = release.metadata.version,
+                "deprecated: {}",
+                message
+            );
+        }
+        Ok(wasm)
+    }
+
+    fn or_mirror<T>(
+        &self,
+        result: Result<T, Error>,
+        fallback: impl FnOnce(&Mirror) -> Result<Option<T>, Error>,
+    ) -> Result<T, Error> {
+        match (result, &self.mirror) {
+            (Err(Error::Request(e)), Some(mirror)) => fallback(mirror)?.ok_or(Error::Request(e)),
+            (result, _) => result,
+        }
+    }
+
+    fn api(&self, segments: &[&str]) -> String {
+        let mut url = format!("{}/api/v1", self.url);
+        for segment in segments {
+            url.push('/');
+            url.push_str(segment);
+        }
+        url
+    }
+
+    fn get(&self, segments: &[&str]) -> Result<Vec<u8>, Error> {
+        let url = self.api(segments);
+        let response = self
+            .authorize(self.agent.get(&url), None)
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        read(&url, response)
+    }
+
+    fn delete(&self, segments: &[&str], token: &str) -> Result<Vec<u8>, Error> {
+        let url = self.api(segments);
+        let response = self
+            .authorize(self.agent.delete(&url), Some(token))
+            .call()
+            .map_err(|e| request_error(&url, e))?;
+        read(&url, response)
+    }
+
+    /// Send a JSON body with `method`
+    fn send_json<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        segments: &[&str],
+        body: &impl serde::Serialize,
+        token: &str,
+    ) -> Result<T, Error> {
+        let url = self.api(segments);
+        let request = match method {
+            "POST" => self.agent.post(&url),
+            _ => self.agent.put(&url),
+        };
+        let response = self
+            .authorize(request, Some(token))
+            .header("Content-Type", "application/json")
+            .send(&serde_json::to_vec(body)?[..])
+            .map_err(|e| request_error(&url, e))?;
+        Ok(serde_json::from_slice(&read(&url, response)?)?)
+    }
+
+    /// Add a bearer token, or the client's token, to a request
+    fn authorize<B>(
+        &self,
+        request: ureq::RequestBuilder<B>,
+        token: Option<&str>,
+    ) -> ureq::RequestBuilder<B> {
+        match token.or(self.token.as_deref()) {
+            Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    fn get_json<T: serde::de::DeserializeOwned>(&self, segments: &[&str]) -> Result<T, Error> {
+        Ok(serde_json::from_slice(&self.get(segments)?)?)
+    }
+}
+
+/// Read a response body, turning error statuses into errors
+fn read(url: &str, mut response: ureq::http::Response<ureq::Body>) -> Result<Vec<u8>, Error> {
+    let status = response.status().as_u16();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD_SIZE)
+        .read_to_vec()
+        .map_err(|e| request_error(url, e))?;
+    if (200..300).contains(&status) {
+        return Ok(body);
+    }
+    let message = serde_json::from_slice::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|body| Some(body.get("error")?.as_str()?.to_string()))
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+    Err(match status {
+        401 => Error::Unauthorized,
+        404 => Error::NotFound(url.to_string()),
+        _ => Error::Remote { status, message },
+    })
+}
+
+fn request_error(url: &str, e: ureq::Error) -> Error {
+    Error::Request(format!("{}: {}", url, e))
+}
@@ -0,0 +1,95 @@
+//! Registry tokens stored on disk
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::storage::write_atomic;
+use super::Error;
+
+/// Tokens for registries, by URL, kept in a file readable only by its owner
+///
+/// The default file is `~/.extismx/credentials.json`, or the path in the
+/// `EXTISMX_CREDENTIALS` environment variable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    #[serde(default)]
+    tokens: BTreeMap<String, String>,
+}
+
+impl Credentials {
+    /// Get the path of the default credentials file
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("EXTISMX_CREDENTIALS") {
+            return Some(PathBuf::from(path));
+        }
+        let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+        Some(
+            PathBuf::from(home)
+                .join(".extismx")
+                .join("credentials.json"),
+        )
+    }
+
+    /// Load credentials from a file, which may not exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        match fs::read(path) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Load the default credentials file
+    pub fn load_default() -> Result<Self, Error> {
+        match Self::default_path() {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Save credentials to a file, readable only by the current user
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent().filter(|parent| !parent.exists()) {
+            fs::create_dir_all(parent)?;
+            restrict(parent, 0o700)?;
+        }
+        write_atomic(path, &serde_json::to_vec_pretty(self)?)?;
+        restrict(path, 0o600)
+    }
+
+    /// Get the token of a registry
+    pub fn token(&self, url: &str) -> Option<&str> {
+        self.tokens.get(normalize(url)).map(String::as_str)
+    }
+
+    /// Set the token of a registry
+    pub fn set_token(&mut self, url: &str, token: impl Into<String>) {
+        self.tokens.insert(normalize(url).to_string(), token.into());
+    }
+
+    /// Forget the token of a registry, returning whether there was one
+    pub fn remove_token(&mut self, url: &str) -> bool {
+        self.tokens.remove(normalize(url)).is_some()
+    }
+}
+
+fn normalize(url: &str) -> &str {
+    url.trim_end_matches('/')
+}
+
+#[cfg(unix)]
+fn restrict(path: &Path, mode: u32) -> Result<(), Error> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict(_path: &Path, _mode: u32) -> Result<(), Error> {
+    Ok(())
+}
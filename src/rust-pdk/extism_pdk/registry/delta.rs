@@ -0,0 +1,46 @@
+//! Binary patches between versions of a wasm module
+
+use std::io::{Read, Write};
+
+use super::Error;
+
+/// zstd compression level of patches, which are computed once and cached
+const LEVEL: i32 = 19;
+
+/// Largest zstd window, in bits, so patches can reference a whole 100 MiB module
+const MAX_WINDOW_LOG: u32 = 27;
+
+/// Maximum size of a patched module
+const MAX_PATCHED_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Compute a patch turning `old` into `new`
+///
+/// The patch is `new` compressed with zstd using `old` as a reference
+/// prefix, so unchanged code compresses to back-references into `old`.
+pub(crate) fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>, Error> {
+    let error = |e: std::io::Error| Error::InvalidArtifact(format!("cannot compute patch: {}", e));
+    let window_log = (old.len() + new.len())
+        .next_power_of_two()
+        .trailing_zeros()
+        .clamp(10, MAX_WINDOW_LOG);
+    let mut encoder =
+        zstd::stream::write::Encoder::with_ref_prefix(Vec::new(), LEVEL, old).map_err(error)?;
+    encoder.window_log(window_log).map_err(error)?;
+    encoder.long_distance_matching(true).map_err(error)?;
+    encoder.include_checksum(true).map_err(error)?;
+    encoder.write_all(new).map_err(error)?;
+    encoder.finish().map_err(error)
+}
+
+/// Apply a patch computed by `diff` from `old`
+pub(crate) fn apply(old: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let error = |e: std::io::Error| Error::InvalidArtifact(format!("cannot apply patch: {}", e));
+    let mut decoder = zstd::stream::read::Decoder::with_ref_prefix(patch, old).map_err(error)?;
+    decoder.window_log_max(MAX_WINDOW_LOG).map_err(error)?;
+    let mut new = Vec::new();
+    decoder
+        .take(MAX_PATCHED_SIZE)
+        .read_to_end(&mut new)
+        .map_err(error)?;
+    Ok(new)
+}
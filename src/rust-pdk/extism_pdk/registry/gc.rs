@@ -0,0 +1,186 @@
+//! Garbage collection of modules no release needs anymore
+
+use std::collections::BTreeSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::blobs::{blob_key, refs_key, BLOB_PREFIX};
+use super::{artifact_key, BlobReference, Error, Registry, Release};
+
+/// Storage key prefix of cached patches
+const DELTA_PREFIX: &str = "deltas/";
+
+/// How long the modules of yanked versions are kept by default
+const DEFAULT_RETENTION: Duration = Duration::from_secs(30 * 86400);
+
+/// Settings of a garbage collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GcOptions {
+    /// Seconds the modules of a yanked version are kept after it was yanked
+    retention: u64,
+    /// Only report what would be deleted
+    dry_run: bool,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            retention: DEFAULT_RETENTION.as_secs(),
+            dry_run: false,
+        }
+    }
+}
+
+impl GcOptions {
+    /// Keep the modules of versions yanked in the last 30 days
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the modules of versions yanked within `retention`, so lockfiles
+    /// pinning them keep installing for a while
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention.as_secs();
+        self
+    }
+
+    /// Report what would be deleted without deleting anything
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+}
+
+/// A module deleted by a garbage collection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollectedBlob {
+    /// SHA-256 of the module, as hex
+    pub sha256: String,
+    /// Size of the module in bytes
+    pub size: u64,
+    /// The yanked releases that referenced it
+    #[serde(default)]
+    pub references: Vec<BlobReference>,
+}
+
+/// What a garbage collection deleted, or would delete in a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcReport {
+    /// Whether the collection was a dry run that deleted nothing
+    pub dry_run: bool,
+    /// Modules no release needs anymore
+    pub blobs: Vec<CollectedBlob>,
+    /// Number of cached patches from or to the versions of those modules
+    pub patches: usize,
+    /// Number of modules kept
+    pub kept: usize,
+    /// Bytes of the deleted modules
+    pub freed: u64,
+}
+
+impl Registry {
+    /// Delete the modules no release needs anymore
+    ///
+    /// Every build of a version that is not yanked is kept, as are those of
+    /// versions yanked within the retention window. The other modules are
+    /// deleted along with the patches cached for their versions; the
+    /// releases themselves stay listed, but can no longer be downloaded.
+    pub fn gc(&self, options: &GcOptions) -> Result<GcReport, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut live = BTreeSet::new();
+        let mut expired = BTreeSet::new();
+        for name in self.plugins()? {
+            for release in self.index(&name)? {
+                if is_expired(&release, now, options.retention) {
+                    expired.insert(BlobReference {
+                        name: name.clone(),
+                        version: release.metadata.version,
+                    });
+                } else {
+                    live.insert(release.sha256);
+                    live.extend(release.targets.into_iter().map(|target| target.sha256));
+                }
+            }
+        }
+
+        let mut report = GcReport {
+            dry_run: options.dry_run,
+            ..GcReport::default()
+        };
+        for key in self.storage.list(BLOB_PREFIX)? {
+            let Some(sha256) = key.strip_prefix(BLOB_PREFIX) else {
+                continue;
+            };
+            if live.contains(sha256) {
+                report.kept += 1;
+                continue;
+            }
+            let size = self.storage.get(&key)?.map_or(0, |data| data.len() as u64);
+            let references = self.blob_references(sha256).unwrap_or_default();
+            if !options.dry_run {
+                self.storage.delete(&blob_key(sha256))?;
+                self.storage.delete(&refs_key(sha256))?;
+            }
+            report.freed += size;
+            report.blobs.push(CollectedBlob {
+                sha256: sha256.to_string(),
+                size,
+                references,
+            });
+        }
+
+        for release in &expired {
+            // Registries from before modules were stored by digest
+            let key = artifact_key(&release.name, &release.version);
+            if let Some(data) = self.storage.get(&key)? {
+                if !options.dry_run {
+                    self.storage.delete(&key)?;
+                }
+                report.freed += data.len() as u64;
+            }
+        }
+        for key in self.storage.list(DELTA_PREFIX)? {
+            // Patches are stored as `deltas/{name}/{version}/{from}.zst`
+            let Some((rest, from)) = key.rsplit_once('/') else {
+                continue;
+            };
+            let Some((name, version)) = rest.rsplit_once('/') else {
+                continue;
+            };
+            let name = name.strip_prefix(DELTA_PREFIX).unwrap_or(name);
+            let from = from.strip_suffix(".zst").unwrap_or(from);
+            let stale = [version, from].iter().any(|version| {
+                expired.contains(&BlobReference {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                })
+            });
+            if stale {
+                if !options.dry_run {
+                    self.storage.delete(&key)?;
+                }
+                report.patches += 1;
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Check if a release was yanked longer ago than the retention window
+///
+/// Releases yanked before yank times were recorded count from when they
+/// were published.
+fn is_expired(release: &Release, now: u64, retention: u64) -> bool {
+    release.yanked
+        && release
+            .yanked_at
+            .unwrap_or(release.published_at)
+            .saturating_add(retention)
+            <= now
+}
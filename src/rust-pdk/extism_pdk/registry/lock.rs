@@ -0,0 +1,169 @@
+//! Lockfiles recording the exact plugin versions of a deployment
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{sha256_hex, Client, Error, Release, Resolution, Target, TargetArtifact, VersionReq};
+
+/// Conventional name of a lockfile
+pub const LOCKFILE_NAME: &str = "extismx.lock";
+
+/// Version of the lockfile format
+const LOCKFILE_VERSION: u32 = 1;
+
+/// A plugin version pinned by a lockfile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPlugin {
+    /// Plugin name
+    pub name: String,
+    /// Exact version
+    pub version: String,
+    /// SHA-256 of the wasm module, as hex
+    pub sha256: String,
+    /// What the locked build was compiled for
+    #[serde(default, skip_serializing_if = "Target::is_default")]
+    pub target: Target,
+    /// Names of the plugins it depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+}
+
+/// Resolved plugin versions with their content hashes
+///
+/// Installing from a lockfile downloads exactly the recorded versions and
+/// refuses modules whose hash changed, so the same plugin set is deployed
+/// in every environment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Lockfile format version
+    pub version: u32,
+    /// Target the builds were selected for
+    #[serde(default, skip_serializing_if = "Target::is_default")]
+    pub target: Target,
+    /// Locked plugins, sorted by name
+    pub plugins: Vec<LockedPlugin>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            target: Target::default(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Lock the releases selected by a `Resolver`
+    pub fn from_resolution(resolution: &Resolution) -> Self {
+        Self {
+            plugins: resolution
+                .values()
+                .map(|release| locked(release, release.module()))
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Lock the builds of the releases selected by a `Resolver` to install on
+    /// a host that runs `target`, as selected by `Release::artifact()`
+    pub fn from_resolution_for(resolution: &Resolution, target: Target) -> Result<Self, Error> {
+        let plugins = resolution
+            .values()
+            .map(|release| {
+                let artifact = release.artifact(target).ok_or_else(|| {
+                    Error::Unresolvable(format!(
+                        "{} {} has no build for {}",
+                        release.metadata.name, release.metadata.version, target
+                    ))
+                })?;
+                Ok(locked(release, artifact))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            version: LOCKFILE_VERSION,
+            target,
+            plugins,
+        })
+    }
+
+    /// Read a lockfile
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let lockfile: Self = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| Error::InvalidLockfile(e.to_string()))?;
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(Error::InvalidLockfile(format!(
+                "unsupported version {}",
+                lockfile.version
+            )));
+        }
+        Ok(lockfile)
+    }
+
+    /// Write the lockfile
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut data = serde_json::to_vec_pretty(self)?;
+        data.push(b'\n');
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Get a locked plugin
+    pub fn get(&self, name: &str) -> Option<&LockedPlugin> {
+        self.plugins.iter().find(|plugin| plugin.name == name)
+    }
+
+    /// Check if the locked versions still satisfy `requirements`
+    ///
+    /// When they don't, the requirements changed since the lockfile was
+    /// written and the plugins must be resolved again.
+    pub fn satisfies(&self, requirements: &BTreeMap<String, VersionReq>) -> bool {
+        requirements.iter().all(|(name, req)| {
+            self.get(name).is_some_and(|plugin| {
+                semver::Version::parse(&plugin.version).is_ok_and(|v| req.matches(&v))
+            })
+        })
+    }
+
+    /// Check a wasm module against the hash locked for a plugin
+    pub fn verify(&self, name: &str, wasm: &[u8]) -> Result<(), Error> {
+        let plugin = self
+            .get(name)
+            .ok_or_else(|| Error::NotFound(format!("{} in lockfile", name)))?;
+        let actual = sha256_hex(wasm);
+        if !plugin.sha256.eq_ignore_ascii_case(&actual) {
+            return Err(Error::LockMismatch {
+                name: format!("{} {}", plugin.name, plugin.version),
+                expected: plugin.sha256.clone(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Download every locked plugin, verifying each against its locked hash
+    pub fn fetch(&self, client: &Client) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        self.plugins
+            .iter()
+            .map(|plugin| {
+                let wasm = client.download_target(&plugin.name, &plugin.version, plugin.target)?;
+                self.verify(&plugin.name, &wasm)?;
+                Ok((plugin.name.clone(), wasm))
+            })
+            .collect()
+    }
+}
+
+fn locked(release: &Release, artifact: TargetArtifact) -> LockedPlugin {
+    LockedPlugin {
+        name: release.metadata.name.clone(),
+        version: release.metadata.version.clone(),
+        sha256: artifact.sha256,
+        target: artifact.target,
+        dependencies: release.metadata.dependencies.keys().cloned().collect(),
+    }
+}
@@ -0,0 +1,123 @@
+//! Local mirror of a registry, for offline installs
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::storage::write_atomic;
+use super::{sha256_hex, validate_name, Error, Release};
+
+/// A local copy of registry indexes and wasm modules
+///
+/// Modules are stored by SHA-256 under `blobs/sha256/`, so each is kept once
+/// however many versions share it, and verified whenever it is read. Index
+/// records are stored under `index/`. A `Client` with a mirror reads
+/// modules from it and falls back to its indexes when the registry cannot
+/// be reached.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    root: PathBuf,
+}
+
+impl Mirror {
+    /// Use the mirror in the directory `root`, which is created as needed
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Get the mirror's directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// List the names of the mirrored plugins
+    pub fn plugins(&self) -> Result<Vec<String>, Error> {
+        let index = self.root.join("index");
+        let mut names = Vec::new();
+        if !index.exists() {
+            return Ok(names);
+        }
+        for entry in fs::read_dir(&index)? {
+            let entry = entry?;
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() && file_name.starts_with('@') {
+                for scoped in fs::read_dir(entry.path())? {
+                    let scoped = scoped?.file_name().to_string_lossy().into_owned();
+                    if let Some(name) = scoped.strip_suffix(".json") {
+                        names.push(format!("{}/{}", file_name, name));
+                    }
+                }
+            } else if let Some(name) = file_name.strip_suffix(".json") {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Get the mirrored releases of a plugin
+    pub fn releases(&self, name: &str) -> Result<Option<Vec<Release>>, Error> {
+        validate_name(name)?;
+        match fs::read(self.index_path(name)) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Get a mirrored wasm module by its SHA-256
+    ///
+    /// A module whose content no longer matches its hash is reported as
+    /// `Error::InvalidArtifact`.
+    pub fn blob(&self, sha256: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.blob_path(sha256)?;
+        let wasm = match fs::read(&path) {
+            Ok(wasm) => wasm,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let actual = sha256_hex(&wasm);
+        if actual != sha256 {
+            return Err(Error::InvalidArtifact(format!(
+                "mirrored module {} has SHA-256 {}",
+                path.display(),
+                actual
+            )));
+        }
+        Ok(Some(wasm))
+    }
+
+    /// Check if a wasm module is mirrored
+    pub fn has_blob(&self, sha256: &str) -> bool {
+        self.blob_path(sha256).is_ok_and(|path| path.exists())
+    }
+
+    /// Store the releases of a plugin
+    pub(crate) fn store_releases(&self, name: &str, releases: &[Release]) -> Result<(), Error> {
+        validate_name(name)?;
+        write_atomic(&self.index_path(name), &serde_json::to_vec(releases)?)
+    }
+
+    /// Store a wasm module, returning its SHA-256
+    pub(crate) fn store_blob(&self, wasm: &[u8]) -> Result<String, Error> {
+        let sha256 = sha256_hex(wasm);
+        let path = self.blob_path(&sha256)?;
+        if !path.exists() {
+            write_atomic(&path, wasm)?;
+        }
+        Ok(sha256)
+    }
+
+    fn index_path(&self, name: &str) -> PathBuf {
+        self.root.join("index").join(format!("{}.json", name))
+    }
+
+    fn blob_path(&self, sha256: &str) -> Result<PathBuf, Error> {
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::InvalidArtifact(format!(
+                "invalid SHA-256 {}",
+                sha256
+            )));
+        }
+        Ok(self.root.join("blobs").join("sha256").join(sha256))
+    }
+}
@@ -0,0 +1,354 @@
+//! OCI distribution API compatibility
+//!
+//! Each release is also an OCI artifact: an image manifest with the wasm
+//! config and a single `application/vnd.wasm.content.layer.v1+wasm` layer,
+//! so `oras`, container registry tooling and `Wasm::oci` hosts can pull
+//! Extismx packages. `@org/plugin` is the repository `org/plugin`, and a
+//! version is the tag of the same name, with `+` spelled `_` as tags do not
+//! allow it. Pushing a manifest with a wasm layer publishes its tag as a new
+//! version, with metadata from the standard `org.opencontainers.image.*`
+//! annotations.
+
+use std::collections::BTreeMap;
+
+use rand_core::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{civil_date, sha256_hex, Attachments, Error, PluginMetadata, Registry, Release};
+
+/// Media type of OCI image manifests
+pub(crate) const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Media type of the config of wasm artifacts
+pub(crate) const CONFIG_TYPE: &str = "application/vnd.wasm.config.v0+json";
+
+/// Media type of wasm module layers
+pub(crate) const LAYER_TYPE: &str = "application/vnd.wasm.content.layer.v1+wasm";
+
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+const DESCRIPTION_ANNOTATION: &str = "org.opencontainers.image.description";
+const LICENSES_ANNOTATION: &str = "org.opencontainers.image.licenses";
+const AUTHORS_ANNOTATION: &str = "org.opencontainers.image.authors";
+const CREATED_ANNOTATION: &str = "org.opencontainers.image.created";
+
+/// Storage key prefix of blob uploads in progress
+const UPLOAD_PREFIX: &str = "oci/uploads/";
+
+/// Storage key prefix of pushed blobs, such as configs, by SHA-256
+const BLOB_PREFIX: &str = "oci/blobs/sha256/";
+
+/// Maximum size of an uploaded blob
+const MAX_UPLOAD_SIZE: usize = 100 * 1024 * 1024;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageManifest {
+    #[serde(default)]
+    media_type: Option<String>,
+    #[serde(default)]
+    config: Option<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+}
+
+/// Get the plugin name of a repository, such as `@org/plugin` for `org/plugin`
+pub(crate) fn plugin_name(repository: &str) -> String {
+    if repository.contains('/') {
+        format!("@{}", repository)
+    } else {
+        repository.to_string()
+    }
+}
+
+/// Get the repository of a plugin name, such as `org/plugin` for `@org/plugin`
+pub(crate) fn repository(name: &str) -> &str {
+    name.strip_prefix('@').unwrap_or(name)
+}
+
+fn tag(version: &str) -> String {
+    version.replace('+', "_")
+}
+
+impl Registry {
+    /// List the tags of a plugin's releases
+    pub(crate) fn oci_tags(&self, name: &str) -> Result<Vec<String>, Error> {
+        Ok(self
+            .releases(name)?
+            .iter()
+            .map(|release| tag(&release.metadata.version))
+            .collect())
+    }
+
+    /// Get the manifest of a release by tag or `sha256:` digest, and its digest
+    pub(crate) fn oci_manifest(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<(Vec<u8>, String), Error> {
+        let not_found = || Error::NotFound(format!("manifest {} {}", name, reference));
+        if reference.starts_with("sha256:") {
+            for release in self.releases(name)? {
+                let manifest = self.release_manifest(&release)?;
+                let digest = digest(&manifest);
+                if digest.eq_ignore_ascii_case(reference) {
+                    return Ok((manifest, digest));
+                }
+            }
+            return Err(not_found());
+        }
+        let release = self
+            .releases(name)?
+            .into_iter()
+            .find(|release| tag(&release.metadata.version) == reference)
+            .ok_or_else(not_found)?;
+        let manifest = self.release_manifest(&release)?;
+        let digest = digest(&manifest);
+        Ok((manifest, digest))
+    }
+
+    /// Get a blob of a plugin's manifests by `sha256:` digest, with the
+    /// version whose module it is, if it is a layer
+    pub(crate) fn oci_blob(
+        &self,
+        name: &str,
+        digest: &str,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        let sha256 = parse_digest(digest)?;
+        let releases = self.releases(name)?;
+        if let Some(release) = releases.iter().find(|release| release.sha256 == sha256) {
+            let wasm = self.download(name, &release.metadata.version)?;
+            return Ok((wasm, Some(release.metadata.version.clone())));
+        }
+        for release in &releases {
+            let config = config(release);
+            if sha256_hex(&config) == sha256 {
+                return Ok((config, None));
+            }
+            let manifest: ImageManifest = serde_json::from_slice(&self.release_manifest(release)?)?;
+            let pushed = manifest
+                .config
+                .is_some_and(|config| config.digest.eq_ignore_ascii_case(digest));
+            if pushed {
+                if let Some(data) = self.storage.get(&blob_key(&sha256))? {
+                    return Ok((data, None));
+                }
+            }
+        }
+        Err(Error::NotFound(format!("blob {}", digest)))
+    }
+
+    /// Start a blob upload, returning its ID
+    pub(crate) fn oci_start_upload(&self) -> Result<String, Error> {
+        let mut id = [0u8; 16];
+        rand_core::OsRng.fill_bytes(&mut id);
+        let id: String = id.iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.storage.put(&upload_key(&id), &[])?;
+        Ok(id)
+    }
+
+    /// Append a chunk to an upload, returning the size uploaded so far
+    pub(crate) fn oci_append_upload(&self, id: &str, chunk: &[u8]) -> Result<usize, Error> {
+        let mut data = self.upload(id)?;
+        if data.len() + chunk.len() > MAX_UPLOAD_SIZE {
+            return Err(Error::InvalidArtifact("blob is too large".to_string()));
+        }
+        data.extend_from_slice(chunk);
+        self.storage.put(&upload_key(id), &data)?;
+        Ok(data.len())
+    }
+
+    /// Complete an upload with its last chunk, storing the blob if it has
+    /// the expected `sha256:` digest
+    pub(crate) fn oci_finish_upload(
+        &self,
+        id: &str,
+        digest: &str,
+        chunk: &[u8],
+    ) -> Result<(), Error> {
+        let sha256 = parse_digest(digest)?;
+        let mut data = self.upload(id)?;
+        data.extend_from_slice(chunk);
+        self.storage.delete(&upload_key(id))?;
+        let actual = sha256_hex(&data);
+        if actual != sha256 {
+            return Err(Error::InvalidArtifact(format!(
+                "SHA-256 mismatch: expected {}, got {}",
+                sha256, actual
+            )));
+        }
+        self.storage.put(&blob_key(&sha256), &data)
+    }
+
+    /// Publish the wasm layer of a pushed manifest as version `tag`,
+    /// returning the manifest's digest
+    pub(crate) fn oci_push_manifest(
+        &self,
+        name: &str,
+        tag: &str,
+        body: &[u8],
+    ) -> Result<String, Error> {
+        let invalid = |e: String| Error::InvalidArtifact(format!("invalid manifest: {}", e));
+        let manifest: ImageManifest =
+            serde_json::from_slice(body).map_err(|e| invalid(e.to_string()))?;
+        if manifest
+            .media_type
+            .as_deref()
+            .is_some_and(|media_type| media_type != MANIFEST_TYPE)
+        {
+            return Err(invalid(format!(
+                "unsupported media type {}",
+                manifest.media_type.unwrap_or_default()
+            )));
+        }
+        let layer = match manifest
+            .layers
+            .iter()
+            .find(|layer| layer.media_type == LAYER_TYPE)
+        {
+            Some(layer) => layer,
+            None if manifest.layers.len() == 1 => &manifest.layers[0],
+            None => return Err(invalid("no wasm layer".to_string())),
+        };
+        let sha256 = parse_digest(&layer.digest)?;
+        let wasm = match self.storage.get(&blob_key(&sha256))? {
+            Some(wasm) => wasm,
+            None => self.blob(&sha256)?,
+        };
+
+        let annotation = |key: &str| manifest.annotations.get(key).cloned();
+        let metadata = PluginMetadata {
+            name: name.to_string(),
+            version: tag.replace('_', "+"),
+            description: annotation(DESCRIPTION_ANNOTATION),
+            license: annotation(LICENSES_ANNOTATION),
+            authors: annotation(AUTHORS_ANNOTATION)
+                .iter()
+                .flat_map(|authors| authors.split(','))
+                .map(str::trim)
+                .filter(|author| !author.is_empty())
+                .map(str::to_string)
+                .collect(),
+            ..PluginMetadata::default()
+        };
+        let release = self.publish_with(metadata, &wasm, Attachments::default())?;
+        self.storage
+            .put(&manifest_key(name, &release.metadata.version), body)?;
+        // The module is now stored by digest with the release
+        self.storage.delete(&blob_key(&sha256))?;
+        Ok(digest(body))
+    }
+
+    /// Get the manifest of a release: the one it was pushed with, or one
+    /// describing its module
+    fn release_manifest(&self, release: &Release) -> Result<Vec<u8>, Error> {
+        let metadata = &release.metadata;
+        if let Some(manifest) = self
+            .storage
+            .get(&manifest_key(&metadata.name, &metadata.version))?
+        {
+            return Ok(manifest);
+        }
+        let config = config(release);
+        let file = format!("{}.wasm", repository(&metadata.name).replace('/', "-"));
+        let mut annotations = BTreeMap::from([
+            (VERSION_ANNOTATION, metadata.version.clone()),
+            (CREATED_ANNOTATION, timestamp(release.published_at)),
+        ]);
+        if let Some(description) = &metadata.description {
+            annotations.insert(DESCRIPTION_ANNOTATION, description.clone());
+        }
+        if let Some(license) = &metadata.license {
+            annotations.insert(LICENSES_ANNOTATION, license.clone());
+        }
+        if !metadata.authors.is_empty() {
+            annotations.insert(AUTHORS_ANNOTATION, metadata.authors.join(", "));
+        }
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": MANIFEST_TYPE,
+            "config": {
+                "mediaType": CONFIG_TYPE,
+                "digest": digest(&config),
+                "size": config.len(),
+            },
+            "layers": [{
+                "mediaType": LAYER_TYPE,
+                "digest": format!("sha256:{}", release.sha256),
+                "size": release.size,
+                "annotations": { TITLE_ANNOTATION: file },
+            }],
+            "annotations": annotations,
+        });
+        Ok(serde_json::to_vec(&manifest)?)
+    }
+
+    fn upload(&self, id: &str) -> Result<Vec<u8>, Error> {
+        let not_found = || Error::NotFound(format!("upload {}", id));
+        if !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(not_found());
+        }
+        self.storage.get(&upload_key(id))?.ok_or_else(not_found)
+    }
+}
+
+/// Wasm artifact config of a release's module
+fn config(release: &Release) -> Vec<u8> {
+    let config = json!({
+        "architecture": "wasm",
+        "os": "wasip1",
+        "created": timestamp(release.published_at),
+        "layerDigests": [format!("sha256:{}", release.sha256)],
+    });
+    serde_json::to_vec(&config).unwrap_or_default()
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp
+fn timestamp(seconds: u64) -> String {
+    let (year, month, day) = civil_date(seconds / 86400);
+    let time = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn digest(data: &[u8]) -> String {
+    format!("sha256:{}", sha256_hex(data))
+}
+
+/// Get the hex SHA-256 of a `sha256:` digest, in lowercase
+fn parse_digest(digest: &str) -> Result<String, Error> {
+    digest
+        .strip_prefix("sha256:")
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+        .ok_or_else(|| Error::InvalidArtifact(format!("invalid digest {}", digest)))
+}
+
+fn upload_key(id: &str) -> String {
+    format!("{}{}", UPLOAD_PREFIX, id)
+}
+
+fn blob_key(sha256: &str) -> String {
+    format!("{}{}", BLOB_PREFIX, sha256)
+}
+
+fn manifest_key(name: &str, version: &str) -> String {
+    format!("oci/manifests/{}/{}.json", name, version)
+}
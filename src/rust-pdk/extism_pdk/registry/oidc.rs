@@ -0,0 +1,237 @@
+//! Validation of OpenID Connect bearer tokens
+
+use std::sync::RwLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde::Deserialize;
+
+use super::Error;
+
+/// How long fetched signing keys are used before they are fetched again
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+
+/// Minimum time between fetches when a token names an unknown key
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Allowed clock difference when checking expiry, in seconds
+const CLOCK_SKEW: u64 = 60;
+
+/// Maximum size of a discovery document or key set
+const MAX_DOCUMENT_SIZE: u64 = 1024 * 1024;
+
+/// An OpenID Connect provider whose ID tokens authenticate registry users
+///
+/// Tokens must be JWTs signed with RS256, ES256 or EdDSA by one of the
+/// provider's published keys, issued by the provider for the configured
+/// audience and unexpired. The signing keys are discovered from
+/// `{issuer}/.well-known/openid-configuration` and cached.
+pub struct OidcProvider {
+    issuer: String,
+    audience: String,
+    user_claim: String,
+    jwks_url: Option<String>,
+    agent: ureq::Agent,
+    /// Cached signing keys and when they were fetched
+    keys: RwLock<Option<(Instant, Vec<Jwk>)>>,
+}
+
+/// A public key of a JSON Web Key Set
+#[derive(Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    /// RSA modulus
+    #[serde(default)]
+    n: Option<String>,
+    /// RSA exponent
+    #[serde(default)]
+    e: Option<String>,
+    /// Curve of EC and OKP keys
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+impl OidcProvider {
+    /// Accept ID tokens issued by `issuer`, such as
+    /// `https://accounts.google.com`, for `audience`, the registry's client ID
+    pub fn new(issuer: impl Into<String>, audience: impl Into<String>) -> Self {
+        Self {
+            issuer: issuer.into().trim_end_matches('/').to_string(),
+            audience: audience.into(),
+            user_claim: "sub".to_string(),
+            jwks_url: None,
+            agent: ureq::Agent::new_with_defaults(),
+            keys: RwLock::new(None),
+        }
+    }
+
+    /// Name users by `claim` rather than `sub`, such as `email`, so they
+    /// can be added to organizations by that name
+    pub fn with_user_claim(mut self, claim: impl Into<String>) -> Self {
+        self.user_claim = claim.into();
+        self
+    }
+
+    /// Fetch the signing keys from `url` instead of discovering it
+    pub fn with_jwks_url(mut self, url: impl Into<String>) -> Self {
+        self.jwks_url = Some(url.into());
+        self
+    }
+
+    /// Check an ID token, returning the user it was issued to
+    ///
+    /// Tokens that are not valid for this provider are reported as
+    /// `Error::Unauthorized`; failing to fetch the keys is `Error::Request`.
+    pub(crate) fn validate(&self, token: &str) -> Result<String, Error> {
+        let Some((message, signature)) = token.rsplit_once('.') else {
+            return Err(Error::Unauthorized);
+        };
+        let Some((header, claims)) = message.split_once('.') else {
+            return Err(Error::Unauthorized);
+        };
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| Error::Unauthorized)
+        };
+        let header: Header =
+            serde_json::from_slice(&decode(header)?).map_err(|_| Error::Unauthorized)?;
+        let signature = decode(signature)?;
+
+        let key = self.key(header.kid.as_deref())?;
+        verify(&header.alg, &key, message.as_bytes(), &signature)?;
+
+        let claims: serde_json::Value =
+            serde_json::from_slice(&decode(claims)?).map_err(|_| Error::Unauthorized)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let issuer = claims["iss"].as_str().map(|iss| iss.trim_end_matches('/'));
+        let audience = match &claims["aud"] {
+            serde_json::Value::String(aud) => *aud == self.audience,
+            serde_json::Value::Array(auds) => auds.iter().any(|aud| *aud == *self.audience),
+            _ => false,
+        };
+        let unexpired = claims["exp"]
+            .as_u64()
+            .is_some_and(|exp| now < exp + CLOCK_SKEW);
+        let started = claims["nbf"]
+            .as_u64()
+            .is_none_or(|nbf| nbf <= now + CLOCK_SKEW);
+        if issuer != Some(self.issuer.as_str()) || !audience || !unexpired || !started {
+            return Err(Error::Unauthorized);
+        }
+        claims[self.user_claim.as_str()]
+            .as_str()
+            .filter(|user| !user.is_empty())
+            .map(str::to_string)
+            .ok_or(Error::Unauthorized)
+    }
+
+    /// Find the signing key with the given ID, fetching the key set if it
+    /// is stale or does not have it
+    fn key(&self, kid: Option<&str>) -> Result<Jwk, Error> {
+        let find = |keys: &[Jwk]| {
+            keys.iter()
+                .find(|key| kid.is_none() || key.kid.as_deref() == kid)
+                .cloned()
+        };
+        let cached = self.keys.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some((fetched, keys)) = &cached {
+            let age = fetched.elapsed();
+            if age < JWKS_TTL {
+                if let Some(key) = find(keys) {
+                    return Ok(key);
+                }
+                if age < JWKS_REFRESH_INTERVAL {
+                    return Err(Error::Unauthorized);
+                }
+            }
+        }
+        let keys = self.fetch_keys()?;
+        let key = find(&keys);
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = Some((Instant::now(), keys));
+        key.ok_or(Error::Unauthorized)
+    }
+
+    fn fetch_keys(&self) -> Result<Vec<Jwk>, Error> {
+        let jwks_url = match &self.jwks_url {
+            Some(url) => url.clone(),
+            None => {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let discovery: Discovery = self.fetch(&url)?;
+                discovery.jwks_uri
+            }
+        };
+        let jwks: Jwks = self.fetch(&jwks_url)?;
+        Ok(jwks.keys)
+    }
+
+    fn fetch<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, Error> {
+        let error = |e: String| Error::Request(format!("{}: {}", url, e));
+        let body = self
+            .agent
+            .get(url)
+            .call()
+            .map_err(|e| error(e.to_string()))?
+            .body_mut()
+            .with_config()
+            .limit(MAX_DOCUMENT_SIZE)
+            .read_to_vec()
+            .map_err(|e| error(e.to_string()))?;
+        serde_json::from_slice(&body).map_err(|e| error(e.to_string()))
+    }
+}
+
+/// Verify a JWT signature made with `alg` by `key`
+fn verify(alg: &str, key: &Jwk, message: &[u8], sig: &[u8]) -> Result<(), Error> {
+    let param = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+            .ok_or(Error::Unauthorized)
+    };
+    let verified = match (alg, key.kty.as_str(), key.crv.as_deref()) {
+        ("RS256", "RSA", _) => RsaPublicKeyComponents {
+            n: param(&key.n)?,
+            e: param(&key.e)?,
+        }
+        .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, sig),
+        ("ES256", "EC", Some("P-256")) => {
+            let mut point = vec![4];
+            point.extend(param(&key.x)?);
+            point.extend(param(&key.y)?);
+            UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point).verify(message, sig)
+        }
+        ("EdDSA", "OKP", Some("Ed25519")) => {
+            UnparsedPublicKey::new(&signature::ED25519, param(&key.x)?).verify(message, sig)
+        }
+        _ => return Err(Error::Unauthorized),
+    };
+    verified.map_err(|_| Error::Unauthorized)
+}
@@ -0,0 +1,633 @@
+//! OpenAPI description of the registry's HTTP API
+
+use serde_json::{json, Map, Value};
+
+use super::server::API_PREFIX;
+
+/// Who may call a route
+#[derive(Clone, Copy)]
+enum Auth {
+    /// Anyone who can read the plugin, so everyone unless its organization is private
+    Read,
+    /// A token with publish access to the plugin's namespace
+    Publish,
+    /// The admin token
+    Admin,
+}
+
+/// What a request or response body holds
+#[derive(Clone, Copy)]
+enum Body {
+    None,
+    /// JSON described by the named schema
+    Json(&'static str),
+    /// JSON array of the named schema
+    List(&'static str),
+    Wasm,
+    /// zstd frame patching the module of an older version
+    Patch,
+    /// Length-prefixed metadata, module and attachments
+    Publish,
+}
+
+/// A route of the API
+struct Route {
+    method: &'static str,
+    path: &'static str,
+    /// Name of the `Client` method calling the route
+    operation: &'static str,
+    summary: &'static str,
+    auth: Auth,
+    request: Body,
+    response: Body,
+}
+
+const ROUTES: &[Route] = &[
+    Route {
+        method: "get",
+        path: "/plugins",
+        operation: "plugins",
+        summary: "List plugin names",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::List("string"),
+    },
+    Route {
+        method: "put",
+        path: "/plugins/new",
+        operation: "publish",
+        summary: "Publish a version",
+        auth: Auth::Publish,
+        request: Body::Publish,
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}",
+        operation: "releases",
+        summary: "List releases",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::List("Release"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/versions",
+        operation: "versions",
+        summary: "List versions",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::List("string"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/stats",
+        operation: "downloads",
+        summary: "Get download statistics",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Json("Downloads"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}",
+        operation: "release",
+        summary: "Get version metadata",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}/download",
+        operation: "download",
+        summary: "Download the wasm",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Wasm,
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}/download/{target}",
+        operation: "download_target",
+        summary: "Download a build for a target",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Wasm,
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}/provenance",
+        operation: "provenance",
+        summary: "Get the SLSA provenance",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Json("Provenance"),
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}/delta/{from}",
+        operation: "download_delta",
+        summary: "Download a patch",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Patch,
+    },
+    Route {
+        method: "get",
+        path: "/plugins/{name}/{version}/sbom",
+        operation: "sbom",
+        summary: "Get the CycloneDX SBOM",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Json("Sbom"),
+    },
+    Route {
+        method: "put",
+        path: "/plugins/{name}/{version}/yank",
+        operation: "yank",
+        summary: "Yank a version",
+        auth: Auth::Publish,
+        request: Body::None,
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "delete",
+        path: "/plugins/{name}/{version}/yank",
+        operation: "unyank",
+        summary: "Unyank a version",
+        auth: Auth::Publish,
+        request: Body::None,
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "put",
+        path: "/plugins/{name}/{version}/deprecation",
+        operation: "deprecate",
+        summary: "Deprecate a version",
+        auth: Auth::Publish,
+        request: Body::Json("Deprecation"),
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "delete",
+        path: "/plugins/{name}/{version}/deprecation",
+        operation: "undeprecate",
+        summary: "Undeprecate a version",
+        auth: Auth::Publish,
+        request: Body::None,
+        response: Body::Json("Release"),
+    },
+    Route {
+        method: "get",
+        path: "/search",
+        operation: "search",
+        summary: "Search plugins",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::List("SearchResult"),
+    },
+    Route {
+        method: "get",
+        path: "/blobs/sha256/{digest}",
+        operation: "blob",
+        summary: "Download a wasm module by SHA-256",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Wasm,
+    },
+    Route {
+        method: "put",
+        path: "/orgs/{org}",
+        operation: "create_org",
+        summary: "Create an organization",
+        auth: Auth::Admin,
+        request: Body::Json("NewOrg"),
+        response: Body::Json("Organization"),
+    },
+    Route {
+        method: "get",
+        path: "/orgs/{org}",
+        operation: "org",
+        summary: "Get an organization",
+        auth: Auth::Admin,
+        request: Body::None,
+        response: Body::Json("Organization"),
+    },
+    Route {
+        method: "put",
+        path: "/orgs/{org}/members/{user}",
+        operation: "add_member",
+        summary: "Add a member",
+        auth: Auth::Admin,
+        request: Body::None,
+        response: Body::Json("Organization"),
+    },
+    Route {
+        method: "delete",
+        path: "/orgs/{org}/members/{user}",
+        operation: "remove_member",
+        summary: "Remove a member",
+        auth: Auth::Admin,
+        request: Body::None,
+        response: Body::Json("Organization"),
+    },
+    Route {
+        method: "post",
+        path: "/tokens",
+        operation: "issue_token",
+        summary: "Issue a token",
+        auth: Auth::Admin,
+        request: Body::Json("NewToken"),
+        response: Body::Json("IssuedToken"),
+    },
+    Route {
+        method: "delete",
+        path: "/tokens/{id}",
+        operation: "revoke_token",
+        summary: "Revoke a token",
+        auth: Auth::Admin,
+        request: Body::None,
+        response: Body::None,
+    },
+    Route {
+        method: "post",
+        path: "/gc",
+        operation: "gc",
+        summary: "Delete unneeded modules",
+        auth: Auth::Admin,
+        request: Body::Json("GcOptions"),
+        response: Body::Json("GcReport"),
+    },
+    Route {
+        method: "get",
+        path: "/openapi.json",
+        operation: "openapi",
+        summary: "Get this document",
+        auth: Auth::Read,
+        request: Body::None,
+        response: Body::Json("OpenApi"),
+    },
+];
+
+/// Build the OpenAPI 3.0 document describing the API
+///
+/// Each operation's `operationId` is the name of the `Client` method that
+/// calls it, so `Client` serves as the typed Rust binding of the document.
+pub fn openapi() -> Value {
+    let mut paths = Map::new();
+    for route in ROUTES {
+        let path = paths
+            .entry(format!("{}{}", API_PREFIX, route.path))
+            .or_insert_with(|| json!({}));
+        path[route.method] = operation(route);
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Extismx plugin registry",
+            "version": "1.0.0",
+        },
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+fn operation(route: &Route) -> Value {
+    let mut parameters: Vec<Value> = route
+        .path
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            let description = match name {
+                "name" => "Plugin name; a scoped `@org/name` spans two path segments",
+                "version" => "Semantic version",
+                "org" => "Organization name, without the `@`",
+                "user" => "User name",
+                "digest" => "Hex SHA-256 of the module",
+                "from" => "Version whose module the patch applies to",
+                "target" => "wasm32-unknown-unknown, wasm32-wasip1 or component",
+                _ => "Token ID",
+            };
+            json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "description": description,
+                "schema": { "type": "string" },
+            })
+        })
+        .collect();
+    if route.operation == "search" {
+        parameters.extend(
+            [
+                (
+                    "q",
+                    "Words to match against names, exports, descriptions and authors",
+                ),
+                ("capability", "Host capability the plugin must require"),
+                (
+                    "license",
+                    "License the plugin's SPDX expression must include",
+                ),
+                ("author", "Part of an author's name"),
+            ]
+            .map(|(name, description)| {
+                json!({
+                    "name": name,
+                    "in": "query",
+                    "description": description,
+                    "schema": { "type": "string" },
+                })
+            }),
+        );
+    }
+
+    let (status, response) = match route.response {
+        Body::None => ("204", json!({ "description": "Done" })),
+        body => (
+            "200",
+            json!({ "description": "OK", "content": content(body) }),
+        ),
+    };
+    let error = json!({
+        "description": "Error",
+        "content": content(Body::Json("Error")),
+    });
+    let mut operation = json!({
+        "operationId": route.operation,
+        "summary": route.summary,
+        "parameters": parameters,
+        "responses": { status: response, "default": error },
+    });
+    if !matches!(route.request, Body::None) {
+        operation["requestBody"] = json!({ "required": true, "content": content(route.request) });
+    }
+    match route.auth {
+        Auth::Read => {
+            // Plugins of private organizations also need a token
+            operation["security"] = json!([{}, { "bearer": [] }]);
+        }
+        Auth::Publish => {
+            operation["security"] = json!([{ "bearer": [] }]);
+            operation["description"] =
+                json!("Requires a token with publish access to the plugin's namespace.");
+        }
+        Auth::Admin => {
+            operation["security"] = json!([{ "bearer": [] }]);
+            operation["description"] = json!("Requires the admin token.");
+        }
+    }
+    operation
+}
+
+fn content(body: Body) -> Value {
+    match body {
+        Body::None => json!({}),
+        Body::Json(name) => json!({ "application/json": { "schema": schema(name) } }),
+        Body::List(name) => json!({
+            "application/json": { "schema": { "type": "array", "items": schema(name) } },
+        }),
+        Body::Wasm => json!({
+            "application/wasm": { "schema": { "type": "string", "format": "binary" } },
+        }),
+        Body::Patch => json!({
+            "application/octet-stream": {
+                "schema": {
+                    "type": "string",
+                    "format": "binary",
+                    "description": "zstd frame compressed with the module of version `from` \
+                        as its reference prefix",
+                },
+            },
+        }),
+        Body::Publish => json!({
+            "application/octet-stream": {
+                "schema": {
+                    "type": "string",
+                    "format": "binary",
+                    "description": "The metadata JSON, the wasm module, optionally the \
+                        attachments JSON and then, for each build for another target, a JSON \
+                        header with its target and signature followed by the build, each \
+                        prefixed with its length as a little-endian u32",
+                },
+            },
+        }),
+    }
+}
+
+fn schema(name: &str) -> Value {
+    match name {
+        "string" => json!({ "type": "string" }),
+        name => json!({ "$ref": format!("#/components/schemas/{}", name) }),
+    }
+}
+
+fn schemas() -> Value {
+    let string = || json!({ "type": "string" });
+    let strings = || json!({ "type": "array", "items": { "type": "string" } });
+    let ranges = || json!({ "type": "object", "additionalProperties": { "type": "string" } });
+    let counts = || json!({ "type": "object", "additionalProperties": { "type": "integer" } });
+    let target = || {
+        json!({
+            "type": "string",
+            "enum": ["wasm32-unknown-unknown", "wasm32-wasip1", "component"],
+        })
+    };
+    json!({
+        "PluginMetadata": {
+            "type": "object",
+            "required": ["name", "version"],
+            "properties": {
+                "name": string(),
+                "version": string(),
+                "description": string(),
+                "license": string(),
+                "authors": strings(),
+                "exports": strings(),
+                "dependencies": ranges(),
+                "host": ranges(),
+                "capabilities": {
+                    "type": "array",
+                    "description": "http, wasi, wasi-fs, kv or function:<name>",
+                    "items": string(),
+                },
+                "target": target(),
+                "schemas": { "type": "array", "items": schema("FunctionSchema") },
+            },
+        },
+        "FunctionSchema": {
+            "type": "object",
+            "description": "JSON Schemas of an exported function's input and output",
+            "required": ["name", "input", "output"],
+            "properties": {
+                "name": string(),
+                "input": { "type": "object" },
+                "output": { "type": "object" },
+            },
+        },
+        "TargetArtifact": {
+            "type": "object",
+            "required": ["target", "sha256", "size"],
+            "properties": {
+                "target": target(),
+                "sha256": string(),
+                "size": { "type": "integer" },
+                "signature": schema("Signature"),
+            },
+        },
+        "Signature": {
+            "type": "object",
+            "required": ["key", "signature"],
+            "properties": { "key": string(), "signature": string() },
+        },
+        "Release": {
+            "type": "object",
+            "required": ["metadata", "sha256", "size", "published_at"],
+            "properties": {
+                "metadata": schema("PluginMetadata"),
+                "sha256": string(),
+                "size": { "type": "integer" },
+                "published_at": { "type": "integer" },
+                "signature": schema("Signature"),
+                "sbom": { "type": "boolean" },
+                "provenance": { "type": "boolean" },
+                "yanked": { "type": "boolean" },
+                "yanked_at": { "type": "integer" },
+                "deprecated": string(),
+                "targets": { "type": "array", "items": schema("TargetArtifact") },
+            },
+        },
+        "Sbom": {
+            "type": "object",
+            "description": "CycloneDX 1.5 JSON document",
+        },
+        "Provenance": {
+            "type": "object",
+            "description": "DSSE envelope of an in-toto statement with a SLSA provenance predicate",
+            "required": ["payloadType", "payload", "signatures"],
+            "properties": {
+                "payloadType": string(),
+                "payload": string(),
+                "signatures": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["keyid", "sig"],
+                        "properties": { "keyid": string(), "sig": string() },
+                    },
+                },
+            },
+        },
+        "SearchResult": {
+            "type": "object",
+            "required": ["score", "release"],
+            "properties": {
+                "score": { "type": "integer" },
+                "release": schema("Release"),
+            },
+        },
+        "VersionDownloads": {
+            "type": "object",
+            "required": ["total"],
+            "properties": { "total": { "type": "integer" }, "daily": counts() },
+        },
+        "Downloads": {
+            "type": "object",
+            "required": ["total"],
+            "properties": {
+                "total": { "type": "integer" },
+                "versions": {
+                    "type": "object",
+                    "additionalProperties": schema("VersionDownloads"),
+                },
+            },
+        },
+        "Deprecation": {
+            "type": "object",
+            "required": ["message"],
+            "properties": { "message": string() },
+        },
+        "NewOrg": {
+            "type": "object",
+            "properties": { "private": { "type": "boolean" } },
+        },
+        "Organization": {
+            "type": "object",
+            "properties": { "members": strings(), "private": { "type": "boolean" } },
+        },
+        "TokenScope": {
+            "type": "object",
+            "required": ["namespace", "access"],
+            "properties": {
+                "namespace": string(),
+                "access": { "type": "string", "enum": ["read", "publish"] },
+            },
+        },
+        "NewToken": {
+            "type": "object",
+            "required": ["user", "scopes"],
+            "properties": {
+                "user": string(),
+                "scopes": { "type": "array", "items": schema("TokenScope") },
+            },
+        },
+        "IssuedToken": {
+            "type": "object",
+            "required": ["id", "token"],
+            "properties": { "id": string(), "token": string() },
+        },
+        "GcOptions": {
+            "type": "object",
+            "properties": {
+                "retention": {
+                    "type": "integer",
+                    "description": "Seconds the modules of yanked versions are kept, 30 days by default",
+                },
+                "dry_run": { "type": "boolean" },
+            },
+        },
+        "GcReport": {
+            "type": "object",
+            "required": ["dry_run", "blobs", "patches", "kept", "freed"],
+            "properties": {
+                "dry_run": { "type": "boolean" },
+                "blobs": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["sha256", "size"],
+                        "properties": {
+                            "sha256": string(),
+                            "size": { "type": "integer" },
+                            "references": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "properties": { "name": string(), "version": string() },
+                                },
+                            },
+                        },
+                    },
+                },
+                "patches": { "type": "integer" },
+                "kept": { "type": "integer" },
+                "freed": { "type": "integer" },
+            },
+        },
+        "OpenApi": {
+            "type": "object",
+            "description": "OpenAPI 3.0 document",
+        },
+        "Error": {
+            "type": "object",
+            "required": ["error"],
+            "properties": { "error": string() },
+        },
+    })
+}
@@ -0,0 +1,209 @@
+//! Resolving plugin dependencies to a compatible set of versions
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+
+use semver::{Version, VersionReq};
+
+use super::{release_version, Client, Error, Registry, Release};
+
+/// A source of published releases
+pub trait Index {
+    /// List the releases of a plugin
+    fn releases(&self, name: &str) -> Result<Vec<Release>, Error>;
+}
+
+impl Index for Registry {
+    fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        Registry::releases(self, name)
+    }
+}
+
+impl Index for Client {
+    fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        Client::releases(self, name)
+    }
+}
+
+/// The release selected for each plugin
+pub type Resolution = BTreeMap<String, Release>;
+
+/// A version requirement and what it came from
+#[derive(Clone)]
+struct Requirement {
+    name: String,
+    req: VersionReq,
+    /// `root` or the plugin and version that declared the requirement
+    by: String,
+}
+
+/// The state of a search for a resolution
+#[derive(Clone, Default)]
+struct State {
+    selected: Resolution,
+    /// Every requirement seen for each plugin
+    constraints: BTreeMap<String, Vec<Requirement>>,
+    pending: VecDeque<Requirement>,
+}
+
+/// Computes a set of plugin versions satisfying every dependency requirement
+///
+/// The newest matching version of each plugin is tried first, backtracking
+/// to older versions when a choice leads to a conflict. Yanked releases are
+/// never selected, and a release is only selected if the host provides the
+/// capabilities it requires.
+pub struct Resolver<'a> {
+    index: &'a dyn Index,
+    host: BTreeMap<String, Version>,
+    releases: RefCell<BTreeMap<String, Vec<Release>>>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Create a resolver for the releases in `index`
+    pub fn new(index: &'a dyn Index) -> Self {
+        Self {
+            index,
+            host: BTreeMap::new(),
+            releases: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Declare a capability the host provides, at the given version
+    pub fn with_host_capability(mut self, name: impl Into<String>, version: Version) -> Self {
+        self.host.insert(name.into(), version);
+        self
+    }
+
+    /// Resolve the plugins in `requirements` and their dependencies
+    ///
+    /// Fails with `Error::Unresolvable` describing the conflicting
+    /// requirements if no set of versions satisfies them all.
+    pub fn resolve(
+        &self,
+        requirements: &BTreeMap<String, VersionReq>,
+    ) -> Result<Resolution, Error> {
+        let state = State {
+            pending: requirements
+                .iter()
+                .map(|(name, req)| Requirement {
+                    name: name.clone(),
+                    req: req.clone(),
+                    by: "root".to_string(),
+                })
+                .collect(),
+            ..State::default()
+        };
+        Ok(self.solve(state)?.selected)
+    }
+
+    fn solve(&self, mut state: State) -> Result<State, Error> {
+        let Some(requirement) = state.pending.pop_front() else {
+            return Ok(state);
+        };
+        let name = requirement.name.clone();
+        let constraints = state.constraints.entry(name.clone()).or_default();
+        constraints.push(requirement.clone());
+        let constraints = constraints.clone();
+
+        if let Some(release) = state.selected.get(&name) {
+            if requirement.req.matches(&release_version(release)) {
+                return self.solve(state);
+            }
+            return Err(Error::Unresolvable(format!(
+                "{} {} does not satisfy {}",
+                name,
+                release.metadata.version,
+                describe(&constraints)
+            )));
+        }
+
+        let mut candidates: Vec<Release> = self
+            .releases(&name)?
+            .into_iter()
+            .filter(|release| {
+                if release.yanked {
+                    return false;
+                }
+                let version = release_version(release);
+                constraints.iter().all(|c| c.req.matches(&version))
+            })
+            .collect();
+        candidates.sort_by_key(|release| std::cmp::Reverse(release_version(release)));
+        let mut conflict = Error::Unresolvable(format!(
+            "no version of {} satisfies {}",
+            name,
+            describe(&constraints)
+        ));
+
+        for release in candidates {
+            if let Err(e) = self.check_host(&release) {
+                conflict = e;
+                continue;
+            }
+            let mut next = state.clone();
+            let by = format!("{} {}", name, release.metadata.version);
+            for (dependency, req) in &release.metadata.dependencies {
+                next.pending.push_back(Requirement {
+                    name: dependency.clone(),
+                    req: req.clone(),
+                    by: by.clone(),
+                });
+            }
+            next.selected.insert(name.clone(), release);
+            match self.solve(next) {
+                Ok(state) => return Ok(state),
+                Err(e @ Error::Unresolvable(_)) => conflict = e,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(conflict)
+    }
+
+    /// Check that the host provides the capabilities a release requires
+    fn check_host(&self, release: &Release) -> Result<(), Error> {
+        for (capability, req) in &release.metadata.host {
+            let satisfied = self
+                .host
+                .get(capability)
+                .is_some_and(|version| req.matches(version));
+            if !satisfied {
+                let provided = self
+                    .host
+                    .get(capability)
+                    .map_or("does not provide it".to_string(), |v| {
+                        format!("provides {}", v)
+                    });
+                return Err(Error::Unresolvable(format!(
+                    "{} {} requires host capability {} {}, but the host {}",
+                    release.metadata.name, release.metadata.version, capability, req, provided
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the releases of a plugin, fetching them once
+    fn releases(&self, name: &str) -> Result<Vec<Release>, Error> {
+        if let Some(releases) = self.releases.borrow().get(name) {
+            return Ok(releases.clone());
+        }
+        let releases = match self.index.releases(name) {
+            Ok(releases) => releases,
+            Err(Error::NotFound(_)) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        self.releases
+            .borrow_mut()
+            .insert(name.to_string(), releases.clone());
+        Ok(releases)
+    }
+}
+
+/// Describe the requirements on a plugin, such as `^1.2 (required by root)`
+fn describe(constraints: &[Requirement]) -> String {
+    constraints
+        .iter()
+        .map(|c| format!("{} (required by {})", c.req, c.by))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,209 @@
+//! CycloneDX software bills of materials for plugin artifacts
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::Error;
+
+/// CycloneDX specification version the documents follow
+const SPEC_VERSION: &str = "1.5";
+
+/// A CycloneDX SBOM listing the crates built into a plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sbom {
+    /// Always `CycloneDX`
+    pub bom_format: String,
+    /// CycloneDX specification version
+    pub spec_version: String,
+    /// Version of this SBOM document
+    pub version: u32,
+    /// The plugin the SBOM describes
+    pub metadata: SbomMetadata,
+    /// Every crate the plugin depends on, directly or not
+    #[serde(default)]
+    pub components: Vec<Component>,
+    /// Dependency graph between the plugin and its components
+    #[serde(default)]
+    pub dependencies: Vec<DependencyNode>,
+}
+
+/// The subject of an SBOM
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SbomMetadata {
+    /// The plugin crate
+    pub component: Component,
+}
+
+/// A crate in an SBOM
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Component {
+    /// `application` for the plugin, `library` for its dependencies
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Reference used by the dependency graph
+    pub bom_ref: String,
+    /// Crate name
+    pub name: String,
+    /// Crate version
+    pub version: String,
+    /// Package URL, such as `pkg:cargo/serde@1.0.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purl: Option<String>,
+    /// Crate description
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Declared licenses
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub licenses: Vec<LicenseChoice>,
+}
+
+/// A license declaration, as an SPDX expression
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseChoice {
+    /// SPDX license expression
+    pub expression: String,
+}
+
+/// The direct dependencies of one component
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyNode {
+    /// The component's `bom-ref`
+    #[serde(rename = "ref")]
+    pub reference: String,
+    /// The `bom-ref`s of its direct dependencies
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+}
+
+impl Sbom {
+    /// Generate the SBOM of the crate at `manifest_path` by running `cargo metadata`
+    ///
+    /// Only dependencies built for `target`, such as `wasm32-unknown-unknown`,
+    /// are listed; build and dev dependencies are left out.
+    pub fn generate(manifest_path: impl AsRef<Path>, target: &str) -> Result<Self, Error> {
+        let output = Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+            .args([
+                "metadata",
+                "--format-version",
+                "1",
+                "--filter-platform",
+                target,
+            ])
+            .arg("--manifest-path")
+            .arg(manifest_path.as_ref())
+            .output()?;
+        if !output.status.success() {
+            return Err(Error::InvalidArtifact(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let metadata: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| Error::InvalidArtifact(format!("invalid cargo metadata: {}", e)))?;
+        Self::from_cargo_metadata(&metadata)
+    }
+
+    /// Build an SBOM from the JSON output of `cargo metadata --format-version 1`
+    ///
+    /// The SBOM describes the resolve root, which must be a single package.
+    pub fn from_cargo_metadata(metadata: &Value) -> Result<Self, Error> {
+        let invalid = |what: &str| Error::InvalidArtifact(format!("cargo metadata {}", what));
+        let packages: BTreeMap<&str, &Value> = metadata["packages"]
+            .as_array()
+            .ok_or_else(|| invalid("has no packages"))?
+            .iter()
+            .filter_map(|package| Some((package["id"].as_str()?, package)))
+            .collect();
+        let root = metadata["resolve"]["root"]
+            .as_str()
+            .ok_or_else(|| invalid("has no root package"))?;
+
+        // Direct normal dependencies of each package
+        let mut graph: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for node in metadata["resolve"]["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let Some(id) = node["id"].as_str() else {
+                continue;
+            };
+            let deps = node["deps"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|dep| {
+                    dep["dep_kinds"]
+                        .as_array()
+                        .is_some_and(|kinds| kinds.iter().any(|kind| kind["kind"].is_null()))
+                })
+                .filter_map(|dep| dep["pkg"].as_str())
+                .collect();
+            graph.insert(id, deps);
+        }
+
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![root];
+        while let Some(id) = stack.pop() {
+            if reachable.insert(id) {
+                stack.extend(graph.get(id).into_iter().flatten());
+            }
+        }
+
+        let component = |id: &str, kind: &str| -> Result<Component, Error> {
+            let package = packages
+                .get(id)
+                .ok_or_else(|| invalid(&format!("has no package {}", id)))?;
+            let name = package["name"].as_str().unwrap_or_default().to_string();
+            let version = package["version"].as_str().unwrap_or_default().to_string();
+            Ok(Component {
+                kind: kind.to_string(),
+                bom_ref: id.to_string(),
+                purl: Some(format!("pkg:cargo/{}@{}", name, version)),
+                description: package["description"].as_str().map(str::to_string),
+                licenses: package["license"]
+                    .as_str()
+                    .map(|expression| LicenseChoice {
+                        expression: expression.to_string(),
+                    })
+                    .into_iter()
+                    .collect(),
+                name,
+                version,
+            })
+        };
+
+        Ok(Sbom {
+            bom_format: "CycloneDX".to_string(),
+            spec_version: SPEC_VERSION.to_string(),
+            version: 1,
+            metadata: SbomMetadata {
+                component: component(root, "application")?,
+            },
+            components: reachable
+                .iter()
+                .filter(|&&id| id != root)
+                .map(|id| component(id, "library"))
+                .collect::<Result<_, _>>()?,
+            dependencies: reachable
+                .iter()
+                .map(|&id| DependencyNode {
+                    reference: id.to_string(),
+                    depends_on: graph
+                        .get(id)
+                        .into_iter()
+                        .flatten()
+                        .map(|dep| dep.to_string())
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,136 @@
+//! Finding plugins by keyword, capability, license or author
+
+use serde::{Deserialize, Serialize};
+
+use super::Release;
+
+/// Criteria of a plugin search
+///
+/// Every set criterion must match. Keywords are matched against the name,
+/// exports, description and authors of a plugin's latest release.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchQuery {
+    /// Keywords, separated by whitespace
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Capability the plugin declares, such as `http` or `function:kv_get`,
+    /// or host capability it requires a version of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capability: Option<String>,
+    /// SPDX license identifier, such as `MIT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// Part of an author's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+impl SearchQuery {
+    /// Create a query matching every plugin
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match plugins by keywords
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// Only match plugins declaring a capability
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capability = Some(capability.into());
+        self
+    }
+
+    /// Only match plugins whose license expression includes `license`
+    pub fn with_license(mut self, license: impl Into<String>) -> Self {
+        self.license = Some(license.into());
+        self
+    }
+
+    /// Only match plugins by an author
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Score a release against the query, or `None` if it does not match
+    ///
+    /// Name matches rank above export matches, which rank above matches in
+    /// the description and authors.
+    pub(crate) fn score(&self, release: &Release) -> Option<u32> {
+        let metadata = &release.metadata;
+        if let Some(capability) = &self.capability {
+            let declared = metadata
+                .capabilities
+                .iter()
+                .any(|declared| declared.to_string() == *capability);
+            if !declared && !metadata.host.contains_key(capability) {
+                return None;
+            }
+        }
+        if let Some(license) = &self.license {
+            let expression = metadata.license.as_deref().unwrap_or_default();
+            let found = expression
+                .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .any(|id| id.eq_ignore_ascii_case(license));
+            if !found {
+                return None;
+            }
+        }
+        if let Some(author) = &self.author {
+            let author = author.to_lowercase();
+            if !metadata
+                .authors
+                .iter()
+                .any(|a| a.to_lowercase().contains(&author))
+            {
+                return None;
+            }
+        }
+
+        let name = metadata.name.to_lowercase();
+        let description = metadata
+            .description
+            .as_deref()
+            .unwrap_or_default()
+            .to_lowercase();
+        let mut score = 0;
+        for term in self.text.iter().flat_map(|text| text.split_whitespace()) {
+            let term = term.to_lowercase();
+            let term_score = if name == term {
+                10
+            } else if name.contains(&term) {
+                5
+            } else if metadata
+                .exports
+                .iter()
+                .any(|export| export.to_lowercase().contains(&term))
+            {
+                3
+            } else if description.contains(&term) {
+                2
+            } else if metadata
+                .authors
+                .iter()
+                .any(|author| author.to_lowercase().contains(&term))
+            {
+                1
+            } else {
+                return None;
+            };
+            score += term_score;
+        }
+        Some(score)
+    }
+}
+
+/// A plugin matching a search
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchResult {
+    /// How well the plugin matches; results are sorted by descending score
+    pub score: u32,
+    /// The plugin's latest release
+    pub release: Release,
+}
@@ -0,0 +1,651 @@
+//! HTTP API of a registry
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::Deserialize;
+
+use super::target::BuildHeader;
+use super::{
+    oci, openapi, sha256_hex, Access, Attachments, Error, GcOptions, PluginMetadata, Registry,
+    SearchQuery, TargetBuild, TokenScope, CHECKSUM_HEADER,
+};
+
+/// Maximum size of a request body
+const MAX_BODY_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Path prefix of the API
+pub(crate) const API_PREFIX: &str = "/api/v1";
+
+/// Path prefix of the OCI distribution API
+const OCI_PREFIX: &str = "/v2";
+
+/// A request, decoupled from the HTTP library
+struct Request {
+    method: String,
+    path: String,
+    query: BTreeMap<String, String>,
+    headers: BTreeMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Get the token of an `Authorization` header: a bearer token, or the
+    /// password of basic credentials, which is how OCI clients send one
+    fn token(&self) -> Option<String> {
+        let authorization = self.headers.get("authorization")?;
+        if let Some(token) = authorization.strip_prefix("Bearer ") {
+            return Some(token.to_string());
+        }
+        let credentials = STANDARD
+            .decode(authorization.strip_prefix("Basic ")?)
+            .ok()?;
+        let credentials = String::from_utf8(credentials).ok()?;
+        let (_, password) = credentials.split_once(':')?;
+        Some(password.to_string())
+    }
+}
+
+/// Body of a request creating an organization
+#[derive(Default, Deserialize)]
+struct NewOrg {
+    #[serde(default)]
+    private: bool,
+}
+
+/// Body of a request deprecating a version
+#[derive(Deserialize)]
+struct Deprecation {
+    message: String,
+}
+
+/// Body of a request issuing a token
+#[derive(Deserialize)]
+struct NewToken {
+    user: String,
+    scopes: Vec<TokenScope>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(value: &impl serde::Serialize) -> Result<Self, Error> {
+        Ok(Self {
+            status: 200,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::to_vec(value)?,
+        })
+    }
+
+    fn empty() -> Self {
+        Self {
+            status: 204,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    fn wasm(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/wasm",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    fn binary(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            headers: Vec::new(),
+            body,
+        }
+    }
+
+    fn error(e: &Error) -> Self {
+        let status = match e {
+            Error::InvalidName(_)
+            | Error::InvalidVersion(_)
+            | Error::InvalidArtifact(_)
+            | Error::InvalidRequest(_)
+            | Error::Signature(_)
+            | Error::Provenance(_) => 400,
+            Error::Unauthorized => 401,
+            Error::NotFound(_) => 404,
+            Error::AlreadyExists(_) | Error::VersionExists { .. } | Error::Unresolvable(_) => 409,
+            Error::Io(_)
+            | Error::Storage(_)
+            | Error::Request(_)
+            | Error::Remote { .. }
+            | Error::InvalidLockfile(_)
+            | Error::LockMismatch { .. } => 500,
+        };
+        Self {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::json!({ "error": e.to_string() })
+                .to_string()
+                .into_bytes(),
+        }
+    }
+
+    /// Report an error of an OCI distribution API request at `path`, in
+    /// the format and with the codes of the spec
+    fn oci_error(e: &Error, path: &str) -> Self {
+        let status = Self::error(e).status;
+        let code = match e {
+            Error::Unauthorized => "UNAUTHORIZED",
+            Error::InvalidName(_) => "NAME_INVALID",
+            Error::NotFound(_) if path.contains("/blobs/uploads/") => "BLOB_UPLOAD_UNKNOWN",
+            Error::NotFound(_) if path.contains("/blobs/") => "BLOB_UNKNOWN",
+            Error::NotFound(_) if path.contains("/manifests/") => "MANIFEST_UNKNOWN",
+            Error::NotFound(_) => "NAME_UNKNOWN",
+            _ if path.contains("/blobs/") && status == 400 => "DIGEST_INVALID",
+            _ if status == 400 => "MANIFEST_INVALID",
+            _ if status == 409 => "DENIED",
+            _ => "UNKNOWN",
+        };
+        let mut response = Self {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::json!({
+                "errors": [{ "code": code, "message": e.to_string() }],
+            })
+            .to_string()
+            .into_bytes(),
+        };
+        if status == 401 {
+            // Clients send tokens as the password of basic credentials
+            response = response.with_header("WWW-Authenticate", r#"Basic realm="extismx""#);
+        }
+        response
+    }
+
+    fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+}
+
+/// Serves a registry's HTTP API
+///
+/// Each request is handled on its own thread.
+pub struct Server {
+    http: tiny_http::Server,
+    registry: Arc<Registry>,
+}
+
+impl Server {
+    /// Listen for requests on `addr`
+    pub fn bind(addr: impl ToSocketAddrs, registry: Registry) -> Result<Self, Error> {
+        let http =
+            tiny_http::Server::http(addr).map_err(|e| Error::Io(std::io::Error::other(e)))?;
+        Ok(Self {
+            http,
+            registry: Arc::new(registry),
+        })
+    }
+
+    /// Get the address the server listens on
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Get the registry being served
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Handle requests until `shutdown()` is called
+    pub fn run(&self) {
+        for mut request in self.http.incoming_requests() {
+            let registry = self.registry.clone();
+            std::thread::spawn(move || {
+                let response = match read_request(&mut request) {
+                    Ok(req) => handle(&registry, &req),
+                    Err(response) => response,
+                };
+                let header = tiny_http::Header::from_bytes("Content-Type", response.content_type)
+                    .expect("content type is a valid header");
+                let mut http_response = tiny_http::Response::from_data(response.body)
+                    .with_status_code(response.status)
+                    .with_header(header);
+                for (name, value) in &response.headers {
+                    if let Ok(header) = tiny_http::Header::from_bytes(*name, value.as_bytes()) {
+                        http_response.add_header(header);
+                    }
+                }
+                let _ = request.respond(http_response);
+            });
+        }
+    }
+
+    /// Stop a running server
+    pub fn shutdown(&self) {
+        self.http.unblock();
+    }
+}
+
+/// Read a request, or the response rejecting it, with 413 for a body over
+/// `MAX_BODY_SIZE`
+fn read_request(request: &mut tiny_http::Request) -> Result<Request, Response> {
+    let too_large = || {
+        let e = Error::InvalidRequest(format!(
+            "request body is larger than {} bytes",
+            MAX_BODY_SIZE
+        ));
+        Response::error(&e).with_status(413)
+    };
+    if request
+        .body_length()
+        .is_some_and(|len| len as u64 > MAX_BODY_SIZE)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| Response::error(&Error::Io(e)))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err(too_large());
+    }
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    Ok(Request {
+        method: request.method().as_str().to_string(),
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(key), decode_component(value))
+            })
+            .collect(),
+        headers: request
+            .headers()
+            .iter()
+            .map(|header| {
+                (
+                    header.field.as_str().as_str().to_ascii_lowercase(),
+                    header.value.as_str().to_string(),
+                )
+            })
+            .collect(),
+        body,
+    })
+}
+
+fn handle(registry: &Registry, request: &Request) -> Response {
+    if request.path == OCI_PREFIX || request.path.starts_with("/v2/") {
+        return route_oci(registry, request)
+            .unwrap_or_else(|e| Response::oci_error(&e, &request.path));
+    }
+    route(registry, request).unwrap_or_else(|e| Response::error(&e))
+}
+
+fn route(registry: &Registry, request: &Request) -> Result<Response, Error> {
+    let not_found = || Error::NotFound(request.path.clone());
+    let rest = request
+        .path
+        .strip_prefix(API_PREFIX)
+        .ok_or_else(not_found)?;
+    let mut segments: Vec<&str> = rest.split('/').filter(|s| !s.is_empty()).collect();
+    // `@org/plugin` names span two segments
+    let scoped: String;
+    if segments.len() > 2 && segments[0] == "plugins" && segments[1].starts_with('@') {
+        scoped = format!("{}/{}", segments[1], segments[2]);
+        segments.splice(1..3, [scoped.as_str()]);
+    }
+    let token = request.token();
+    let token = token.as_deref();
+    registry.authenticate(token)?;
+    let readable = |name: &str| registry.authorize(token, name, Access::Read).is_ok();
+    if let ("GET", ["plugins", name, ..]) = (request.method.as_str(), segments.as_slice()) {
+        registry.authorize(token, name, Access::Read)?;
+    }
+
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["openapi.json"]) => Response::json(&openapi()),
+        ("GET", ["search"]) => {
+            let param = |key: &str| request.query.get(key).filter(|v| !v.is_empty()).cloned();
+            let query = SearchQuery {
+                text: param("q"),
+                capability: param("capability"),
+                license: param("license"),
+                author: param("author"),
+            };
+            let mut results = registry.search(&query)?;
+            results.retain(|result| readable(&result.release.metadata.name));
+            Response::json(&results)
+        }
+        ("GET", ["plugins"]) => {
+            let mut plugins = registry.plugins()?;
+            plugins.retain(|name| readable(name));
+            Response::json(&plugins)
+        }
+        ("PUT", ["plugins", "new"]) => {
+            let (metadata, wasm, attachments) = decode_publish(&request.body)?;
+            registry.authorize(token, &metadata.name, Access::Publish)?;
+            let checksum = request.headers.get(&CHECKSUM_HEADER.to_ascii_lowercase());
+            if let Some(expected) = checksum {
+                let actual = sha256_hex(wasm);
+                if !expected.eq_ignore_ascii_case(&actual) {
+                    return Err(Error::InvalidArtifact(format!(
+                        "SHA-256 mismatch: expected {}, got {}",
+                        expected, actual
+                    )));
+                }
+            }
+            Response::json(&registry.publish_with(metadata, wasm, attachments)?)
+        }
+        ("GET", ["plugins", name]) => Response::json(&registry.releases(name)?),
+        ("GET", ["plugins", name, "versions"]) => {
+            let versions: Vec<String> = registry
+                .releases(name)?
+                .into_iter()
+                .map(|release| release.metadata.version)
+                .collect();
+            Response::json(&versions)
+        }
+        ("GET", ["plugins", name, "stats"]) => Response::json(&registry.downloads(name)?),
+        ("GET", ["plugins", name, version]) => Response::json(&registry.release(name, version)?),
+        ("GET", ["plugins", name, version, "sbom"]) => {
+            Response::json(&registry.sbom(name, version)?)
+        }
+        ("GET", ["plugins", name, version, "download"]) => {
+            let wasm = registry.download(name, version)?;
+            registry.record_download(name, version)?;
+            Ok(Response::wasm(wasm))
+        }
+        ("GET", ["plugins", name, version, "download", target]) => {
+            let target = target.parse().map_err(Error::InvalidRequest)?;
+            let wasm = registry.download_target(name, version, target)?;
+            registry.record_download(name, version)?;
+            Ok(Response::wasm(wasm))
+        }
+        ("GET", ["plugins", name, version, "provenance"]) => {
+            Response::json(&registry.provenance(name, version)?)
+        }
+        ("GET", ["plugins", name, version, "delta", from]) => {
+            let patch = registry.delta(name, from, version)?;
+            registry.record_download(name, version)?;
+            Ok(Response::binary(patch))
+        }
+        ("PUT", ["plugins", name, version, "yank"]) => {
+            registry.authorize(token, name, Access::Publish)?;
+            Response::json(&registry.yank(name, version)?)
+        }
+        ("DELETE", ["plugins", name, version, "yank"]) => {
+            registry.authorize(token, name, Access::Publish)?;
+            Response::json(&registry.unyank(name, version)?)
+        }
+        ("PUT", ["plugins", name, version, "deprecation"]) => {
+            registry.authorize(token, name, Access::Publish)?;
+            let deprecation: Deprecation = decode_json(&request.body)?;
+            Response::json(&registry.deprecate(name, version, deprecation.message)?)
+        }
+        ("DELETE", ["plugins", name, version, "deprecation"]) => {
+            registry.authorize(token, name, Access::Publish)?;
+            Response::json(&registry.undeprecate(name, version)?)
+        }
+        ("GET", ["blobs", "sha256", digest]) => {
+            // Readable if any release sharing the module is
+            let references = registry.blob_references(digest)?;
+            if !references.iter().any(|reference| readable(&reference.name)) {
+                return Err(if references.is_empty() {
+                    Error::NotFound(format!("blob {}", digest))
+                } else {
+                    Error::Unauthorized
+                });
+            }
+            Ok(Response::wasm(registry.blob(digest)?))
+        }
+        ("PUT", ["orgs", org]) => {
+            registry.authorize_admin(token)?;
+            let settings: NewOrg = if request.body.is_empty() {
+                NewOrg::default()
+            } else {
+                decode_json(&request.body)?
+            };
+            Response::json(&registry.create_org(org, settings.private)?)
+        }
+        ("GET", ["orgs", org]) => {
+            registry.authorize_admin(token)?;
+            Response::json(&registry.org(org)?)
+        }
+        ("PUT", ["orgs", org, "members", user]) => {
+            registry.authorize_admin(token)?;
+            Response::json(&registry.add_member(org, user)?)
+        }
+        ("DELETE", ["orgs", org, "members", user]) => {
+            registry.authorize_admin(token)?;
+            Response::json(&registry.remove_member(org, user)?)
+        }
+        ("POST", ["tokens"]) => {
+            registry.authorize_admin(token)?;
+            let new: NewToken = decode_json(&request.body)?;
+            Response::json(&registry.issue_token(&new.user, new.scopes)?)
+        }
+        ("DELETE", ["tokens", id]) => {
+            registry.authorize_admin(token)?;
+            registry.revoke_token(id)?;
+            Ok(Response::empty())
+        }
+        ("POST", ["gc"]) => {
+            registry.authorize_admin(token)?;
+            let options: GcOptions = if request.body.is_empty() {
+                GcOptions::default()
+            } else {
+                decode_json(&request.body)?
+            };
+            Response::json(&registry.gc(&options)?)
+        }
+        _ => Err(not_found()),
+    }
+}
+
+/// Route a request of the OCI distribution API
+///
+/// Repository names span any number of path segments, so routes are
+/// matched from the end of the path.
+fn route_oci(registry: &Registry, request: &Request) -> Result<Response, Error> {
+    let token = request.token();
+    let token = token.as_deref();
+    registry.authenticate(token)?;
+    let rest = &request.path[OCI_PREFIX.len()..];
+    let segments: Vec<&str> = rest.split('/').filter(|s| !s.is_empty()).collect();
+    let digest = request.query.get("digest").map(String::as_str);
+    let method = match request.method.as_str() {
+        "HEAD" => "GET",
+        method => method,
+    };
+    let plugin = |repository: &[&str], access: Access| -> Result<String, Error> {
+        let name = oci::plugin_name(&repository.join("/"));
+        registry.authorize(token, &name, access)?;
+        Ok(name)
+    };
+    let uploads =
+        |repository: &[&str]| format!("{}/{}/blobs/uploads", OCI_PREFIX, repository.join("/"));
+    let blob = |repository: &[&str], digest: &str| {
+        format!("{}/{}/blobs/{}", OCI_PREFIX, repository.join("/"), digest)
+    };
+
+    match (method, segments.as_slice()) {
+        ("GET", []) => Ok(Response::json(&serde_json::json!({}))?
+            .with_header("Docker-Distribution-API-Version", "registry/2.0")),
+        ("GET", ["_catalog"]) => {
+            let mut plugins = registry.plugins()?;
+            plugins.retain(|name| registry.authorize(token, name, Access::Read).is_ok());
+            let repositories: Vec<&str> =
+                plugins.iter().map(|name| oci::repository(name)).collect();
+            Response::json(&serde_json::json!({ "repositories": repositories }))
+        }
+        ("GET", [repository @ .., "tags", "list"]) => {
+            let name = plugin(repository, Access::Read)?;
+            Response::json(&serde_json::json!({
+                "name": repository.join("/"),
+                "tags": registry.oci_tags(&name)?,
+            }))
+        }
+        ("GET", [repository @ .., "manifests", reference]) => {
+            let name = plugin(repository, Access::Read)?;
+            let (manifest, digest) = registry.oci_manifest(&name, reference)?;
+            Ok(Response {
+                status: 200,
+                content_type: oci::MANIFEST_TYPE,
+                headers: Vec::new(),
+                body: manifest,
+            }
+            .with_header("Docker-Content-Digest", digest))
+        }
+        ("PUT", [repository @ .., "manifests", tag]) => {
+            let name = plugin(repository, Access::Publish)?;
+            let digest = registry.oci_push_manifest(&name, tag, &request.body)?;
+            let location = format!(
+                "{}/{}/manifests/{}",
+                OCI_PREFIX,
+                repository.join("/"),
+                digest
+            );
+            Ok(Response::empty()
+                .with_status(201)
+                .with_header("Location", location)
+                .with_header("Docker-Content-Digest", digest))
+        }
+        ("POST", [repository @ .., "blobs", "uploads"]) => {
+            plugin(repository, Access::Publish)?;
+            let id = registry.oci_start_upload()?;
+            if let Some(digest) = digest {
+                // Monolithic upload
+                registry.oci_finish_upload(&id, digest, &request.body)?;
+                return Ok(Response::empty()
+                    .with_status(201)
+                    .with_header("Location", blob(repository, digest))
+                    .with_header("Docker-Content-Digest", digest));
+            }
+            Ok(Response::empty()
+                .with_status(202)
+                .with_header("Location", format!("{}/{}", uploads(repository), id))
+                .with_header("Range", "0-0")
+                .with_header("Docker-Upload-UUID", id))
+        }
+        ("PATCH", [repository @ .., "blobs", "uploads", id]) => {
+            plugin(repository, Access::Publish)?;
+            let size = registry.oci_append_upload(id, &request.body)?;
+            Ok(Response::empty()
+                .with_status(202)
+                .with_header("Location", format!("{}/{}", uploads(repository), id))
+                .with_header("Range", format!("0-{}", size.saturating_sub(1)))
+                .with_header("Docker-Upload-UUID", *id))
+        }
+        ("PUT", [repository @ .., "blobs", "uploads", id]) => {
+            plugin(repository, Access::Publish)?;
+            let digest =
+                digest.ok_or_else(|| Error::InvalidArtifact("missing digest".to_string()))?;
+            registry.oci_finish_upload(id, digest, &request.body)?;
+            Ok(Response::empty()
+                .with_status(201)
+                .with_header("Location", blob(repository, digest))
+                .with_header("Docker-Content-Digest", digest))
+        }
+        ("GET", [repository @ .., "blobs", digest]) => {
+            let name = plugin(repository, Access::Read)?;
+            let (data, version) = registry.oci_blob(&name, digest)?;
+            if let (Some(version), "GET") = (version, request.method.as_str()) {
+                registry.record_download(&name, &version)?;
+            }
+            Ok(Response::binary(data).with_header("Docker-Content-Digest", *digest))
+        }
+        _ => Err(Error::NotFound(request.path.clone())),
+    }
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    serde_json::from_slice(body).map_err(|e| Error::InvalidRequest(e.to_string()))
+}
+
+/// Split a publish request body into the metadata, the wasm module and the
+/// attachments, followed by the header and binary of each build for another target
+fn decode_publish(body: &[u8]) -> Result<(PluginMetadata, &[u8], Attachments), Error> {
+    let mut rest = body;
+    let metadata = serde_json::from_slice(next_part(&mut rest)?)
+        .map_err(|e| Error::InvalidArtifact(format!("invalid metadata: {}", e)))?;
+    let wasm = next_part(&mut rest)?;
+    // Requests without attachments end after the module
+    let mut attachments: Attachments = if rest.is_empty() {
+        Attachments::default()
+    } else {
+        serde_json::from_slice(next_part(&mut rest)?)
+            .map_err(|e| Error::InvalidArtifact(format!("invalid attachments: {}", e)))?
+    };
+    while !rest.is_empty() {
+        let header: BuildHeader = serde_json::from_slice(next_part(&mut rest)?)
+            .map_err(|e| Error::InvalidArtifact(format!("invalid build: {}", e)))?;
+        attachments.builds.push(TargetBuild {
+            target: header.target,
+            wasm: next_part(&mut rest)?.to_vec(),
+            signature: header.signature,
+        });
+    }
+    Ok((metadata, wasm, attachments))
+}
+
+/// Split the next length-prefixed part off a publish request body
+fn next_part<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let invalid = || Error::InvalidArtifact("malformed publish request".to_string());
+    let (len, tail) = rest.split_first_chunk::<4>().ok_or_else(invalid)?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if tail.len() < len {
+        return Err(invalid());
+    }
+    let (head, tail) = tail.split_at(len);
+    *rest = tail;
+    Ok(head)
+}
+
+/// Decode a percent-encoded query string component
+fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+                if let Some(byte) = hex {
+                    decoded.push(byte);
+                    i += 2;
+                } else {
+                    decoded.push(b'%');
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
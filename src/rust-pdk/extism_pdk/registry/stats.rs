@@ -0,0 +1,134 @@
+//! Download counts of published versions
+
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{civil_date, release_version, validate_name, Error, Registry, Release};
+
+/// Storage key prefix of download statistics records
+const STATS_PREFIX: &str = "stats/";
+
+/// How many days of daily download counts are kept
+const DAILY_RETENTION_DAYS: u64 = 90;
+
+/// Download counts of a plugin
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Downloads {
+    /// Downloads of every version
+    pub total: u64,
+    /// Downloads of each version, by version
+    #[serde(default)]
+    pub versions: BTreeMap<String, VersionDownloads>,
+}
+
+/// Download counts of a plugin version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionDownloads {
+    /// Downloads since the version was published
+    pub total: u64,
+    /// Downloads per UTC day, such as `2024-01-31`, over the last 90 days
+    #[serde(default)]
+    pub daily: BTreeMap<String, u64>,
+}
+
+impl VersionDownloads {
+    /// Count the downloads of the last `days` days, including today
+    pub fn recent(&self, days: u64) -> u64 {
+        let since = day_key(today().saturating_sub(days.saturating_sub(1)));
+        self.daily.range(since..).map(|(_, count)| count).sum()
+    }
+}
+
+impl Downloads {
+    /// Get each version's share of the downloads of the last `days` days,
+    /// from 0 to 1, showing which versions are still in use
+    pub fn adoption(&self, days: u64) -> BTreeMap<String, f64> {
+        let recent: BTreeMap<&String, u64> = self
+            .versions
+            .iter()
+            .map(|(version, downloads)| (version, downloads.recent(days)))
+            .collect();
+        let total: u64 = recent.values().sum();
+        recent
+            .into_iter()
+            .map(|(version, count)| {
+                let share = if total == 0 {
+                    0.0
+                } else {
+                    count as f64 / total as f64
+                };
+                (version.clone(), share)
+            })
+            .collect()
+    }
+}
+
+/// A plugin's releases and download counts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginInfo {
+    /// Plugin name
+    pub name: String,
+    /// Releases, oldest version first
+    pub releases: Vec<Release>,
+    /// Download counts
+    pub downloads: Downloads,
+}
+
+impl PluginInfo {
+    /// Get the newest release that is not yanked
+    pub fn latest(&self) -> Option<&Release> {
+        self.releases
+            .iter()
+            .filter(|release| !release.yanked)
+            .max_by_key(|release| release_version(release))
+    }
+}
+
+impl Registry {
+    /// Get the download counts of a plugin
+    pub fn downloads(&self, name: &str) -> Result<Downloads, Error> {
+        self.releases(name)?;
+        match self.storage.get(&stats_key(name))? {
+            Some(data) => Ok(serde_json::from_slice(&data)?),
+            None => Ok(Downloads::default()),
+        }
+    }
+
+    /// Count a download of a release
+    pub fn record_download(&self, name: &str, version: &str) -> Result<(), Error> {
+        validate_name(name)?;
+        let _write = self.write.lock().unwrap_or_else(|e| e.into_inner());
+        let mut downloads: Downloads = match self.storage.get(&stats_key(name))? {
+            Some(data) => serde_json::from_slice(&data)?,
+            None => Downloads::default(),
+        };
+        let today = today();
+        let cutoff = day_key(today.saturating_sub(DAILY_RETENTION_DAYS - 1));
+        let counts = downloads.versions.entry(version.to_string()).or_default();
+        counts.total += 1;
+        *counts.daily.entry(day_key(today)).or_default() += 1;
+        counts.daily = counts.daily.split_off(&cutoff);
+        downloads.total += 1;
+        self.storage
+            .put(&stats_key(name), &serde_json::to_vec(&downloads)?)
+    }
+}
+
+fn stats_key(name: &str) -> String {
+    format!("{}{}.json", STATS_PREFIX, name)
+}
+
+/// Days since the Unix epoch
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() / 86400)
+}
+
+/// Format a day as `2024-01-31`
+fn day_key(days: u64) -> String {
+    let (year, month, day) = civil_date(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
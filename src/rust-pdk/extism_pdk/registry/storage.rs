@@ -0,0 +1,189 @@
+//! Where a registry keeps its records and artifacts
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use super::Error;
+
+mod s3;
+
+pub use s3::S3Storage;
+
+/// A key-value store for registry records and artifacts
+///
+/// Keys are `/`-separated paths such as `index/hello.json`. Implementations
+/// must be safe to share between the server's request threads.
+pub trait Storage: Send + Sync {
+    /// Get the value stored under `key`
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Store `value` under `key`, replacing any previous value
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), Error>;
+
+    /// Remove the value stored under `key`, returning whether it existed
+    fn delete(&self, key: &str) -> Result<bool, Error>;
+
+    /// List the keys starting with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+}
+
+/// Storage that keeps everything in memory, for tests and development
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    entries: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    /// Create empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        Ok(entries.get(key).cloned())
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        entries.insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        Ok(entries.remove(key).is_some())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        Ok(entries
+            .range(prefix.to_string()..)
+            .map(|(key, _)| key)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Storage that keeps each value in a file under a directory
+///
+/// Keys map to paths below the root, so the directory can be backed up or
+/// inspected with ordinary tools. Writes go through a temporary file and a
+/// rename, so a crash never leaves a half-written value behind.
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    root: PathBuf,
+}
+
+impl FileStorage {
+    /// Store values under `root`, creating it if needed
+    pub fn new(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    /// Get the storage directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, Error> {
+        let valid = !key.is_empty()
+            && key.split('/').all(|part| {
+                !part.is_empty() && part != "." && part != ".." && !part.starts_with('.')
+            });
+        if !valid || key.contains('\\') {
+            return Err(Error::Storage(format!("invalid key {}", key)));
+        }
+        Ok(self.root.join(key))
+    }
+
+    /// Collect the keys starting with `prefix` in `dir`, whose key is `base`
+    fn list_dir(
+        &self,
+        dir: &Path,
+        base: &str,
+        prefix: &str,
+        keys: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Temporary files of writes in progress
+            if name.starts_with('.') {
+                continue;
+            }
+            let key = format!("{}{}", base, name);
+            if entry.file_type()?.is_dir() {
+                let base = format!("{}/", key);
+                if prefix.starts_with(&base) || base.starts_with(prefix) {
+                    self.list_dir(&entry.path(), &base, prefix, keys)?;
+                }
+            } else if key.starts_with(prefix) {
+                keys.push(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.path(key)?) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        write_atomic(&self.path(key)?, value)
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        match fs::remove_file(self.path(key)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        self.list_dir(&self.root, "", prefix, &mut keys)?;
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// Write a file through a temporary file, so readers never see it half written
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<(), Error> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let parent = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = parent.join(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&tmp, data)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+    Ok(())
+}
@@ -0,0 +1,359 @@
+//! Storage in an S3-compatible object store
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::hmac;
+
+use super::Storage;
+use crate::extism_pdk::registry::{civil_date, sha256_hex, Error};
+
+/// Maximum size of an object read from the store
+const MAX_OBJECT_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Storage in a bucket of Amazon S3 or a compatible store, such as MinIO,
+/// Ceph or Cloudflare R2
+///
+/// Requests are signed with AWS Signature Version 4 and use path-style
+/// URLs (`{endpoint}/{bucket}/{key}`).
+#[derive(Clone)]
+pub struct S3Storage {
+    /// Endpoint URL, without a trailing `/`
+    endpoint: String,
+    bucket: String,
+    region: String,
+    /// Prepended to every key
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl S3Storage {
+    /// Store objects in `bucket` at `endpoint`, such as `https://s3.us-east-1.amazonaws.com`
+    pub fn new(
+        endpoint: impl Into<String>,
+        bucket: impl Into<String>,
+        access_key: impl Into<String>,
+        secret_key: impl Into<String>,
+    ) -> Self {
+        let config = ureq::Agent::config_builder()
+            .http_status_as_error(false)
+            .build();
+        Self {
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            bucket: bucket.into(),
+            region: "us-east-1".to_string(),
+            prefix: String::new(),
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            session_token: None,
+            agent: ureq::Agent::new_with_config(config),
+        }
+    }
+
+    /// Read the credentials and region from the standard `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN` and `AWS_REGION` variables
+    pub fn from_env(endpoint: impl Into<String>, bucket: impl Into<String>) -> Result<Self, Error> {
+        let var = |name: &str| {
+            std::env::var(name).map_err(|_| Error::Storage(format!("{} is not set", name)))
+        };
+        let mut storage = Self::new(
+            endpoint,
+            bucket,
+            var("AWS_ACCESS_KEY_ID")?,
+            var("AWS_SECRET_ACCESS_KEY")?,
+        );
+        storage.session_token = var("AWS_SESSION_TOKEN").ok();
+        if let Ok(region) = var("AWS_REGION") {
+            storage.region = region;
+        }
+        Ok(storage)
+    }
+
+    /// Set the region requests are signed for, `us-east-1` by default
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = region.into();
+        self
+    }
+
+    /// Keep every object under `prefix`, such as `registry/`, to share a bucket
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sign requests with temporary credentials' session token
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Send a signed request, returning the status and body of the response
+    fn send(
+        &self,
+        method: &str,
+        key: Option<&str>,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<(u16, Vec<u8>), Error> {
+        let mut path = format!("/{}", uri_encode(&self.bucket, true));
+        if let Some(key) = key {
+            path.push('/');
+            path.push_str(&uri_encode(&format!("{}{}", self.prefix, key), false));
+        }
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, rest)| rest);
+        let payload_hash = sha256_hex(body);
+        let amz_date = amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = authorization(
+            &Credentials {
+                access_key: &self.access_key,
+                secret_key: &self.secret_key,
+                region: &self.region,
+            },
+            method,
+            &path,
+            &query,
+            &headers,
+            &payload_hash,
+        );
+
+        let mut url = format!("{}{}", self.endpoint, path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&url)
+            .header("Authorization", authorization);
+        let request = headers
+            .iter()
+            .filter(|(name, _)| *name != "host")
+            .fold(request, |request, (name, value)| {
+                request.header(*name, *value)
+            })
+            .body(body.to_vec())
+            .map_err(|e| Error::Storage(format!("S3 request {}: {}", url, e)))?;
+        let mut response = self
+            .agent
+            .run(request)
+            .map_err(|e| Error::Storage(format!("S3 request {}: {}", url, e)))?;
+        let status = response.status().as_u16();
+        let body = if method == "HEAD" {
+            Vec::new()
+        } else {
+            response
+                .body_mut()
+                .with_config()
+                .limit(MAX_OBJECT_SIZE)
+                .read_to_vec()
+                .map_err(|e| Error::Storage(format!("S3 response {}: {}", url, e)))?
+        };
+        Ok((status, body))
+    }
+
+    fn fail(&self, operation: &str, key: &str, status: u16, body: &[u8]) -> Error {
+        let body = String::from_utf8_lossy(body);
+        let code = xml_values(&body, "Code").next().unwrap_or_default();
+        Error::Storage(format!(
+            "S3 {} {}{} failed with HTTP {} {}",
+            operation, self.prefix, key, status, code
+        ))
+    }
+}
+
+impl Storage for S3Storage {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self.send("GET", Some(key), &[], &[])? {
+            (200, body) => Ok(Some(body)),
+            (404, _) => Ok(None),
+            (status, body) => Err(self.fail("GET", key, status, &body)),
+        }
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        match self.send("PUT", Some(key), &[], value)? {
+            (200, _) => Ok(()),
+            (status, body) => Err(self.fail("PUT", key, status, &body)),
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<bool, Error> {
+        // Deleting a missing object succeeds, so check that it exists first
+        match self.send("HEAD", Some(key), &[], &[])? {
+            (200, _) => {}
+            (404, _) => return Ok(false),
+            (status, body) => return Err(self.fail("HEAD", key, status, &body)),
+        }
+        match self.send("DELETE", Some(key), &[], &[])? {
+            (200 | 204, _) => Ok(true),
+            (status, body) => Err(self.fail("DELETE", key, status, &body)),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let full_prefix = format!("{}{}", self.prefix, prefix);
+        let mut keys = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", full_prefix.as_str())];
+            if let Some(token) = &continuation {
+                query.push(("continuation-token", token));
+            }
+            let body = match self.send("GET", None, &query, &[])? {
+                (200, body) => String::from_utf8_lossy(&body).into_owned(),
+                (status, body) => return Err(self.fail("LIST", prefix, status, &body)),
+            };
+            keys.extend(
+                xml_values(&body, "Key")
+                    .filter_map(|key| Some(key.strip_prefix(&self.prefix)?.to_string())),
+            );
+            let truncated = xml_values(&body, "IsTruncated").next() == Some("true".to_string());
+            continuation = xml_values(&body, "NextContinuationToken").next();
+            if !truncated || continuation.is_none() {
+                break;
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+}
+
+/// What a request is signed with
+struct Credentials<'a> {
+    access_key: &'a str,
+    secret_key: &'a str,
+    region: &'a str,
+}
+
+/// Compute the `Authorization` header of an S3 request
+///
+/// `headers` must be lowercase and sorted by name, and include `x-amz-date`.
+fn authorization(
+    credentials: &Credentials,
+    method: &str,
+    path: &str,
+    query: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+) -> String {
+    let amz_date = headers
+        .iter()
+        .find(|(name, _)| *name == "x-amz-date")
+        .map_or("", |(_, value)| value);
+    let date = &amz_date[..amz_date.len().min(8)];
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, query, canonical_headers, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, credentials.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        sha256_hex(canonical_request.as_bytes())
+    );
+    let key = [date, credentials.region, "s3", "aws4_request"]
+        .iter()
+        .fold(
+            format!("AWS4{}", credentials.secret_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part.as_bytes()).as_ref().to_vec(),
+        );
+    let signature: String = hmac_sha256(&key, string_to_sign.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+        credentials.access_key, scope, signed_headers, signature
+    )
+}
+
+/// Percent-encode everything but unreserved characters, and `/` unless `slash` is set
+fn uri_encode(value: &str, slash: bool) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if !slash => "/".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Format a time as `20240102T030405Z`
+fn amz_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_date(days);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+/// Iterate over the text of the `<tag>` elements of an XML document
+fn xml_values<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = String> + 'a {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let value = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(
+            value
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&quot;", "\"")
+                .replace("&apos;", "'")
+                .replace("&amp;", "&"),
+        )
+    })
+}
+
+/// Compute the HMAC-SHA256 of `data` keyed with `key`
+fn hmac_sha256(key: &[u8], data: &[u8]) -> hmac::Tag {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data)
+}
@@ -0,0 +1,139 @@
+//! Compilation targets of plugin builds
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Signature, WASM_MAGIC};
+
+/// Version and layer that follow the magic number of a core wasm module
+const MODULE_HEADER: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+
+/// Version and layer that follow the magic number of a wasm component
+const COMPONENT_HEADER: [u8; 4] = [0x0d, 0x00, 0x01, 0x00];
+
+/// What a plugin build was compiled for, and so which hosts can run it
+///
+/// Targets are written as their Rust target names, `wasm32-unknown-unknown`
+/// and `wasm32-wasip1`, or `component` for component model builds.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    /// A core module using only Extism host functions, which every host runs
+    #[default]
+    Wasm32UnknownUnknown,
+    /// A core module importing WASI preview 1, for hosts with WASI enabled
+    Wasm32Wasip1,
+    /// A component, for hosts supporting the component model
+    Component,
+}
+
+impl Target {
+    /// Every target, in the order hosts usually prefer them
+    pub const ALL: [Target; 3] = [
+        Target::Wasm32UnknownUnknown,
+        Target::Wasm32Wasip1,
+        Target::Component,
+    ];
+
+    /// Check if a binary has the right encoding for the target
+    pub(crate) fn matches(self, wasm: &[u8]) -> bool {
+        let header = match self {
+            Target::Component => COMPONENT_HEADER,
+            _ => MODULE_HEADER,
+        };
+        wasm.starts_with(WASM_MAGIC) && wasm.get(4..8) == Some(&header[..])
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Target::default()
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Target::Wasm32UnknownUnknown => "wasm32-unknown-unknown",
+            Target::Wasm32Wasip1 => "wasm32-wasip1",
+            Target::Component => "component",
+        })
+    }
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Target::ALL
+            .into_iter()
+            .find(|target| target.to_string() == s)
+            .ok_or_else(|| format!("unknown target {:?}", s))
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> Self {
+        target.to_string()
+    }
+}
+
+/// A build of a plugin version for one target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetArtifact {
+    /// What the build was compiled for
+    pub target: Target,
+    /// SHA-256 of the build, as hex
+    pub sha256: String,
+    /// Size of the build in bytes
+    pub size: u64,
+    /// Detached signature of the build, if it was published signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+}
+
+/// A build for another target, published along with a version's main module
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetBuild {
+    /// What the build was compiled for
+    pub target: Target,
+    /// The wasm binary
+    pub wasm: Vec<u8>,
+    /// Detached signature of the binary, made by `signing::sign()`
+    pub signature: Option<Signature>,
+}
+
+impl TargetBuild {
+    /// A build of `wasm` for `target`
+    pub fn new(target: Target, wasm: impl Into<Vec<u8>>) -> Self {
+        Self {
+            target,
+            wasm: wasm.into(),
+            signature: None,
+        }
+    }
+
+    /// Publish the build with a signature
+    pub fn with_signature(mut self, signature: Signature) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+}
+
+/// Part of a publish request describing the build that follows it
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BuildHeader {
+    pub(crate) target: Target,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) signature: Option<Signature>,
+}
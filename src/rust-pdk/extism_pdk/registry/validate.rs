@@ -0,0 +1,244 @@
+//! Checks run on a plugin before it is published
+
+use std::collections::BTreeSet;
+
+use super::{valid_identifier, Error, PluginMetadata, Target, TargetBuild};
+
+/// Maximum length of a description
+const MAX_DESCRIPTION_LEN: usize = 1024;
+
+/// Check a module and its metadata, reporting every problem found
+///
+/// The module and each build for another target must be valid wasm for
+/// their target, core modules exporting each function the metadata
+/// declares, and there must be one binary per target; the license must be
+/// an SPDX expression, host capabilities must be valid names and declared
+/// capabilities must not repeat, and each function may be described by one
+/// schema.
+pub(crate) fn validate(
+    metadata: &PluginMetadata,
+    wasm: &[u8],
+    builds: &[TargetBuild],
+) -> Result<(), Error> {
+    let mut problems = Vec::new();
+
+    check_binary(metadata, metadata.target, wasm, "module", &mut problems);
+    let mut targets = BTreeSet::from([metadata.target]);
+    for build in builds {
+        let label = format!("{} build", build.target);
+        check_binary(metadata, build.target, &build.wasm, &label, &mut problems);
+        if !targets.insert(build.target) {
+            problems.push(format!("{} is published twice", label));
+        }
+    }
+
+    let mut seen = BTreeSet::new();
+    for export in &metadata.exports {
+        if !seen.insert(export) {
+            problems.push(format!("export {} is declared twice", export));
+        }
+    }
+    if let Some(license) = &metadata.license {
+        if !valid_license(license) {
+            problems.push(format!("license {:?} is not an SPDX expression", license));
+        }
+    }
+    if let Some(description) = &metadata.description {
+        if description.len() > MAX_DESCRIPTION_LEN {
+            problems.push(format!(
+                "description is longer than {} bytes",
+                MAX_DESCRIPTION_LEN
+            ));
+        }
+    }
+    if metadata
+        .authors
+        .iter()
+        .any(|author| author.trim().is_empty())
+    {
+        problems.push("author names must not be empty".to_string());
+    }
+    for capability in metadata.host.keys() {
+        if !valid_identifier(capability) {
+            problems.push(format!("invalid host capability name {:?}", capability));
+        }
+    }
+    let mut seen = BTreeSet::new();
+    for capability in &metadata.capabilities {
+        if !seen.insert(capability) {
+            problems.push(format!("capability {} is declared twice", capability));
+        }
+    }
+    let mut seen = BTreeSet::new();
+    for schema in &metadata.schemas {
+        if !seen.insert(&schema.name) {
+            problems.push(format!("function {} is described twice", schema.name));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArtifact(problems.join("; ")))
+    }
+}
+
+/// Check that a binary is valid wasm for `target` and that core modules
+/// export the functions the metadata declares or describes
+fn check_binary(
+    metadata: &PluginMetadata,
+    target: Target,
+    wasm: &[u8],
+    label: &str,
+    problems: &mut Vec<String>,
+) {
+    if !target.matches(wasm) {
+        let expected = match target {
+            Target::Component => "a wasm component",
+            _ => "a core wasm module",
+        };
+        problems.push(format!("{} is not {}", label, expected));
+        return;
+    }
+    // Components are only checked for their encoding, since the host
+    // runtime does not load them
+    if target == Target::Component {
+        return;
+    }
+    match wasmtime::Module::validate(&wasmtime::Engine::default(), wasm) {
+        Ok(()) => match exported_functions(wasm) {
+            Ok(exported) => {
+                problems.extend(
+                    metadata
+                        .exports
+                        .iter()
+                        .filter(|export| !exported.contains(export.as_str()))
+                        .map(|export| {
+                            format!(
+                                "declared export {} is not exported by the {}",
+                                export, label
+                            )
+                        }),
+                );
+                problems.extend(
+                    metadata
+                        .schemas
+                        .iter()
+                        .filter(|schema| !exported.contains(schema.name.as_str()))
+                        .map(|schema| {
+                            format!(
+                                "described function {} is not exported by the {}",
+                                schema.name, label
+                            )
+                        }),
+                );
+            }
+            Err(e) => problems.push(e),
+        },
+        Err(e) => problems.push(format!("invalid wasm {}: {}", label, e)),
+    }
+}
+
+/// List the names of the functions a valid module exports
+pub(crate) fn exported_functions(wasm: &[u8]) -> Result<BTreeSet<&str>, String> {
+    let truncated = || "truncated wasm module".to_string();
+    let mut reader = Reader {
+        data: wasm.get(8..).ok_or_else(truncated)?,
+    };
+    let mut names = BTreeSet::new();
+    while !reader.data.is_empty() {
+        let id = reader.byte().ok_or_else(truncated)?;
+        let size = reader.leb().ok_or_else(truncated)? as usize;
+        let mut section = Reader {
+            data: reader.take(size).ok_or_else(truncated)?,
+        };
+        // Export section
+        if id != 7 {
+            continue;
+        }
+        for _ in 0..section.leb().ok_or_else(truncated)? {
+            let len = section.leb().ok_or_else(truncated)? as usize;
+            let name = section.take(len).ok_or_else(truncated)?;
+            let kind = section.byte().ok_or_else(truncated)?;
+            section.leb().ok_or_else(truncated)?;
+            if kind == 0 {
+                names.insert(std::str::from_utf8(name).map_err(|e| e.to_string())?);
+            }
+        }
+    }
+    Ok(names)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*byte)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head)
+    }
+
+    /// Read an unsigned LEB128 integer
+    fn leb(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+/// Check the syntax of an SPDX license expression, such as `MIT OR Apache-2.0`
+///
+/// License identifiers are not checked against the SPDX list, so new and
+/// `LicenseRef-` licenses are accepted.
+fn valid_license(expression: &str) -> bool {
+    let spaced = expression.replace('(', " ( ").replace(')', " ) ");
+    let mut depth = 0usize;
+    // Whether the next token must be a license rather than an operator
+    let mut expect_license = true;
+    let mut after_with = false;
+    for token in spaced.split_whitespace() {
+        match token {
+            "(" if expect_license => depth += 1,
+            ")" if !expect_license && depth > 0 => depth -= 1,
+            "AND" | "OR" | "WITH" if !expect_license => {
+                expect_license = true;
+                after_with = token == "WITH";
+            }
+            _ if expect_license => {
+                let id = if after_with {
+                    token
+                } else {
+                    token.strip_suffix('+').unwrap_or(token)
+                };
+                let valid = !id.is_empty()
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+                if !valid {
+                    return false;
+                }
+                expect_license = false;
+                after_with = false;
+            }
+            _ => return false,
+        }
+    }
+    depth == 0 && !expect_license
+}
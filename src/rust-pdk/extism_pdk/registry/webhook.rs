@@ -0,0 +1,190 @@
+//! Webhooks notifying other systems of registry events
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use ring::hmac;
+
+use super::{Error, Release};
+use crate::extism_pdk::binary::{from_hex, to_hex};
+
+/// Header naming the event a delivery is for
+const EVENT_HEADER: &str = "X-Extismx-Event";
+
+/// Header carrying the HMAC-SHA256 of a delivery's body
+const SIGNATURE_HEADER: &str = "X-Extismx-Signature";
+
+/// How many times a delivery is attempted before it is given up
+const ATTEMPTS: u32 = 3;
+
+/// Time allowed for each delivery attempt
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An event that triggers webhooks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookEvent {
+    /// A version was published
+    Published,
+    /// A version was yanked
+    Yanked,
+}
+
+impl WebhookEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            WebhookEvent::Published => "published",
+            WebhookEvent::Yanked => "yanked",
+        }
+    }
+}
+
+/// JSON body of a webhook delivery
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    /// What happened
+    pub event: WebhookEvent,
+    /// Name of the plugin
+    pub name: String,
+    /// Version the event is about
+    pub version: String,
+    /// The release after the event
+    pub release: Release,
+    /// When the event happened, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// An endpoint the registry posts events to
+///
+/// Each delivery is a `POST` of a `WebhookPayload`, with its event in the
+/// `X-Extismx-Event` header and `sha256=` followed by the hex HMAC-SHA256 of
+/// the body, keyed with the webhook's secret, in `X-Extismx-Signature`.
+/// Receivers should check the signature with `Webhook::verify()` before
+/// acting on a delivery.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    secret: String,
+    /// Events to deliver, or every event if empty
+    events: Vec<WebhookEvent>,
+    agent: ureq::Agent,
+}
+
+impl Webhook {
+    /// Post every event to `url`, signed with `secret`
+    pub fn new(url: impl Into<String>, secret: impl Into<String>) -> Self {
+        let config = ureq::Agent::config_builder()
+            .timeout_global(Some(TIMEOUT))
+            .build();
+        Self {
+            url: url.into(),
+            secret: secret.into(),
+            events: Vec::new(),
+            agent: ureq::Agent::new_with_config(config),
+        }
+    }
+
+    /// Only post `event`, and any other events added this way
+    pub fn with_event(mut self, event: WebhookEvent) -> Self {
+        if !self.events.contains(&event) {
+            self.events.push(event);
+        }
+        self
+    }
+
+    /// Check the `X-Extismx-Signature` header of a delivery against its body
+    pub fn verify(secret: &str, body: &[u8], signature: &str) -> bool {
+        let Some(tag) = signature
+            .strip_prefix("sha256=")
+            .and_then(|tag| from_hex(tag).ok())
+        else {
+            return false;
+        };
+        // `ring` compares the tag in constant time, so it cannot be guessed byte by byte
+        hmac::verify(&signing_key(secret), body, &tag).is_ok()
+    }
+
+    fn wants(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Post a payload, retrying with backoff if the endpoint fails
+    fn deliver(&self, event: WebhookEvent, body: &[u8]) -> Result<(), Error> {
+        let signature = signature_header(&self.secret, body);
+        let mut attempt = 1;
+        loop {
+            let result = self
+                .agent
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .header(EVENT_HEADER, event.as_str())
+                .header(SIGNATURE_HEADER, &signature)
+                .send(body);
+            match result {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt == ATTEMPTS => {
+                    return Err(Error::Request(format!("{}: {}", self.url, e)))
+                }
+                Err(_) => {
+                    std::thread::sleep(Duration::from_secs(1 << attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Deliver an event to each webhook that wants it, in the background
+///
+/// Publishing does not wait for deliveries, and failed deliveries are only
+/// logged, so an unreachable endpoint never fails a publish or yank.
+pub(crate) fn notify(webhooks: &[Webhook], event: WebhookEvent, release: &Release) {
+    let webhooks: Vec<Webhook> = webhooks
+        .iter()
+        .filter(|webhook| webhook.wants(event))
+        .cloned()
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    let payload = WebhookPayload {
+        event,
+        name: release.metadata.name.clone(),
+        version: release.metadata.version.clone(),
+        release: release.clone(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()),
+    };
+    let body = match serde_json::to_vec(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::warn!("failed to encode webhook payload: {}", e);
+            return;
+        }
+    };
+    for webhook in webhooks {
+        let body = body.clone();
+        let name = payload.name.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = webhook.deliver(event, &body) {
+                tracing::warn!(
+                    event = event.as_str(),
+                    plugin = name,
+                    "webhook delivery failed: {}",
+                    e
+                );
+            }
+        });
+    }
+}
+
+fn signature_header(secret: &str, body: &[u8]) -> String {
+    let tag = hmac::sign(&signing_key(secret), body);
+    format!("sha256={}", to_hex(tag.as_ref()))
+}
+
+fn signing_key(secret: &str) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes())
+}
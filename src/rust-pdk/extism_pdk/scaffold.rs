@@ -0,0 +1,497 @@
+//! Generating new plugin crates from templates
+//!
+//! A `Scaffold` writes a crate with the PDK wired up, an example export, a
+//! test harness that builds the plugin and runs it in the host, and the
+//! host manifest the tests load it with:
+//!
+//! ```text
+//! resize/
+//!   Cargo.toml       package, PDK dependency and [package.metadata.extismx]
+//!   manifest.json    config and policy the host runs the plugin with
+//!   src/lib.rs       the example export
+//!   tests/plugin.rs  calls the export through the host
+//!   README.md
+//!   .gitignore
+//! ```
+//!
+//! `with_language` writes a plugin for the Go, JavaScript or Python PDK
+//! instead, with the template's export, or with the functions and types of
+//! an interface given by `with_interface`: the metadata a Rust plugin
+//! declares with `plugin_metadata!`, a WIT world, or a published plugin's
+//! metadata. Such a plugin keeps its sources and `manifest.json` next to a
+//! `plugin.json` to publish it with.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::capability::Capability;
+use super::codegen::Codegen;
+use super::host::{Manifest, Wasm};
+use super::registry::{self, PluginMetadata, Target};
+use super::schema::FunctionSchema;
+
+const CARGO_TOML: &str = include_str!("scaffold/Cargo.toml.in");
+const README: &str = include_str!("scaffold/README.md.in");
+const GUEST_README: &str = include_str!("scaffold/guest_README.md.in");
+
+/// A new plugin crate could not be generated
+#[derive(Debug)]
+pub enum ScaffoldError {
+    /// A file could not be written
+    Io(std::io::Error),
+    /// The plugin name is not a valid registry name
+    InvalidName(String),
+    /// The directory already exists
+    Exists(PathBuf),
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaffoldError::Io(e) => write!(f, "Scaffold I/O error: {}", e),
+            ScaffoldError::InvalidName(name) => write!(f, "Invalid plugin name: {}", name),
+            ScaffoldError::Exists(path) => write!(f, "{} already exists", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ScaffoldError {}
+
+impl From<std::io::Error> for ScaffoldError {
+    fn from(e: std::io::Error) -> Self {
+        ScaffoldError::Io(e)
+    }
+}
+
+/// The kind of plugin a new crate starts as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Template {
+    /// Calls an HTTP API configured by the host, exporting `fetch`
+    #[default]
+    HttpApi,
+    /// Cleans up batches of JSON records, exporting `transform`
+    DataTransform,
+    /// Checks and handles webhook deliveries forwarded by the host,
+    /// exporting `handle`
+    Webhook,
+}
+
+impl Template {
+    /// Every template
+    pub const ALL: [Template; 3] = [
+        Template::HttpApi,
+        Template::DataTransform,
+        Template::Webhook,
+    ];
+
+    fn description(self) -> &'static str {
+        match self {
+            Template::HttpApi => "An Extism plugin calling an HTTP API",
+            Template::DataTransform => "An Extism plugin transforming JSON records",
+            Template::Webhook => "An Extism plugin handling webhook deliveries",
+        }
+    }
+
+    fn export(self) -> &'static str {
+        match self {
+            Template::HttpApi => "fetch",
+            Template::DataTransform => "transform",
+            Template::Webhook => "handle",
+        }
+    }
+
+    fn capabilities(self) -> Vec<Capability> {
+        match self {
+            Template::HttpApi => vec![Capability::Http],
+            Template::DataTransform | Template::Webhook => Vec::new(),
+        }
+    }
+
+    fn sources(self) -> (&'static str, &'static str) {
+        match self {
+            Template::HttpApi => (
+                include_str!("scaffold/http_api.rs.in"),
+                include_str!("scaffold/http_api_test.rs.in"),
+            ),
+            Template::DataTransform => (
+                include_str!("scaffold/data_transform.rs.in"),
+                include_str!("scaffold/data_transform_test.rs.in"),
+            ),
+            Template::Webhook => (
+                include_str!("scaffold/webhook.rs.in"),
+                include_str!("scaffold/webhook_test.rs.in"),
+            ),
+        }
+    }
+
+    /// The manifest the tests and hosts load the plugin with
+    fn manifest(self, wasm: &str) -> Manifest {
+        let manifest = Manifest::new([Wasm::file(wasm)]);
+        match self {
+            Template::HttpApi => manifest
+                .with_config_key("base_url", "https://api.example.com")
+                .with_allowed_host("api.example.com"),
+            Template::DataTransform => manifest.with_config_key("drop_fields", "password"),
+            Template::Webhook => manifest.with_config_key("token", "change-me"),
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Template::HttpApi => "http-api",
+            Template::DataTransform => "data-transform",
+            Template::Webhook => "webhook",
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Template::ALL
+            .into_iter()
+            .find(|template| template.to_string() == s)
+            .ok_or_else(|| format!("unknown template {:?}", s))
+    }
+}
+
+/// The PDK a new plugin is written with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PluginLanguage {
+    /// A crate using this PDK
+    #[default]
+    Rust,
+    /// A `main` package using `github.com/extism/go-pdk`, built with TinyGo
+    Go,
+    /// A module built with `extism-js`
+    JavaScript,
+    /// A module built with `extism-py`
+    Python,
+}
+
+impl PluginLanguage {
+    /// Every language
+    pub const ALL: [PluginLanguage; 4] = [
+        PluginLanguage::Rust,
+        PluginLanguage::Go,
+        PluginLanguage::JavaScript,
+        PluginLanguage::Python,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            PluginLanguage::Rust => "Rust",
+            PluginLanguage::Go => "Go",
+            PluginLanguage::JavaScript => "JavaScript",
+            PluginLanguage::Python => "Python",
+        }
+    }
+}
+
+impl fmt::Display for PluginLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PluginLanguage::Rust => "rust",
+            PluginLanguage::Go => "go",
+            PluginLanguage::JavaScript => "js",
+            PluginLanguage::Python => "python",
+        })
+    }
+}
+
+impl FromStr for PluginLanguage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" | "rs" => Ok(PluginLanguage::Rust),
+            "go" => Ok(PluginLanguage::Go),
+            "js" | "javascript" => Ok(PluginLanguage::JavaScript),
+            "python" | "py" => Ok(PluginLanguage::Python),
+            _ => Err(format!("unknown plugin language {:?}", s)),
+        }
+    }
+}
+
+/// Where a new crate gets the PDK from
+#[derive(Debug, Clone)]
+enum PdkSource {
+    Path(PathBuf),
+    Git(String),
+}
+
+/// Generates a new plugin crate
+#[derive(Debug, Clone)]
+pub struct Scaffold {
+    name: String,
+    template: Template,
+    pdk: PdkSource,
+    language: PluginLanguage,
+    interface: Option<PluginMetadata>,
+}
+
+impl Scaffold {
+    /// Generate a plugin published as `name`, such as `resize` or
+    /// `@acme/resize`, from the `http-api` template
+    ///
+    /// The crate depends on the PDK sources `extismx` was built from.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            template: Template::default(),
+            pdk: PdkSource::Path(PathBuf::from(env!("CARGO_MANIFEST_DIR"))),
+            language: PluginLanguage::default(),
+            interface: None,
+        }
+    }
+
+    /// Start from `template`
+    pub fn with_template(mut self, template: Template) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Depend on the PDK crate at `path`
+    pub fn with_pdk_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.pdk = PdkSource::Path(path.into());
+        self
+    }
+
+    /// Depend on the PDK from a git repository
+    pub fn with_pdk_git(mut self, url: impl Into<String>) -> Self {
+        self.pdk = PdkSource::Git(url.into());
+        self
+    }
+
+    /// Write the plugin with another PDK
+    pub fn with_language(mut self, language: PluginLanguage) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Give a Go, JavaScript or Python plugin the exports, types,
+    /// capabilities and config keys of `metadata` rather than the
+    /// template's
+    ///
+    /// A Rust crate declares its interface itself with `plugin_metadata!`,
+    /// so only the other languages use it.
+    pub fn with_interface(mut self, metadata: PluginMetadata) -> Self {
+        self.interface = Some(metadata);
+        self
+    }
+
+    /// Name of the crate, the plugin name without its organization
+    pub fn crate_name(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map_or(self.name.as_str(), |(_, name)| name)
+    }
+
+    /// Write the crate to `dir`, which must not exist yet, returning the
+    /// files written
+    pub fn generate(&self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, ScaffoldError> {
+        let dir = dir.as_ref();
+        registry::validate_name(&self.name)
+            .map_err(|_| ScaffoldError::InvalidName(self.name.clone()))?;
+        if dir.exists() {
+            return Err(ScaffoldError::Exists(dir.to_path_buf()));
+        }
+        if self.language != PluginLanguage::Rust {
+            return self.generate_guest(dir);
+        }
+
+        let crate_name = self.crate_name();
+        let quoted = |items: &[String]| {
+            items
+                .iter()
+                .map(|item| format!("{:?}", item))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let pdk = match &self.pdk {
+            PdkSource::Path(path) => format!("extism-hello-plugin = {{ path = {:?} }}", path),
+            PdkSource::Git(url) => format!("extism-hello-plugin = {{ git = {:?} }}", url),
+        };
+        let capabilities: Vec<String> = self
+            .template
+            .capabilities()
+            .iter()
+            .map(Capability::to_string)
+            .collect();
+        let render = |template: &str| {
+            template
+                .replace("{{crate}}", crate_name)
+                .replace("{{plugin}}", &self.name)
+                .replace("{{description}}", self.template.description())
+                .replace("{{pdk}}", &pdk)
+                .replace(
+                    "{{exports}}",
+                    &quoted(&[self.template.export().to_string()]),
+                )
+                .replace("{{capabilities}}", &quoted(&capabilities))
+        };
+
+        let wasm = format!("target/extismx/{}.wasm", crate_name);
+        let manifest = to_json(&self.template.manifest(&wasm))?;
+        let (lib, tests) = self.template.sources();
+        let files = [
+            ("Cargo.toml", render(CARGO_TOML)),
+            ("README.md", render(README)),
+            ("manifest.json", manifest),
+            ("src/lib.rs", render(lib)),
+            ("tests/plugin.rs", render(tests)),
+            (".gitignore", "/target\n".to_string()),
+        ];
+        write_files(dir, files)
+    }
+
+    /// Write a plugin for the Go, JavaScript or Python PDK
+    fn generate_guest(&self, dir: &Path) -> Result<Vec<PathBuf>, ScaffoldError> {
+        let crate_name = self.crate_name();
+        let wasm = format!("{}.wasm", crate_name);
+        let metadata = self.guest_metadata();
+        let codegen = Codegen::from_metadata(&metadata);
+
+        let manifest = match &self.interface {
+            Some(interface) => interface
+                .config
+                .keys()
+                .fold(Manifest::new([Wasm::file(&wasm)]), |manifest, key| {
+                    manifest.with_config_key(key, "")
+                }),
+            None => self.template.manifest(&wasm),
+        };
+        let mut files = vec![
+            ("manifest.json", to_json(&manifest.with_wasi(true))?),
+            ("plugin.json", to_json(&metadata)?),
+            (".gitignore", "*.wasm\n".to_string()),
+        ];
+        let (source, build) = match self.language {
+            PluginLanguage::Go => {
+                let module = self.name.trim_start_matches('@');
+                files.push((
+                    "go.mod",
+                    format!(
+                        "module {}\n\ngo 1.24\n\nrequire github.com/extism/go-pdk v1.1.3\n",
+                        module
+                    ),
+                ));
+                files.push(("main.go", codegen.go_plugin()));
+                (
+                    "main.go",
+                    format!(
+                        "go mod tidy\ntinygo build -target wasip1 -buildmode=c-shared -o {} .",
+                        wasm
+                    ),
+                )
+            }
+            PluginLanguage::JavaScript => {
+                files.push(("src/index.js", codegen.js_plugin()));
+                files.push(("src/index.d.ts", codegen.js_declarations()));
+                (
+                    "src/index.js",
+                    format!("extism-js src/index.js -i src/index.d.ts -o {}", wasm),
+                )
+            }
+            PluginLanguage::Python => {
+                files.push(("plugin/__init__.py", codegen.python_plugin()));
+                (
+                    "plugin/__init__.py",
+                    format!("extism-py plugin/__init__.py -o {}", wasm),
+                )
+            }
+            PluginLanguage::Rust => unreachable!("Rust plugins are generated from templates"),
+        };
+        let export = metadata.exports.first().map_or("", String::as_str);
+        let readme = GUEST_README
+            .replace("{{crate}}", crate_name)
+            .replace("{{plugin}}", &self.name)
+            .replace(
+                "{{description}}",
+                metadata.description.as_deref().unwrap_or_default(),
+            )
+            .replace("{{language}}", self.language.title())
+            .replace("{{source}}", source)
+            .replace("{{build}}", &build)
+            .replace("{{export}}", export);
+        files.push(("README.md", readme));
+        write_files(dir, files)
+    }
+
+    /// The metadata a Go, JavaScript or Python plugin is published with
+    ///
+    /// Without an interface, the template's export takes and returns any
+    /// JSON value.
+    fn guest_metadata(&self) -> PluginMetadata {
+        let mut metadata = match &self.interface {
+            Some(interface) => PluginMetadata {
+                description: interface.description.clone(),
+                exports: interface.exports.clone(),
+                capabilities: interface.capabilities.clone(),
+                config: interface.config.clone(),
+                schemas: interface.schemas.clone(),
+                ..PluginMetadata::new(&self.name, "0.1.0")
+            },
+            None => PluginMetadata {
+                description: Some(self.template.description().to_string()),
+                capabilities: self.template.capabilities(),
+                ..PluginMetadata::new(&self.name, "0.1.0")
+            },
+        };
+        // Exports without schemas take and return any JSON value
+        let mut exports = metadata.exports.clone();
+        if self.interface.is_none() {
+            exports.push(self.template.export().to_string());
+        }
+        for schema in &metadata.schemas {
+            if !exports.contains(&schema.name) {
+                exports.push(schema.name.clone());
+            }
+        }
+        for export in &exports {
+            if !metadata.schemas.iter().any(|schema| &schema.name == export) {
+                metadata.schemas.push(FunctionSchema {
+                    name: export.clone(),
+                    input: serde_json::Value::Bool(true),
+                    output: serde_json::Value::Bool(true),
+                });
+            }
+        }
+        metadata.exports = exports;
+        if !metadata.capabilities.contains(&Capability::Wasi) {
+            metadata.capabilities.push(Capability::Wasi);
+        }
+        metadata.target = Target::Wasm32Wasip1;
+        metadata
+    }
+}
+
+/// Pretty JSON ending with a newline
+fn to_json(value: &impl serde::Serialize) -> Result<String, ScaffoldError> {
+    let mut json = serde_json::to_string_pretty(value)
+        .map_err(|e| ScaffoldError::Io(std::io::Error::other(e)))?;
+    json.push('\n');
+    Ok(json)
+}
+
+/// Write `files` under `dir`, returning their paths
+fn write_files<S: AsRef<str>>(
+    dir: &Path,
+    files: impl IntoIterator<Item = (&'static str, S)>,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mut written = Vec::new();
+    for (file, contents) in files {
+        let path = dir.join(file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, contents.as_ref())?;
+        written.push(path);
+    }
+    Ok(written)
+}
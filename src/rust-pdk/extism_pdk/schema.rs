@@ -0,0 +1,84 @@
+//! JSON Schemas of the inputs and outputs of plugin functions
+//!
+//! A plugin describes its functions with `plugin_metadata!`, naming the
+//! input and output type of each; the types derive `schemars::JsonSchema`:
+//!
+//! ```ignore
+//! #[derive(Deserialize, JsonSchema)]
+//! struct HelloInput {
+//!     name: String,
+//! }
+//!
+//! plugin_metadata! {
+//!     fn hello(HelloInput) -> HelloOutput;
+//! }
+//! ```
+//!
+//! The macro exports `describe`, returning a `PluginSchema`. Hosts read it
+//! with `Plugin::describe_schema()` to validate payloads or render forms,
+//! and `extismx build` publishes it with the plugin's metadata.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub use schemars;
+
+/// Export returning the `PluginSchema` of a plugin
+pub const DESCRIBE_EXPORT: &str = "describe";
+
+/// The JSON Schemas of a plugin function's input and output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionSchema {
+    /// Name of the exported function
+    pub name: String,
+    /// Schema of the JSON input
+    pub input: serde_json::Value,
+    /// Schema of the JSON output
+    pub output: serde_json::Value,
+}
+
+impl FunctionSchema {
+    /// Describe function `name`, taking `I` and returning `O`
+    pub fn new<I: JsonSchema, O: JsonSchema>(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            input: schemars::schema_for!(I).to_value(),
+            output: schemars::schema_for!(O).to_value(),
+        }
+    }
+}
+
+/// The functions a plugin describes, as returned by its `describe` export
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginSchema {
+    /// The described functions
+    pub functions: Vec<FunctionSchema>,
+}
+
+impl PluginSchema {
+    /// Get the schemas of a function
+    pub fn function(&self, name: &str) -> Option<&FunctionSchema> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+/// Export `describe`, returning the JSON Schemas of plugin functions
+///
+/// Each function is listed with its input and output type; use `()` for a
+/// function that takes no input.
+#[macro_export]
+macro_rules! plugin_metadata {
+    ($(fn $name:ident($input:ty) -> $output:ty;)*) => {
+        $crate::export_plugin! {
+            fn describe() -> $crate::extism_pdk::schema::PluginSchema {
+                Ok($crate::extism_pdk::schema::PluginSchema {
+                    functions: vec![$(
+                        $crate::extism_pdk::schema::FunctionSchema::new::<$input, $output>(
+                            stringify!($name),
+                        ),
+                    )*],
+                })
+            }
+        }
+    };
+}
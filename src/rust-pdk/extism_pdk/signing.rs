@@ -0,0 +1,133 @@
+//! Ed25519 signatures over plugin wasm modules
+//!
+//! A publisher signs a module with `sign()` and distributes the detached
+//! `Signature` alongside it. Consumers configure a `TrustPolicy` listing
+//! the public keys they trust; with a policy in place, unsigned modules and
+//! modules whose signature does not verify are refused.
+
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use ed25519_dalek::{Signer, Verifier};
+use serde::{Deserialize, Serialize};
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// A signature could not be verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The trust policy requires a signature but the module has none
+    Unsigned,
+    /// The module was signed with a key the trust policy does not list
+    UntrustedKey(String),
+    /// The signature does not match the module
+    Invalid,
+    /// A key or signature is not valid base64 encoded Ed25519 data
+    Malformed(String),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Unsigned => write!(f, "Plugin is not signed"),
+            SignatureError::UntrustedKey(key) => {
+                write!(f, "Plugin signed by untrusted key {}", key)
+            }
+            SignatureError::Invalid => write!(f, "Plugin signature does not match the module"),
+            SignatureError::Malformed(e) => write!(f, "Malformed key or signature: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+/// A detached signature over a wasm module
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signature {
+    /// Public key of the signer, base64 encoded
+    pub key: String,
+    /// Ed25519 signature of the module bytes, base64 encoded
+    pub signature: String,
+}
+
+impl Signature {
+    /// Check that the signature was made over `wasm` by the key it names
+    pub fn verify(&self, wasm: &[u8]) -> Result<(), SignatureError> {
+        let key = decode_key(&self.key)?;
+        let bytes: [u8; 64] = STANDARD
+            .decode(&self.signature)
+            .map_err(|e| SignatureError::Malformed(e.to_string()))?
+            .try_into()
+            .map_err(|_| SignatureError::Malformed("signature must be 64 bytes".to_string()))?;
+        key.verify(wasm, &ed25519_dalek::Signature::from_bytes(&bytes))
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+/// Generate a new signing key
+pub fn generate_key() -> SigningKey {
+    SigningKey::generate(&mut rand_core::OsRng)
+}
+
+/// Sign a wasm module
+pub fn sign(wasm: &[u8], key: &SigningKey) -> Signature {
+    Signature {
+        key: encode_key(&key.verifying_key()),
+        signature: STANDARD.encode(key.sign(wasm).to_bytes()),
+    }
+}
+
+/// Encode a public key as base64, as used in signatures and trust policies
+pub fn encode_key(key: &VerifyingKey) -> String {
+    STANDARD.encode(key.as_bytes())
+}
+
+/// Decode a base64 encoded public key
+pub fn decode_key(key: &str) -> Result<VerifyingKey, SignatureError> {
+    let bytes: [u8; 32] = STANDARD
+        .decode(key)
+        .map_err(|e| SignatureError::Malformed(e.to_string()))?
+        .try_into()
+        .map_err(|_| SignatureError::Malformed("public key must be 32 bytes".to_string()))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|e| SignatureError::Malformed(e.to_string()))
+}
+
+/// The public keys whose signatures are accepted
+///
+/// An empty policy accepts every module, signed or not.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustPolicy {
+    /// Trusted public keys, base64 encoded
+    pub trusted_keys: Vec<String>,
+}
+
+impl TrustPolicy {
+    /// Create a policy that trusts no keys yet, and so accepts every module
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust signatures made with `key`
+    pub fn with_key(mut self, key: &VerifyingKey) -> Self {
+        self.trusted_keys.push(encode_key(key));
+        self
+    }
+
+    /// Check if the policy requires modules to be signed
+    pub fn is_enforced(&self) -> bool {
+        !self.trusted_keys.is_empty()
+    }
+
+    /// Check a module's signature against the policy
+    pub fn verify(&self, wasm: &[u8], signature: Option<&Signature>) -> Result<(), SignatureError> {
+        if !self.is_enforced() {
+            return Ok(());
+        }
+        let signature = signature.ok_or(SignatureError::Unsigned)?;
+        if !self.trusted_keys.contains(&signature.key) {
+            return Err(SignatureError::UntrustedKey(signature.key.clone()));
+        }
+        signature.verify(wasm)
+    }
+}
@@ -0,0 +1,550 @@
+//! Code size analysis of plugin modules
+//!
+//! A `SizeReport` breaks a core module down by section, by function and by
+//! the crate each function comes from, using the function names of the
+//! module's `name` section, and flags code known to bloat plugins, such as
+//! panic formatting and serde's generated visitors. Wasm size decides how
+//! long a plugin takes to download and compile, so it is worth tracking
+//! between releases against a `SizeBaseline`:
+//!
+//! ```ignore
+//! let report = SizeReport::analyze(&std::fs::read("resize.wasm")?)?;
+//! println!("{}", report);
+//! for delta in report.compare(&baseline) {
+//!     println!("{}", delta);
+//! }
+//! ```
+//!
+//! `extismx build` strips the `name` section, so functions are only named
+//! in modules built with `--no-strip` or straight from cargo.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+/// A module could not be analyzed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SizeError {
+    /// The module is not wasm, or is truncated
+    Invalid(String),
+    /// The module is a component rather than a core module
+    Component,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SizeError::Invalid(message) => write!(f, "Invalid wasm module: {}", message),
+            SizeError::Component => write!(f, "Components cannot be analyzed, only core modules"),
+        }
+    }
+}
+
+impl std::error::Error for SizeError {}
+
+/// The size of a section, including its header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionSize {
+    /// Kind of the section, such as `code`, or `custom:<name>`
+    pub name: String,
+    pub size: usize,
+}
+
+/// The size of a function's body
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionSize {
+    /// Demangled name, without the hash, or `[unnamed]`
+    pub name: String,
+    /// Crate the function comes from, `[unknown]` for functions without a
+    /// Rust path, such as exports and C functions
+    pub crate_name: String,
+    pub size: usize,
+}
+
+/// Code from a known source of bloat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bloat {
+    /// What the code is
+    pub source: &'static str,
+    /// Bytes of code or sections it takes
+    pub size: usize,
+    /// How to shrink it
+    pub hint: &'static str,
+}
+
+/// Where the bytes of a module go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Size of the whole module
+    pub total: usize,
+    /// Sections, largest first
+    pub sections: Vec<SectionSize>,
+    /// Function bodies, largest first
+    pub functions: Vec<FunctionSize>,
+    /// Code size of each crate, largest first
+    pub crates: Vec<(String, usize)>,
+    /// Known sources of bloat found in the module, largest first
+    pub bloat: Vec<Bloat>,
+    /// Whether the module names its functions
+    pub named: bool,
+}
+
+/// Sizes recorded to compare later builds with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SizeBaseline {
+    pub total: usize,
+    #[serde(default)]
+    pub sections: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub crates: BTreeMap<String, usize>,
+}
+
+/// A size that changed since the baseline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeDelta {
+    /// `total`, `section <name>` or `crate <name>`
+    pub name: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl SizeDelta {
+    /// Bytes gained, negative when the size shrank
+    pub fn change(&self) -> i64 {
+        self.after as i64 - self.before as i64
+    }
+}
+
+impl fmt::Display for SizeDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} -> {} ({:+}",
+            self.name,
+            bytes(self.before),
+            bytes(self.after),
+            self.change()
+        )?;
+        if self.before > 0 {
+            write!(
+                f,
+                " bytes, {:+.1}%)",
+                self.change() as f64 * 100.0 / self.before as f64
+            )
+        } else {
+            write!(f, " bytes)")
+        }
+    }
+}
+
+/// Known sources of bloat, by the crates or paths of their functions
+const BLOAT: &[(&str, &[&str], &str)] = &[
+    (
+        "panic and formatting machinery",
+        &[
+            "core::panicking",
+            "std::panicking",
+            "core::fmt",
+            "alloc::fmt",
+            "core::result::unwrap_failed",
+            "core::option::expect_failed",
+        ],
+        "return errors rather than unwrap or expect, and build with panic = \"abort\"",
+    ),
+    (
+        "serde derives and serde_json",
+        &[
+            "serde::",
+            "serde_json::",
+            "__FieldVisitor",
+            "__Visitor",
+            "_serde::",
+        ],
+        "each derived type adds visitors; share types, and skip unneeded derives",
+    ),
+    (
+        "memory allocator",
+        &["dlmalloc::"],
+        "a bump allocator is smaller, for plugins that allocate little",
+    ),
+];
+
+impl SizeReport {
+    /// Analyze a core module
+    pub fn analyze(wasm: &[u8]) -> Result<Self, SizeError> {
+        let truncated = || SizeError::Invalid("truncated module".to_string());
+        let header = wasm.get(..8).ok_or_else(truncated)?;
+        if &header[..4] != b"\0asm" {
+            return Err(SizeError::Invalid("missing wasm magic number".to_string()));
+        }
+        if header[6..8] != [0, 0] {
+            return Err(SizeError::Component);
+        }
+
+        let mut reader = Reader { data: &wasm[8..] };
+        let mut sections = BTreeMap::<String, usize>::new();
+        let mut imported_functions = 0u32;
+        let mut bodies = Vec::new();
+        let mut names = BTreeMap::new();
+        let mut debug = 0;
+        while !reader.data.is_empty() {
+            let start = reader.data.len();
+            let id = reader.byte().ok_or_else(truncated)?;
+            let len = reader.leb().ok_or_else(truncated)? as usize;
+            let mut section = Reader {
+                data: reader.take(len).ok_or_else(truncated)?,
+            };
+            let size = start - reader.data.len();
+            let name = match id {
+                0 => {
+                    let len = section.leb().ok_or_else(truncated)? as usize;
+                    let name = String::from_utf8_lossy(section.take(len).ok_or_else(truncated)?)
+                        .into_owned();
+                    if name == "name" {
+                        names = function_names(section).ok_or_else(truncated)?;
+                    } else if name.starts_with(".debug") {
+                        debug += size;
+                    }
+                    format!("custom:{}", name)
+                }
+                2 => {
+                    imported_functions = count_imported_functions(section).ok_or_else(truncated)?;
+                    "import".to_string()
+                }
+                10 => {
+                    for _ in 0..section.leb().ok_or_else(truncated)? {
+                        let len = section.leb().ok_or_else(truncated)? as usize;
+                        section.take(len).ok_or_else(truncated)?;
+                        bodies.push(len);
+                    }
+                    "code".to_string()
+                }
+                id => SECTIONS
+                    .get(id as usize)
+                    .map_or_else(|| format!("unknown:{}", id), |name| name.to_string()),
+            };
+            *sections.entry(name).or_default() += size;
+        }
+
+        let mut crates = BTreeMap::<String, usize>::new();
+        let mut bloat = vec![0; BLOAT.len()];
+        let mut functions: Vec<FunctionSize> = bodies
+            .into_iter()
+            .enumerate()
+            .map(|(i, size)| {
+                let index = imported_functions + i as u32;
+                let (name, crate_name) = match names.get(&index) {
+                    Some(name) => {
+                        let name = format!("{:#}", rustc_demangle::demangle(name));
+                        let crate_name = crate_of(&name);
+                        (name, crate_name)
+                    }
+                    None => ("[unnamed]".to_string(), "[unknown]".to_string()),
+                };
+                *crates.entry(crate_name.clone()).or_default() += size;
+                if let Some(i) = BLOAT
+                    .iter()
+                    .position(|(_, patterns, _)| patterns.iter().any(|p| name.contains(p)))
+                {
+                    bloat[i] += size;
+                }
+                FunctionSize {
+                    name,
+                    crate_name,
+                    size,
+                }
+            })
+            .collect();
+        functions.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+
+        let mut bloat: Vec<Bloat> = BLOAT
+            .iter()
+            .zip(bloat)
+            .filter(|(_, size)| *size > 0)
+            .map(|((source, _, hint), size)| Bloat { source, size, hint })
+            .collect();
+        if debug > 0 {
+            bloat.push(Bloat {
+                source: "DWARF debug info",
+                size: debug,
+                hint: "build without debug info, or strip it with extismx build",
+            });
+        }
+        bloat.sort_by_key(|bloat| Reverse(bloat.size));
+
+        let mut sections: Vec<SectionSize> = sections
+            .into_iter()
+            .map(|(name, size)| SectionSize { name, size })
+            .collect();
+        sections.sort_by_key(|section| Reverse(section.size));
+        let mut crates: Vec<(String, usize)> = crates.into_iter().collect();
+        crates.sort_by_key(|(_, size)| Reverse(*size));
+        Ok(Self {
+            total: wasm.len(),
+            sections,
+            functions,
+            crates,
+            bloat,
+            named: !names.is_empty(),
+        })
+    }
+
+    /// The sizes to compare later builds with
+    pub fn baseline(&self) -> SizeBaseline {
+        SizeBaseline {
+            total: self.total,
+            sections: self
+                .sections
+                .iter()
+                .map(|section| (section.name.clone(), section.size))
+                .collect(),
+            crates: self.crates.iter().cloned().collect(),
+        }
+    }
+
+    /// The sizes that changed since `baseline`: the total first, then
+    /// sections and crates, largest change first
+    pub fn compare(&self, baseline: &SizeBaseline) -> Vec<SizeDelta> {
+        let current = self.baseline();
+        let mut deltas = Vec::new();
+        for (kind, before, after) in [
+            ("section", &baseline.sections, &current.sections),
+            ("crate", &baseline.crates, &current.crates),
+        ] {
+            for name in before.keys().chain(after.keys()) {
+                let delta = SizeDelta {
+                    name: format!("{} {}", kind, name),
+                    before: before.get(name).copied().unwrap_or_default(),
+                    after: after.get(name).copied().unwrap_or_default(),
+                };
+                if delta.change() != 0 && !deltas.contains(&delta) {
+                    deltas.push(delta);
+                }
+            }
+        }
+        deltas.sort_by_key(|delta| Reverse(delta.change().unsigned_abs()));
+        deltas.insert(
+            0,
+            SizeDelta {
+                name: "total".to_string(),
+                before: baseline.total,
+                after: self.total,
+            },
+        );
+        deltas
+    }
+
+    /// Render the report, listing the `top` largest functions and crates
+    pub fn render(&self, top: usize) -> String {
+        let share = |size: usize| size as f64 * 100.0 / self.total.max(1) as f64;
+        let mut out = format!("Total: {}\n\nSections:\n", bytes(self.total));
+        for section in &self.sections {
+            let _ = writeln!(
+                out,
+                "  {:>10} {:>5.1}%  {}",
+                bytes(section.size),
+                share(section.size),
+                section.name
+            );
+        }
+        if !self.named {
+            out.push_str(
+                "\nThe module has no name section, so its functions cannot be attributed;\n\
+                 analyze a build made with `extismx build --no-strip`.\n",
+            );
+        } else {
+            out.push_str("\nCrates:\n");
+            for (name, size) in self.crates.iter().take(top) {
+                let _ = writeln!(
+                    out,
+                    "  {:>10} {:>5.1}%  {}",
+                    bytes(*size),
+                    share(*size),
+                    name
+                );
+            }
+            out.push_str("\nFunctions:\n");
+            for function in self.functions.iter().take(top) {
+                let _ = writeln!(
+                    out,
+                    "  {:>10} {:>5.1}%  {}",
+                    bytes(function.size),
+                    share(function.size),
+                    function.name
+                );
+            }
+        }
+        if !self.bloat.is_empty() {
+            out.push_str("\nKnown bloat:\n");
+            for bloat in &self.bloat {
+                let _ = writeln!(
+                    out,
+                    "  {:>10} {:>5.1}%  {}: {}",
+                    bytes(bloat.size),
+                    share(bloat.size),
+                    bloat.source,
+                    bloat.hint
+                );
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(10))
+    }
+}
+
+/// Names of the known sections, by id
+const SECTIONS: &[&str] = &[
+    "custom",
+    "type",
+    "import",
+    "function",
+    "table",
+    "memory",
+    "global",
+    "export",
+    "start",
+    "element",
+    "code",
+    "data",
+    "datacount",
+    "tag",
+];
+
+/// The crate a demangled function path comes from
+///
+/// `<serde_json::Value as core::fmt::Debug>::fmt` comes from serde_json,
+/// and `<char as core::fmt::Debug>::fmt`, implemented for a primitive, from
+/// core.
+fn crate_of(name: &str) -> String {
+    let path = name.trim_start_matches(['<', '&', '[', '(', '*']);
+    let path = ["mut ", "const ", "dyn "]
+        .iter()
+        .fold(path, |path, prefix| {
+            path.strip_prefix(prefix).unwrap_or(path)
+        });
+    let end = path
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(path.len());
+    if end > 0 && path[end..].starts_with("::") {
+        return path[..end].to_string();
+    }
+    match path.split_once(" as ") {
+        Some((_, the_trait)) => crate_of(the_trait),
+        None => "[unknown]".to_string(),
+    }
+}
+
+/// Count the functions a module imports, which come before its own in the
+/// function index space
+fn count_imported_functions(mut section: Reader<'_>) -> Option<u32> {
+    let mut functions = 0;
+    for _ in 0..section.leb()? {
+        for _ in 0..2 {
+            let len = section.leb()? as usize;
+            section.take(len)?;
+        }
+        match section.byte()? {
+            0 => {
+                section.leb()?;
+                functions += 1;
+            }
+            1 => {
+                section.byte()?;
+                section.limits()?;
+            }
+            2 => section.limits()?,
+            3 => {
+                section.take(2)?;
+            }
+            4 => {
+                section.byte()?;
+                section.leb()?;
+            }
+            _ => return None,
+        }
+    }
+    Some(functions)
+}
+
+/// Read the function names subsection of a `name` section
+fn function_names(mut section: Reader<'_>) -> Option<BTreeMap<u32, String>> {
+    let mut names = BTreeMap::new();
+    while !section.data.is_empty() {
+        let id = section.byte()?;
+        let len = section.leb()? as usize;
+        let mut subsection = Reader {
+            data: section.take(len)?,
+        };
+        if id != 1 {
+            continue;
+        }
+        for _ in 0..subsection.leb()? {
+            let index = subsection.leb()? as u32;
+            let len = subsection.leb()? as usize;
+            let name = String::from_utf8_lossy(subsection.take(len)?).into_owned();
+            names.insert(index, name);
+        }
+    }
+    Some(names)
+}
+
+/// A size in bytes, KiB or MiB
+fn bytes(size: usize) -> String {
+    match size {
+        0..=1023 => format!("{} B", size),
+        1024..=1_048_575 => format!("{:.1} KiB", size as f64 / 1024.0),
+        _ => format!("{:.2} MiB", size as f64 / 1_048_576.0),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (byte, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*byte)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(head)
+    }
+
+    /// Read an unsigned LEB128 integer of up to 64 bits
+    fn leb(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..70).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Skip the limits of a table or memory
+    fn limits(&mut self) -> Option<()> {
+        let flags = self.byte()?;
+        self.leb()?;
+        if flags & 1 != 0 {
+            self.leb()?;
+        }
+        Some(())
+    }
+}
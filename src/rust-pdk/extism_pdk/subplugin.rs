@@ -0,0 +1,66 @@
+//! Calling other plugins through the host
+//!
+//! Enabled with the `subplugin` feature. An orchestrator plugin calls the
+//! plugins its host registered for it by name, each running in its own
+//! instance under the host's limits:
+//!
+//! ```ignore
+//! export_plugin! {
+//!     fn thumbnail() -> Vec<u8> {
+//!         let image = Host::input();
+//!         let resized = subplugin::call("resize", "run", &image)?;
+//!         Ok(subplugin::call("compress", "run", &resized)?)
+//!     }
+//! }
+//! ```
+//!
+//! A sub-plugin shares nothing with its caller but the input and output of
+//! the call. It runs within what is left of the caller's timeout and fuel,
+//! and is cancelled with it. Its errors reach the caller as it set them;
+//! calls to plugins or functions the host does not provide fail with an
+//! `ErrorKind::Invalid` error.
+
+use super::{Error, Memory};
+
+/// Call `function` of the sub-plugin `plugin` with `input`
+pub fn call(plugin: &str, function: &str, input: impl AsRef<[u8]>) -> Result<Vec<u8>, Error> {
+    let input = input.as_ref();
+    let mut out: u64 = 0;
+    let rc = unsafe {
+        super::extism_plugin_call(
+            plugin.as_ptr(),
+            plugin.len() as u64,
+            function.as_ptr(),
+            function.len() as u64,
+            input.as_ptr(),
+            input.len() as u64,
+            &mut out as *mut u64,
+        )
+    };
+    let data = match out {
+        0 => Vec::new(),
+        offset => Memory { offset, length: 0 }.load_all(),
+    };
+    if rc == 0 {
+        return Ok(data);
+    }
+    Err(serde_json::from_slice(&data)
+        .unwrap_or_else(|_| Error::fatal(format!("Call to {}.{} failed", plugin, function))))
+}
+
+/// Call a sub-plugin function with JSON input, parsing the JSON output
+pub fn call_json<I, O>(plugin: &str, function: &str, input: &I) -> Result<O, Error>
+where
+    I: serde::Serialize,
+    O: serde::de::DeserializeOwned,
+{
+    let input = serde_json::to_vec(input)
+        .map_err(|e| Error::invalid(format!("Failed to serialize input: {}", e)))?;
+    let output = call(plugin, function, input)?;
+    serde_json::from_slice(&output).map_err(|e| {
+        Error::invalid(format!(
+            "Failed to parse output of {}.{}: {}",
+            plugin, function, e
+        ))
+    })
+}
@@ -0,0 +1,378 @@
+//! Unit testing plugins natively, against a mock host
+//!
+//! With the `testing` feature, builds for targets other than wasm replace
+//! the kernel functions plugins import with an in-process `MockHost`, so
+//! exports and the functions behind them run under plain `cargo test`:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! extism-hello-plugin = { path = "..", features = ["testing"] }
+//! ```
+//!
+//! ```ignore
+//! #[test]
+//! fn greets() {
+//!     let host = MockHost::new().with_config("greeting", "Hi");
+//!     let output: HelloOutput = host.call_json(hello, &json!({ "name": "Ada" })).unwrap();
+//!     assert_eq!(output.message, "Hi, Ada!");
+//!     assert_eq!(host.logs()[0].message, "greeting Ada");
+//! }
+//! ```
+//!
+//! Each test thread has its own host, which `MockHost::new` resets.
+//! `assert_call_snapshot!` compares the outputs of calls with golden files,
+//! and `MockHost::replay` re-runs calls the host runtime recorded.
+//! With the `fuzz` feature, `fuzz` turns exports into `cargo fuzz` targets.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::marker::PhantomData;
+
+#[cfg(feature = "arrow")]
+use super::arrow::{self, RecordBatch};
+#[cfg(feature = "audit")]
+use super::audit::{self, AuditRecord};
+use super::trace::HostCall;
+use super::Error;
+
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub(crate) mod kernel;
+mod replay;
+mod snapshot;
+
+pub use crate::assert_call_snapshot;
+pub use snapshot::{assert_snapshot, UPDATE_ENV};
+
+/// Severity of a log message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A message the plugin logged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// An HTTP request the plugin made
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockRequest {
+    pub method: String,
+    pub url: String,
+    /// Headers, by name as the plugin set them
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// A sub-plugin call the plugin made
+#[cfg(feature = "subplugin")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockPluginCall {
+    pub plugin: String,
+    pub function: String,
+    pub input: Vec<u8>,
+}
+
+/// The response the mock host returns for a URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    /// Respond with `status` and an empty body
+    pub fn new(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Add a response header
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Respond with `body`
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+}
+
+/// Everything the mock host holds for the current thread
+#[derive(Default)]
+struct State {
+    /// Inputs of upcoming calls; the first is the current input
+    inputs: VecDeque<Vec<u8>>,
+    config: BTreeMap<String, String>,
+    vars: BTreeMap<String, Vec<u8>>,
+    output: Option<Vec<u8>>,
+    error: Option<Vec<u8>>,
+    logs: Vec<LogEntry>,
+    /// Memory blocks by offset
+    memory: BTreeMap<u64, Vec<u8>>,
+    next_offset: u64,
+    /// Memory blocks allocated since the host was reset
+    allocations: u64,
+    responses: BTreeMap<String, MockResponse>,
+    requests: Vec<MockRequest>,
+    /// Status of each response handed to the plugin, by offset
+    statuses: BTreeMap<u64, u16>,
+    /// Results of sub-plugin functions, by plugin and function name
+    #[cfg(feature = "subplugin")]
+    subplugins: BTreeMap<(String, String), Result<Vec<u8>, Error>>,
+    #[cfg(feature = "subplugin")]
+    plugin_calls: Vec<MockPluginCall>,
+    /// The clock, in milliseconds since the Unix epoch
+    #[cfg(feature = "time")]
+    now_ms: u64,
+    /// Recorded HTTP exchanges, sub-plugin calls and clock reads left to
+    /// replay, answering them in place of `responses`, `subplugins` and
+    /// `now_ms`
+    replay: Option<VecDeque<HostCall>>,
+}
+
+impl State {
+    fn alloc(&mut self, data: Vec<u8>) -> u64 {
+        self.next_offset += 1;
+        self.allocations += 1;
+        self.memory.insert(self.next_offset, data);
+        self.next_offset
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn with_state<T>(f: impl FnOnce(&mut State) -> T) -> T {
+    STATE.with(|state| f(&mut state.borrow_mut()))
+}
+
+/// The host plugin code on the current thread runs against
+///
+/// A `MockHost` is a handle to the thread's host, so it cannot be sent to
+/// other threads.
+#[derive(Debug)]
+pub struct MockHost {
+    _thread: PhantomData<*const ()>,
+}
+
+impl MockHost {
+    /// Reset the current thread's host, clearing its inputs, config, vars,
+    /// output, logs, HTTP responses and sub-plugins
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        with_state(|state| *state = State::default());
+        Self {
+            _thread: PhantomData,
+        }
+    }
+
+    /// A handle to the current thread's host, without resetting it
+    pub fn current() -> Self {
+        Self {
+            _thread: PhantomData,
+        }
+    }
+
+    /// Set config key `key`
+    pub fn with_config(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        with_state(|state| state.config.insert(key.into(), value.into()));
+        self
+    }
+
+    /// Set var `name`
+    pub fn with_var(self, name: impl Into<String>, value: impl Into<Vec<u8>>) -> Self {
+        with_state(|state| state.vars.insert(name.into(), value.into()));
+        self
+    }
+
+    /// Queue an input, read by `Host::input` once the inputs queued before
+    /// it are consumed
+    pub fn with_input(self, input: impl Into<Vec<u8>>) -> Self {
+        with_state(|state| state.inputs.push_back(input.into()));
+        self
+    }
+
+    /// Respond to requests for `url` with `response`
+    pub fn with_http_response(self, url: impl Into<String>, response: MockResponse) -> Self {
+        with_state(|state| state.responses.insert(url.into(), response));
+        self
+    }
+
+    /// Set the clock `Host::now_ms` reads, which starts at 0 and only
+    /// moves when the test moves it
+    #[cfg(feature = "time")]
+    pub fn with_time_ms(self, ms: u64) -> Self {
+        with_state(|state| state.now_ms = ms);
+        self
+    }
+
+    /// Move the clock forward by `ms` milliseconds
+    #[cfg(feature = "time")]
+    pub fn advance_time_ms(&self, ms: u64) {
+        with_state(|state| state.now_ms += ms);
+    }
+
+    /// Answer sub-plugin calls to `plugin`'s `function` with `output`
+    #[cfg(feature = "subplugin")]
+    pub fn with_subplugin_output(
+        self,
+        plugin: impl Into<String>,
+        function: impl Into<String>,
+        output: impl Into<Vec<u8>>,
+    ) -> Self {
+        let key = (plugin.into(), function.into());
+        with_state(|state| state.subplugins.insert(key, Ok(output.into())));
+        self
+    }
+
+    /// Fail sub-plugin calls to `plugin`'s `function` with `error`
+    #[cfg(feature = "subplugin")]
+    pub fn with_subplugin_error(
+        self,
+        plugin: impl Into<String>,
+        function: impl Into<String>,
+        error: Error,
+    ) -> Self {
+        let key = (plugin.into(), function.into());
+        with_state(|state| state.subplugins.insert(key, Err(error)));
+        self
+    }
+
+    /// Drop the current input, making the next queued input current
+    pub fn next_input(&self) -> Option<Vec<u8>> {
+        with_state(|state| state.inputs.pop_front())
+    }
+
+    /// Call an export with `input`, returning its output or the error it
+    /// set
+    ///
+    /// Inputs queued with `with_input` are read after this call.
+    pub fn call(
+        &self,
+        export: extern "C" fn() -> i32,
+        input: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, Error> {
+        with_state(|state| {
+            state.inputs.push_front(input.as_ref().to_vec());
+            state.output = None;
+            state.error = None;
+        });
+        let rc = export();
+        with_state(|state| {
+            state.inputs.pop_front();
+            if rc == 0 {
+                return Ok(state.output.take().unwrap_or_default());
+            }
+            let error = state.error.take().unwrap_or_default();
+            Err(serde_json::from_slice(&error)
+                .unwrap_or_else(|_| Error::fatal(String::from_utf8_lossy(&error).into_owned())))
+        })
+    }
+
+    /// Call an export with JSON input, parsing the JSON output
+    pub fn call_json<I, O>(&self, export: extern "C" fn() -> i32, input: &I) -> Result<O, Error>
+    where
+        I: serde::Serialize,
+        O: serde::de::DeserializeOwned,
+    {
+        let input = serde_json::to_vec(input)
+            .map_err(|e| Error::invalid(format!("Failed to serialize input: {}", e)))?;
+        let output = self.call(export, input)?;
+        serde_json::from_slice(&output)
+            .map_err(|e| Error::invalid(format!("Failed to parse output: {}", e)))
+    }
+
+    /// Call an export with a protobuf message, decoding the protobuf output
+    #[cfg(feature = "prost")]
+    pub fn call_proto<I, O>(&self, export: extern "C" fn() -> i32, input: &I) -> Result<O, Error>
+    where
+        I: prost::Message,
+        O: prost::Message + Default,
+    {
+        let output = self.call(export, input.encode_to_vec())?;
+        O::decode(output.as_slice())
+            .map_err(|e| Error::invalid(format!("Failed to decode output: {}", e)))
+    }
+
+    /// Call an export with record batches, reading the record batches of
+    /// its output
+    #[cfg(feature = "arrow")]
+    pub fn call_arrow(
+        &self,
+        export: extern "C" fn() -> i32,
+        input: &[RecordBatch],
+    ) -> Result<Vec<RecordBatch>, Error> {
+        let input = arrow::write_stream(input)
+            .map_err(|e| Error::invalid(format!("Failed to write input: {}", e)))?;
+        let output = self.call(export, input)?;
+        arrow::read_stream(&output)
+            .map_err(|e| Error::invalid(format!("Failed to read output: {}", e)))
+    }
+
+    /// The output last set with `Host::output`
+    pub fn output(&self) -> Option<Vec<u8>> {
+        with_state(|state| state.output.clone())
+    }
+
+    /// The error last set with `Host::error`
+    pub fn error(&self) -> Option<Vec<u8>> {
+        with_state(|state| state.error.clone())
+    }
+
+    /// Get var `name`
+    pub fn var(&self, name: &str) -> Option<Vec<u8>> {
+        with_state(|state| state.vars.get(name).cloned())
+    }
+
+    /// The messages logged so far
+    pub fn logs(&self) -> Vec<LogEntry> {
+        with_state(|state| state.logs.clone())
+    }
+
+    /// The audit records made since the host was reset
+    ///
+    /// The mock host keeps every record in the `audit:records` var, where
+    /// the host runtime takes them out after each call.
+    #[cfg(feature = "audit")]
+    pub fn audit_records(&self) -> Vec<AuditRecord> {
+        self.var(audit::RECORDS_VAR)
+            .and_then(|records| audit::parse_records(&records).ok())
+            .unwrap_or_default()
+    }
+
+    /// The HTTP requests made so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        with_state(|state| state.requests.clone())
+    }
+
+    /// The sub-plugin calls made so far
+    #[cfg(feature = "subplugin")]
+    pub fn plugin_calls(&self) -> Vec<MockPluginCall> {
+        with_state(|state| state.plugin_calls.clone())
+    }
+
+    /// Number of memory blocks allocated and not freed, to catch leaks
+    pub fn allocated(&self) -> usize {
+        with_state(|state| state.memory.len())
+    }
+
+    /// Number of memory blocks allocated since the host was reset, freed or
+    /// not
+    pub fn allocations(&self) -> u64 {
+        with_state(|state| state.allocations)
+    }
+}
@@ -0,0 +1,77 @@
+//! Fuzz targets of plugin exports
+//!
+//! With the `fuzz` feature, exports can be fuzzed natively with
+//! `cargo fuzz`, under the mock host. Exports catch panics and report them
+//! as errors, so these helpers turn them back into panics for the fuzzer to
+//! find, along with outputs that cannot be serialized:
+//!
+//! ```ignore
+//! // fuzz/fuzz_targets/hello.rs
+//! #![no_main]
+//! use extism_hello_plugin::extism_pdk::testing::{fuzz, MockHost};
+//! use hello::{hello, HelloInput};
+//!
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     fuzz::bytes(MockHost::new(), hello, data);
+//!     fuzz::structured::<HelloInput>(MockHost::new(), hello, data);
+//! });
+//! ```
+//!
+//! Arbitrary bytes exercise parsing the input, while structured inputs,
+//! generated with `arbitrary` and passed as JSON, reach the code behind it.
+
+use arbitrary::{Arbitrary, Unstructured};
+use serde::Serialize;
+
+use super::MockHost;
+use crate::extism_pdk::ErrorKind;
+
+pub use arbitrary;
+
+/// Call `export` with `data` as its input, panicking when it panics
+///
+/// Each call should get a new host, so that state from earlier inputs does
+/// not build up.
+pub fn bytes(host: MockHost, export: extern "C" fn() -> i32, data: &[u8]) {
+    check(&host, export, data);
+}
+
+/// Call `export` with a `T` generated from `data` as its JSON input,
+/// panicking when it panics
+///
+/// Data too short to generate a `T` from is skipped.
+pub fn structured<'a, T>(host: MockHost, export: extern "C" fn() -> i32, data: &'a [u8])
+where
+    T: Arbitrary<'a> + Serialize,
+{
+    if let Ok(input) = T::arbitrary_take_rest(Unstructured::new(data)) {
+        json(host, export, &input);
+    }
+}
+
+/// Call `export` with `input` as JSON, panicking when it panics
+///
+/// For targets taking typed inputs, such as
+/// `fuzz_target!(|input: HelloInput| fuzz::json(MockHost::new(), hello, &input))`.
+pub fn json<T: Serialize>(host: MockHost, export: extern "C" fn() -> i32, input: &T) {
+    match serde_json::to_vec(input) {
+        Ok(input) => check(&host, export, &input),
+        Err(e) => panic!("failed to serialize input: {}", e),
+    }
+}
+
+fn check(host: &MockHost, export: extern "C" fn() -> i32, input: &[u8]) {
+    let Err(error) = host.call(export, input) else {
+        return;
+    };
+    if error.kind == ErrorKind::Fatal
+        && (error.message.starts_with("Plugin panicked: ")
+            || error.message.starts_with("Failed to serialize output: "))
+    {
+        panic!(
+            "{} with input {:?}",
+            error.message,
+            String::from_utf8_lossy(input)
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! The kernel functions plugins import, implemented by the mock host
+//!
+//! They mirror the host's kernel, including how HTTP requests and
+//! responses are passed in `request:*` and `response:*` vars.
+
+use std::ptr;
+use std::slice;
+
+use super::with_state;
+#[cfg(feature = "subplugin")]
+use super::MockPluginCall;
+#[cfg(any(feature = "http", feature = "subplugin"))]
+use super::State;
+#[cfg(feature = "log")]
+use super::{LogEntry, LogLevel};
+#[cfg(feature = "http")]
+use super::{MockRequest, MockResponse};
+#[cfg(any(feature = "http", feature = "subplugin", feature = "time"))]
+use crate::extism_pdk::trace::HostCall;
+#[cfg(feature = "var-batch")]
+use crate::extism_pdk::var;
+#[cfg(any(feature = "http", feature = "subplugin"))]
+use crate::extism_pdk::Error;
+
+unsafe fn bytes<'a>(data: *const u8, len: u64) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    slice::from_raw_parts(data, len as usize)
+}
+
+#[cfg(any(
+    feature = "config",
+    feature = "vars",
+    feature = "log",
+    feature = "subplugin"
+))]
+unsafe fn string(data: *const u8, len: u64) -> String {
+    String::from_utf8_lossy(bytes(data, len)).into_owned()
+}
+
+/// Copy `len` bytes of `data` from `offset` to `buf`
+unsafe fn copy_out(data: &[u8], offset: u64, len: u64, buf: *mut u8) {
+    let start = (offset as usize).min(data.len());
+    let end = start.saturating_add(len as usize).min(data.len());
+    ptr::copy_nonoverlapping(data[start..end].as_ptr(), buf, end - start);
+}
+
+pub(crate) unsafe fn extism_input_length() -> u64 {
+    with_state(|state| state.inputs.front().map_or(0, |input| input.len() as u64))
+}
+
+pub(crate) unsafe fn extism_input_load_u8(offset: u64, len: u64, buf: *mut u8) {
+    with_state(|state| {
+        if let Some(input) = state.inputs.front() {
+            copy_out(input, offset, len, buf);
+        }
+    })
+}
+
+pub(crate) unsafe fn extism_output_set(data: *const u8, len: u64) {
+    let output = bytes(data, len).to_vec();
+    with_state(|state| state.output = Some(output));
+}
+
+pub(crate) unsafe fn extism_error_set(data: *const u8, len: u64) {
+    let error = bytes(data, len).to_vec();
+    with_state(|state| state.error = Some(error));
+}
+
+pub(crate) unsafe fn extism_alloc(n: u64) -> u64 {
+    with_state(|state| state.alloc(vec![0; n as usize]))
+}
+
+pub(crate) unsafe fn extism_free(pointer: u64) {
+    with_state(|state| {
+        state.memory.remove(&pointer);
+        state.statuses.remove(&pointer);
+    })
+}
+
+pub(crate) unsafe fn extism_length(pointer: u64) -> u64 {
+    with_state(|state| {
+        state
+            .memory
+            .get(&pointer)
+            .map_or(0, |block| block.len() as u64)
+    })
+}
+
+pub(crate) unsafe fn extism_store_u8(pointer: u64, offset: u64, buf: *const u8, len: u64) {
+    let data = bytes(buf, len);
+    with_state(|state| {
+        if let Some(block) = state.memory.get_mut(&pointer) {
+            let start = (offset as usize).min(block.len());
+            let end = start.saturating_add(data.len()).min(block.len());
+            block[start..end].copy_from_slice(&data[..end - start]);
+        }
+    })
+}
+
+pub(crate) unsafe fn extism_load_u8(pointer: u64, offset: u64, len: u64, buf: *mut u8) {
+    with_state(|state| {
+        if let Some(block) = state.memory.get(&pointer) {
+            copy_out(block, offset, len, buf);
+        }
+    })
+}
+
+#[cfg(feature = "http")]
+pub(crate) unsafe fn extism_http_request(_req: u64, out: *mut u64) -> i32 {
+    with_state(|state| {
+        // The request vars are consumed, and old response vars dropped
+        let mut request = MockRequest {
+            method: "GET".to_string(),
+            ..MockRequest::default()
+        };
+        let keys: Vec<String> = state
+            .vars
+            .keys()
+            .filter(|key| key.starts_with("request:") || key.starts_with("response:"))
+            .cloned()
+            .collect();
+        for key in keys {
+            let value = state.vars.remove(&key).unwrap_or_default();
+            let text = || String::from_utf8_lossy(&value).into_owned();
+            match key.strip_prefix("request:") {
+                Some("method") => request.method = text(),
+                Some("url") => request.url = text(),
+                Some("body") => request.body = Some(value.clone()),
+                Some(name) => {
+                    if let Some(header) = name.strip_prefix("header:") {
+                        request.headers.insert(header.to_string(), text());
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let response = respond(state, &request);
+        state.requests.push(request);
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => {
+                let error = serde_json::to_vec(&error).unwrap_or_default();
+                state.vars.insert("response:error".to_string(), error);
+                return 1;
+            }
+        };
+        for (name, value) in response.headers {
+            state.vars.insert(
+                format!("response:header:{}", name.to_ascii_lowercase()),
+                value.into_bytes(),
+            );
+        }
+        state
+            .vars
+            .insert("response:body".to_string(), response.body);
+        let handle = state.alloc(Vec::new());
+        state.statuses.insert(handle, response.status);
+        *out = handle;
+        0
+    })
+}
+
+/// The response to a request, from the trace being replayed or the mock
+/// responses
+#[cfg(feature = "http")]
+fn respond(state: &mut State, request: &MockRequest) -> Result<MockResponse, Error> {
+    let Some(replay) = &mut state.replay else {
+        return state.responses.get(&request.url).cloned().ok_or_else(|| {
+            Error::invalid(format!(
+                "No mock response for {} {}",
+                request.method, request.url
+            ))
+        });
+    };
+    match replay.pop_front() {
+        Some(HostCall::Http {
+            request: recorded,
+            response,
+            error,
+        }) if recorded.method == request.method && recorded.url == request.url => {
+            match (response, error) {
+                (_, Some(error)) => Err(error),
+                (Some(response), None) => Ok(MockResponse {
+                    status: response.status,
+                    headers: response.headers.into_iter().collect(),
+                    body: response.body,
+                }),
+                (None, None) => Err(Error::invalid("The recorded request has no response")),
+            }
+        }
+        Some(HostCall::Http {
+            request: recorded, ..
+        }) => Err(Error::invalid(format!(
+            "Replay diverged: the plugin requested {} {} where {} {} was recorded",
+            request.method, request.url, recorded.method, recorded.url
+        ))),
+        _ => Err(Error::invalid(format!(
+            "Replay diverged: the plugin requested {} {}, which was not recorded",
+            request.method, request.url
+        ))),
+    }
+}
+
+#[cfg(feature = "http")]
+pub(crate) unsafe fn extism_http_status_code(resp: u64) -> i32 {
+    with_state(|state| state.statuses.get(&resp).map_or(0, |status| *status as i32))
+}
+
+#[cfg(feature = "config")]
+pub(crate) unsafe fn extism_config_get(key: *const u8, key_len: u64) -> u64 {
+    let key = string(key, key_len);
+    with_state(|state| match state.config.get(&key) {
+        Some(value) => {
+            let value = value.as_bytes().to_vec();
+            state.alloc(value)
+        }
+        None => 0,
+    })
+}
+
+#[cfg(feature = "vars")]
+pub(crate) unsafe fn extism_var_get(name: *const u8, name_len: u64) -> u64 {
+    let name = string(name, name_len);
+    with_state(|state| match state.vars.get(&name) {
+        Some(value) => {
+            let value = value.clone();
+            state.alloc(value)
+        }
+        None => 0,
+    })
+}
+
+#[cfg(feature = "vars")]
+pub(crate) unsafe fn extism_var_set(
+    name: *const u8,
+    name_len: u64,
+    value: *const u8,
+    value_len: u64,
+) {
+    let name = string(name, name_len);
+    let value = bytes(value, value_len).to_vec();
+    with_state(|state| state.vars.insert(name, value));
+}
+
+#[cfg(feature = "var-batch")]
+pub(crate) unsafe fn extism_var_get_many(payload: *const u8, payload_len: u64) -> u64 {
+    let names = var::decode_batch(bytes(payload, payload_len)).expect("a valid var batch");
+    with_state(|state| {
+        let response = var::encode_batch(
+            names
+                .iter()
+                .map(|(name, _)| (name.as_str(), state.vars.get(name).map(Vec::as_slice))),
+        );
+        state.alloc(response)
+    })
+}
+
+#[cfg(feature = "var-batch")]
+pub(crate) unsafe fn extism_var_set_many(payload: *const u8, payload_len: u64) {
+    let entries = var::decode_batch(bytes(payload, payload_len)).expect("a valid var batch");
+    with_state(|state| {
+        for (name, value) in entries {
+            if let Some(value) = value {
+                state.vars.insert(name, value);
+            }
+        }
+    })
+}
+
+#[cfg(feature = "log")]
+unsafe fn log(level: LogLevel, msg: *const u8, msg_len: u64) {
+    let message = string(msg, msg_len);
+    with_state(|state| state.logs.push(LogEntry { level, message }));
+}
+
+#[cfg(feature = "log")]
+pub(crate) unsafe fn extism_log_info(msg: *const u8, msg_len: u64) {
+    log(LogLevel::Info, msg, msg_len)
+}
+
+#[cfg(feature = "log")]
+pub(crate) unsafe fn extism_log_debug(msg: *const u8, msg_len: u64) {
+    log(LogLevel::Debug, msg, msg_len)
+}
+
+#[cfg(feature = "log")]
+pub(crate) unsafe fn extism_log_warn(msg: *const u8, msg_len: u64) {
+    log(LogLevel::Warn, msg, msg_len)
+}
+
+#[cfg(feature = "log")]
+pub(crate) unsafe fn extism_log_error(msg: *const u8, msg_len: u64) {
+    log(LogLevel::Error, msg, msg_len)
+}
+
+#[cfg(feature = "time")]
+pub(crate) unsafe fn extism_time_now_ms() -> u64 {
+    with_state(|state| {
+        if let Some(replay) = &mut state.replay {
+            if let Some(&HostCall::Time { ms }) = replay.front() {
+                replay.pop_front();
+                return ms;
+            }
+        }
+        state.now_ms
+    })
+}
+
+#[cfg(feature = "subplugin")]
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn extism_plugin_call(
+    plugin: *const u8,
+    plugin_len: u64,
+    function: *const u8,
+    function_len: u64,
+    input: *const u8,
+    input_len: u64,
+    out: *mut u64,
+) -> i32 {
+    let call = MockPluginCall {
+        plugin: string(plugin, plugin_len),
+        function: string(function, function_len),
+        input: bytes(input, input_len).to_vec(),
+    };
+    with_state(|state| {
+        let result = subplugin_result(state, &call);
+        state.plugin_calls.push(call);
+        let (rc, data) = match result {
+            Ok(output) => (0, output),
+            Err(error) => (1, serde_json::to_vec(&error).unwrap_or_default()),
+        };
+        *out = state.alloc(data);
+        rc
+    })
+}
+
+/// The result of a sub-plugin call, from the trace being replayed or the
+/// mock results
+#[cfg(feature = "subplugin")]
+fn subplugin_result(state: &mut State, call: &MockPluginCall) -> Result<Vec<u8>, Error> {
+    let Some(replay) = &mut state.replay else {
+        let key = (call.plugin.clone(), call.function.clone());
+        return state.subplugins.get(&key).cloned().unwrap_or_else(|| {
+            Err(Error::invalid(format!(
+                "No mock result for {}.{}",
+                call.plugin, call.function
+            )))
+        });
+    };
+    match replay.pop_front() {
+        Some(HostCall::PluginCall {
+            plugin,
+            function,
+            output,
+            error,
+            ..
+        }) if plugin == call.plugin && function == call.function => match error {
+            Some(error) => Err(error),
+            None => Ok(output.unwrap_or_default()),
+        },
+        Some(HostCall::PluginCall {
+            plugin, function, ..
+        }) => Err(Error::invalid(format!(
+            "Replay diverged: the plugin called {}.{} where {}.{} was recorded",
+            call.plugin, call.function, plugin, function
+        ))),
+        _ => Err(Error::invalid(format!(
+            "Replay diverged: the plugin called {}.{}, which was not recorded",
+            call.plugin, call.function
+        ))),
+    }
+}
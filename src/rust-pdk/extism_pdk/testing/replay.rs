@@ -0,0 +1,71 @@
+//! Replaying calls recorded by the host runtime
+//!
+//! A `Trace` recorded with `CallOptions::record` sets up the mock host to
+//! answer the plugin's host calls as the host did in the recorded call:
+//!
+//! ```ignore
+//! #[test]
+//! fn reproduces_production_failure() {
+//!     let trace = Trace::load("tests/traces/resize.json").unwrap();
+//!     let output = MockHost::replay(&trace, resize);
+//!     assert!(output.is_ok(), "{:?}", output);
+//! }
+//! ```
+
+use std::collections::btree_map::Entry;
+use std::collections::BTreeSet;
+
+use super::{with_state, MockHost};
+use crate::extism_pdk::trace::{HostCall, Trace};
+use crate::extism_pdk::Error;
+
+impl MockHost {
+    /// Reset the current thread's host to answer as the host did in
+    /// `trace`
+    ///
+    /// Config keys and vars hold the values the plugin read, as they were
+    /// before it set any, and HTTP requests, sub-plugin calls and clock
+    /// reads are answered with the recorded results in order. A request or
+    /// call other than the next recorded one fails like one without a mock
+    /// result; a clock read out of place reads the mock clock.
+    pub fn from_trace(trace: &Trace) -> Self {
+        let host = Self::new();
+        with_state(|state| {
+            let mut set = BTreeSet::new();
+            let mut requests = Vec::new();
+            for event in &trace.events {
+                match &event.call {
+                    HostCall::Config {
+                        key,
+                        value: Some(value),
+                    } => {
+                        state.config.insert(key.clone(), value.clone());
+                    }
+                    HostCall::VarGet {
+                        name,
+                        value: Some(value),
+                    } if !set.contains(name) => {
+                        if let Entry::Vacant(entry) = state.vars.entry(name.clone()) {
+                            entry.insert(value.clone());
+                        }
+                    }
+                    HostCall::VarSet { name, .. } => {
+                        set.insert(name.clone());
+                    }
+                    HostCall::Http { .. } | HostCall::PluginCall { .. } | HostCall::Time { .. } => {
+                        requests.push(event.call.clone())
+                    }
+                    _ => {}
+                }
+            }
+            state.replay = Some(requests.into());
+        });
+        host
+    }
+
+    /// Call `export` with the input of `trace`, answering its host calls
+    /// as recorded
+    pub fn replay(trace: &Trace, export: extern "C" fn() -> i32) -> Result<Vec<u8>, Error> {
+        Self::from_trace(trace).call(export, &trace.input)
+    }
+}
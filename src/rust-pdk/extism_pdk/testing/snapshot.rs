@@ -0,0 +1,205 @@
+//! Golden files of plugin outputs
+//!
+//! `assert_call_snapshot!` calls an export under the mock host and compares
+//! the call, rendered as text, with a file under `tests/snapshots/` of the
+//! crate being tested:
+//!
+//! ```ignore
+//! #[test]
+//! fn greets() {
+//!     MockHost::new().with_config("greeting", "Hi");
+//!     assert_call_snapshot!(hello, r#"{"name": "Ada"}"#);
+//!     assert_call_snapshot!(hello, r#"{"name": ""}"#, "hello_empty");
+//! }
+//! ```
+//!
+//! Missing snapshots are written on the first run, except on CI, where
+//! `$CI` is set. When a snapshot differs the test fails with a line diff;
+//! rerun with `EXTISMX_UPDATE_SNAPSHOTS=1` to accept the new output.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use super::MockHost;
+
+/// Set to overwrite snapshots that differ
+pub const UPDATE_ENV: &str = "EXTISMX_UPDATE_SNAPSHOTS";
+
+/// Lines of context shown around each change in a diff
+const CONTEXT: usize = 3;
+
+/// Call an export under the current mock host and compare the call with a
+/// snapshot
+///
+/// Takes the export, its input as bytes or a string, and optionally the
+/// snapshot's name, which defaults to the export's. Snapshots are stored in
+/// `tests/snapshots/<module>__<name>.snap`.
+#[macro_export]
+macro_rules! assert_call_snapshot {
+    ($export:ident, $input:expr $(,)?) => {
+        $crate::assert_call_snapshot!($export, $input, stringify!($export))
+    };
+    ($export:ident, $input:expr, $name:expr $(,)?) => {
+        $crate::extism_pdk::testing::assert_snapshot(
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("snapshots")
+                .join(format!(
+                    "{}__{}.snap",
+                    module_path!().replace("::", "__"),
+                    $name
+                )),
+            &$crate::extism_pdk::testing::MockHost::current().snapshot(
+                $export,
+                stringify!($export),
+                $input,
+            ),
+        )
+    };
+}
+
+impl MockHost {
+    /// Call an export and render the call as a snapshot: the export and
+    /// its input, then its output or error, with JSON pretty-printed
+    pub fn snapshot(
+        &self,
+        export: extern "C" fn() -> i32,
+        name: &str,
+        input: impl AsRef<[u8]>,
+    ) -> String {
+        let input = input.as_ref();
+        let mut out = format!(
+            "export: {}\ninput: {}\n",
+            name,
+            String::from_utf8_lossy(input)
+        );
+        let body = match self.call(export, input) {
+            Ok(output) => {
+                out.push_str("---\n");
+                pretty(&output)
+            }
+            Err(e) => {
+                out.push_str("--- error\n");
+                serde_json::to_string_pretty(&e).unwrap_or(e.message)
+            }
+        };
+        out.push_str(&body);
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Pretty-print JSON output, and write anything else as text
+fn pretty(output: &[u8]) -> String {
+    serde_json::from_slice::<serde_json::Value>(output)
+        .ok()
+        .and_then(|value| serde_json::to_string_pretty(&value).ok())
+        .unwrap_or_else(|| String::from_utf8_lossy(output).into_owned())
+}
+
+/// Compare `actual` with the snapshot at `path`, panicking with a diff
+/// when they differ
+///
+/// A missing snapshot is written, unless running on CI.
+#[track_caller]
+pub fn assert_snapshot(path: impl AsRef<Path>, actual: &str) {
+    let path = path.as_ref();
+    let update = std::env::var_os(UPDATE_ENV).is_some_and(|value| value != "0");
+    let expected = match fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if std::env::var_os("CI").is_some() && !update {
+                panic!(
+                    "snapshot {} is missing; run the tests locally to write it\n{}",
+                    path.display(),
+                    actual
+                );
+            }
+            write(path, actual);
+            return;
+        }
+        Err(e) => panic!("failed to read snapshot {}: {}", path.display(), e),
+    };
+    if expected == actual {
+        return;
+    }
+    if update {
+        write(path, actual);
+        return;
+    }
+    panic!(
+        "snapshot {} does not match; rerun with {}=1 to update it\n\n{}",
+        path.display(),
+        UPDATE_ENV,
+        diff(&expected, actual)
+    );
+}
+
+fn write(path: &Path, contents: &str) {
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Err(e) = fs::write(path, contents) {
+        panic!("failed to write snapshot {}: {}", path.display(), e);
+    }
+}
+
+/// A line diff of `expected` and `actual`, with removed lines marked `-`,
+/// added lines marked `+` and a few lines of context around each change
+fn diff(expected: &str, actual: &str) -> String {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    // Longest common subsequence lengths of every pair of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|&index| lines[index].0 != ' ')
+        .collect();
+    let mut out = String::new();
+    let mut last = None;
+    for (index, (mark, line)) in lines.iter().enumerate() {
+        let near = changed
+            .iter()
+            .any(|&change| index + CONTEXT >= change && index <= change + CONTEXT);
+        if !near {
+            continue;
+        }
+        if last.is_some_and(|last| last + 1 < index) || (last.is_none() && index > 0) {
+            out.push_str("   ...\n");
+        }
+        let _ = writeln!(out, "{} {}", mark, line);
+        last = Some(index);
+    }
+    if last.is_some_and(|last| last + 1 < lines.len()) {
+        out.push_str("   ...\n");
+    }
+    out
+}
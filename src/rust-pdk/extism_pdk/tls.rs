@@ -0,0 +1,87 @@
+//! TLS requirements of HTTP requests
+//!
+//! A request carrying a `TlsPolicy` asks the host to only send it over a
+//! connection whose server certificate matches one of the pinned
+//! fingerprints and whose TLS version is at least `min_version`:
+//!
+//! ```ignore
+//! let request = HttpRequest {
+//!     method: HttpMethod::Post,
+//!     url: "https://api.example.com/token".to_string(),
+//!     headers: vec![("Authorization".to_string(), credentials)],
+//!     ..Default::default()
+//! }
+//! .with_tls(
+//!     TlsPolicy::new()
+//!         .with_pinned_sha256("9f:86:d0:81:88:4c:7d:65:...")
+//!         .with_min_version(TlsVersion::Tls13),
+//! );
+//! ```
+//!
+//! Requests whose connection does not satisfy the policy fail with an
+//! `ErrorKind::Fatal` error before anything is sent, its message starting
+//! with "TLS verification". The policy is a hint:
+//! hosts that predate it send the request without enforcing it.
+
+use serde::{Deserialize, Serialize};
+
+/// A TLS protocol version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+/// Requirements on the connection an HTTP request is sent over
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsPolicy {
+    /// SHA-256 fingerprints of the DER encoded server certificates to
+    /// accept, in hex with or without colons; empty accepts any certificate
+    /// the host trusts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pinned_sha256: Vec<String>,
+    /// The oldest TLS version to accept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_version: Option<TlsVersion>,
+}
+
+impl TlsPolicy {
+    /// A policy accepting any connection the host trusts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept a server certificate with this SHA-256 fingerprint
+    ///
+    /// Pinning several certificates allows rotating them.
+    pub fn with_pinned_sha256(mut self, fingerprint: impl Into<String>) -> Self {
+        self.pinned_sha256.push(fingerprint.into());
+        self
+    }
+
+    /// Refuse TLS versions older than `version`
+    pub fn with_min_version(mut self, version: TlsVersion) -> Self {
+        self.min_version = Some(version);
+        self
+    }
+
+    /// Check if a certificate fingerprint, as raw SHA-256 bytes, is pinned
+    ///
+    /// Every certificate is accepted when nothing is pinned.
+    pub fn accepts(&self, fingerprint: &[u8]) -> bool {
+        if self.pinned_sha256.is_empty() {
+            return true;
+        }
+        let hex: String = fingerprint.iter().map(|b| format!("{:02x}", b)).collect();
+        self.pinned_sha256.iter().any(|pin| {
+            let pin: String = pin
+                .chars()
+                .filter(|c| *c != ':')
+                .map(|c| c.to_ascii_lowercase())
+                .collect();
+            pin == hex
+        })
+    }
+}
@@ -0,0 +1,380 @@
+//! Recorded plugin calls
+//!
+//! A `Trace` holds everything a plugin exchanged with the host during one
+//! call: its input, each config read, var access, clock read, HTTP request,
+//! sub-plugin call and log message with the host's answer, and the output
+//! or error the call ended with. The host runtime records traces with
+//! `CallOptions::record`:
+//!
+//! ```ignore
+//! let options = CallOptions::new().record("traces/resize.json");
+//! plugin.call_with_options("resize", input, &options)?;
+//! ```
+//!
+//! With the `testing` feature, `MockHost::replay` runs the export natively
+//! against the trace, answering each host call as the host did, so a call
+//! that failed in production can be reproduced under a debugger:
+//!
+//! ```ignore
+//! let trace = Trace::load("traces/resize.json")?;
+//! let output = MockHost::replay(&trace, resize);
+//! ```
+//!
+//! Traces are JSON. Payloads are written as strings when they are UTF-8,
+//! and as `{"base64": "..."}` otherwise.
+//!
+//! `chrome_trace` turns traces into a timeline in the Chrome trace event
+//! format, which Perfetto (<https://ui.perfetto.dev>) and `chrome://tracing`
+//! open, showing each host call within its plugin call with its duration and
+//! payload sizes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::Error as PluginError;
+
+/// Error reading or writing a trace
+#[derive(Debug)]
+pub enum TraceError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(e) => write!(f, "Trace I/O error: {}", e),
+            TraceError::Json(e) => write!(f, "Invalid trace: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl From<std::io::Error> for TraceError {
+    fn from(e: std::io::Error) -> Self {
+        TraceError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TraceError {
+    fn from(e: serde_json::Error) -> Self {
+        TraceError::Json(e)
+    }
+}
+
+/// One plugin call and its exchanges with the host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trace {
+    /// The function that was called
+    pub function: String,
+    #[serde(with = "payload")]
+    pub input: Vec<u8>,
+    /// When the call started, in milliseconds since the Unix epoch
+    pub started_at_ms: u64,
+    /// How long the call took, in microseconds
+    pub duration_us: u64,
+    /// The host calls the plugin made, in order
+    pub events: Vec<TraceEvent>,
+    /// The output the plugin set
+    #[serde(with = "payload")]
+    pub output: Vec<u8>,
+    /// Why the call failed, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Trace {
+    /// Read a trace written by `save`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, TraceError> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    /// Write the trace as JSON, creating its directory if needed
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TraceError> {
+        let path = path.as_ref();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// A host call made during a traced call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEvent {
+    /// When the host call started, in microseconds since the plugin call
+    /// started
+    pub at_us: u64,
+    /// How long the host took to answer, in microseconds
+    pub duration_us: u64,
+    #[serde(flatten)]
+    pub call: HostCall,
+}
+
+/// What the plugin asked the host for, and what the host answered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HostCall {
+    /// `Host::config`
+    Config { key: String, value: Option<String> },
+    /// `Host::var_get`
+    VarGet {
+        name: String,
+        #[serde(with = "optional_payload")]
+        value: Option<Vec<u8>>,
+    },
+    /// `Host::var_set`
+    VarSet {
+        name: String,
+        #[serde(with = "payload")]
+        value: Vec<u8>,
+    },
+    /// `Host::http_request`, with the response or the error the plugin
+    /// received
+    Http {
+        request: TracedRequest,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        response: Option<TracedResponse>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<PluginError>,
+    },
+    /// `Host::log_*`
+    Log { level: String, message: String },
+    /// `Host::now_ms`
+    Time { ms: u64 },
+    /// `subplugin::call`, with the output or the error the plugin received
+    PluginCall {
+        plugin: String,
+        function: String,
+        #[serde(with = "payload")]
+        input: Vec<u8>,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "optional_payload"
+        )]
+        output: Option<Vec<u8>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<PluginError>,
+    },
+}
+
+/// An HTTP request a plugin made
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TracedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "optional_payload"
+    )]
+    pub body: Option<Vec<u8>>,
+}
+
+/// The response a plugin received
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TracedResponse {
+    pub status: u16,
+    /// Headers, by lowercased name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(with = "payload")]
+    pub body: Vec<u8>,
+}
+
+/// Build a timeline of traces in the Chrome trace event format
+///
+/// Calls are placed at the time they started, each on its own track named
+/// after its function, with their host calls nested inside them.
+pub fn chrome_trace(traces: &[Trace]) -> Value {
+    let mut events = Vec::new();
+    for (index, trace) in traces.iter().enumerate() {
+        let tid = index + 1;
+        let start = trace.started_at_ms * 1000;
+        events.push(json!({
+            "name": "thread_name",
+            "ph": "M",
+            "pid": 1,
+            "tid": tid,
+            "args": { "name": format!("{} #{}", trace.function, tid) },
+        }));
+        let mut args = json!({
+            "input_bytes": trace.input.len(),
+            "output_bytes": trace.output.len(),
+            "host_calls": trace.events.len(),
+        });
+        if let Some(error) = &trace.error {
+            args["error"] = json!(error);
+        }
+        events.push(json!({
+            "name": trace.function,
+            "cat": "call",
+            "ph": "X",
+            "ts": start,
+            "dur": trace.duration_us,
+            "pid": 1,
+            "tid": tid,
+            "args": args,
+        }));
+        for event in &trace.events {
+            let (name, args) = event.call.describe();
+            events.push(json!({
+                "name": name,
+                "cat": event.call.kind(),
+                "ph": "X",
+                "ts": start + event.at_us,
+                "dur": event.duration_us,
+                "pid": 1,
+                "tid": tid,
+                "args": args,
+            }));
+        }
+    }
+    json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+}
+
+impl HostCall {
+    /// The kind of host call, as written in traces
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HostCall::Config { .. } => "config",
+            HostCall::VarGet { .. } => "var_get",
+            HostCall::VarSet { .. } => "var_set",
+            HostCall::Http { .. } => "http",
+            HostCall::Log { .. } => "log",
+            HostCall::Time { .. } => "time",
+            HostCall::PluginCall { .. } => "plugin_call",
+        }
+    }
+
+    /// A name for the call on a timeline, and its payload sizes
+    fn describe(&self) -> (String, Value) {
+        let len = |value: &Option<Vec<u8>>| value.as_ref().map(Vec::len);
+        match self {
+            HostCall::Config { key, value } => (
+                format!("config {}", key),
+                json!({ "value_bytes": value.as_ref().map(String::len) }),
+            ),
+            HostCall::VarGet { name, value } => (
+                format!("var_get {}", name),
+                json!({ "value_bytes": len(value) }),
+            ),
+            HostCall::VarSet { name, value } => (
+                format!("var_set {}", name),
+                json!({ "value_bytes": value.len() }),
+            ),
+            HostCall::Http {
+                request,
+                response,
+                error,
+            } => {
+                let mut args = json!({
+                    "url": request.url,
+                    "request_bytes": len(&request.body).unwrap_or(0),
+                });
+                if let Some(response) = response {
+                    args["status"] = json!(response.status);
+                    args["response_bytes"] = json!(response.body.len());
+                }
+                if let Some(error) = error {
+                    args["error"] = json!(error.message);
+                }
+                (format!("http {} {}", request.method, request.url), args)
+            }
+            HostCall::Log { level, message } => {
+                (format!("log {}", level), json!({ "message": message }))
+            }
+            HostCall::Time { ms } => ("time".to_string(), json!({ "ms": ms })),
+            HostCall::PluginCall {
+                plugin,
+                function,
+                input,
+                output,
+                error,
+            } => {
+                let mut args = json!({
+                    "input_bytes": input.len(),
+                    "output_bytes": len(output),
+                });
+                if let Some(error) = error {
+                    args["error"] = json!(error.message);
+                }
+                (format!("plugin_call {}.{}", plugin, function), args)
+            }
+        }
+    }
+}
+
+/// Bytes as a string when they are UTF-8, or as `{"base64": "..."}`
+mod payload {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    pub(super) enum Payload {
+        Text(String),
+        Binary { base64: String },
+    }
+
+    impl Payload {
+        pub(super) fn new(data: &[u8]) -> Self {
+            match std::str::from_utf8(data) {
+                Ok(text) => Payload::Text(text.to_string()),
+                Err(_) => Payload::Binary {
+                    base64: STANDARD.encode(data),
+                },
+            }
+        }
+
+        pub(super) fn into_bytes<E: serde::de::Error>(self) -> Result<Vec<u8>, E> {
+            match self {
+                Payload::Text(text) => Ok(text.into_bytes()),
+                Payload::Binary { base64 } => STANDARD.decode(base64).map_err(E::custom),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        Payload::new(data).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        Payload::deserialize(deserializer)?.into_bytes()
+    }
+}
+
+mod optional_payload {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::payload::Payload;
+
+    pub fn serialize<S: Serializer>(
+        data: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        data.as_deref().map(Payload::new).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<Payload>::deserialize(deserializer)?
+            .map(Payload::into_bytes)
+            .transpose()
+    }
+}
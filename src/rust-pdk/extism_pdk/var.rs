@@ -0,0 +1,245 @@
+//! Var and config names, and batched var reads and writes
+//!
+//! `Host::var_get`, `Host::var_set` and `Host::config` pass names to the
+//! host as they are, so reading a `&'static str` key allocates nothing.
+//! Names built at runtime, such as per-tenant keys, allocate each time they
+//! are formatted; a `VarName` is built once and copied freely after:
+//!
+//! ```ignore
+//! let quota = VarName::intern(&format!("quota:{}", tenant));
+//! for request in requests {
+//!     let used = Host::var_get(&quota);
+//!     // ...
+//! }
+//! ```
+//!
+//! `set_many` and `get_many` read and write many vars at once. With the
+//! `var-batch` feature they send every entry in a single host call, through
+//! the `extism_var_set_many` and `extism_var_get_many` extension functions
+//! of the `extismx` host; without it they call `Host::var_set` and
+//! `Host::var_get` for each entry, which works on every host:
+//!
+//! ```ignore
+//! var::set_many(&[("count", count.to_string()), ("updated", now.to_string())]);
+//! let [count, updated] = var::get_many(&["count", "updated"]).try_into().unwrap();
+//! ```
+//!
+//! The feature requires host support: a plugin built with it imports the
+//! extension functions, so it fails to instantiate on a host that does not
+//! provide them. Build plugins for other hosts without the feature.
+//!
+//! A batch payload is a sequence of entries, each a little-endian `u32`
+//! name length, the UTF-8 name, a little-endian `u32` value length and the
+//! value. A missing value has the length `u32::MAX` and no bytes.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+
+#[cfg(feature = "vars")]
+use super::{guard, Host};
+
+/// The value length of an entry without a value
+const MISSING: u32 = u32::MAX;
+
+/// A var or config name that is cheap to copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VarName(&'static str);
+
+thread_local! {
+    static INTERNED: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+}
+
+impl VarName {
+    /// A name known at compile time
+    pub const fn new(name: &'static str) -> Self {
+        Self(name)
+    }
+
+    /// A name built at runtime, kept for the life of the plugin instance
+    ///
+    /// Interning a name already interned returns it without allocating.
+    /// Interned names are never freed, so names should come from a bounded
+    /// set, such as tenants or regions, not from arbitrary input.
+    pub fn intern(name: &str) -> Self {
+        INTERNED.with(|interned| {
+            let mut interned = interned.borrow_mut();
+            if let Some(name) = interned.get(name) {
+                return Self(name);
+            }
+            let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+            interned.insert(name);
+            Self(name)
+        })
+    }
+
+    /// The name
+    pub const fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl Deref for VarName {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl AsRef<str> for VarName {
+    fn as_ref(&self) -> &str {
+        self.0
+    }
+}
+
+impl From<&'static str> for VarName {
+    fn from(name: &'static str) -> Self {
+        Self(name)
+    }
+}
+
+impl fmt::Display for VarName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A batch payload does not decode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError(String);
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Malformed var batch: {}", self.0)
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// Encode names and values as a batch payload
+pub fn encode_batch<'a>(entries: impl IntoIterator<Item = (&'a str, Option<&'a [u8]>)>) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for (name, value) in entries {
+        payload.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        payload.extend_from_slice(name.as_bytes());
+        match value {
+            Some(value) => {
+                payload.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                payload.extend_from_slice(value);
+            }
+            None => payload.extend_from_slice(&MISSING.to_le_bytes()),
+        }
+    }
+    payload
+}
+
+/// Decode the names and values of a batch payload
+#[allow(clippy::type_complexity)]
+pub fn decode_batch(mut payload: &[u8]) -> Result<Vec<(String, Option<Vec<u8>>)>, BatchError> {
+    fn take<'a>(payload: &mut &'a [u8], len: usize) -> Result<&'a [u8], BatchError> {
+        if payload.len() < len {
+            return Err(BatchError(format!(
+                "{} bytes left where {} were expected",
+                payload.len(),
+                len
+            )));
+        }
+        let (head, rest) = payload.split_at(len);
+        *payload = rest;
+        Ok(head)
+    }
+    fn length(payload: &mut &[u8]) -> Result<u32, BatchError> {
+        let bytes = take(payload, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    let mut entries = Vec::new();
+    while !payload.is_empty() {
+        let name_len = length(&mut payload)? as usize;
+        let name = std::str::from_utf8(take(&mut payload, name_len)?)
+            .map_err(|e| BatchError(format!("invalid name: {}", e)))?
+            .to_string();
+        let value = match length(&mut payload)? {
+            MISSING => None,
+            len => Some(take(&mut payload, len as usize)?.to_vec()),
+        };
+        entries.push((name, value));
+    }
+    Ok(entries)
+}
+
+/// Set many vars at once
+///
+/// Like `Host::var_set`, each entry counts against the var writes of the
+/// `guard::Budget` in force, and entries over budget are skipped.
+#[cfg(feature = "vars")]
+pub fn set_many<N: AsRef<str>, V: AsRef<[u8]>>(entries: &[(N, V)]) {
+    let entries = entries
+        .iter()
+        .filter(|_| guard::charge_var_write())
+        .map(|(name, value)| (name.as_ref(), value.as_ref()));
+    write_many(entries);
+}
+
+#[cfg(all(feature = "vars", feature = "var-batch"))]
+fn write_many<'a>(entries: impl Iterator<Item = (&'a str, &'a [u8])>) {
+    let payload = encode_batch(entries.map(|(name, value)| (name, Some(value))));
+    if !payload.is_empty() {
+        unsafe { super::extism_var_set_many(payload.as_ptr(), payload.len() as u64) }
+    }
+}
+
+#[cfg(all(feature = "vars", not(feature = "var-batch")))]
+fn write_many<'a>(entries: impl Iterator<Item = (&'a str, &'a [u8])>) {
+    for (name, value) in entries {
+        Host::write_var(name, value);
+    }
+}
+
+/// Get many vars at once, in the order of `names`
+///
+/// With the `var-batch` feature, a host that links the extension but does
+/// not answer the batch, returning no block or one for other names, is read
+/// one var at a time instead.
+#[cfg(feature = "vars")]
+pub fn get_many<N: AsRef<str>>(names: &[N]) -> Vec<Option<Vec<u8>>> {
+    #[cfg(feature = "var-batch")]
+    if let Some(values) = read_many(names) {
+        return values;
+    }
+    names
+        .iter()
+        .map(|name| Host::var_get(name.as_ref()))
+        .collect()
+}
+
+/// Read `names` in one host call, or `None` if the host's response does
+/// not answer them
+#[cfg(all(feature = "vars", feature = "var-batch"))]
+fn read_many<N: AsRef<str>>(names: &[N]) -> Option<Vec<Option<Vec<u8>>>> {
+    if names.is_empty() {
+        return Some(Vec::new());
+    }
+    let payload = encode_batch(names.iter().map(|name| (name.as_ref(), None)));
+    let response = unsafe { super::extism_var_get_many(payload.as_ptr(), payload.len() as u64) };
+    if response == 0 {
+        return None;
+    }
+    let len = unsafe { super::extism_length(response) };
+    let mut data = super::pool::take(len as usize);
+    unsafe {
+        super::extism_load_u8(response, 0, len, data.as_mut_ptr());
+        super::extism_free(response);
+    }
+    let entries = decode_batch(&data).ok();
+    super::pool::recycle(data);
+    let entries = entries?;
+    let answers = entries.len() == names.len()
+        && entries
+            .iter()
+            .zip(names)
+            .all(|((name, _), expected)| name == expected.as_ref());
+    answers.then(|| entries.into_iter().map(|(_, value)| value).collect())
+}
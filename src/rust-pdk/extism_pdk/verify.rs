@@ -0,0 +1,424 @@
+//! Smoke tests of built plugins from declarative specs
+//!
+//! A spec lists function calls and what each must return, so a release
+//! pipeline can check a module's behavior before publishing it:
+//!
+//! ```yaml
+//! config:
+//!   greeting: Hello
+//! tests:
+//!   - name: greets by name
+//!     function: hello
+//!     input_json: { name: Ada }
+//!     expect:
+//!       output_json: { greeting: "Hello, Ada!" }
+//!   - name: rejects bad input
+//!     function: hello
+//!     input: "{"
+//!     expect:
+//!       exit_code: 1
+//!       error: Failed to read input
+//! ```
+//!
+//! Each test runs in a fresh instance of the plugin. `Suite::junit_xml`
+//! writes the results in the JUnit XML format CI systems display.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::host::{self, CallError, CallOptions, CompiledPlugin, Manifest, PluginBuilder};
+use super::ErrorKind;
+
+/// A spec could not be read or run
+#[derive(Debug)]
+pub enum VerifyError {
+    Io(std::io::Error),
+    /// The spec is not valid YAML or does not describe tests
+    Spec(String),
+    /// The plugin could not be loaded
+    Host(host::Error),
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Io(e) => write!(f, "Verify I/O error: {}", e),
+            VerifyError::Spec(message) => write!(f, "Invalid spec: {}", message),
+            VerifyError::Host(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+impl From<std::io::Error> for VerifyError {
+    fn from(e: std::io::Error) -> Self {
+        VerifyError::Io(e)
+    }
+}
+
+impl From<host::Error> for VerifyError {
+    fn from(e: host::Error) -> Self {
+        VerifyError::Host(e)
+    }
+}
+
+/// Calls to make and what they must return
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    /// Config keys of the plugin, added to the manifest's
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+    /// Hosts the plugin may send HTTP requests to
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    /// Run the plugin with WASI
+    #[serde(default)]
+    pub wasi: bool,
+    /// Timeout of each call in milliseconds, unless a test sets its own
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    pub tests: Vec<TestCase>,
+    /// Directory input files are read from, the spec's own
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+/// A function call and what it must return
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TestCase {
+    pub name: String,
+    pub function: String,
+    /// Input as text
+    #[serde(default)]
+    pub input: Option<String>,
+    /// Input as JSON, written as YAML
+    #[serde(default)]
+    pub input_json: Option<Value>,
+    /// File holding the input, relative to the spec
+    #[serde(default)]
+    pub input_file: Option<PathBuf>,
+    /// Config keys overriding the spec's for this call
+    #[serde(default)]
+    pub config: BTreeMap<String, String>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub expect: Expect,
+}
+
+/// What a call must return; unset fields are not checked
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    /// 0 when the call succeeds, 1 when the function fails or traps; 1 by
+    /// default when an error is expected, and 0 otherwise
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Exact output text
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Output as JSON, compared as values rather than text
+    #[serde(default)]
+    pub output_json: Option<Value>,
+    /// Text the output must contain
+    #[serde(default)]
+    pub output_contains: Option<String>,
+    /// Text the error message must contain
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Kind of error the function must fail with
+    #[serde(default)]
+    pub error_kind: Option<ErrorKind>,
+}
+
+impl Spec {
+    /// Read a YAML (or JSON) spec
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VerifyError> {
+        let path = path.as_ref();
+        let mut spec = Self::parse(&fs::read_to_string(path)?)?;
+        spec.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(spec)
+    }
+
+    /// Parse a YAML (or JSON) spec, reading input files relative to the
+    /// working directory
+    pub fn parse(yaml: &str) -> Result<Self, VerifyError> {
+        let spec: Self =
+            serde_yaml::from_str(yaml).map_err(|e| VerifyError::Spec(e.to_string()))?;
+        for test in &spec.tests {
+            let inputs = [
+                test.input.is_some(),
+                test.input_json.is_some(),
+                test.input_file.is_some(),
+            ];
+            if inputs.into_iter().filter(|given| *given).count() > 1 {
+                return Err(VerifyError::Spec(format!(
+                    "test {:?} gives more than one of input, input_json and input_file",
+                    test.name
+                )));
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Add the spec's config, allowed hosts and WASI setting to `manifest`
+    pub fn apply(&self, manifest: Manifest) -> Manifest {
+        let mut manifest = self
+            .allowed_hosts
+            .iter()
+            .fold(manifest, |manifest, host| manifest.with_allowed_host(host))
+            .with_config(self.config.clone());
+        if self.wasi {
+            manifest = manifest.with_wasi(true);
+        }
+        manifest
+    }
+
+    /// Run the tests against the plugin of `manifest`, after applying the
+    /// spec's settings to it
+    pub fn run(&self, manifest: Manifest) -> Result<Suite, VerifyError> {
+        let logs: Arc<Mutex<String>> = Arc::default();
+        let sink = logs.clone();
+        let compiled = PluginBuilder::new(self.apply(manifest))
+            .with_log_sink(move |level, message| {
+                if let Ok(mut logs) = sink.lock() {
+                    let _ = writeln!(logs, "[{}] {}", level, message);
+                }
+            })
+            .compile()?;
+        let mut results = Vec::new();
+        for test in &self.tests {
+            let mut result = self.run_test(&compiled, test);
+            if let Ok(mut logs) = logs.lock() {
+                result.logs = std::mem::take(&mut *logs);
+            }
+            results.push(result);
+        }
+        Ok(Suite { results })
+    }
+
+    fn run_test(&self, compiled: &CompiledPlugin, test: &TestCase) -> TestResult {
+        let started = Instant::now();
+        let outcome = self.input(test).and_then(|input| {
+            let mut plugin = compiled.instantiate().map_err(|e| e.to_string())?;
+            let mut options = CallOptions::new().config_override(test.config.clone());
+            if let Some(timeout) = test.timeout_ms.or(self.timeout_ms) {
+                options = options.timeout(Duration::from_millis(timeout));
+            }
+            let result = plugin
+                .call_with_options(&test.function, input, &options)
+                .map(|output| output.output);
+            Ok(result)
+        });
+        let failure = match outcome {
+            Ok(result) => check(&test.expect, &result).err(),
+            Err(e) => Some(e),
+        };
+        TestResult {
+            name: test.name.clone(),
+            function: test.function.clone(),
+            duration: started.elapsed(),
+            failure,
+            logs: String::new(),
+        }
+    }
+
+    fn input(&self, test: &TestCase) -> Result<Vec<u8>, String> {
+        if let Some(input) = &test.input {
+            return Ok(input.clone().into_bytes());
+        }
+        if let Some(input) = &test.input_json {
+            return serde_json::to_vec(input).map_err(|e| e.to_string());
+        }
+        match &test.input_file {
+            Some(file) => {
+                let path = self.dir.join(file);
+                fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Compare a call's result with what was expected
+fn check(expect: &Expect, result: &Result<Vec<u8>, CallError>) -> Result<(), String> {
+    let expects_error = expect.error.is_some() || expect.error_kind.is_some();
+    let exit_code = expect.exit_code.unwrap_or(i32::from(expects_error));
+    let output = match (result, exit_code) {
+        (Ok(output), 0) => output,
+        (Ok(_), code) => {
+            return Err(format!(
+                "expected exit code {}, but the call succeeded",
+                code
+            ))
+        }
+        (Err(e), 0) => return Err(format!("expected success, but the call failed: {}", e)),
+        (Err(e), _) => {
+            if let Some(text) = &expect.error {
+                let message = e.to_string();
+                if !message.contains(text.as_str()) {
+                    return Err(format!(
+                        "expected an error containing {:?}, got {:?}",
+                        text, message
+                    ));
+                }
+            }
+            if let Some(kind) = expect.error_kind {
+                match e {
+                    CallError::Plugin(error) if error.kind == kind => {}
+                    e => return Err(format!("expected a {:?} error, got: {}", kind, e)),
+                }
+            }
+            return Ok(());
+        }
+    };
+    let text = String::from_utf8_lossy(output);
+    if let Some(expected) = &expect.output {
+        if text != expected.as_str() {
+            return Err(format!("expected output {:?}, got {:?}", expected, text));
+        }
+    }
+    if let Some(expected) = &expect.output_json {
+        match serde_json::from_slice::<Value>(output) {
+            Ok(actual) if &actual == expected => {}
+            Ok(actual) => return Err(format!("expected output {}, got {}", expected, actual)),
+            Err(e) => return Err(format!("expected JSON output, got {:?}: {}", text, e)),
+        }
+    }
+    if let Some(expected) = &expect.output_contains {
+        if !text.contains(expected.as_str()) {
+            return Err(format!(
+                "expected output containing {:?}, got {:?}",
+                expected, text
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of a test
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub function: String,
+    pub duration: Duration,
+    /// Why the test failed, if it did
+    pub failure: Option<String>,
+    /// What the plugin logged during the call
+    pub logs: String,
+}
+
+/// The outcomes of a spec's tests
+#[derive(Debug, Clone, Default)]
+pub struct Suite {
+    pub results: Vec<TestResult>,
+}
+
+impl Suite {
+    /// Whether every test passed
+    pub fn passed(&self) -> bool {
+        self.failures() == 0
+    }
+
+    /// The number of failed tests
+    pub fn failures(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.failure.is_some())
+            .count()
+    }
+
+    /// Render the results as a JUnit XML report of a suite named `name`,
+    /// with each test's logs as its standard output
+    pub fn junit_xml(&self, name: &str) -> String {
+        let time: Duration = self.results.iter().map(|result| result.duration).sum();
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            "<testsuites tests=\"{tests}\" failures=\"{failures}\" time=\"{time:.3}\">\n  <testsuite name=\"{name}\" tests=\"{tests}\" failures=\"{failures}\" time=\"{time:.3}\">",
+            tests = self.results.len(),
+            failures = self.failures(),
+            time = time.as_secs_f64(),
+            name = xml_escape(name),
+        );
+        for result in &self.results {
+            let _ = write!(
+                out,
+                "    <testcase name=\"{}\" classname=\"{}.{}\" time=\"{:.3}\"",
+                xml_escape(&result.name),
+                xml_escape(name),
+                xml_escape(&result.function),
+                result.duration.as_secs_f64()
+            );
+            if result.failure.is_none() && result.logs.is_empty() {
+                out.push_str("/>\n");
+                continue;
+            }
+            out.push_str(">\n");
+            if let Some(failure) = &result.failure {
+                let _ = writeln!(
+                    out,
+                    "      <failure message=\"{}\">{}</failure>",
+                    xml_escape(failure),
+                    xml_escape(failure)
+                );
+            }
+            if !result.logs.is_empty() {
+                let _ = writeln!(
+                    out,
+                    "      <system-out>{}</system-out>",
+                    xml_escape(&result.logs)
+                );
+            }
+            out.push_str("    </testcase>\n");
+        }
+        out.push_str("  </testsuite>\n</testsuites>\n");
+        out
+    }
+}
+
+impl fmt::Display for Suite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            match &result.failure {
+                None => writeln!(f, "test {} ... ok", result.name)?,
+                Some(failure) => writeln!(f, "test {} ... FAILED: {}", result.name, failure)?,
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.results.len() - self.failures(),
+            self.failures()
+        )
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than tabs and newlines are not allowed
+            // in XML 1.0
+            c if c.is_control() && c != '\t' && c != '\n' && c != '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,453 @@
+//! Plugin interfaces described in WIT
+//!
+//! A world lists the functions a plugin exports and the types they take
+//! and return, in the interface language of the WebAssembly component
+//! model:
+//!
+//! ```text
+//! package acme:resize@1.0.0;
+//!
+//! world resize {
+//!     enum image-format { png, jpeg }
+//!
+//!     record resize-input {
+//!         url: string,
+//!         width: u32,
+//!         format: option<image-format>,
+//!     }
+//!
+//!     /// Resize the image at `url`
+//!     export resize: func(input: resize-input) -> list<u8>;
+//! }
+//! ```
+//!
+//! Until plugins are built as components, values cross the Extism ABI as
+//! JSON: records are objects with `snake_case` fields, enum cases are
+//! strings, variant cases with a payload are objects with a single key,
+//! `option`s are `null` when absent and `result`s are `{"Ok": ..}` or
+//! `{"Err": ..}`. A function taking several parameters takes an object of
+//! them. `Wit::schemas` describes the exports with the JSON Schemas
+//! `plugin_metadata!` declares, so `Codegen` generates host-side types from
+//! them, and `Wit::guest_bindings` generates the plugin's side.
+//!
+//! Worlds, interfaces, records, enums, variants, type aliases and the
+//! built-in types are supported; imports are ignored, and flags and
+//! resources are rejected.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use super::codegen::{pascal_case, serde_case, RUST_KEYWORDS};
+use super::schema::FunctionSchema;
+
+mod guest;
+mod parser;
+
+/// A WIT document could not be read or used
+#[derive(Debug)]
+pub enum WitError {
+    /// The document could not be read
+    Io(std::io::Error),
+    /// The document is not valid WIT
+    Syntax { line: usize, message: String },
+    /// The document uses a WIT feature plugins cannot use
+    Unsupported { line: usize, feature: String },
+    /// A type or function is defined twice
+    Duplicate(String),
+    /// A type is used but not defined
+    UnknownType(String),
+    /// A world exports an interface that is not defined
+    UnknownInterface(String),
+    /// No world has the requested name
+    UnknownWorld(String),
+    /// No world was named and the document does not define exactly one,
+    /// holding the worlds it defines
+    AmbiguousWorld(Vec<String>),
+}
+
+impl fmt::Display for WitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WitError::Io(e) => write!(f, "WIT I/O error: {}", e),
+            WitError::Syntax { line, message } => write!(f, "WIT line {}: {}", line, message),
+            WitError::Unsupported { line, feature } => {
+                write!(f, "WIT line {}: {} are not supported", line, feature)
+            }
+            WitError::Duplicate(name) => write!(f, "{} is defined twice", name),
+            WitError::UnknownType(name) => write!(f, "Unknown type {}", name),
+            WitError::UnknownInterface(name) => write!(f, "Unknown interface {}", name),
+            WitError::UnknownWorld(name) => write!(f, "Unknown world {}", name),
+            WitError::AmbiguousWorld(worlds) if worlds.is_empty() => {
+                write!(f, "The document defines no world")
+            }
+            WitError::AmbiguousWorld(worlds) => {
+                write!(f, "Pick one of the worlds {}", worlds.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for WitError {}
+
+impl From<std::io::Error> for WitError {
+    fn from(e: std::io::Error) -> Self {
+        WitError::Io(e)
+    }
+}
+
+/// A type of a parameter, result, field or payload
+#[derive(Debug, Clone, PartialEq)]
+enum WitType {
+    Bool,
+    /// An integer, by its WIT name, such as `u32`
+    Integer(&'static str),
+    /// `f32` or `f64`
+    Float(&'static str),
+    Char,
+    String,
+    List(Box<WitType>),
+    Option(Box<WitType>),
+    Result(Option<Box<WitType>>, Option<Box<WitType>>),
+    Tuple(Vec<WitType>),
+    Named(String),
+}
+
+/// A named type
+#[derive(Debug, Clone)]
+struct TypeDef {
+    name: String,
+    docs: Option<String>,
+    kind: TypeKind,
+}
+
+#[derive(Debug, Clone)]
+enum TypeKind {
+    Record(Vec<Field>),
+    Enum(Vec<Case>),
+    Variant(Vec<Case>),
+    Alias(WitType),
+}
+
+/// A record field, or a function parameter
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    docs: Option<String>,
+    ty: WitType,
+}
+
+/// A case of an enum, or of a variant with its payload
+#[derive(Debug, Clone)]
+struct Case {
+    name: String,
+    docs: Option<String>,
+    ty: Option<WitType>,
+}
+
+#[derive(Debug, Clone)]
+struct Func {
+    name: String,
+    docs: Option<String>,
+    params: Vec<Field>,
+    result: Option<WitType>,
+}
+
+#[derive(Debug, Clone)]
+struct Interface {
+    name: String,
+    funcs: Vec<Func>,
+}
+
+#[derive(Debug, Clone)]
+struct World {
+    name: String,
+    docs: Option<String>,
+    /// Functions the world exports itself
+    funcs: Vec<Func>,
+    /// Interfaces the world exports, by name
+    exports: Vec<String>,
+}
+
+/// A parsed WIT document
+#[derive(Debug, Clone, Default)]
+pub struct Wit {
+    package: Option<String>,
+    /// Types of every interface and world, which share one namespace
+    types: Vec<TypeDef>,
+    interfaces: Vec<Interface>,
+    worlds: Vec<World>,
+}
+
+impl Wit {
+    /// Parse a WIT document
+    pub fn parse(source: &str) -> Result<Self, WitError> {
+        parser::parse(source)
+    }
+
+    /// Read and parse a `.wit` file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, WitError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Name of the package, such as `acme:resize@1.0.0`, if declared
+    pub fn package(&self) -> Option<&str> {
+        self.package.as_deref()
+    }
+
+    /// Names of the worlds the document defines
+    pub fn worlds(&self) -> Vec<&str> {
+        self.worlds
+            .iter()
+            .map(|world| world.name.as_str())
+            .collect()
+    }
+
+    /// Find world `name`, or the only world when no name is given
+    fn world(&self, name: Option<&str>) -> Result<&World, WitError> {
+        match name {
+            Some(name) => self
+                .worlds
+                .iter()
+                .find(|world| world.name == name)
+                .ok_or_else(|| WitError::UnknownWorld(name.to_string())),
+            None => match self.worlds.as_slice() {
+                [world] => Ok(world),
+                worlds => Err(WitError::AmbiguousWorld(
+                    worlds.iter().map(|world| world.name.clone()).collect(),
+                )),
+            },
+        }
+    }
+
+    /// Functions a world exports, directly or through its interfaces
+    fn exports<'a>(&'a self, world: &'a World) -> Result<Vec<&'a Func>, WitError> {
+        let mut funcs: Vec<&Func> = world.funcs.iter().collect();
+        for name in &world.exports {
+            let interface = self
+                .interfaces
+                .iter()
+                .find(|interface| &interface.name == name)
+                .ok_or_else(|| WitError::UnknownInterface(name.clone()))?;
+            funcs.extend(&interface.funcs);
+        }
+        let mut names = BTreeSet::new();
+        for func in &funcs {
+            if !names.insert(export_name(&func.name)) {
+                return Err(WitError::Duplicate(func.name.clone()));
+            }
+        }
+        Ok(funcs)
+    }
+
+    fn type_def(&self, name: &str) -> Result<&TypeDef, WitError> {
+        self.types
+            .iter()
+            .find(|def| def.name == name)
+            .ok_or_else(|| WitError::UnknownType(name.to_string()))
+    }
+
+    /// The JSON Schemas of the functions a world exports, as the plugin
+    /// declares them with `plugin_metadata!`
+    ///
+    /// `world` may be omitted when the document defines a single world.
+    pub fn schemas(&self, world: Option<&str>) -> Result<Vec<FunctionSchema>, WitError> {
+        let world = self.world(world)?;
+        self.exports(world)?
+            .into_iter()
+            .map(|func| {
+                let input = match func.params.as_slice() {
+                    [] => json!({ "type": "null" }),
+                    [param] => self.root_schema(&param.ty)?,
+                    params => {
+                        let mut defs = Map::new();
+                        let object = self.object_schema(params, &mut defs)?;
+                        let mut schema = json!({ "title": params_name(&func.name) });
+                        schema.as_object_mut().unwrap().extend(object);
+                        with_defs(schema, defs)
+                    }
+                };
+                let output = match &func.result {
+                    Some(ty) => self.root_schema(ty)?,
+                    None => json!({ "type": "null" }),
+                };
+                Ok(FunctionSchema {
+                    name: export_name(&func.name),
+                    input,
+                    output,
+                })
+            })
+            .collect()
+    }
+
+    /// Generate the Rust side of a plugin exporting a world
+    ///
+    /// The bindings declare the world's types, a `Guest` trait with a
+    /// method per export and an `export_<world>!` macro exporting the
+    /// functions of a type implementing it, with their schemas. They are
+    /// meant to be written to the plugin's `src/<module>.rs`, where
+    /// `module_path`, such as `crate::bindings`, refers to them.
+    pub fn guest_bindings(
+        &self,
+        world: Option<&str>,
+        module_path: &str,
+    ) -> Result<String, WitError> {
+        let world = self.world(world)?;
+        guest::generate(self, world, &self.exports(world)?, module_path)
+    }
+
+    fn root_schema(&self, ty: &WitType) -> Result<Value, WitError> {
+        let mut defs = Map::new();
+        let schema = self.schema(ty, &mut defs)?;
+        Ok(with_defs(schema, defs))
+    }
+
+    /// The schema of a type, adding the named types it refers to to `defs`
+    fn schema(&self, ty: &WitType, defs: &mut Map<String, Value>) -> Result<Value, WitError> {
+        Ok(match ty {
+            WitType::Bool => json!({ "type": "boolean" }),
+            WitType::Integer(name) => {
+                let format = match name.strip_prefix('s') {
+                    Some(bits) => format!("int{}", bits),
+                    None => format!("uint{}", &name[1..]),
+                };
+                json!({ "type": "integer", "format": format })
+            }
+            WitType::Float("f32") => json!({ "type": "number", "format": "float" }),
+            WitType::Float(_) => json!({ "type": "number", "format": "double" }),
+            WitType::Char => json!({ "type": "string", "minLength": 1, "maxLength": 1 }),
+            WitType::String => json!({ "type": "string" }),
+            WitType::List(item) => json!({ "type": "array", "items": self.schema(item, defs)? }),
+            WitType::Option(ty) => json!({
+                "anyOf": [self.schema(ty, defs)?, { "type": "null" }],
+            }),
+            WitType::Result(ok, err) => {
+                let mut cases = Vec::new();
+                for (case, ty) in [("Ok", ok), ("Err", err)] {
+                    let payload = match ty {
+                        Some(ty) => self.schema(ty, defs)?,
+                        None => json!({ "type": "null" }),
+                    };
+                    cases.push(json!({
+                        "type": "object",
+                        "properties": { case: payload },
+                        "required": [case],
+                        "additionalProperties": false,
+                    }));
+                }
+                json!({ "oneOf": cases })
+            }
+            WitType::Tuple(types) => json!({
+                "type": "array",
+                "prefixItems": types
+                    .iter()
+                    .map(|ty| self.schema(ty, defs))
+                    .collect::<Result<Vec<_>, _>>()?,
+                "minItems": types.len(),
+                "maxItems": types.len(),
+            }),
+            WitType::Named(name) => {
+                let def = self.type_def(name)?;
+                if let TypeKind::Alias(ty) = &def.kind {
+                    return self.schema(ty, defs);
+                }
+                let key = pascal_case(name, false);
+                if !defs.contains_key(&key) {
+                    // Reserve the name first, in case the type refers to itself
+                    defs.insert(key.clone(), Value::Bool(true));
+                    let schema = self.def_schema(def, defs)?;
+                    defs.insert(key.clone(), schema);
+                }
+                json!({ "$ref": format!("#/$defs/{}", key) })
+            }
+        })
+    }
+
+    fn def_schema(&self, def: &TypeDef, defs: &mut Map<String, Value>) -> Result<Value, WitError> {
+        let mut schema = match &def.kind {
+            TypeKind::Record(fields) => Value::Object(self.object_schema(fields, defs)?),
+            TypeKind::Enum(cases) => json!({
+                "type": "string",
+                "enum": cases.iter().map(|case| case.name.as_str()).collect::<Vec<_>>(),
+            }),
+            TypeKind::Variant(cases) => {
+                let mut variants = Vec::new();
+                for case in cases {
+                    let mut variant = match &case.ty {
+                        Some(ty) => json!({
+                            "type": "object",
+                            "properties": { &case.name: self.schema(ty, defs)? },
+                            "required": [&case.name],
+                            "additionalProperties": false,
+                        }),
+                        None => json!({ "type": "string", "const": &case.name }),
+                    };
+                    if let Some(docs) = &case.docs {
+                        variant["description"] = docs.as_str().into();
+                    }
+                    variants.push(variant);
+                }
+                json!({ "oneOf": variants })
+            }
+            TypeKind::Alias(ty) => self.schema(ty, defs)?,
+        };
+        if let (Some(docs), Some(object)) = (&def.docs, schema.as_object_mut()) {
+            object.insert("description".to_string(), docs.as_str().into());
+        }
+        Ok(schema)
+    }
+
+    /// The schema of an object with `fields`, where `option` fields are
+    /// not required
+    fn object_schema(
+        &self,
+        fields: &[Field],
+        defs: &mut Map<String, Value>,
+    ) -> Result<Map<String, Value>, WitError> {
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+        for field in fields {
+            let name = field_name(&field.name);
+            let mut schema = self.schema(&field.ty, defs)?;
+            if let (Some(docs), Some(object)) = (&field.docs, schema.as_object_mut()) {
+                object.insert("description".to_string(), docs.as_str().into());
+            }
+            if !matches!(field.ty, WitType::Option(_)) {
+                required.push(Value::String(name.clone()));
+            }
+            properties.insert(name, schema);
+        }
+        let mut object = Map::new();
+        object.insert("type".to_string(), "object".into());
+        object.insert("properties".to_string(), Value::Object(properties));
+        object.insert("required".to_string(), Value::Array(required));
+        Ok(object)
+    }
+}
+
+/// Add the named types a root schema refers to
+fn with_defs(mut schema: Value, defs: Map<String, Value>) -> Value {
+    if let (false, Some(object)) = (defs.is_empty(), schema.as_object_mut()) {
+        object.insert("$defs".to_string(), Value::Object(defs));
+    }
+    schema
+}
+
+/// Name of the export of WIT function `name`, such as `resize_image` for
+/// `resize-image`
+fn export_name(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// JSON name of record field or parameter `name`
+fn field_name(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+/// Name of the object a function taking several parameters takes
+fn params_name(func: &str) -> String {
+    format!("{}Params", pascal_case(func, false))
+}
@@ -0,0 +1,307 @@
+//! Rust bindings of the plugin side of a world
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+use super::{
+    export_name, params_name, pascal_case, serde_case, Func, TypeKind, Wit, WitError, WitType,
+    World, RUST_KEYWORDS,
+};
+
+pub(super) fn generate(
+    wit: &Wit,
+    world: &World,
+    funcs: &[&Func],
+    module_path: &str,
+) -> Result<String, WitError> {
+    let mut out = format!(
+        "// Generated by extismx bindgen from world `{}`; do not edit.\n//\n\
+         // Implement `Guest` for a type and export its functions with\n\
+         // `export_{}!(Type)`.\n\n",
+        world.name,
+        export_name(&world.name)
+    );
+    out.push_str("use extism_hello_plugin::extism_pdk::Error;\n");
+    out.push_str("use schemars::JsonSchema;\n");
+    out.push_str("use serde::{Deserialize, Serialize};\n");
+
+    // The types the exports use, in the order they are defined
+    let mut used = BTreeSet::new();
+    for func in funcs {
+        for param in &func.params {
+            uses(wit, &param.ty, &mut used)?;
+        }
+        if let Some(result) = &func.result {
+            uses(wit, result, &mut used)?;
+        }
+    }
+    for def in wit.types.iter().filter(|def| used.contains(&def.name)) {
+        out.push('\n');
+        doc(&mut out, "", def.docs.as_deref());
+        let name = pascal_case(&def.name, false);
+        match &def.kind {
+            TypeKind::Record(fields) => {
+                out.push_str(
+                    "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]\n",
+                );
+                let _ = writeln!(out, "pub struct {} {{", name);
+                for field in fields {
+                    doc(&mut out, "    ", field.docs.as_deref());
+                    let _ = writeln!(
+                        out,
+                        "    pub {}: {},",
+                        snake_case(&field.name),
+                        rust_type(&field.ty, "")
+                    );
+                }
+                out.push_str("}\n");
+            }
+            TypeKind::Enum(cases) | TypeKind::Variant(cases) => {
+                if matches!(def.kind, TypeKind::Enum(_)) {
+                    out.push_str(
+                        "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, \
+                         Deserialize, JsonSchema)]\n",
+                    );
+                } else {
+                    out.push_str(
+                        "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]\n",
+                    );
+                }
+                out.push_str("#[serde(rename_all = \"kebab-case\")]\n");
+                let _ = writeln!(out, "pub enum {} {{", name);
+                for case in cases {
+                    doc(&mut out, "    ", case.docs.as_deref());
+                    let variant = pascal_case(&case.name, false);
+                    if serde_case(&variant, '-') != case.name {
+                        let _ = writeln!(out, "    #[serde(rename = {:?})]", case.name);
+                    }
+                    match &case.ty {
+                        Some(ty) => {
+                            let _ = writeln!(out, "    {}({}),", variant, rust_type(ty, ""));
+                        }
+                        None => {
+                            let _ = writeln!(out, "    {},", variant);
+                        }
+                    }
+                }
+                out.push_str("}\n");
+            }
+            TypeKind::Alias(ty) => {
+                let _ = writeln!(out, "pub type {} = {};", name, rust_type(ty, ""));
+            }
+        }
+    }
+
+    for func in funcs.iter().filter(|func| func.params.len() > 1) {
+        let _ = write!(
+            out,
+            "\n/// The parameters of `{}`\n\
+             #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]\n\
+             pub struct {} {{\n",
+            export_name(&func.name),
+            params_name(&func.name)
+        );
+        for param in &func.params {
+            doc(&mut out, "    ", param.docs.as_deref());
+            let _ = writeln!(
+                out,
+                "    pub {}: {},",
+                snake_case(&param.name),
+                rust_type(&param.ty, "")
+            );
+        }
+        out.push_str("}\n");
+    }
+
+    out.push('\n');
+    match &world.docs {
+        Some(docs) => doc(&mut out, "", Some(docs)),
+        None => {
+            let _ = writeln!(out, "/// The functions world `{}` exports", world.name);
+        }
+    }
+    out.push_str("pub trait Guest {\n");
+    for (i, func) in funcs.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        doc(&mut out, "    ", func.docs.as_deref());
+        let params: Vec<String> = func
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", snake_case(&param.name), rust_type(&param.ty, "")))
+            .collect();
+        let _ = writeln!(
+            out,
+            "    fn {}({}) -> Result<{}, Error>;",
+            snake_case(&export_name(&func.name)),
+            params.join(", "),
+            result_type(func, "")
+        );
+    }
+    out.push_str("}\n");
+
+    let prefix = format!("{}::", module_path);
+    let macro_name = format!("export_{}", export_name(&world.name));
+    let _ = write!(
+        out,
+        "\n/// Export the functions of a type implementing `Guest`, with their schemas\n\
+         macro_rules! {} {{\n    ($guest:ty) => {{\n        \
+         extism_hello_plugin::export_plugin! {{\n",
+        macro_name
+    );
+    for func in funcs {
+        let name = export_name(&func.name);
+        let _ = writeln!(
+            out,
+            "            fn {}() -> {} {{",
+            snake_case(&name),
+            result_type(func, &prefix)
+        );
+        let call = format!("<$guest as {}Guest>::{}", prefix, snake_case(&name));
+        match func.params.as_slice() {
+            [] => {
+                let _ = writeln!(out, "                {}()", call);
+            }
+            [param] => {
+                let _ = writeln!(
+                    out,
+                    "                let input: {} =\n                    \
+                     extism_hello_plugin::extism_pdk::Host::input_json()?;\n                \
+                     {}(input)",
+                    rust_type(&param.ty, &prefix),
+                    call
+                );
+            }
+            params => {
+                let args: Vec<String> = params
+                    .iter()
+                    .map(|param| format!("params.{}", snake_case(&param.name)))
+                    .collect();
+                let _ = writeln!(
+                    out,
+                    "                let params: {}{} =\n                    \
+                     extism_hello_plugin::extism_pdk::Host::input_json()?;\n                \
+                     {}({})",
+                    prefix,
+                    params_name(&func.name),
+                    call,
+                    args.join(", ")
+                );
+            }
+        }
+        out.push_str("            }\n");
+    }
+    out.push_str("        }\n        extism_hello_plugin::plugin_metadata! {\n");
+    for func in funcs {
+        let _ = writeln!(
+            out,
+            "            fn {}({}) -> {};",
+            export_name(&func.name),
+            input_type(func, &prefix),
+            result_type(func, &prefix)
+        );
+    }
+    let _ = write!(
+        out,
+        "        }}\n    }};\n}}\npub(crate) use {};\n",
+        macro_name
+    );
+    Ok(out)
+}
+
+/// Add the named types `ty` refers to, directly or through other types
+fn uses(wit: &Wit, ty: &WitType, used: &mut BTreeSet<String>) -> Result<(), WitError> {
+    match ty {
+        WitType::List(ty) | WitType::Option(ty) => uses(wit, ty, used)?,
+        WitType::Result(ok, err) => {
+            for ty in [ok, err].into_iter().flatten() {
+                uses(wit, ty, used)?;
+            }
+        }
+        WitType::Tuple(types) => {
+            for ty in types {
+                uses(wit, ty, used)?;
+            }
+        }
+        WitType::Named(name) if used.insert(name.clone()) => match &wit.type_def(name)?.kind {
+            TypeKind::Record(fields) => {
+                for field in fields {
+                    uses(wit, &field.ty, used)?;
+                }
+            }
+            TypeKind::Enum(_) => {}
+            TypeKind::Variant(cases) => {
+                for ty in cases.iter().filter_map(|case| case.ty.as_ref()) {
+                    uses(wit, ty, used)?;
+                }
+            }
+            TypeKind::Alias(ty) => uses(wit, ty, used)?,
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// The Rust type of `ty`, with named types prefixed by `prefix`
+fn rust_type(ty: &WitType, prefix: &str) -> String {
+    match ty {
+        WitType::Bool => "bool".to_string(),
+        WitType::Integer(name) => name.replace('s', "i"),
+        WitType::Float(name) => name.to_string(),
+        WitType::Char => "char".to_string(),
+        WitType::String => "String".to_string(),
+        WitType::List(item) => format!("Vec<{}>", rust_type(item, prefix)),
+        WitType::Option(ty) => format!("Option<{}>", rust_type(ty, prefix)),
+        WitType::Result(ok, err) => {
+            let part = |ty: &Option<Box<WitType>>| {
+                ty.as_ref()
+                    .map_or("()".to_string(), |ty| rust_type(ty, prefix))
+            };
+            format!("Result<{}, {}>", part(ok), part(err))
+        }
+        WitType::Tuple(types) => {
+            let types: Vec<String> = types.iter().map(|ty| rust_type(ty, prefix)).collect();
+            match types.as_slice() {
+                [only] => format!("({},)", only),
+                _ => format!("({})", types.join(", ")),
+            }
+        }
+        WitType::Named(name) => format!("{}{}", prefix, pascal_case(name, false)),
+    }
+}
+
+/// The type a function's input is read as
+fn input_type(func: &Func, prefix: &str) -> String {
+    match func.params.as_slice() {
+        [] => "()".to_string(),
+        [param] => rust_type(&param.ty, prefix),
+        _ => format!("{}{}", prefix, params_name(&func.name)),
+    }
+}
+
+fn result_type(func: &Func, prefix: &str) -> String {
+    func.result
+        .as_ref()
+        .map_or("()".to_string(), |ty| rust_type(ty, prefix))
+}
+
+/// Rust name of a field, parameter or function
+fn snake_case(name: &str) -> String {
+    let name = name.replace('-', "_");
+    if RUST_KEYWORDS.contains(&name.as_str()) {
+        format!("r#{}", name)
+    } else {
+        name
+    }
+}
+
+fn doc(out: &mut String, indent: &str, docs: Option<&str>) {
+    for line in docs.into_iter().flat_map(str::lines) {
+        if line.is_empty() {
+            let _ = writeln!(out, "{}///", indent);
+        } else {
+            let _ = writeln!(out, "{}/// {}", indent, line);
+        }
+    }
+}
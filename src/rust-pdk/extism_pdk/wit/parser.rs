@@ -0,0 +1,467 @@
+//! Parsing the subset of WIT plugins use
+
+use super::{Case, Field, Func, Interface, TypeDef, TypeKind, Wit, WitError, WitType, World};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A name or keyword, without the `%` escaping keywords used as names
+    Ident(String),
+    /// A `///` comment
+    Doc(String),
+    Arrow,
+    Punct(char),
+}
+
+/// Split a document into tokens with their line numbers
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, WitError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.peek() == Some(&'/') => {
+                let comment: String =
+                    std::iter::from_fn(|| chars.next_if(|c| *c != '\n')).collect();
+                if let Some(doc) = comment.strip_prefix("//") {
+                    tokens.push((Token::Doc(doc.trim().to_string()), line));
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            last = c;
+                        }
+                        None => {
+                            return Err(WitError::Syntax {
+                                line,
+                                message: "unterminated comment".to_string(),
+                            })
+                        }
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                tokens.push((Token::Arrow, line));
+            }
+            c if c.is_ascii_alphanumeric() || c == '%' || c == '_' => {
+                let mut ident = String::new();
+                if c != '%' {
+                    ident.push(c);
+                }
+                ident.extend(std::iter::from_fn(|| {
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+                }));
+                tokens.push((Token::Ident(ident), line));
+            }
+            c if "{}()<>,:;=.@/*".contains(c) => tokens.push((Token::Punct(c), line)),
+            c => {
+                return Err(WitError::Syntax {
+                    line,
+                    message: format!("unexpected character {:?}", c),
+                })
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+pub(super) fn parse(source: &str) -> Result<Wit, WitError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        pos: 0,
+        wit: Wit::default(),
+    };
+    while parser.peek().is_some() {
+        let docs = parser.docs();
+        let line = parser.line();
+        match parser.ident()?.as_str() {
+            "package" => {
+                let mut package = String::new();
+                while let Some(token) = parser.next() {
+                    match token {
+                        Token::Punct(';') => break,
+                        Token::Ident(ident) => package.push_str(&ident),
+                        Token::Punct(c) => package.push(c),
+                        _ => return Err(parser.error("invalid package name")),
+                    }
+                }
+                parser.wit.package = Some(package);
+            }
+            "interface" => {
+                let name = parser.ident()?;
+                parser.expect('{')?;
+                let mut funcs = Vec::new();
+                while !parser.eat('}') {
+                    if let Some(func) = parser.item()? {
+                        funcs.push(func);
+                    }
+                }
+                parser.wit.interfaces.push(Interface { name, funcs });
+            }
+            "world" => {
+                let name = parser.ident()?;
+                parser.expect('{')?;
+                let mut world = World {
+                    name,
+                    docs,
+                    funcs: Vec::new(),
+                    exports: Vec::new(),
+                };
+                while !parser.eat('}') {
+                    parser.world_item(&mut world)?;
+                }
+                parser.wit.worlds.push(world);
+            }
+            "use" => parser.skip()?,
+            keyword => {
+                return Err(WitError::Syntax {
+                    line,
+                    message: format!("expected package, interface or world, found {}", keyword),
+                })
+            }
+        }
+    }
+    Ok(parser.wit)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    wit: Wit,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    /// Line of the next token
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self, message: impl Into<String>) -> WitError {
+        WitError::Syntax {
+            line: self.line(),
+            message: message.into(),
+        }
+    }
+
+    /// Consume the `///` comments before an item, joined into its docs
+    fn docs(&mut self) -> Option<String> {
+        let mut lines = Vec::new();
+        while let Some(Token::Doc(doc)) = self.peek() {
+            lines.push(doc.clone());
+            self.pos += 1;
+        }
+        Some(lines.join("\n")).filter(|docs| !docs.is_empty())
+    }
+
+    fn ident(&mut self) -> Result<String, WitError> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => {
+                let ident = ident.clone();
+                self.pos += 1;
+                Ok(ident)
+            }
+            Some(token) => Err(self.error(format!("expected a name, found {:?}", token))),
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+
+    /// Consume `punct` if it is next
+    fn eat(&mut self, punct: char) -> bool {
+        // Comments on nothing, such as at the end of a block, are ignored
+        let next = self.tokens[self.pos.min(self.tokens.len())..]
+            .iter()
+            .position(|(token, _)| !matches!(token, Token::Doc(_)));
+        match next {
+            Some(offset) if self.tokens[self.pos + offset].0 == Token::Punct(punct) => {
+                self.pos += offset + 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), WitError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {:?}", punct)))
+        }
+    }
+
+    /// Skip a statement plugins do not need, up to its `;` or its block
+    fn skip(&mut self) -> Result<(), WitError> {
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some(Token::Punct('{')) => depth += 1,
+                Some(Token::Punct('}')) if depth == 1 => {
+                    // Blocks end statements, as in `use types.{a, b};`
+                    self.eat(';');
+                    return Ok(());
+                }
+                Some(Token::Punct('}')) => depth -= 1,
+                Some(Token::Punct(';')) if depth == 0 => return Ok(()),
+                Some(_) => {}
+                None => return Err(self.error("unexpected end of document")),
+            }
+        }
+    }
+
+    fn unsupported(&self, line: usize, feature: &str) -> WitError {
+        WitError::Unsupported {
+            line,
+            feature: feature.to_string(),
+        }
+    }
+
+    /// Parse an item of an interface, returning it if it is a function
+    fn item(&mut self) -> Result<Option<Func>, WitError> {
+        let docs = self.docs();
+        let line = self.line();
+        let name = self.ident()?;
+        match name.as_str() {
+            "use" => self.skip()?,
+            "record" | "enum" | "variant" | "type" | "flags" | "resource" => {
+                self.type_def(&name, docs, line)?
+            }
+            _ => {
+                self.expect(':')?;
+                return Ok(Some(self.func(name, docs)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn world_item(&mut self, world: &mut World) -> Result<(), WitError> {
+        let docs = self.docs();
+        let line = self.line();
+        let keyword = self.ident()?;
+        match keyword.as_str() {
+            // Host functions are provided by the host, not generated
+            "import" | "use" | "include" => self.skip()?,
+            "export" => {
+                let name = self.ident()?;
+                if self.eat(';') {
+                    world.exports.push(name);
+                    return Ok(());
+                }
+                self.expect(':')?;
+                match self.peek() {
+                    Some(Token::Ident(ident)) if ident == "func" => {
+                        world.funcs.push(self.func(name, docs)?);
+                    }
+                    Some(Token::Ident(ident)) if ident == "interface" => {
+                        return Err(self.unsupported(line, "inline interfaces"));
+                    }
+                    _ => return Err(self.unsupported(line, "interfaces of other packages")),
+                }
+            }
+            _ => self.type_def(&keyword, docs, line)?,
+        }
+        Ok(())
+    }
+
+    /// Parse `func(params) -> result;`, after the name and colon
+    fn func(&mut self, name: String, docs: Option<String>) -> Result<Func, WitError> {
+        let line = self.line();
+        if self.ident()? != "func" {
+            return Err(WitError::Syntax {
+                line,
+                message: format!("expected func after {}:", name),
+            });
+        }
+        self.expect('(')?;
+        let params = self.fields(')')?;
+        let result = if self.peek() == Some(&Token::Arrow) {
+            self.pos += 1;
+            if self.peek() == Some(&Token::Punct('(')) {
+                return Err(self.unsupported(line, "named results"));
+            }
+            Some(self.ty()?)
+        } else {
+            None
+        };
+        self.expect(';')?;
+        Ok(Func {
+            name,
+            docs,
+            params,
+            result,
+        })
+    }
+
+    /// Parse `name: type` pairs separated by commas, up to `close`
+    fn fields(&mut self, close: char) -> Result<Vec<Field>, WitError> {
+        let mut fields = Vec::new();
+        while !self.eat(close) {
+            let docs = self.docs();
+            let name = self.ident()?;
+            self.expect(':')?;
+            let ty = self.ty()?;
+            fields.push(Field { name, docs, ty });
+            if !self.eat(',') {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Parse a type definition after its keyword
+    fn type_def(
+        &mut self,
+        keyword: &str,
+        docs: Option<String>,
+        line: usize,
+    ) -> Result<(), WitError> {
+        let kind = match keyword {
+            "record" | "enum" | "variant" | "type" => keyword,
+            "flags" => return Err(self.unsupported(line, "flags")),
+            "resource" => return Err(self.unsupported(line, "resources")),
+            _ => {
+                return Err(WitError::Syntax {
+                    line,
+                    message: format!("unexpected {}", keyword),
+                })
+            }
+        };
+        let name = self.ident()?;
+        let kind = match kind {
+            "record" => {
+                self.expect('{')?;
+                TypeKind::Record(self.fields('}')?)
+            }
+            "enum" => {
+                self.expect('{')?;
+                TypeKind::Enum(self.cases(false)?)
+            }
+            "variant" => {
+                self.expect('{')?;
+                TypeKind::Variant(self.cases(true)?)
+            }
+            _ => {
+                self.expect('=')?;
+                let ty = self.ty()?;
+                self.expect(';')?;
+                TypeKind::Alias(ty)
+            }
+        };
+        if self.wit.types.iter().any(|def| def.name == name) {
+            return Err(WitError::Duplicate(name));
+        }
+        self.wit.types.push(TypeDef { name, docs, kind });
+        Ok(())
+    }
+
+    /// Parse the cases of an enum, or of a variant when they may have a
+    /// payload
+    fn cases(&mut self, payloads: bool) -> Result<Vec<Case>, WitError> {
+        let mut cases = Vec::new();
+        while !self.eat('}') {
+            let docs = self.docs();
+            let name = self.ident()?;
+            let ty = if payloads && self.eat('(') {
+                let ty = self.ty()?;
+                self.expect(')')?;
+                Some(ty)
+            } else {
+                None
+            };
+            cases.push(Case { name, docs, ty });
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Ok(cases)
+    }
+
+    fn ty(&mut self) -> Result<WitType, WitError> {
+        let line = self.line();
+        let name = self.ident()?;
+        Ok(match name.as_str() {
+            "bool" => WitType::Bool,
+            "s8" => WitType::Integer("s8"),
+            "s16" => WitType::Integer("s16"),
+            "s32" => WitType::Integer("s32"),
+            "s64" => WitType::Integer("s64"),
+            "u8" => WitType::Integer("u8"),
+            "u16" => WitType::Integer("u16"),
+            "u32" => WitType::Integer("u32"),
+            "u64" => WitType::Integer("u64"),
+            "f32" | "float32" => WitType::Float("f32"),
+            "f64" | "float64" => WitType::Float("f64"),
+            "char" => WitType::Char,
+            "string" => WitType::String,
+            "list" => {
+                self.expect('<')?;
+                let item = self.ty()?;
+                self.expect('>')?;
+                WitType::List(Box::new(item))
+            }
+            "option" => {
+                self.expect('<')?;
+                let ty = self.ty()?;
+                self.expect('>')?;
+                WitType::Option(Box::new(ty))
+            }
+            "result" => {
+                if !self.eat('<') {
+                    return Ok(WitType::Result(None, None));
+                }
+                let ok = if self.peek() == Some(&Token::Ident("_".to_string())) {
+                    self.pos += 1;
+                    None
+                } else {
+                    Some(Box::new(self.ty()?))
+                };
+                let err = if self.eat(',') {
+                    Some(Box::new(self.ty()?))
+                } else {
+                    None
+                };
+                self.expect('>')?;
+                WitType::Result(ok, err)
+            }
+            "tuple" => {
+                self.expect('<')?;
+                let mut types = vec![self.ty()?];
+                while self.eat(',') {
+                    if self.peek() == Some(&Token::Punct('>')) {
+                        break;
+                    }
+                    types.push(self.ty()?);
+                }
+                self.expect('>')?;
+                WitType::Tuple(types)
+            }
+            "own" | "borrow" | "stream" | "future" => {
+                return Err(self.unsupported(line, &format!("{} types", name)))
+            }
+            _ => WitType::Named(name),
+        })
+    }
+}
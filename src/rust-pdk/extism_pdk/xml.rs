@@ -0,0 +1,60 @@
+//! XML input and output
+//!
+//! Enabled with the `xml` feature. `Host::input_xml` deserializes the input
+//! with serde and `Host::output_xml` serializes the output, for plugins
+//! integrating with APIs that still speak XML:
+//!
+//! ```ignore
+//! let request: QuoteRequest = Host::input_xml()?;
+//! let response = Host::http_request(&soap_request(&request))?;
+//! let envelope: Envelope<QuoteResponse> = xml::from_slice(&response.body())?;
+//! Host::output_xml(&envelope.body)?;
+//! ```
+//!
+//! Documents too large to deserialize whole are read with `reader`, a pull
+//! parser over the input as it is read:
+//!
+//! ```ignore
+//! let mut reader = xml::reader();
+//! let mut buf = Vec::new();
+//! let mut items = 0;
+//! loop {
+//!     match reader.read_event_into(&mut buf)? {
+//!         Event::Start(e) if e.name().as_ref() == "item" => items += 1,
+//!         Event::Eof => break,
+//!         _ => {}
+//!     }
+//!     buf.clear();
+//! }
+//! ```
+
+use std::io::{BufRead, BufReader};
+
+use super::io::{InputReader, CHUNK_SIZE};
+
+pub use quick_xml::events::{self, Event};
+pub use quick_xml::{DeError, SeError};
+
+/// A pull parser over the plugin input
+pub type Reader = quick_xml::Reader<BufReader<InputReader>>;
+
+/// Parse the plugin input as it is read, one event at a time
+pub fn reader() -> Reader {
+    quick_xml::Reader::from_reader(BufReader::with_capacity(CHUNK_SIZE, InputReader::new()))
+}
+
+/// Deserialize a value from an XML document
+pub fn from_slice<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, DeError> {
+    from_reader(data)
+}
+
+/// Deserialize a value from an XML document read from `reader`
+pub fn from_reader<T: serde::de::DeserializeOwned>(reader: impl BufRead) -> Result<T, DeError> {
+    quick_xml::de::from_reader(reader)
+}
+
+/// Serialize a value as an XML document, its root element named after its
+/// type
+pub fn to_string<T: serde::Serialize>(value: &T) -> Result<String, SeError> {
+    quick_xml::se::to_string(value)
+}
@@ -0,0 +1,1164 @@
+//! `extismx`: install, publish and search plugins of an Extismx registry
+//!
+//! ```text
+//! extismx install [NAME@REQ...] [--locked]
+//! extismx publish WASM [--metadata FILE] [--name NAME] [--version VERSION] ...
+//! extismx search [TEXT] [--capability NAME] [--license SPDX] [--author NAME]
+//! extismx info NAME
+//! extismx build [--target TARGET] [--manifest-path FILE] [--out-dir DIR] [--debug]
+//! extismx lint [--manifest-path FILE]
+//! extismx new NAME [--template http-api|data-transform|webhook] [--lang go|js|python] [--from SOURCE]
+//! extismx codegen WASM|METADATA|WIT|NAME [--lang typescript|go|rust] [--out FILE]
+//! extismx bindgen WIT [--world NAME] [--out FILE]
+//! extismx docs WASM|METADATA|WIT|NAME [--out FILE]
+//! extismx run WASM|MANIFEST FUNCTION [--input TEXT] [--config KEY=VALUE]
+//! extismx bench WASM|MANIFEST FUNCTION [--input TEXT] [--iterations N]
+//! extismx size WASM [--top N] [--baseline FILE] [--save-baseline FILE]
+//! extismx timeline TRACE... [--out FILE]
+//! extismx conformance WASM [--report FILE]
+//! extismx verify WASM|MANIFEST --spec FILE [--junit FILE]
+//! ```
+//!
+//! Like cargo, `install` keeps the plugins a project needs in `extismx.json`,
+//! resolves them with their dependencies, records the result in
+//! `extismx.lock` and downloads the locked modules to `plugins/`. The
+//! registry is given by `--registry URL` or `$EXTISMX_REGISTRY`, and tokens
+//! are read from `$EXTISMX_TOKEN` or the credentials file. `build`
+//! compiles the plugin crate in the current directory into a module and
+//! metadata ready to publish; installed as `cargo-extismx`, it also runs as
+//! `cargo extismx build`. `lint` checks the crate's sources for misuses of
+//! the PDK, which `build` also rejects. `new` generates a plugin crate to
+//! start from, or a Go, JavaScript or Python plugin with the interface of
+//! another, and `codegen` generates host-side types from the schemas a
+//! plugin declares or from a WIT world, and `bindgen` the plugin side of a
+//! WIT world.
+//! `docs` renders the same sources as Markdown documentation.
+//! `run` calls a function of a module once, printing its output and logs,
+//! and `bench` calls it repeatedly and reports its latency. `size` breaks
+//! a module's size down by section, crate and function. `timeline` turns
+//! traces recorded with `run --record` into a Perfetto timeline.
+//! `conformance` runs the host conformance suite of a plugin built with the
+//! `conformance` feature, and `verify` runs the calls listed in a YAML spec
+//! and checks their outputs, writing a JUnit XML report for CI.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = cli::run(args) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod cli {
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use extism_hello_plugin::extism_pdk::bench::Bench;
+    use extism_hello_plugin::extism_pdk::build::Builder;
+    use extism_hello_plugin::extism_pdk::capability::Capability;
+    use extism_hello_plugin::extism_pdk::codegen::{Codegen, Language};
+    use extism_hello_plugin::extism_pdk::conformance::Harness;
+    use extism_hello_plugin::extism_pdk::docs;
+    use extism_hello_plugin::extism_pdk::host::{
+        CallOptions, Manifest, Plugin, PluginBuilder, Wasm,
+    };
+    use extism_hello_plugin::extism_pdk::lint::{self, Severity};
+    use extism_hello_plugin::extism_pdk::registry::{
+        Attachments, Client, Credentials, GcOptions, Lockfile, PluginMetadata, Resolver,
+        SearchQuery, Target, TargetBuild, Version, VersionReq, LOCKFILE_NAME,
+    };
+    use extism_hello_plugin::extism_pdk::scaffold::{PluginLanguage, Scaffold};
+    use extism_hello_plugin::extism_pdk::size::{SizeBaseline, SizeReport};
+    use extism_hello_plugin::extism_pdk::trace::{self, Trace};
+    use extism_hello_plugin::extism_pdk::verify::Spec;
+    use extism_hello_plugin::extism_pdk::wit::Wit;
+    use serde::{Deserialize, Serialize};
+
+    /// File listing the plugins a project needs
+    const PROJECT_FILE: &str = "extismx.json";
+
+    /// Directory installed modules are written to
+    const PLUGINS_DIR: &str = "plugins";
+
+    const USAGE: &str = "\
+Usage: extismx [--registry URL] <command> [args]
+
+Commands:
+  install [NAME@REQ...] [--locked] [--target TARGET]
+                                     Add plugins to extismx.json and install them
+  publish WASM [options]             Publish a wasm module
+  search [TEXT] [options]            Search plugins
+  info NAME                          Show a plugin's releases and downloads
+  gc [--dry-run] [--retention DAYS]  Delete modules of versions yanked more than
+                                     DAYS (30) days ago, with the admin token
+  build [options]                    Build the plugin crate into a module and
+                                     metadata to publish
+  lint [--manifest-path FILE]        Check the plugin crate's sources for misuses
+                                     of the PDK
+  new NAME [options]                 Create a plugin crate in directory NAME, or
+                                     a Go, JavaScript or Python plugin
+  codegen SOURCE [options]           Generate TypeScript, Go or Rust types of the
+                                     functions of a wasm module, a metadata
+                                     JSON file, a WIT world or a published
+                                     plugin
+  bindgen WIT [options]              Generate the Rust bindings of a plugin
+                                     exporting a WIT world
+  docs SOURCE [--out FILE]           Generate Markdown documentation of the
+                                     functions, config and capabilities of a
+                                     plugin, from the same sources as codegen
+  run WASM|MANIFEST FUNCTION [options]
+                                     Call a function and print its output, with
+                                     its logs on standard error
+  bench WASM|MANIFEST FUNCTION [options]
+                                     Call a function repeatedly and report its
+                                     latency, allocations and fuel
+  size WASM [options]                Break the size of a module down by section,
+                                     crate and function, and flag known bloat
+  timeline TRACE... [--out FILE]     Convert recorded call traces into a Chrome
+                                     trace event timeline for Perfetto
+  conformance WASM [--report FILE]   Check the kernel functions of the host with a
+                                     plugin built with the conformance feature,
+                                     writing the report as JSON to FILE
+  verify WASM|MANIFEST --spec FILE [--junit FILE]
+                                     Run the calls of a YAML spec and check
+                                     their outputs and exit codes, writing a
+                                     JUnit XML report to FILE
+
+Publish options:
+  --metadata FILE        Read plugin metadata JSON from FILE
+  --name NAME            Plugin name, such as @org/plugin
+  --version VERSION      Semantic version
+  --description TEXT     Short description
+  --license SPDX         License expression
+  --author NAME          Plugin author, repeatable
+  --export NAME          Exported function, repeatable
+  --dependency NAME@REQ  Plugin dependency, repeatable
+  --capability NAME      Needed capability (http, wasi, wasi-fs, kv,
+                         function:NAME), repeatable
+  --target TARGET        Target the module was built for (wasm32-unknown-unknown,
+                         wasm32-wasip1, component)
+  --build TARGET=FILE    Build of the plugin for another target, repeatable
+
+Build options:
+  --manifest-path FILE   Cargo.toml of the plugin crate (./Cargo.toml)
+  --target TARGET        Target to build for, as configured in
+                         [package.metadata.extismx] or wasm32-unknown-unknown
+  --out-dir DIR          Where to write the module and metadata (target/extismx)
+  --export NAME          Function the module must export, repeatable
+  --no-wasm-opt          Do not run wasm-opt, even when it is installed
+  --no-strip             Keep custom sections, such as debug info
+  --debug                Keep DWARF debug info, so traps report source lines
+
+New options:
+  --template NAME        http-api (default), data-transform or webhook
+  --dir DIR              Directory to create, instead of the crate name
+  --pdk-path DIR         Depend on the PDK crate in DIR, instead of the
+                         sources extismx was built from
+  --pdk-git URL          Depend on the PDK from a git repository
+  --lang LANG            rust (default), go, js or python
+  --from SOURCE          Give a Go, JavaScript or Python plugin the functions
+                         and types of a wasm module, metadata JSON file, WIT
+                         world or published plugin, as codegen reads them
+  --world NAME           World of a WIT file defining several, for --from
+
+Codegen and docs options:
+  --lang LANG            typescript (default), go or rust
+  --out FILE             Write to FILE instead of standard output
+  --package NAME         Go package name, instead of the plugin's name
+  --name NAME            Plugin name for a wasm module, instead of its file name
+  --world NAME           World of a WIT file defining several
+
+Bindgen options:
+  --world NAME           World of a WIT file defining several
+  --out FILE             Write to FILE instead of standard output
+  --module PATH          Module path of the bindings (crate::bindings)
+
+Run and bench options:
+  --input TEXT           Input of each call
+  --input-file FILE      Read the input of each call from FILE, or - for
+                         standard input
+  --config KEY=VALUE     Config key of the plugin, repeatable
+  --allow-host HOST      Host the plugin may send HTTP requests to, repeatable
+  --wasi                 Run the plugin with WASI
+  --iterations N         Calls to measure (1000), for bench
+  --warmup N             Calls to make before measuring (100), for bench
+  --record FILE          Record the call's host calls to a trace file, for run
+
+Size options:
+  --top N                Largest crates and functions to list (20)
+  --baseline FILE        Compare with sizes saved by --save-baseline
+  --save-baseline FILE   Save the sizes to compare later builds with
+
+Search options:
+  --capability NAME      Only plugins declaring capability NAME
+  --license SPDX         Only plugins under this license
+  --author NAME          Only plugins by this author";
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    /// The plugins a project needs
+    #[derive(Default, Serialize, Deserialize)]
+    struct Project {
+        /// Version requirement of each plugin, such as `^1.2`, `~1.2.3` or `=1.2.3`
+        #[serde(default)]
+        plugins: BTreeMap<String, VersionReq>,
+        /// Version of each capability the host provides
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        host: BTreeMap<String, Version>,
+        /// Target of the builds to install, `wasm32-unknown-unknown` if unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<Target>,
+    }
+
+    /// Command line arguments split into positional arguments and options
+    struct Args {
+        positional: Vec<String>,
+        options: BTreeMap<String, Vec<String>>,
+        flags: Vec<String>,
+    }
+
+    impl Args {
+        /// Options that do not take a value
+        const FLAGS: &'static [&'static str] = &[
+            "--locked",
+            "--dry-run",
+            "--no-wasm-opt",
+            "--no-strip",
+            "--debug",
+            "--wasi",
+            "--help",
+        ];
+
+        fn parse(args: Vec<String>) -> Result<Self> {
+            let mut parsed = Args {
+                positional: Vec::new(),
+                options: BTreeMap::new(),
+                flags: Vec::new(),
+            };
+            let mut args = args.into_iter();
+            while let Some(arg) = args.next() {
+                if Self::FLAGS.contains(&arg.as_str()) || arg == "-h" {
+                    parsed.flags.push(arg);
+                } else if let Some((key, value)) =
+                    arg.split_once('=').filter(|_| arg.starts_with("--"))
+                {
+                    parsed
+                        .options
+                        .entry(key.to_string())
+                        .or_default()
+                        .push(value.to_string());
+                } else if arg.starts_with("--") {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("{} requires a value", arg))?;
+                    parsed.options.entry(arg).or_default().push(value);
+                } else {
+                    parsed.positional.push(arg);
+                }
+            }
+            Ok(parsed)
+        }
+
+        fn flag(&self, name: &str) -> bool {
+            self.flags.iter().any(|flag| flag == name)
+        }
+
+        /// Get the last value of an option
+        fn option(&self, name: &str) -> Option<String> {
+            self.options.get(name)?.last().cloned()
+        }
+
+        fn options(&self, name: &str) -> Vec<String> {
+            self.options.get(name).cloned().unwrap_or_default()
+        }
+
+        /// Fail on options the command does not take
+        fn check(&self, allowed: &[&str]) -> Result<()> {
+            match self
+                .options
+                .keys()
+                .find(|key| !allowed.contains(&key.as_str()))
+            {
+                Some(key) => Err(format!("unexpected option {}\n\n{}", key, USAGE).into()),
+                None => Ok(()),
+            }
+        }
+    }
+
+    pub fn run(args: Vec<String>) -> Result<()> {
+        let mut args = Args::parse(args)?;
+        if args.flag("--help") || args.flag("-h") || args.positional.is_empty() {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        // Building and scaffolding need no registry
+        match args.positional[0].as_str() {
+            "build" => {
+                args.positional.remove(0);
+                return build(&args);
+            }
+            "lint" => {
+                args.positional.remove(0);
+                return lint(&args);
+            }
+            "new"
+                if args
+                    .option("--from")
+                    .is_none_or(|source| local_source(&source)) =>
+            {
+                args.positional.remove(0);
+                return new(None, &args);
+            }
+            "bindgen" => {
+                args.positional.remove(0);
+                return bindgen(&args);
+            }
+            "run" => {
+                args.positional.remove(0);
+                return run_function(&args);
+            }
+            "size" => {
+                args.positional.remove(0);
+                return size(&args);
+            }
+            "bench" => {
+                args.positional.remove(0);
+                return bench(&args);
+            }
+            "timeline" => {
+                args.positional.remove(0);
+                return timeline(&args);
+            }
+            "conformance" => {
+                args.positional.remove(0);
+                return conformance(&args);
+            }
+            "verify" => {
+                args.positional.remove(0);
+                return verify(&args);
+            }
+            "codegen" | "docs"
+                if args
+                    .positional
+                    .get(1)
+                    .is_some_and(|source| local_source(source)) =>
+            {
+                let command = args.positional.remove(0);
+                if command == "docs" {
+                    return docs(None, &args);
+                }
+                return codegen(None, &args);
+            }
+            _ => {}
+        }
+        let registry = args
+            .options
+            .remove("--registry")
+            .and_then(|urls| urls.last().cloned())
+            .or_else(|| std::env::var("EXTISMX_REGISTRY").ok())
+            .ok_or("no registry given; pass --registry URL or set EXTISMX_REGISTRY")?;
+        let credentials = Credentials::load_default()?;
+        let mut client = Client::new(&registry).with_credentials(&credentials);
+        if let Ok(token) = std::env::var("EXTISMX_TOKEN") {
+            client = client.with_token(token);
+        }
+        let token = std::env::var("EXTISMX_TOKEN")
+            .ok()
+            .or_else(|| credentials.token(client.url()).map(str::to_string));
+
+        let command = args.positional.remove(0);
+        match command.as_str() {
+            "install" => install(&client, &args),
+            "publish" => publish(&client, &args, token.as_deref()),
+            "search" => search(&client, &args),
+            "info" => info(&client, &args),
+            "gc" => gc(&client, &args, token.as_deref()),
+            "codegen" => codegen(Some(&client), &args),
+            "docs" => docs(Some(&client), &args),
+            "new" => new(Some(&client), &args),
+            _ => Err(format!("unknown command {}\n\n{}", command, USAGE).into()),
+        }
+    }
+
+    /// Parse `org/plugin@^1.2` or `@org/plugin@^1.2` into a name and requirement
+    ///
+    /// Without a requirement, any version is accepted.
+    fn parse_spec(spec: &str) -> Result<(String, VersionReq)> {
+        let (name, req) = match spec.get(1..).and_then(|rest| rest.rfind('@')) {
+            Some(i) => (&spec[..i + 1], &spec[i + 2..]),
+            None => (spec, "*"),
+        };
+        let name = if name.contains('/') && !name.starts_with('@') {
+            format!("@{}", name)
+        } else {
+            name.to_string()
+        };
+        let req = VersionReq::parse(req)
+            .map_err(|e| format!("invalid version requirement {}: {}", req, e))?;
+        Ok((name, req))
+    }
+
+    fn install(client: &Client, args: &Args) -> Result<()> {
+        args.check(&["--target"])?;
+        let mut project: Project = match fs::read(PROJECT_FILE) {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| format!("invalid {}: {}", PROJECT_FILE, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Project::default(),
+            Err(e) => return Err(e.into()),
+        };
+        for spec in &args.positional {
+            let (name, req) = parse_spec(spec)?;
+            project.plugins.insert(name, req);
+        }
+        let changed_target = match args.option("--target") {
+            Some(target) => {
+                let target = Some(target.parse()?);
+                let changed = target != project.target;
+                project.target = target;
+                changed
+            }
+            None => false,
+        };
+        let target = project.target.unwrap_or_default();
+
+        let locked = match Lockfile::load(LOCKFILE_NAME) {
+            Ok(lockfile) if lockfile.satisfies(&project.plugins) && lockfile.target == target => {
+                Some(lockfile)
+            }
+            _ => None,
+        };
+        let lockfile = match locked {
+            Some(lockfile) => lockfile,
+            None if args.flag("--locked") => {
+                return Err(format!(
+                    "{} needs to be updated but --locked was given",
+                    LOCKFILE_NAME
+                )
+                .into());
+            }
+            None => {
+                let mut resolver = Resolver::new(client);
+                for (capability, version) in &project.host {
+                    resolver = resolver.with_host_capability(capability, version.clone());
+                }
+                let resolution = resolver.resolve(&project.plugins)?;
+                let lockfile = Lockfile::from_resolution_for(&resolution, target)?;
+                lockfile.save(LOCKFILE_NAME)?;
+                lockfile
+            }
+        };
+        if !args.positional.is_empty() || changed_target {
+            let mut data = serde_json::to_vec_pretty(&project)?;
+            data.push(b'\n');
+            fs::write(PROJECT_FILE, data)?;
+        }
+
+        let modules = lockfile.fetch(client)?;
+        for plugin in &lockfile.plugins {
+            let path = Path::new(PLUGINS_DIR).join(format!("{}.wasm", plugin.name));
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, &modules[&plugin.name])?;
+            println!(
+                "Installed {} {} ({}) to {}",
+                plugin.name,
+                plugin.version,
+                plugin.target,
+                path.display()
+            );
+        }
+        Ok(())
+    }
+
+    fn publish(client: &Client, args: &Args, token: Option<&str>) -> Result<()> {
+        args.check(&[
+            "--metadata",
+            "--name",
+            "--version",
+            "--description",
+            "--license",
+            "--author",
+            "--export",
+            "--dependency",
+            "--capability",
+            "--target",
+            "--build",
+        ])?;
+        let [wasm] = args.positional.as_slice() else {
+            return Err(format!("publish takes one wasm file\n\n{}", USAGE).into());
+        };
+        let wasm =
+            fs::read(PathBuf::from(wasm)).map_err(|e| format!("cannot read {}: {}", wasm, e))?;
+        let mut metadata = match args.option("--metadata") {
+            Some(path) => serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| format!("invalid metadata {}: {}", path, e))?,
+            None => PluginMetadata::default(),
+        };
+        if let Some(name) = args.option("--name") {
+            metadata.name = name;
+        }
+        if let Some(version) = args.option("--version") {
+            metadata.version = version;
+        }
+        if let Some(description) = args.option("--description") {
+            metadata.description = Some(description);
+        }
+        if let Some(license) = args.option("--license") {
+            metadata.license = Some(license);
+        }
+        metadata.authors.extend(args.options("--author"));
+        metadata.exports.extend(args.options("--export"));
+        for spec in args.options("--dependency") {
+            let (name, req) = parse_spec(&spec)?;
+            metadata.dependencies.insert(name, req);
+        }
+        for capability in args.options("--capability") {
+            metadata
+                .capabilities
+                .push(capability.parse::<Capability>()?);
+        }
+        if let Some(target) = args.option("--target") {
+            metadata.target = target.parse()?;
+        }
+        if metadata.name.is_empty() || metadata.version.is_empty() {
+            return Err("publishing needs a plugin name and version".into());
+        }
+        let mut attachments = Attachments::default();
+        for build in args.options("--build") {
+            let (target, path) = build
+                .split_once('=')
+                .ok_or_else(|| format!("--build takes TARGET=FILE, got {}", build))?;
+            let wasm = fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+            attachments
+                .builds
+                .push(TargetBuild::new(target.parse()?, wasm));
+        }
+
+        let token = token.ok_or("publishing needs a token; set EXTISMX_TOKEN")?;
+        let release = client.publish_with(&wasm, &metadata, &attachments, token)?;
+        println!(
+            "Published {} {} ({})",
+            release.metadata.name, release.metadata.version, release.sha256
+        );
+        Ok(())
+    }
+
+    fn search(client: &Client, args: &Args) -> Result<()> {
+        args.check(&["--capability", "--license", "--author"])?;
+        let mut query = SearchQuery::new();
+        if !args.positional.is_empty() {
+            query = query.with_text(args.positional.join(" "));
+        }
+        if let Some(capability) = args.option("--capability") {
+            query = query.with_capability(capability);
+        }
+        if let Some(license) = args.option("--license") {
+            query = query.with_license(license);
+        }
+        if let Some(author) = args.option("--author") {
+            query = query.with_author(author);
+        }
+        let results = client.search(&query)?;
+        if results.is_empty() {
+            println!("No plugins found");
+        }
+        for result in results {
+            let metadata = &result.release.metadata;
+            let mut line = format!("{} {}", metadata.name, metadata.version);
+            if !metadata.capabilities.is_empty() {
+                line.push_str(&format!(" [{}]", capabilities(&metadata.capabilities)));
+            }
+            if let Some(description) = &metadata.description {
+                line.push_str(&format!(": {}", description));
+            }
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    fn gc(client: &Client, args: &Args, token: Option<&str>) -> Result<()> {
+        args.check(&["--retention"])?;
+        let mut options = GcOptions::new().with_dry_run(args.flag("--dry-run"));
+        if let Some(days) = args.option("--retention") {
+            let days: u64 = days
+                .parse()
+                .map_err(|_| format!("--retention takes a number of days, got {}", days))?;
+            options = options.with_retention(Duration::from_secs(days * 86400));
+        }
+        let token = token.ok_or("gc needs the admin token; set EXTISMX_TOKEN")?;
+        let report = client.gc(&options, token)?;
+        let verb = if report.dry_run {
+            "Would delete"
+        } else {
+            "Deleted"
+        };
+        for blob in &report.blobs {
+            let versions: Vec<String> = blob
+                .references
+                .iter()
+                .map(|reference| format!("{} {}", reference.name, reference.version))
+                .collect();
+            println!(
+                "{} {} ({} bytes) {}",
+                verb,
+                blob.sha256,
+                blob.size,
+                versions.join(", ")
+            );
+        }
+        println!(
+            "{} {} modules and {} patches, freeing {} bytes; kept {} modules",
+            verb,
+            report.blobs.len(),
+            report.patches,
+            report.freed,
+            report.kept
+        );
+        Ok(())
+    }
+
+    fn build(args: &Args) -> Result<()> {
+        args.check(&["--manifest-path", "--target", "--out-dir", "--export"])?;
+        if !args.positional.is_empty() {
+            return Err(format!("build takes no arguments\n\n{}", USAGE).into());
+        }
+        let mut builder = Builder::new(
+            args.option("--manifest-path")
+                .unwrap_or_else(|| "Cargo.toml".to_string()),
+        )
+        .with_wasm_opt(!args.flag("--no-wasm-opt"))
+        .with_strip(!args.flag("--no-strip"))
+        .with_debug(args.flag("--debug"));
+        if let Some(target) = args.option("--target") {
+            builder = builder.with_target(target.parse()?);
+        }
+        if let Some(out_dir) = args.option("--out-dir") {
+            builder = builder.with_out_dir(out_dir);
+        }
+        for export in args.options("--export") {
+            builder = builder.with_export(export);
+        }
+        let output = builder.build()?;
+        for lint in &output.lints {
+            eprintln!("{}\n", lint);
+        }
+        let metadata = &output.metadata;
+        if !output.optimized
+            && !args.flag("--no-wasm-opt")
+            && !args.flag("--debug")
+            && metadata.target != Target::Component
+        {
+            eprintln!("note: wasm-opt was not found, so the module was not optimized");
+        }
+        println!(
+            "Built {} {} for {} ({} bytes, sha256 {})",
+            metadata.name, metadata.version, metadata.target, output.size, output.sha256
+        );
+        println!("  module:   {}", output.wasm.display());
+        println!("  metadata: {}", output.metadata_path.display());
+        println!("  exports:  {}", metadata.exports.join(", "));
+        Ok(())
+    }
+
+    /// Check the plugin crate's sources, failing when a lint is an error
+    fn lint(args: &Args) -> Result<()> {
+        args.check(&["--manifest-path"])?;
+        if !args.positional.is_empty() {
+            return Err(format!("lint takes no arguments\n\n{}", USAGE).into());
+        }
+        let lints = lint::lint_crate(
+            args.option("--manifest-path")
+                .unwrap_or_else(|| "Cargo.toml".to_string()),
+        )?;
+        for lint in &lints {
+            eprintln!("{}\n", lint);
+        }
+        let errors = lints
+            .iter()
+            .filter(|lint| lint.severity == Severity::Error)
+            .count();
+        if errors > 0 {
+            return Err(format!(
+                "{} error(s) and {} warning(s)",
+                errors,
+                lints.len() - errors
+            )
+            .into());
+        }
+        println!("{} warning(s)", lints.len());
+        Ok(())
+    }
+
+    fn new(client: Option<&Client>, args: &Args) -> Result<()> {
+        args.check(&[
+            "--template",
+            "--dir",
+            "--pdk-path",
+            "--pdk-git",
+            "--lang",
+            "--from",
+            "--world",
+        ])?;
+        let [name] = args.positional.as_slice() else {
+            return Err(format!("new takes one plugin name\n\n{}", USAGE).into());
+        };
+        let language: PluginLanguage = args
+            .option("--lang")
+            .map_or(Ok(PluginLanguage::Rust), |lang| lang.parse())?;
+        let mut scaffold = Scaffold::new(name).with_language(language);
+        if let Some(template) = args.option("--template") {
+            scaffold = scaffold.with_template(template.parse()?);
+        }
+        if let Some(source) = args.option("--from") {
+            if language == PluginLanguage::Rust {
+                return Err("--from needs --lang go, js or python".into());
+            }
+            scaffold = scaffold.with_interface(source_metadata(client, args, &source)?);
+        }
+        match (args.option("--pdk-path"), args.option("--pdk-git")) {
+            (Some(_), Some(_)) => return Err("pass --pdk-path or --pdk-git, not both".into()),
+            (Some(path), None) => scaffold = scaffold.with_pdk_path(fs::canonicalize(path)?),
+            (None, Some(url)) => scaffold = scaffold.with_pdk_git(url),
+            (None, None) => {}
+        }
+        let dir = args
+            .option("--dir")
+            .unwrap_or_else(|| scaffold.crate_name().to_string());
+        for file in scaffold.generate(&dir)? {
+            println!("  created {}", file.display());
+        }
+        if language == PluginLanguage::Rust {
+            println!(
+                "Created {} in {}; run `cargo extismx build` there to build it",
+                name, dir
+            );
+        } else {
+            println!("Created {} in {}; see its README.md to build it", name, dir);
+        }
+        Ok(())
+    }
+
+    /// Check if a codegen source is a file rather than a plugin name, which
+    /// cannot contain dots
+    fn local_source(source: &str) -> bool {
+        source.contains('.') || Path::new(source).is_file()
+    }
+
+    /// Read the metadata of a wasm module, a metadata file, a WIT world or
+    /// the latest release of a published plugin
+    ///
+    /// Metadata of a module or WIT world only has the functions' schemas.
+    fn source_metadata(
+        client: Option<&Client>,
+        args: &Args,
+        source: &str,
+    ) -> Result<PluginMetadata> {
+        let path = Path::new(source);
+        if path.extension().is_some_and(|ext| ext == "wasm") {
+            let wasm = fs::read(path)?;
+            let name = args.option("--name").unwrap_or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            });
+            let mut plugin = Plugin::from_manifest(&Manifest::new([Wasm::data(wasm)]))?;
+            let schema = plugin.describe_schema()?.ok_or_else(|| {
+                format!(
+                    "{} does not describe its functions with plugin_metadata!",
+                    source
+                )
+            })?;
+            Ok(PluginMetadata {
+                exports: schema.functions.iter().map(|f| f.name.clone()).collect(),
+                schemas: schema.functions,
+                ..PluginMetadata::new(name, "")
+            })
+        } else if path.extension().is_some_and(|ext| ext == "wit") {
+            let wit = Wit::load(path)?;
+            let world = args.option("--world");
+            let name = world
+                .clone()
+                .or_else(|| wit.worlds().first().map(|world| world.to_string()))
+                .unwrap_or_default();
+            Ok(PluginMetadata {
+                schemas: wit.schemas(world.as_deref())?,
+                ..PluginMetadata::new(name, "")
+            })
+        } else if let Some(client) = client.filter(|_| !local_source(source)) {
+            let (name, _) = parse_spec(source)?;
+            let info = client.info(&name)?;
+            let latest = info
+                .latest()
+                .ok_or_else(|| format!("{} has no releases", name))?;
+            Ok(latest.metadata.clone())
+        } else {
+            serde_json::from_slice(&fs::read(path)?)
+                .map_err(|e| format!("invalid metadata {}: {}", source, e).into())
+        }
+    }
+
+    /// Generate types from the schemas of a plugin
+    fn codegen(client: Option<&Client>, args: &Args) -> Result<()> {
+        args.check(&["--lang", "--out", "--package", "--name", "--world"])?;
+        let [source] = args.positional.as_slice() else {
+            return Err(format!("codegen takes one source\n\n{}", USAGE).into());
+        };
+        let mut codegen = Codegen::from_metadata(&source_metadata(client, args, source)?);
+        if let Some(package) = args.option("--package") {
+            codegen = codegen.with_package(package);
+        }
+        let language: Language = args
+            .option("--lang")
+            .map_or(Ok(Language::TypeScript), |lang| lang.parse())?;
+        let code = codegen.generate(language);
+        match args.option("--out") {
+            Some(out) => {
+                fs::write(&out, code)?;
+                println!("Wrote {} types to {}", language, out);
+            }
+            None => print!("{}", code),
+        }
+        Ok(())
+    }
+
+    /// Generate the Markdown documentation of a plugin
+    fn docs(client: Option<&Client>, args: &Args) -> Result<()> {
+        args.check(&["--out", "--name", "--world"])?;
+        let [source] = args.positional.as_slice() else {
+            return Err(format!("docs takes one source\n\n{}", USAGE).into());
+        };
+        let markdown = docs::markdown(&source_metadata(client, args, source)?);
+        match args.option("--out") {
+            Some(out) => {
+                fs::write(&out, markdown)?;
+                println!("Wrote documentation to {}", out);
+            }
+            None => print!("{}", markdown),
+        }
+        Ok(())
+    }
+
+    /// Generate the Rust bindings of a plugin exporting a WIT world
+    fn bindgen(args: &Args) -> Result<()> {
+        args.check(&["--world", "--out", "--module"])?;
+        let [source] = args.positional.as_slice() else {
+            return Err(format!("bindgen takes one WIT file\n\n{}", USAGE).into());
+        };
+        let wit = Wit::load(source)?;
+        let module = args
+            .option("--module")
+            .unwrap_or_else(|| "crate::bindings".to_string());
+        let code = wit.guest_bindings(args.option("--world").as_deref(), &module)?;
+        match args.option("--out") {
+            Some(out) => {
+                fs::write(&out, code)?;
+                println!("Wrote bindings to {}", out);
+            }
+            None => print!("{}", code),
+        }
+        Ok(())
+    }
+
+    /// Load a wasm module, or the plugin a manifest describes, with the
+    /// config keys, hosts and WASI access the options grant
+    fn load_manifest(source: &str, args: &Args) -> Result<Manifest> {
+        let mut manifest = if Path::new(source)
+            .extension()
+            .is_some_and(|ext| ext == "json")
+        {
+            serde_json::from_slice(&fs::read(source)?)
+                .map_err(|e| format!("invalid manifest {}: {}", source, e))?
+        } else {
+            Manifest::new([Wasm::file(source)])
+        };
+        for pair in args.options("--config") {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("--config takes KEY=VALUE, not {}", pair))?;
+            manifest = manifest.with_config_key(key, value);
+        }
+        for host in args.options("--allow-host") {
+            manifest = manifest.with_allowed_host(host);
+        }
+        if args.flag("--wasi") {
+            manifest = manifest.with_wasi(true);
+        }
+        Ok(manifest)
+    }
+
+    /// Read the input given by `--input`, or by `--input-file`, where `-`
+    /// is standard input
+    fn call_input(args: &Args) -> Result<Vec<u8>> {
+        match (args.option("--input"), args.option("--input-file")) {
+            (Some(_), Some(_)) => Err("pass --input or --input-file, not both".into()),
+            (Some(input), None) => Ok(input.into_bytes()),
+            (None, Some(file)) if file == "-" => {
+                let mut input = Vec::new();
+                std::io::Read::read_to_end(&mut std::io::stdin(), &mut input)?;
+                Ok(input)
+            }
+            (None, Some(file)) => Ok(fs::read(file)?),
+            (None, None) => Ok(Vec::new()),
+        }
+    }
+
+    /// Call a function of a wasm module, or of the plugin a manifest
+    /// describes, printing its output and, on standard error, its logs
+    fn run_function(args: &Args) -> Result<()> {
+        args.check(&[
+            "--input",
+            "--input-file",
+            "--config",
+            "--allow-host",
+            "--record",
+        ])?;
+        let [source, function] = args.positional.as_slice() else {
+            return Err(format!("run takes a module and a function\n\n{}", USAGE).into());
+        };
+        let manifest = load_manifest(source, args)?;
+        let input = call_input(args)?;
+        let mut plugin = PluginBuilder::new(manifest)
+            .with_log_sink(|level, message| eprintln!("[{}] {}", level, message))
+            .build()?;
+        let mut options = CallOptions::new();
+        if let Some(record) = args.option("--record") {
+            options = options.record(record);
+        }
+        let output = plugin
+            .call_with_options(function, input, &options)
+            .map_err(|e| {
+                let backtrace = e.backtrace();
+                if backtrace.is_empty() {
+                    return e.to_string();
+                }
+                let mut message = format!("{}\n\nwasm backtrace:", e);
+                for (index, frame) in backtrace.iter().enumerate() {
+                    message.push_str(&format!("\n  {:>3}: {}", index, frame));
+                }
+                if backtrace.iter().all(|frame| frame.file.is_none()) {
+                    message.push_str(
+                    "\n\nnote: build the module with `extismx build --debug` to see source lines",
+                );
+                }
+                message
+            })?
+            .output;
+        let mut stdout = std::io::stdout().lock();
+        std::io::Write::write_all(&mut stdout, &output)?;
+        if !output.is_empty() && !output.ends_with(b"\n") {
+            std::io::Write::write_all(&mut stdout, b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Convert recorded traces into a Chrome trace event timeline
+    fn timeline(args: &Args) -> Result<()> {
+        args.check(&["--out"])?;
+        if args.positional.is_empty() {
+            return Err(format!("timeline takes one or more traces\n\n{}", USAGE).into());
+        }
+        let traces = args
+            .positional
+            .iter()
+            .map(Trace::load)
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let timeline = serde_json::to_string(&trace::chrome_trace(&traces))?;
+        match args.option("--out") {
+            Some(out) => {
+                fs::write(&out, timeline)?;
+                println!(
+                    "Wrote a timeline of {} call(s) to {}; open it in https://ui.perfetto.dev",
+                    traces.len(),
+                    out
+                );
+            }
+            None => println!("{}", timeline),
+        }
+        Ok(())
+    }
+
+    /// Run the host conformance suite with a conformance plugin
+    fn conformance(args: &Args) -> Result<()> {
+        args.check(&["--report"])?;
+        let [wasm] = args.positional.as_slice() else {
+            return Err(format!("conformance takes one module\n\n{}", USAGE).into());
+        };
+        let harness = Harness::start()?;
+        let report = harness.run_plugin(Wasm::file(wasm))?;
+        println!("{}", report);
+        if let Some(out) = args.option("--report") {
+            fs::write(&out, serde_json::to_vec_pretty(&report)?)?;
+            println!("Wrote the report to {}", out);
+        }
+        if !report.passed() {
+            return Err(format!("{} check(s) failed", report.failures().count()).into());
+        }
+        Ok(())
+    }
+
+    /// Smoke test a module against the calls of a spec
+    fn verify(args: &Args) -> Result<()> {
+        args.check(&["--spec", "--junit", "--config", "--allow-host"])?;
+        let [source] = args.positional.as_slice() else {
+            return Err(format!("verify takes one module\n\n{}", USAGE).into());
+        };
+        let spec = args
+            .option("--spec")
+            .ok_or_else(|| format!("verify needs --spec FILE\n\n{}", USAGE))?;
+        let suite = Spec::load(&spec)?.run(load_manifest(source, args)?)?;
+        println!("{}", suite);
+        if let Some(junit) = args.option("--junit") {
+            let name = Path::new(source).file_stem().map_or_else(
+                || source.clone(),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+            fs::write(&junit, suite.junit_xml(&name))?;
+            println!("Wrote the JUnit report to {}", junit);
+        }
+        if !suite.passed() {
+            return Err(format!("{} test(s) failed", suite.failures()).into());
+        }
+        Ok(())
+    }
+
+    /// Benchmark a function of a wasm module, or of the plugin a manifest
+    /// describes
+    fn bench(args: &Args) -> Result<()> {
+        args.check(&[
+            "--input",
+            "--input-file",
+            "--iterations",
+            "--warmup",
+            "--config",
+            "--allow-host",
+        ])?;
+        let [source, function] = args.positional.as_slice() else {
+            return Err(format!("bench takes a module and a function\n\n{}", USAGE).into());
+        };
+        let manifest = load_manifest(source, args)?;
+        let mut bench = Bench::new(function, call_input(args)?);
+        if let Some(iterations) = args.option("--iterations") {
+            bench = bench.with_iterations(iterations.parse()?);
+        }
+        if let Some(warmup) = args.option("--warmup") {
+            bench = bench.with_warmup(warmup.parse()?);
+        }
+        let mut plugin = Plugin::from_manifest(&manifest)?;
+        println!("{}", bench.run(&mut plugin)?);
+        Ok(())
+    }
+
+    /// Report where the bytes of a module go, and how they changed since a
+    /// baseline
+    fn size(args: &Args) -> Result<()> {
+        args.check(&["--top", "--baseline", "--save-baseline"])?;
+        let [wasm] = args.positional.as_slice() else {
+            return Err(format!("size takes one module\n\n{}", USAGE).into());
+        };
+        let report = SizeReport::analyze(&fs::read(wasm)?)?;
+        let top = args.option("--top").map_or(Ok(20), |top| top.parse())?;
+        print!("{}", report.render(top));
+        if let Some(file) = args.option("--baseline") {
+            let baseline: SizeBaseline = serde_json::from_slice(&fs::read(&file)?)
+                .map_err(|e| format!("invalid baseline {}: {}", file, e))?;
+            println!("\nSince {}:", file);
+            for delta in report.compare(&baseline) {
+                println!("  {}", delta);
+            }
+        }
+        if let Some(file) = args.option("--save-baseline") {
+            let mut baseline = serde_json::to_string_pretty(&report.baseline())?;
+            baseline.push('\n');
+            fs::write(&file, baseline)?;
+            println!("\nSaved the sizes to {}", file);
+        }
+        Ok(())
+    }
+
+    fn info(client: &Client, args: &Args) -> Result<()> {
+        args.check(&[])?;
+        let [name] = args.positional.as_slice() else {
+            return Err(format!("info takes one plugin name\n\n{}", USAGE).into());
+        };
+        let (name, _) = parse_spec(name)?;
+        let info = client.info(&name)?;
+        println!("{}", info.name);
+        if let Some(latest) = info.latest() {
+            let metadata = &latest.metadata;
+            println!("latest:       {}", metadata.version);
+            if let Some(description) = &metadata.description {
+                println!("description:  {}", description);
+            }
+            if let Some(license) = &metadata.license {
+                println!("license:      {}", license);
+            }
+            if !metadata.authors.is_empty() {
+                println!("authors:      {}", metadata.authors.join(", "));
+            }
+            if !metadata.exports.is_empty() {
+                println!("exports:      {}", metadata.exports.join(", "));
+            }
+            if !metadata.capabilities.is_empty() {
+                println!("capabilities: {}", capabilities(&metadata.capabilities));
+            }
+            if !metadata.schemas.is_empty() {
+                let described: Vec<&str> = metadata
+                    .schemas
+                    .iter()
+                    .map(|schema| schema.name.as_str())
+                    .collect();
+                println!("schemas:      {}", described.join(", "));
+            }
+            let targets: Vec<String> = std::iter::once(metadata.target)
+                .chain(latest.targets.iter().map(|artifact| artifact.target))
+                .map(|target| target.to_string())
+                .collect();
+            println!("targets:      {}", targets.join(", "));
+            for (dependency, req) in &metadata.dependencies {
+                println!("dependency:   {} {}", dependency, req);
+            }
+        }
+        println!("downloads:    {}", info.downloads.total);
+        println!("versions:");
+        for release in info.releases.iter().rev() {
+            let downloads = info
+                .downloads
+                .versions
+                .get(&release.metadata.version)
+                .map_or(0, |downloads| downloads.total);
+            let mut notes = Vec::new();
+            if release.yanked {
+                notes.push("yanked".to_string());
+            }
+            if let Some(message) = &release.deprecated {
+                notes.push(format!("deprecated: {}", message));
+            }
+            let notes = if notes.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", notes.join("; "))
+            };
+            println!(
+                "  {:<16} {:>8} downloads{}",
+                release.metadata.version, downloads, notes
+            );
+        }
+        Ok(())
+    }
+
+    fn capabilities(capabilities: &[Capability]) -> String {
+        capabilities
+            .iter()
+            .map(Capability::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -5,8 +5,8 @@
 use serde::{Deserialize, Serialize};
 
 // Import the Extism PDK
-mod extism_pdk;
-use extism_pdk::{Host, export_plugin};
+pub mod extism_pdk;
+use extism_pdk::{Error, Host};
 
 /// Input structure for the hello function
 #[derive(Deserialize)]
@@ -21,8 +21,9 @@ struct HelloOutput {
 }
 
 /// Hello function implementation
-fn hello_impl() -> Result<HelloOutput, String> {
+fn hello_impl() -> Result<HelloOutput, Error> {
     // Log the function call
+    #[cfg(feature = "log")]
     Host::log_debug("Hello function called");
 
     // Parse the input JSON
@@ -32,13 +33,14 @@ fn hello_impl() -> Result<HelloOutput, String> {
             Ok(input) => input,
             Err(_) => HelloInput { name: s },
         },
-        Err(e) => return Err(format!("Failed to read input: {}", e)),
+        Err(e) => return Err(Error::invalid(format!("Failed to read input: {}", e))),
     };
 
     // Create the greeting
     let greeting = format!("Hello, {}!", input.name);
     
     // Log the greeting 
+    #[cfg(feature = "log")]
     Host::log_info(&format!("Created greeting: {}", greeting));
     
     // Return the output
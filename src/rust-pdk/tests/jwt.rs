@@ -0,0 +1,120 @@
+//! Time checks and key handling of JWT verification
+//!
+//! Run with `cargo test --features jwt,testing --test jwt`.
+
+#![cfg(all(not(target_arch = "wasm32"), feature = "jwt", feature = "testing"))]
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use extism_hello_plugin::extism_pdk::crypto;
+use extism_hello_plugin::extism_pdk::jwt::{Jwks, JwtError, Validation};
+use serde_json::{json, Value};
+
+const SECRET: &[u8] = b"secret";
+
+/// The current time of the tests, in seconds since the Unix epoch
+const NOW: u64 = 1_700_000_000;
+
+/// A token of `claims`, signed with HS256 and `secret`
+fn hs256(claims: Value, secret: &[u8]) -> String {
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature = crypto::hmac_sha256(secret, message.as_bytes());
+    format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature))
+}
+
+/// A key set holding one RSA key with a modulus of `bytes` bytes
+fn rsa_jwks(bytes: usize) -> Jwks {
+    serde_json::from_value(json!({
+        "keys": [{
+            "kty": "RSA",
+            "kid": "rsa",
+            "n": URL_SAFE_NO_PAD.encode(vec![0xff; bytes]),
+            "e": "AQAB",
+        }]
+    }))
+    .unwrap()
+}
+
+fn verify_at(now: u64, token: &str) -> Result<Value, JwtError> {
+    Validation::new().with_now(now).verify(token, SECRET)
+}
+
+#[test]
+fn expired_token_is_rejected() {
+    let token = hs256(json!({ "exp": NOW - 61 }), SECRET);
+    assert!(matches!(verify_at(NOW, &token), Err(JwtError::Expired)));
+}
+
+#[test]
+fn token_not_yet_valid_is_rejected() {
+    let token = hs256(json!({ "exp": NOW + 3600, "nbf": NOW + 61 }), SECRET);
+    assert!(matches!(verify_at(NOW, &token), Err(JwtError::NotYetValid)));
+}
+
+#[test]
+fn token_within_leeway_is_accepted() {
+    let expired = hs256(json!({ "exp": NOW - 60 }), SECRET);
+    assert!(verify_at(NOW, &expired).is_ok());
+    let early = hs256(json!({ "exp": NOW + 3600, "nbf": NOW + 60 }), SECRET);
+    assert!(verify_at(NOW, &early).is_ok());
+    let strict = Validation::new().with_now(NOW).with_leeway(0);
+    assert!(matches!(
+        strict.verify::<Value>(&expired, SECRET),
+        Err(JwtError::Expired)
+    ));
+}
+
+#[test]
+fn hs256_token_is_rejected_by_rsa_key_set() {
+    let jwks = rsa_jwks(256);
+    // Signed with the public modulus as the HMAC secret, as an attacker who
+    // knows the key set would
+    let modulus = URL_SAFE_NO_PAD
+        .decode(jwks.keys[0].n.as_deref().unwrap())
+        .unwrap();
+    let token = hs256(json!({ "exp": NOW + 3600 }), &modulus);
+    match Validation::new()
+        .with_now(NOW)
+        .verify::<Value>(&token, &jwks)
+    {
+        Err(JwtError::UnsupportedAlgorithm(alg)) => assert_eq!(alg, "HS256"),
+        other => panic!("expected an unsupported algorithm, got {:?}", other),
+    }
+}
+
+#[test]
+fn small_rsa_key_is_rejected() {
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256","kid":"rsa"}"#),
+        URL_SAFE_NO_PAD.encode(json!({ "exp": NOW + 3600 }).to_string())
+    );
+    let token = format!("{}.{}", message, URL_SAFE_NO_PAD.encode([1; 128]));
+    match Validation::new()
+        .with_now(NOW)
+        .verify::<Value>(&token, &rsa_jwks(128))
+    {
+        Err(JwtError::InvalidKey(e)) => assert!(e.contains("1024 bits"), "{}", e),
+        other => panic!("expected an invalid key, got {:?}", other),
+    }
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn expiry_is_checked_against_the_host_clock() {
+    use extism_hello_plugin::extism_pdk::jwt;
+    use extism_hello_plugin::extism_pdk::testing::MockHost;
+
+    let _host = MockHost::new().with_time_ms(NOW * 1000);
+    let expired = hs256(json!({ "exp": NOW - 61 }), SECRET);
+    assert!(matches!(
+        jwt::verify::<Value>(&expired, SECRET),
+        Err(JwtError::Expired)
+    ));
+    let valid = hs256(json!({ "exp": NOW + 60 }), SECRET);
+    assert!(jwt::verify::<Value>(&valid, SECRET).is_ok());
+}
@@ -0,0 +1,117 @@
+//! Kernel functions called with arguments a plugin controls
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use extism_hello_plugin::extism_pdk::host::{CallError, CallOptions, Manifest, Plugin, Wasm};
+
+/// A module whose `run` export runs `body`, with the kernel functions it
+/// calls imported
+fn module(body: &str) -> Vec<u8> {
+    let wat = format!(
+        r#"(module
+            (import "env" "extism_alloc" (func $alloc (param i64) (result i64)))
+            (import "env" "extism_free" (func $free (param i64)))
+            (import "env" "extism_output_set" (func $output_set (param i32 i64)))
+            (import "env" "extism_error_set" (func $error_set (param i32 i64)))
+            (import "env" "extism_var_set" (func $var_set (param i32 i64 i32 i64)))
+            (memory (export "memory") 1)
+            (func (export "run") (result i32) {} i32.const 0))"#,
+        body
+    );
+    wat::parse_str(wat).unwrap()
+}
+
+fn plugin(body: &str) -> Plugin {
+    Plugin::new(module(body)).unwrap()
+}
+
+/// A plugin limited to 16 pages, 1 MiB
+fn limited_plugin(body: &str) -> Plugin {
+    let manifest = Manifest::new([Wasm::data(module(body))]).with_memory_max_pages(16);
+    Plugin::from_manifest(&manifest).unwrap()
+}
+
+#[test]
+fn alloc_beyond_wasm32_memory_traps() {
+    let mut plugin = plugin("(drop (call $alloc (i64.const 35184372088832)))");
+    match plugin.call("run", "") {
+        Err(e @ CallError::Trap(_)) => assert!(e.to_string().contains("memory block too large")),
+        other => panic!("expected a trap, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn output_beyond_linear_memory_traps() {
+    let mut plugin = plugin("(call $output_set (i32.const 0) (i64.const 35184372088832))");
+    match plugin.call("run", "") {
+        Err(e @ CallError::Trap(_)) => assert!(e.to_string().contains("out of bounds")),
+        other => panic!("expected a trap, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn output_within_linear_memory() {
+    let mut plugin = plugin("(call $output_set (i32.const 0) (i64.const 4))");
+    assert_eq!(plugin.call("run", "").unwrap(), vec![0; 4]);
+}
+
+#[test]
+fn alloc_beyond_memory_limit_fails() {
+    let mut plugin = limited_plugin("(drop (call $alloc (i64.const 2097152)))");
+    assert!(matches!(
+        plugin.call("run", ""),
+        Err(CallError::MemoryLimitExceeded)
+    ));
+}
+
+#[test]
+fn freed_blocks_are_not_counted() {
+    let mut plugin = limited_plugin(
+        "(call $free (call $alloc (i64.const 655360)))
+         (call $free (call $alloc (i64.const 655360)))",
+    );
+    plugin.call("run", "").unwrap();
+    plugin.call("run", "").unwrap();
+}
+
+#[test]
+fn vars_beyond_memory_limit_fail() {
+    // Sets the var named by the byte at 0 to 64 KiB, 16 times
+    let mut plugin = limited_plugin(
+        "(local $i i32)
+         (loop $set
+            (i32.store8 (i32.const 0) (local.get $i))
+            (call $var_set (i32.const 0) (i64.const 1) (i32.const 0) (i64.const 65536))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $set (i32.lt_u (local.get $i) (i32.const 16))))",
+    );
+    assert!(matches!(
+        plugin.call("run", ""),
+        Err(CallError::MemoryLimitExceeded)
+    ));
+}
+
+#[test]
+fn peak_memory_counts_blocks_freed_during_the_call() {
+    let mut plugin = plugin("(call $free (call $alloc (i64.const 262144)))");
+    for _ in 0..2 {
+        let usage = plugin
+            .call_with_options("run", "", &CallOptions::default())
+            .unwrap()
+            .usage;
+        assert_eq!(usage.peak_memory_bytes, 65536 + 262144);
+    }
+}
+
+#[test]
+fn peak_memory_counts_the_error() {
+    // Sets an error but succeeds, so the error is only held, not returned
+    let mut plugin = plugin("(call $error_set (i32.const 0) (i64.const 65536))");
+    for _ in 0..2 {
+        let usage = plugin
+            .call_with_options("run", "", &CallOptions::default())
+            .unwrap()
+            .usage;
+        assert_eq!(usage.peak_memory_bytes, 65536 + 65536);
+    }
+}
@@ -0,0 +1,55 @@
+//! Pools replacing instances poisoned by a trap
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::thread;
+use std::time::Duration;
+
+use extism_hello_plugin::extism_pdk::host::{
+    CallError, Error, Manifest, PluginPool, RestartPolicy, Wasm,
+};
+
+/// A plugin whose `trap` export traps and whose `run` export succeeds
+fn pool() -> PluginPool {
+    let wasm = wat::parse_str(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "trap") (result i32) unreachable)
+            (func (export "run") (result i32) i32.const 0))"#,
+    )
+    .unwrap();
+    PluginPool::new(&Manifest::new([Wasm::data(wasm)]), 1)
+        .unwrap()
+        .with_restart_policy(RestartPolicy {
+            max_restarts: Some(0),
+            backoff: Duration::ZERO,
+        })
+        .with_restart_cooldown(Duration::from_millis(100))
+}
+
+#[test]
+fn restart_limit_holds_until_cooldown() {
+    let pool = pool();
+    assert!(matches!(pool.call("trap", ""), Err(CallError::Trap(_))));
+    assert!(matches!(
+        pool.call("run", ""),
+        Err(CallError::Instantiate(Error::RestartLimitExceeded))
+    ));
+
+    thread::sleep(Duration::from_millis(150));
+    pool.call("run", "").unwrap();
+    assert_eq!(pool.stats().consecutive_failures, 0);
+    assert_eq!(pool.stats().restarts, 1);
+}
+
+#[test]
+fn restart_limit_is_reached_again_after_cooldown() {
+    let pool = pool();
+    assert!(pool.call("trap", "").is_err());
+    thread::sleep(Duration::from_millis(150));
+    assert!(matches!(pool.call("trap", ""), Err(CallError::Trap(_))));
+    assert!(matches!(
+        pool.call("run", ""),
+        Err(CallError::Instantiate(Error::RestartLimitExceeded))
+    ));
+}
@@ -0,0 +1,214 @@
+//! The registry's HTTP API
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use extism_hello_plugin::extism_pdk::registry::{
+    MemoryStorage, PluginMetadata, Registry, Server, TokenScope,
+};
+
+/// Serve `registry` on a free port, returning the server
+fn serve(registry: Registry) -> Arc<Server> {
+    let server = Arc::new(Server::bind("127.0.0.1:0", registry).unwrap());
+    let running = server.clone();
+    std::thread::spawn(move || running.run());
+    server
+}
+
+/// Send a raw HTTP request, returning the status of the response
+fn send(server: &Server, request: impl AsRef<[u8]>) -> u16 {
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    stream.write_all(request.as_ref()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap()
+}
+
+#[test]
+fn body_over_limit_is_rejected() {
+    let server = serve(Registry::new(MemoryStorage::new()));
+    let status = send(
+        &server,
+        "POST /api/v1/plugins HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Length: 104857601\r\n\r\n",
+    );
+    assert_eq!(status, 413);
+    server.shutdown();
+}
+
+/// A publish request whose body is `parts`, each prefixed with its length,
+/// followed by `trailer`
+fn publish_request(parts: &[&[u8]], trailer: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(&(part.len() as u32).to_le_bytes());
+        body.extend_from_slice(part);
+    }
+    body.extend_from_slice(trailer);
+    let mut request = format!(
+        "PUT /api/v1/plugins/new HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Length: {}\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    request.extend_from_slice(&body);
+    request
+}
+
+/// The metadata and module of a release to publish
+fn release(name: &str) -> (Vec<u8>, Vec<u8>) {
+    let metadata = PluginMetadata::new(name, "1.0.0").with_export("run");
+    let wasm = wat::parse_str(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "run") (result i32) i32.const 0))"#,
+    )
+    .unwrap();
+    (serde_json::to_vec(&metadata).unwrap(), wasm)
+}
+
+#[test]
+fn publish_without_attachments() {
+    let server = serve(Registry::new(MemoryStorage::new()));
+    let (metadata, wasm) = release("plain");
+    assert_eq!(
+        send(&server, publish_request(&[&metadata, &wasm], b"")),
+        200
+    );
+    assert_eq!(server.registry().releases("plain").unwrap().len(), 1);
+    server.shutdown();
+}
+
+#[test]
+fn publish_with_truncated_attachments_is_rejected() {
+    let server = serve(Registry::new(MemoryStorage::new()));
+    let (metadata, wasm) = release("truncated");
+    // The attachments claim 100 bytes, but the body ends after 5
+    let mut trailer = 100u32.to_le_bytes().to_vec();
+    trailer.extend_from_slice(b"{\"sig");
+    let request = publish_request(&[&metadata, &wasm], &trailer);
+    assert_eq!(send(&server, request), 400);
+    assert!(server
+        .registry()
+        .releases("truncated")
+        .map_or(true, |releases| releases.is_empty()));
+    server.shutdown();
+}
+
+#[test]
+fn publish_with_truncated_build_is_rejected() {
+    let server = serve(Registry::new(MemoryStorage::new()));
+    let (metadata, wasm) = release("partial");
+    // A build header cut short
+    let mut trailer = 50u32.to_le_bytes().to_vec();
+    trailer.extend_from_slice(br#"{"tar"#);
+    let request = publish_request(&[&metadata, &wasm, b"{}"], &trailer);
+    assert_eq!(send(&server, request), 400);
+    assert!(server
+        .registry()
+        .releases("partial")
+        .map_or(true, |releases| releases.is_empty()));
+    server.shutdown();
+}
+
+/// Get `path`, with `token` as a bearer token, returning the status
+fn get(server: &Server, path: &str, token: Option<&str>) -> u16 {
+    let authorization = token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token))
+        .unwrap_or_default();
+    send(
+        server,
+        format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n",
+            path, authorization
+        ),
+    )
+}
+
+/// A registry with the admin token `admin`, the public plugin `open` and
+/// the plugin `@acme/secret` of the private organization `acme`
+fn guarded(registry: Registry) -> Registry {
+    let registry = registry.with_publish_token("admin");
+    let (_, wasm) = release("open");
+    for name in ["open", "@acme/secret"] {
+        if name.starts_with("@acme") {
+            registry.create_org("acme", true).unwrap();
+        }
+        registry
+            .publish(PluginMetadata::new(name, "1.0.0").with_export("run"), &wasm)
+            .unwrap();
+    }
+    registry
+}
+
+#[test]
+fn reads_are_public_without_read_auth() {
+    let registry = guarded(Registry::new(MemoryStorage::new()));
+    let server = serve(registry);
+    assert_eq!(get(&server, "/api/v1/plugins/open", None), 200);
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("wrong")), 200);
+    assert_eq!(get(&server, "/api/v1/plugins/@acme/secret", None), 401);
+    assert_eq!(
+        get(&server, "/api/v1/plugins/@acme/secret", Some("admin")),
+        200
+    );
+    server.shutdown();
+}
+
+#[test]
+fn read_auth_requires_a_valid_token() {
+    let registry = guarded(Registry::new(MemoryStorage::new()))
+        .with_read_auth()
+        .with_read_token("reader");
+    let issued = registry
+        .issue_token("alice", vec![TokenScope::read("*")])
+        .unwrap();
+    let server = serve(registry);
+    assert_eq!(get(&server, "/api/v1/plugins/open", None), 401);
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("wrong")), 401);
+    // Same length as the admin and read tokens, differing in the last byte
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("admiN")), 401);
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("readeR")), 401);
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("admin")), 200);
+    assert_eq!(get(&server, "/api/v1/plugins/open", Some("reader")), 200);
+    assert_eq!(
+        get(&server, "/api/v1/plugins/open", Some(&issued.token)),
+        200
+    );
+    server.shutdown();
+}
+
+#[test]
+fn private_org_is_read_by_members_and_read_tokens() {
+    let registry = guarded(Registry::new(MemoryStorage::new())).with_read_token("reader");
+    registry.add_member("acme", "alice").unwrap();
+    let member = registry
+        .issue_token("alice", vec![TokenScope::read("@acme")])
+        .unwrap();
+    let outsider = registry
+        .issue_token("mallory", vec![TokenScope::read("*")])
+        .unwrap();
+    let server = serve(registry);
+    let path = "/api/v1/plugins/@acme/secret";
+    assert_eq!(get(&server, path, Some(&member.token)), 200);
+    assert_eq!(get(&server, path, Some("reader")), 200);
+    assert_eq!(get(&server, path, Some(&outsider.token)), 401);
+    assert_eq!(get(&server, path, None), 401);
+    server.shutdown();
+}
+
+#[test]
+fn admin_routes_require_the_admin_token() {
+    let server = serve(guarded(Registry::new(MemoryStorage::new())));
+    assert_eq!(get(&server, "/api/v1/orgs/acme", None), 401);
+    assert_eq!(get(&server, "/api/v1/orgs/acme", Some("admiN")), 401);
+    assert_eq!(get(&server, "/api/v1/orgs/acme", Some("admin")), 200);
+    server.shutdown();
+}
@@ -0,0 +1,94 @@
+//! Signing and verifying webhook deliveries
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+
+use extism_hello_plugin::extism_pdk::registry::{MemoryStorage, PluginMetadata, Registry, Webhook};
+
+/// Accept one delivery on `listener`, returning its signature header and body
+fn receive(listener: &TcpListener) -> (String, Vec<u8>) {
+    let (stream, _) = listener.accept().unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut signature = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.to_ascii_lowercase().as_str() {
+                "x-extismx-signature" => signature = value.trim().to_string(),
+                "content-length" => length = value.trim().parse().unwrap(),
+                _ => {}
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    reader
+        .get_mut()
+        .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    (signature, body)
+}
+
+/// Publish a release to a registry posting to a local endpoint, returning the
+/// delivery the endpoint received
+fn delivery(secret: &str) -> (String, Vec<u8>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let registry = Registry::new(MemoryStorage::new()).with_webhook(Webhook::new(url, secret));
+    let wasm = wat::parse_str(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "run") (result i32) i32.const 0))"#,
+    )
+    .unwrap();
+    registry
+        .publish(
+            PluginMetadata::new("hooked", "1.0.0").with_export("run"),
+            &wasm,
+        )
+        .unwrap();
+    receive(&listener)
+}
+
+#[test]
+fn delivery_signature_verifies() {
+    let (signature, body) = delivery("s3cret");
+    assert!(signature.starts_with("sha256="));
+    assert!(Webhook::verify("s3cret", &body, &signature));
+    assert!(!Webhook::verify("other", &body, &signature));
+}
+
+#[test]
+fn tampered_body_is_rejected() {
+    let (signature, mut body) = delivery("s3cret");
+    let last = body.len() - 1;
+    body[last] ^= 1;
+    assert!(!Webhook::verify("s3cret", &body, &signature));
+}
+
+#[test]
+fn malformed_signature_is_rejected() {
+    // HMAC-SHA256 test case 2 of RFC 4231
+    let signature = "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+    let body = b"what do ya want for nothing?";
+    assert!(Webhook::verify("Jefe", body, signature));
+    assert!(!Webhook::verify(
+        "Jefe",
+        body,
+        &signature["sha256=".len()..]
+    ));
+    assert!(!Webhook::verify(
+        "Jefe",
+        body,
+        &signature[..signature.len() - 2]
+    ));
+    assert!(!Webhook::verify("Jefe", body, "sha256=zz"));
+}